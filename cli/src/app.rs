@@ -1,37 +1,86 @@
-use std::{fmt::Debug, io::Stdout, time::Duration};
+use std::{
+    fmt::Debug,
+    io::Stdout,
+    path::PathBuf,
+    process::Stdio,
+    time::{Duration, Instant},
+};
 
 use bytes::Bytes;
 use color_eyre::eyre;
 use derivative::Derivative;
-use ratatui::{Terminal, prelude::CrosstermBackend, restore, widgets::ListState};
+use ratatui::{
+    Terminal,
+    crossterm::event::{
+        DisableBracketedPaste, DisableFocusChange, DisableMouseCapture, EnableBracketedPaste, EnableFocusChange,
+        EnableMouseCapture, KeyboardEnhancementFlags, PopKeyboardEnhancementFlags, PushKeyboardEnhancementFlags,
+    },
+    prelude::CrosstermBackend,
+    restore,
+    widgets::ListState,
+};
 use remux_core::{
     comm,
-    events::{CliEvent, DaemonEvent},
-    states::DaemonState,
+    commands::HookEvent,
+    events::{CliEvent, DaemonEvent, DetachReason, MouseKind},
+    graphics::{Chunk, GraphicsScanner},
+    key_bindings::ClientAction,
+    links::LinkMatch,
+    messages::{
+        RequestBuilder,
+        request::{self, Attach},
+        response::PreviewPane,
+    },
+    states::{DaemonState, PaneLocation, SearchMatch},
 };
-use terminput::Event;
+use terminput::{Event, KeyCode, KeyEvent, MouseButton, MouseEventKind, ScrollDirection};
 use tokio::{
     net::UnixStream,
-    sync::{broadcast, mpsc},
+    sync::{broadcast, mpsc, watch},
     time::interval,
 };
-use uuid::Uuid;
 use vt100::Parser;
 
 use crate::{
     input_parser::{self, InputParser},
     prelude::*,
-    states::status_line_state::StatusLineState,
+    states::{
+        confirm_state::ConfirmState,
+        hint_state::{HintInput, HintState},
+        notification_state::NotificationState,
+        prompt_history::PromptHistory,
+        prompt_state::{PromptInput, PromptState},
+        status_line_state::StatusLineState,
+        tree_state::TreeState,
+    },
     tasks::{
         input::{self, Input},
         lua,
     },
     ui::{
-        self, basic_selector_widget::BasicSelectorWidget, fuzzy_selector_widget::FuzzySelectorWidget,
-        traits::SelectorStatefulWidget,
+        self, basic_selector_widget::BasicSelectorWidget, confirm_widget::ConfirmWidget,
+        fuzzy_selector_widget::FuzzySelectorWidget, traits::SelectorStatefulWidget, tree_widget::TreeWidget,
     },
 };
 
+#[cfg(target_os = "macos")]
+const DEFAULT_OPENER: &str = "open";
+#[cfg(not(target_os = "macos"))]
+const DEFAULT_OPENER: &str = "xdg-open";
+const SCREEN_READER_STATUS_INTERVAL: Duration = Duration::from_secs(10);
+const RECONNECT_ATTEMPTS: u32 = 10;
+const RECONNECT_DELAY: Duration = Duration::from_millis(100); // doubled after every failed attempt
+
+/// feeds a key press to a hint overlay, any key that isn't part of a label (e.g. escape) cancels
+fn hint_input<T: Clone>(hints: &mut HintState<T>, bytes: &[u8]) -> Result<HintInput<T>> {
+    Ok(match Event::parse_from(bytes)? {
+        Some(Event::Key(KeyEvent {
+            code: KeyCode::Char(c), ..
+        })) => hints.input(c),
+        _ => HintInput::NoMatch,
+    })
+}
+
 #[derive(Derivative)]
 #[derivative(Debug)]
 pub struct TerminalState {
@@ -39,12 +88,47 @@ pub struct TerminalState {
     pub emulator: Parser,
     pub size: (u16, u16),
     pub needs_resize: bool,
+    pub window_size: Option<(u16, u16)>, // smaller than `size` while a smaller client shares the session
+    graphics: GraphicsScanner,
+    #[derivative(Debug = "ignore")]
+    pending_images: Vec<((u16, u16), Vec<u8>)>, // written straight to the terminal after the next draw
 }
 
 #[derive(Debug)]
 pub struct UiState {
     pub selector: SelectorState,
     pub status_line: StatusLineState,
+    pub notification: Option<NotificationState>,
+    pub quick_open: Option<HintState<LinkMatch>>,
+    pub pane_jump: Option<HintState<PaneLocation>>,
+    pub prompt: Option<PromptState>,
+    pub confirm: Option<ConfirmState>,
+    pub tree: Option<TreeState>,
+    pub session_preview: Option<(String, PreviewPane)>, // the highlighted session's pane while switching sessions
+    pub prompt_history: PromptHistory,
+    pub search_matches: Vec<SearchMatch>,
+    pub screen_reader: bool,              // the daemon's `screen-reader` option
+    pub status_visible: bool,             // the daemon's `status` option
+    status_line_updated: Option<Instant>, // throttles status line updates for screen readers
+    pub reconnecting: Option<Reconnecting>,
+}
+
+/// the connection to the daemon broke - input is dropped and attaching is retried until it works or
+/// the attempts run out
+#[derive(Debug)]
+pub struct Reconnecting {
+    pub attempt: u32,
+    retry_at: tokio::time::Instant,
+}
+
+impl Reconnecting {
+    fn new(attempt: u32) -> Self {
+        let delay = RECONNECT_DELAY * 2u32.pow(attempt.min(5));
+        Self {
+            attempt,
+            retry_at: tokio::time::Instant::now() + delay,
+        }
+    }
 }
 
 #[derive(Debug)]
@@ -80,6 +164,17 @@ pub struct SelectorState {
 pub enum AppMode {
     Normal,
     SelectingSession,
+    ChoosingBuffer,
+    QuickOpen,
+    PaneJump,
+    SearchPrompt,
+    CopySearchPrompt,
+    CommandPrompt,
+    RenameWindowPrompt,
+    RenameSessionPrompt,
+    ChoosingSearchMatch,
+    Confirming,
+    ChoosingTree,
 }
 
 #[derive(Debug)]
@@ -94,22 +189,44 @@ pub struct App {
     pub state: AppState,
     input_parser: InputParser,
     stream: UnixStream,
+    socket_path: PathBuf,
+    attach: Attach, // sent again with the same client id after reconnecting
     bg_tasks: Vec<CliTask>,
-    id: Uuid,
+    opener: String,               // program quick-open passes the chosen url/path to
+    extended_keys: bool,          // kitty keyboard protocol negotiated with the outer terminal
+    exit_message: Option<String>, // printed once the terminal is restored
+    previewing: Option<String>,   // the session a preview was last asked for
+    preview_tx: mpsc::Sender<(String, PreviewPane)>,
+    preview_rx: mpsc::Receiver<(String, PreviewPane)>,
 }
 
 impl App {
-    pub fn new(id: Uuid, stream: UnixStream, daemon_state: DaemonState) -> Self {
+    pub fn new(
+        attach: Attach,
+        stream: UnixStream,
+        socket_path: PathBuf,
+        daemon_state: DaemonState,
+        input_parser: InputParser,
+        opener: Option<String>,
+        extended_keys: bool,
+    ) -> Self {
+        let (preview_tx, preview_rx) = mpsc::channel(10);
         Self {
-            id,
+            attach,
             stream,
-            input_parser: InputParser::default(),
+            socket_path,
+            input_parser,
+            opener: opener.unwrap_or_else(|| DEFAULT_OPENER.to_owned()),
+            extended_keys,
             state: AppState {
                 mode: AppMode::Normal,
                 terminal: TerminalState {
                     emulator: Parser::default(),
                     size: (0, 0),
                     needs_resize: true,
+                    window_size: None,
+                    graphics: GraphicsScanner::default(),
+                    pending_images: Vec::new(),
                 },
                 daemon: daemon_state,
                 ui: UiState {
@@ -121,26 +238,66 @@ impl App {
                         displaying_list: Vec::new(),
                     },
                     status_line: StatusLineState::default(),
+                    notification: None,
+                    quick_open: None,
+                    pane_jump: None,
+                    prompt: None,
+                    confirm: None,
+                    tree: None,
+                    session_preview: None,
+                    prompt_history: PromptHistory::load(),
+                    search_matches: Vec::new(),
+                    screen_reader: false,
+                    status_visible: true,
+                    status_line_updated: None,
+                    reconnecting: None,
                 },
             },
             bg_tasks: Vec::new(),
+            exit_message: None,
+            previewing: None,
+            preview_tx,
+            preview_rx,
         }
     }
 
-    #[instrument(parent=None, skip(self), fields(id=?self.id), name="App")]
+    #[instrument(parent=None, skip(self), fields(id=?self.attach.id), name="App")]
     pub async fn run(&mut self) -> Result<()> {
         let mut term = ratatui::init();
+        // pastes arrive marked so the input parser can pass them through without reading the prefix out of them
+        ratatui::crossterm::execute!(
+            std::io::stdout(),
+            EnableMouseCapture,
+            EnableFocusChange,
+            EnableBracketedPaste
+        )?;
+        if self.extended_keys {
+            // only disambiguated keys change encoding, plain text is still sent as is
+            let flags = KeyboardEnhancementFlags::DISAMBIGUATE_ESCAPE_CODES;
+            ratatui::crossterm::execute!(std::io::stdout(), PushKeyboardEnhancementFlags(flags))?;
+        }
         debug!("Starting app");
         let (input_tx, mut input_rx) = mpsc::channel::<Input>(100);
         let (lua_tx, mut lua_rx) = broadcast::channel(100);
+        let (lua_command_tx, mut lua_command_rx) = mpsc::channel::<String>(100);
+        let (hook_tx, hook_rx) = mpsc::channel::<(HookEvent, Option<String>)>(100);
+        // what the lua status line can read about the daemon
+        let (daemon_state_tx, daemon_state_rx) = watch::channel(self.state.daemon.clone());
         self.bg_tasks.extend(input::start_input_listeners(input_tx));
-        self.bg_tasks.push(lua::start_status_line_task(lua_tx)?);
+        self.bg_tasks.push(lua::start_status_line_task(
+            lua_tx,
+            lua_command_tx,
+            hook_rx,
+            daemon_state_rx,
+        )?);
         let mut ticker = interval(Duration::from_millis(50));
 
         // need an initial render since ui updates app state to convey terminal size information
         term.draw(|f| ui::draw(f, &mut self.state))?;
+        // screen readers follow every cursor move, so with one enabled only redraw after something changed
+        let mut dirty = false;
         loop {
-            if self.state.terminal.needs_resize {
+            if self.state.terminal.needs_resize && self.state.ui.reconnecting.is_none() {
                 let (rows, cols) = self.state.terminal.size;
                 info!(rows = rows, cols = cols, "Setting terminal emulator size");
                 self.state.terminal.emulator.set_size(rows, cols);
@@ -150,13 +307,18 @@ impl App {
             }
             tokio::select! {
                 Some(input) = input_rx.recv() => {
+                    dirty = true;
                     let span = error_span!("Recieved Input");
                     let _guard = span.enter();
                     use Input::{Stdin, Resize};
                     match &input {
+                        // nothing to send keys to until the daemon is back
+                        Stdin(_) if self.state.ui.reconnecting.is_some() => {}
                         Stdin(bytes) => {
                             trace!(input=?input);
-                            self.dispatch_stdin(bytes.clone()).await.unwrap();
+                            if let Err(e) = self.dispatch_stdin(bytes.clone()).await {
+                                self.lost_connection(e);
+                            }
                         }
                         Resize => {
                             info!(input=?input);
@@ -164,12 +326,41 @@ impl App {
                         }
                     }
                 }
+                Some((session, preview)) = self.preview_rx.recv() => {
+                    // a preview for a session that's no longer highlighted arrived late
+                    let selecting = matches!(self.state.mode, AppMode::SelectingSession);
+                    if selecting && self.previewing.as_ref() == Some(&session) {
+                        dirty = true;
+                        self.state.ui.session_preview = Some((session, preview));
+                    }
+                }
+                Some(command) = lua_command_rx.recv(), if self.state.ui.reconnecting.is_none() => {
+                    dirty = true;
+                    info!(command=%command, "received command from lua");
+                    if let Err(e) = comm::send_event(&mut self.stream, CliEvent::RunCommand(command)).await {
+                        self.lost_connection(e.into());
+                    }
+                }
                 Ok(mut status_line_state) = lua_rx.recv() => {
+                    dirty = true;
                     trace!(status_line_state=?status_line_state, "received status line state");
-                    status_line_state.apply_built_ins(&self.state);
-                    self.state.ui.status_line = status_line_state;
+                    // a status line changing under a screen reader keeps interrupting it
+                    let ui = &self.state.ui;
+                    let throttled = ui.screen_reader
+                        && ui
+                            .status_line_updated
+                            .is_some_and(|updated| updated.elapsed() < SCREEN_READER_STATUS_INTERVAL);
+                    if !throttled {
+                        status_line_state.apply_built_ins(&self.state);
+                        if self.state.ui.screen_reader {
+                            status_line_state.strip_glyphs();
+                        }
+                        self.state.ui.status_line = status_line_state;
+                        self.state.ui.status_line_updated = Some(Instant::now());
+                    }
                 }
-                res = comm::recv_daemon_event(&mut self.stream) => {
+                res = comm::recv_daemon_event(&mut self.stream), if self.state.ui.reconnecting.is_none() => {
+                    dirty = true;
                     match res {
                         Ok(event) => {
                             let span = error_span!("Recieved Daemon Event");
@@ -184,30 +375,142 @@ impl App {
                             }
                             match event {
                                 DaemonEvent::Raw(bytes) => {
-                                    self.state.terminal.emulator.process(&bytes);
+                                    self.process_output(&bytes);
                                 }
-                                DaemonEvent::Disconnected => {
+                                DaemonEvent::Disconnected(reason) => {
+                                    self.exit_message = Some(match (&reason, self.state.daemon.active_session()) {
+                                        (DetachReason::Detached | DetachReason::Replaced, Some(session)) => {
+                                            format!("[{reason} (from session {})]", session.name)
+                                        }
+                                        _ => format!("[{reason}]"),
+                                    });
                                     break;
                                 }
                                 DaemonEvent::ActiveSession(session_id) => {
                                     self.state.daemon.set_active_session(session_id);
                                 }
-                                DaemonEvent::NewSession(session_id, session_name) => {
-                                    self.state.daemon.add_session(session_id, session_name);
+                                DaemonEvent::LastSession(session_id) => {
+                                    self.state.daemon.last_session = session_id;
+                                    self.state.ui.status_line_updated = None;
                                 }
-                                _ => {
-                                    todo!();
+                                DaemonEvent::StateDelta(delta) => {
+                                    // the lua copy gets the same delta rather than a clone of the whole state
+                                    daemon_state_tx.send_modify(|state| state.apply(delta.clone()));
+                                    self.state.daemon.apply(delta);
+                                    self.state.ui.status_line_updated = None;
+                                }
+                                DaemonEvent::ScreenReader(enabled) => {
+                                    self.state.ui.screen_reader = enabled;
+                                    self.state.ui.status_line_updated = None;
+                                }
+                                DaemonEvent::StatusLine(visible) => {
+                                    // the next draw notices the new terminal area and resizes
+                                    self.state.ui.status_visible = visible;
+                                }
+                                DaemonEvent::KeyBindings(bindings) => {
+                                    self.input_parser.set_bindings(bindings.clone());
+                                    self.state.daemon.key_bindings = bindings;
+                                }
+                                DaemonEvent::SessionClients(clients) => {
+                                    self.state.daemon.clients = clients;
+                                    self.state.ui.status_line_updated = None;
+                                }
+                                DaemonEvent::SessionWindows { windows, active } => {
+                                    self.state.daemon.set_windows(windows, active);
+                                    self.state.ui.status_line_updated = None;
+                                }
+                                DaemonEvent::HookFired(hook, session_name) => {
+                                    // a callback that can't keep up misses events rather than stalling the client
+                                    let _ = hook_tx.try_send((hook, session_name));
+                                }
+                                DaemonEvent::SessionAlert { session_id, alert, bell } => {
+                                    self.state.daemon.set_alert(session_id, Some(alert));
+                                    if bell {
+                                        ring_bell()?;
+                                    }
+                                }
+                                DaemonEvent::Bell { .. } => {
+                                    ring_bell()?;
+                                }
+                                DaemonEvent::WindowSize { rows, cols } => {
+                                    self.state.terminal.window_size = Some((rows, cols));
+                                }
+                                DaemonEvent::Message(message) => {
+                                    self.state.ui.notification = Some(NotificationState::from_message(message));
+                                }
+                                DaemonEvent::Links(links) => {
+                                    self.state.ui.quick_open = Some(HintState::new(links));
+                                    self.state.mode = AppMode::QuickOpen;
+                                }
+                                DaemonEvent::PaneLocations(panes) => {
+                                    self.state.ui.pane_jump = Some(HintState::new(panes));
+                                    self.state.mode = AppMode::PaneJump;
+                                }
+                                DaemonEvent::SearchResults(matches) => {
+                                    let items = matches
+                                        .iter()
+                                        .map(|m| format!("pane {} line {}: {}", m.pane_id, m.line, m.text.trim()))
+                                        .collect();
+                                    self.state.ui.search_matches = matches;
+                                    self.open_selector(AppMode::ChoosingSearchMatch, SelectorType::Fuzzy, items);
+                                }
+                                DaemonEvent::Buffers(previews) => {
+                                    self.open_selector(AppMode::ChoosingBuffer, SelectorType::Fuzzy, previews);
+                                }
+                                DaemonEvent::Tree(sessions, sessions_only) => {
+                                    let tree = TreeState::new(sessions, sessions_only, self.state.daemon.active_session);
+                                    self.state.ui.tree = Some(tree);
+                                    self.state.mode = AppMode::ChoosingTree;
+                                    self.request_tree_preview().await?;
+                                }
+                                DaemonEvent::PaneCapture { rows, cols, contents } => {
+                                    if let Some(tree) = self.state.ui.tree.as_mut() {
+                                        tree.set_preview(rows, cols, &contents);
+                                    }
+                                }
+                                DaemonEvent::PaneOutput(..) | DaemonEvent::Exited(..) => {
+                                    // only sent to control mode and exec clients
+                                    warn!("Ignoring event meant for another kind of client");
                                 }
                             }
+                            daemon_state_tx.send_if_modified(|state| {
+                                let modified = *state != self.state.daemon;
+                                if modified {
+                                    state.clone_from(&self.state.daemon);
+                                }
+                                modified
+                            });
+                        }
+                        Err(e) => self.lost_connection(e.into()),
+                    }
+                }
+                _ = tokio::time::sleep_until(
+                    self.state.ui.reconnecting.as_ref().map_or_else(tokio::time::Instant::now, |r| r.retry_at)
+                ), if self.state.ui.reconnecting.is_some() => {
+                    dirty = true;
+                    match self.reconnect().await {
+                        Ok(()) => {
+                            info!("Reconnected to the daemon");
+                            daemon_state_tx.send_replace(self.state.daemon.clone());
                         }
                         Err(e) => {
-                            error!(error=%e, "Error receiving daemon event");
-                            // break;
+                            let attempt = self.state.ui.reconnecting.as_ref().map_or(1, |r| r.attempt);
+                            warn!(error=%e, attempt=attempt, "Reconnecting failed");
+                            if attempt >= RECONNECT_ATTEMPTS {
+                                self.exit_message = Some("[lost connection to the daemon]".to_owned());
+                                break;
+                            }
+                            self.state.ui.reconnecting = Some(Reconnecting::new(attempt + 1));
                         }
                     }
                 }
                 _ = ticker.tick() => {
-                    term.draw(|f| ui::draw(f, &mut self.state))?;
+                    // a shown notification still has to be cleared once it expires
+                    if dirty || !self.state.ui.screen_reader || self.state.ui.notification.is_some() {
+                        term.draw(|f| ui::draw(f, &mut self.state))?;
+                        self.draw_images()?;
+                        dirty = false;
+                    }
                 }
             }
         }
@@ -215,16 +518,45 @@ impl App {
             task.abort();
             let _ = task.await;
         }
+        if self.extended_keys {
+            ratatui::crossterm::execute!(std::io::stdout(), PopKeyboardEnhancementFlags)?;
+        }
+        ratatui::crossterm::execute!(
+            std::io::stdout(),
+            DisableMouseCapture,
+            DisableFocusChange,
+            DisableBracketedPaste
+        )?;
         drop(term);
         restore();
         debug!("Restoring terminal");
+        if let Some(message) = self.exit_message.take() {
+            println!("{message}");
+        }
         Ok(())
     }
 
     async fn dispatch_stdin(&mut self, bytes: Bytes) -> Result<()> {
+        // focus changes of the outer terminal are passed on whatever mode we're in
+        if let Ok(Some(event @ (Event::FocusGained | Event::FocusLost))) = Event::parse_from(&bytes) {
+            let focused = matches!(event, Event::FocusGained);
+            comm::send_event(&mut self.stream, CliEvent::Focus(focused)).await?;
+            return Ok(());
+        }
         match self.state.mode {
             AppMode::Normal => self.handle_stdin_for_normal_mode(bytes).await?,
-            AppMode::SelectingSession => self.handle_stdin_for_selecting_mode(bytes).await?,
+            AppMode::SelectingSession | AppMode::ChoosingBuffer | AppMode::ChoosingSearchMatch => {
+                self.handle_stdin_for_selecting_mode(bytes).await?
+            }
+            AppMode::Confirming => self.handle_stdin_for_confirm_mode(bytes).await?,
+            AppMode::ChoosingTree => self.handle_stdin_for_tree_mode(bytes).await?,
+            AppMode::QuickOpen => self.handle_stdin_for_quick_open_mode(bytes).await?,
+            AppMode::PaneJump => self.handle_stdin_for_pane_jump_mode(bytes).await?,
+            AppMode::SearchPrompt
+            | AppMode::CopySearchPrompt
+            | AppMode::CommandPrompt
+            | AppMode::RenameWindowPrompt
+            | AppMode::RenameSessionPrompt => self.handle_stdin_for_prompt_mode(bytes).await?,
         }
 
         Ok(())
@@ -244,24 +576,257 @@ impl App {
                         let session = &self.state.daemon.sessions[i];
                         comm::send_event(&mut self.stream, CliEvent::SwitchSession(session.name.clone())).await?;
                     }
-                    AppMode::Normal => {}
+                    AppMode::ChoosingBuffer => {
+                        comm::send_event(&mut self.stream, CliEvent::PasteBuffer(i)).await?;
+                    }
+                    AppMode::ChoosingSearchMatch => {
+                        if let Some(m) = self.state.ui.search_matches.get(i) {
+                            let event = CliEvent::JumpToSearchMatch {
+                                pane_id: m.pane_id,
+                                line: m.line,
+                            };
+                            comm::send_event(&mut self.stream, event).await?;
+                        }
+                    }
+                    AppMode::Normal
+                    | AppMode::QuickOpen
+                    | AppMode::PaneJump
+                    | AppMode::SearchPrompt
+                    | AppMode::CopySearchPrompt
+                    | AppMode::CommandPrompt
+                    | AppMode::RenameWindowPrompt
+                    | AppMode::RenameSessionPrompt
+                    | AppMode::Confirming
+                    | AppMode::ChoosingTree => {}
                 },
                 ui::traits::Selection::Cancelled => {}
             }
             self.state.mode = AppMode::Normal;
             self.state.ui.selector.list_state.select(Some(0));
             self.state.ui.selector.list.clear();
+            self.state.ui.session_preview = None;
+            self.previewing = None;
+        } else if let AppMode::SelectingSession = self.state.mode {
+            self.request_session_preview();
         }
         Ok(())
     }
 
+    /// asks for the highlighted session's pane on a connection of its own, the answer comes back through
+    /// `preview_rx` while the selector stays usable
+    fn request_session_preview(&mut self) {
+        let selector = &self.state.ui.selector;
+        let Some(session) = selector
+            .list_state
+            .selected()
+            .and_then(|i| selector.displaying_list.get(i))
+            .and_then(|item| self.state.daemon.sessions.get(item.index))
+            .map(|session| session.name.clone())
+        else {
+            return;
+        };
+        if self.previewing.as_ref() == Some(&session) {
+            return;
+        }
+        self.previewing = Some(session.clone());
+        let (socket_path, tx) = (self.socket_path.clone(), self.preview_tx.clone());
+        // the panel takes the right half of the screen
+        let (rows, cols) = self.state.terminal.size;
+        let _task: CliTask = tokio::spawn(async move {
+            let mut stream = UnixStream::connect(&socket_path).await?;
+            let request = RequestBuilder::default()
+                .body(request::PreviewPane {
+                    preview_session: session.clone(),
+                    rows,
+                    cols: cols / 2,
+                })
+                .build();
+            let preview = comm::send_and_recv_message(&mut stream, &request).await?;
+            let _ = tx.send((session, preview)).await;
+            Ok(())
+        });
+    }
+
+    async fn handle_stdin_for_confirm_mode(&mut self, bytes: Bytes) -> Result<()> {
+        let (Some(confirm), Some(event)) = (self.state.ui.confirm.as_mut(), Event::parse_from(&bytes)?) else {
+            return Ok(());
+        };
+        let Some(selection) = ConfirmWidget::input(event, confirm) else {
+            return Ok(());
+        };
+        let confirm = self.state.ui.confirm.take();
+        self.state.mode = AppMode::Normal;
+        if let (ui::traits::Selection::Index(_), Some(confirm)) = (selection, confirm) {
+            comm::send_event(&mut self.stream, CliEvent::RunCommand(confirm.command)).await?;
+        }
+        Ok(())
+    }
+
+    async fn handle_stdin_for_tree_mode(&mut self, bytes: Bytes) -> Result<()> {
+        let (Some(tree), Some(event)) = (self.state.ui.tree.as_mut(), Event::parse_from(&bytes)?) else {
+            return Ok(());
+        };
+        let Some(selection) = TreeWidget::input(event, tree) else {
+            return self.request_tree_preview().await;
+        };
+        let tree = self.state.ui.tree.take();
+        self.state.mode = AppMode::Normal;
+        if let (ui::traits::Selection::Index(_), Some(tree)) = (selection, tree) {
+            for event in tree.switch_events() {
+                comm::send_event(&mut self.stream, event).await?;
+            }
+        }
+        Ok(())
+    }
+
+    /// asks for the pane under the choose-tree selection unless it's already the one shown
+    async fn request_tree_preview(&mut self) -> Result<()> {
+        let Some(tree) = self.state.ui.tree.as_mut() else {
+            return Ok(());
+        };
+        let target = tree.preview_target();
+        if target == tree.previewing {
+            return Ok(());
+        }
+        tree.previewing = target;
+        if let Some((session, window, pane)) = target {
+            comm::send_event(&mut self.stream, CliEvent::CapturePane { session, window, pane }).await?;
+        }
+        Ok(())
+    }
+
+    async fn handle_stdin_for_prompt_mode(&mut self, bytes: Bytes) -> Result<()> {
+        let (Some(prompt), Some(event)) = (self.state.ui.prompt.as_mut(), Event::parse_from(&bytes)?) else {
+            return Ok(());
+        };
+        let history = match self.state.mode {
+            AppMode::CommandPrompt => self.state.ui.prompt_history.entries.as_slice(),
+            _ => &[],
+        };
+        match prompt.handle_event(event, history) {
+            PromptInput::Pending => return Ok(()),
+            PromptInput::Submitted(input) if !input.is_empty() => {
+                let event = match self.state.mode {
+                    AppMode::CommandPrompt => {
+                        self.state.ui.prompt_history.push(&input);
+                        CliEvent::RunCommand(input)
+                    }
+                    AppMode::RenameWindowPrompt => CliEvent::RenameWindow(input),
+                    AppMode::RenameSessionPrompt => CliEvent::RenameSession(input),
+                    AppMode::CopySearchPrompt => CliEvent::CopyModeSearch(input),
+                    _ => CliEvent::SearchPanes(input),
+                };
+                comm::send_event(&mut self.stream, event).await?;
+            }
+            PromptInput::Submitted(_) | PromptInput::Cancelled => {}
+        }
+        self.state.ui.prompt = None;
+        self.state.mode = AppMode::Normal;
+        Ok(())
+    }
+
+    async fn handle_stdin_for_quick_open_mode(&mut self, bytes: Bytes) -> Result<()> {
+        let Some(quick_open) = self.state.ui.quick_open.as_mut() else {
+            self.state.mode = AppMode::Normal;
+            return Ok(());
+        };
+        let input = hint_input(quick_open, &bytes)?;
+
+        match input {
+            HintInput::Pending => return Ok(()),
+            HintInput::NoMatch => {}
+            // an uppercase label copies the link instead of opening it
+            HintInput::Chosen { item, uppercase: true } => {
+                self.state.ui.notification = Some(NotificationState::new(format!("copied {}", item.text)));
+                comm::send_event(&mut self.stream, CliEvent::SetBuffer(item.text)).await?;
+            }
+            HintInput::Chosen { item, .. } => self.open_link(&item.text),
+        }
+        self.state.ui.quick_open = None;
+        self.state.mode = AppMode::Normal;
+        Ok(())
+    }
+
+    async fn handle_stdin_for_pane_jump_mode(&mut self, bytes: Bytes) -> Result<()> {
+        let Some(pane_jump) = self.state.ui.pane_jump.as_mut() else {
+            self.state.mode = AppMode::Normal;
+            return Ok(());
+        };
+
+        match hint_input(pane_jump, &bytes)? {
+            HintInput::Pending => return Ok(()),
+            HintInput::NoMatch => {}
+            HintInput::Chosen { item, .. } => {
+                comm::send_event(&mut self.stream, CliEvent::SelectPane(item.pane_id)).await?;
+            }
+        }
+        self.state.ui.pane_jump = None;
+        self.state.mode = AppMode::Normal;
+        Ok(())
+    }
+
+    fn open_link(&mut self, text: &str) {
+        let target = match text.strip_prefix("~/") {
+            Some(rest) => std::env::var("HOME").map_or_else(|_| text.to_owned(), |home| format!("{home}/{rest}")),
+            None => text.to_owned(),
+        };
+        let message = match std::process::Command::new(&self.opener)
+            .arg(&target)
+            .stdin(Stdio::null())
+            .stdout(Stdio::null())
+            .stderr(Stdio::null())
+            .spawn()
+        {
+            Ok(_) => format!("opened {text}"),
+            Err(e) => {
+                error!(error=%e, opener=%self.opener, "failed to run opener");
+                format!("failed to run {}: {e}", self.opener)
+            }
+        };
+        self.state.ui.notification = Some(NotificationState::new(message));
+    }
+
     async fn handle_stdin_for_normal_mode(&mut self, bytes: Bytes) -> Result<()> {
+        if let Ok(Some(Event::Mouse(mouse))) = Event::parse_from(&bytes) {
+            let (row, col) = (mouse.row, mouse.column);
+            let event = match mouse.kind {
+                MouseEventKind::Down(MouseButton::Left) => CliEvent::Mouse {
+                    kind: MouseKind::Down,
+                    row,
+                    col,
+                },
+                MouseEventKind::Drag(MouseButton::Left) => CliEvent::Mouse {
+                    kind: MouseKind::Drag,
+                    row,
+                    col,
+                },
+                MouseEventKind::Up(MouseButton::Left) => CliEvent::Mouse {
+                    kind: MouseKind::Up,
+                    row,
+                    col,
+                },
+                MouseEventKind::Scroll(ScrollDirection::Up) => CliEvent::ScrollUp { row, col },
+                MouseEventKind::Scroll(ScrollDirection::Down) => CliEvent::ScrollDown { row, col },
+                _ => return Ok(()),
+            };
+            comm::send_event(&mut self.stream, event).await?;
+            return Ok(());
+        }
         for parsed_event in self.input_parser.process(&bytes) {
             match parsed_event {
                 input_parser::ParsedEvent::LocalAction(action) => {
                     self.dispatch_action(action).await;
                 }
                 input_parser::ParsedEvent::DaemonAction(cli_event) => {
+                    // kills bound to keys are easy to hit by accident, they wait for a yes first
+                    if let CliEvent::RunCommand(line) = &cli_event
+                        && self.state.ui.status_line.confirm_before_kill
+                        && let Some(confirm) = ConfirmState::for_command(line)
+                    {
+                        self.state.ui.confirm = Some(confirm);
+                        self.state.mode = AppMode::Confirming;
+                        continue;
+                    }
                     comm::send_event(&mut self.stream, cli_event).await?;
                 }
             }
@@ -269,31 +834,129 @@ impl App {
         Ok(())
     }
 
-    async fn dispatch_action(&mut self, action: input_parser::Action) {
+    async fn dispatch_action(&mut self, action: ClientAction) {
         match action {
-            input_parser::Action::SwitchSession => {
-                self.state.mode = AppMode::SelectingSession;
-                self.state.ui.selector.query.clear();
-                self.state.ui.selector.list_state.select(Some(0));
-                self.state.ui.selector.selector_type = SelectorType::Basic;
-                self.state
-                    .ui
-                    .selector
-                    .list
-                    .extend(self.state.daemon.sessions.iter().map(|x| x.name.clone()));
-                self.state.ui.selector.displaying_list = self
-                    .state
-                    .ui
-                    .selector
-                    .list
-                    .iter()
-                    .enumerate()
-                    .map(|(i, x)| IndexedItem::new(i, x.clone()))
-                    .collect();
+            ClientAction::ChooseSession => {
+                let sessions = self.state.daemon.sessions.iter().map(|x| x.name.clone()).collect();
+                self.open_selector(AppMode::SelectingSession, SelectorType::Basic, sessions);
+                self.request_session_preview();
+            }
+            ClientAction::SearchPrompt => {
+                self.state.ui.prompt = Some(PromptState::new("search: "));
+                self.state.mode = AppMode::SearchPrompt;
+            }
+            ClientAction::CopySearchPrompt => {
+                self.state.ui.prompt = Some(PromptState::new("search scrollback: "));
+                self.state.mode = AppMode::CopySearchPrompt;
+            }
+            ClientAction::CommandPrompt => {
+                self.state.ui.prompt = Some(PromptState::new(":"));
+                self.state.mode = AppMode::CommandPrompt;
+            }
+            ClientAction::RenamePrompt => {
+                self.state.ui.prompt = Some(PromptState::new("rename window: "));
+                self.state.mode = AppMode::RenameWindowPrompt;
+            }
+            ClientAction::RenameSessionPrompt => {
+                let mut prompt = PromptState::new("rename session: ");
+                if let Some(session) = self.state.daemon.active_session() {
+                    prompt.input = session.name.clone();
+                }
+                self.state.ui.prompt = Some(prompt);
+                self.state.mode = AppMode::RenameSessionPrompt;
+            }
+        }
+    }
+
+    fn open_selector(&mut self, mode: AppMode, selector_type: SelectorType, list: Vec<String>) {
+        self.state.mode = mode;
+        self.state.ui.selector.query.clear();
+        self.state.ui.selector.list_state.select(Some(0));
+        self.state.ui.selector.selector_type = selector_type;
+        self.state.ui.selector.list = list;
+        self.state.ui.selector.displaying_list = self
+            .state
+            .ui
+            .selector
+            .list
+            .iter()
+            .enumerate()
+            .map(|(i, x)| IndexedItem::new(i, x.clone()))
+            .collect();
+    }
+
+    /// vt100 can't draw images, they're held back and written over the frame once it's drawn
+    fn process_output(&mut self, bytes: &[u8]) {
+        let terminal = &mut self.state.terminal;
+        for chunk in terminal.graphics.split(bytes) {
+            match chunk {
+                Chunk::Text(text) => terminal.emulator.process(&text),
+                Chunk::Image(image) => {
+                    let cursor = terminal.emulator.screen().cursor_position();
+                    terminal.pending_images.push((cursor, image));
+                }
             }
         }
     }
 
+    fn draw_images(&mut self) -> Result<()> {
+        use std::io::Write;
+        let images = std::mem::take(&mut self.state.terminal.pending_images);
+        // an overlay would be drawn over by the image
+        if images.is_empty() || !matches!(self.state.mode, AppMode::Normal) {
+            return Ok(());
+        }
+        let mut stdout = std::io::stdout();
+        for ((row, col), image) in images {
+            // the terminal area is drawn at the top left, save and restore the cursor ratatui left behind
+            write!(stdout, "\x1b7\x1b[{};{}H", row + 1, col + 1)?;
+            stdout.write_all(&image)?;
+            stdout.write_all(b"\x1b8")?;
+        }
+        stdout.flush()?;
+        Ok(())
+    }
+
+    fn lost_connection(&mut self, e: Error) {
+        warn!(error=%e, "Lost connection to the daemon, reconnecting");
+        self.state.ui.reconnecting = Some(Reconnecting::new(1));
+    }
+
+    /// attaches again under the same client id to the session that was showing, the daemon then redraws
+    /// everything so the emulator starts over from a blank screen
+    async fn reconnect(&mut self) -> Result<()> {
+        let mut stream = UnixStream::connect(&self.socket_path).await?;
+        let daemon = &self.state.daemon;
+        let session_name = daemon
+            .sessions
+            .iter()
+            .find(|session| Some(session.id) == daemon.active_session)
+            .map_or_else(|| self.attach.session_name.clone(), |session| session.name.clone());
+        let request = RequestBuilder::default()
+            .body(Attach {
+                session_name,
+                // the session's first pane already ran it
+                command: None,
+                argv: vec![],
+                // the others were detached on the first attach
+                detach_others: false,
+                ..self.attach.clone()
+            })
+            .build();
+        let res = comm::send_and_recv_message(&mut stream, &request).await?;
+
+        self.stream = stream;
+        self.input_parser
+            .set_bindings(res.initial_daemon_state.key_bindings.clone());
+        self.state.daemon = res.initial_daemon_state;
+        let terminal = &mut self.state.terminal;
+        terminal.emulator = Parser::new(terminal.size.0, terminal.size.1, 0);
+        terminal.window_size = None;
+        terminal.needs_resize = true;
+        self.state.ui.reconnecting = None;
+        Ok(())
+    }
+
     #[instrument(skip(self, term))]
     async fn handle_resize(&mut self, term: &mut Terminal<CrosstermBackend<Stdout>>) -> Result<()> {
         self.state.terminal.needs_resize = true;
@@ -304,3 +967,12 @@ impl App {
         eyre::Ok(())
     }
 }
+
+/// BEL straight to the outer terminal, it never comes through with the rendered output
+fn ring_bell() -> Result<()> {
+    use std::io::Write;
+    let mut stdout = std::io::stdout();
+    stdout.write_all(b"\x07")?;
+    stdout.flush()?;
+    Ok(())
+}
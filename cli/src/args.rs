@@ -1,9 +1,30 @@
+use std::path::PathBuf;
+
 use clap::{Parser, Subcommand};
+use remux_core::{commands::key_bytes, constants::DEFAULT_SOCKET_NAME};
+use tracing_subscriber::EnvFilter;
 
 #[derive(Parser, Debug)]
 pub struct Args {
     #[command(subcommand)]
     pub command: Commands,
+    #[command(flatten)]
+    pub logging: LogArgs,
+    /// socket name like tmux's `-L`, each name has its own daemon and sessions
+    #[arg(short = 'L', long, global = true, default_value = DEFAULT_SOCKET_NAME)]
+    pub socket_name: String,
+}
+
+#[derive(clap::Args, Debug)]
+pub struct LogArgs {
+    /// tracing filter, e.g. `info` or `remux_cli=trace` - defaults to $RUST_LOG, then `debug`
+    #[arg(long, global = true, value_parser = parse_log_level)]
+    pub log_level: Option<String>,
+    /// defaults to `<state dir>/logs/remux-cli.log`, missing directories are created
+    #[arg(long, global = true)]
+    pub log_file: Option<PathBuf>,
+    #[arg(long, global = true, conflicts_with_all = ["log_level", "log_file"])]
+    pub no_log: bool,
 }
 
 #[derive(Subcommand, Debug)]
@@ -11,14 +32,157 @@ pub enum Commands {
     Attach {
         #[arg(short = 's', long = "session")]
         session_name: String,
+        /// secondary prefix key (e.g. C-a) for when C-b is already taken by an outer multiplexer
+        #[arg(long = "second-prefix", value_parser = parse_prefix_key)]
+        second_prefix: Option<u8>,
+        /// program quick-open (prefix-u) runs with the chosen url or path, defaults to xdg-open/open
+        #[arg(long)]
+        opener: Option<String>,
+        /// ask the terminal for kitty keyboard protocol keys so modifier-rich bindings reach panes that want them
+        #[arg(long)]
+        extended_keys: bool,
+        /// no tui - read json `CliEvent`s from stdin and write json `DaemonEvent`s to stdout, one per line,
+        /// with each pane's output as base64 `PaneOutput` events instead of rendered frames
+        #[arg(long)]
+        control: bool,
+        /// detach every other client of the session
+        #[arg(short = 'd', long)]
+        detach_others: bool,
+    },
+    /// create a session and attach to it, the session's first pane runs `command` through the shell if given,
+    /// or the program after `--` (e.g. `remux new -s build -- cargo watch -x test`). fails if the session exists
+    New {
+        /// defaults to the session's id
+        #[arg(short = 's', long = "session")]
+        session_name: Option<String>,
+        /// don't attach, prints the session's name for scripts to pass to `-t`
+        #[arg(short = 'd', long)]
+        detached: bool,
+        #[arg(short = 'c', long = "command", conflicts_with = "argv")]
+        command: Option<String>,
+        /// share this session's windows, each session keeps its own active window
+        #[arg(short = 't', long = "target", conflicts_with_all = ["command", "argv"])]
+        group_with: Option<String>,
+        #[arg(last = true)]
+        argv: Vec<String>,
+    },
+    /// attach in control mode - line based notifications on stdout, commands on stdin (like `tmux -CC`)
+    Control {
+        #[arg(short = 's', long = "session")]
+        session_name: String,
+    },
+    /// run a command in a temporary session, streaming its output and exiting with its status -
+    /// `remux attach -s <session>` shows it live while it runs
+    Exec {
+        /// name for the temporary session, defaults to `exec-<id>`
+        #[arg(short = 's', long = "session")]
+        session_name: Option<String>,
+        #[arg(required = true, trailing_var_arg = true, allow_hyphen_values = true)]
+        command: Vec<String>,
     },
     Session {
         #[command(subcommand)]
         action: SessionCommands,
     },
+    /// list the daemon's sessions with their windows, panes and attached clients
+    #[command(alias = "list-sessions")]
+    Ls,
+    /// kill a session and everything running in it, attached clients are detached
+    KillSession {
+        /// session name or id
+        name: String,
+    },
+    /// give a session a new name, attached clients keep their place in it
+    #[command(alias = "rename")]
+    RenameSession {
+        /// session name or id
+        #[arg(short = 't', long = "target")]
+        target: String,
+        name: String,
+    },
+    /// store text as the newest paste buffer, replacing buffer `name` if given
+    #[command(alias = "setb")]
+    SetBuffer {
+        #[arg(short = 'b')]
+        name: Option<String>,
+        data: String,
+    },
+    /// print a paste buffer, the newest by default
+    #[command(alias = "showb")]
+    ShowBuffer {
+        /// buffer name or index
+        #[arg(short = 'b')]
+        buffer: Option<String>,
+    },
+    /// paste a buffer into the active pane of a session
+    #[command(alias = "pasteb")]
+    PasteBuffer {
+        #[arg(short = 't', long = "target")]
+        session_name: String,
+        /// buffer name or index
+        #[arg(short = 'b')]
+        buffer: Option<String>,
+        /// wrap the text in bracketed paste markers when the program asked for them
+        #[arg(short = 'p')]
+        bracketed: bool,
+    },
+    /// rerun config files on the running daemon, `remux.toml` and `remux.conf` from the config dir by default
+    #[command(alias = "source")]
+    SourceFile { paths: Vec<PathBuf> },
+    /// stress the running daemon with synthetic sessions and report throughput and render latency
+    Bench {
+        #[arg(long, default_value_t = 1)]
+        sessions: usize,
+        /// output generating panes per session
+        #[arg(long, default_value_t = 2)]
+        panes: usize,
+        /// lines per second written by each pane
+        #[arg(long, default_value_t = 100)]
+        rate: u32,
+        /// seconds to generate output for
+        #[arg(long, default_value_t = 10)]
+        duration: u64,
+        /// lines per second typed into an extra pane of each session to time the echo, 0 for none
+        #[arg(long, default_value_t = 0)]
+        input_rate: u32,
+        /// terminal size of each bench client, `<rows>x<cols>`
+        #[arg(long, default_value = "50x200", value_parser = parse_size)]
+        size: (u16, u16),
+    },
+    /// writes timestamped lines for `remux bench`, runs inside its panes
+    #[command(hide = true)]
+    BenchGenerate {
+        #[arg(long)]
+        stream: usize,
+        #[arg(long)]
+        rate: u32,
+        #[arg(long)]
+        duration: u64,
+    },
 }
 
 #[derive(Subcommand, Debug)]
 pub enum SessionCommands {
+    /// same as `remux ls`
     List,
 }
+
+fn parse_log_level(level: &str) -> Result<String, String> {
+    EnvFilter::try_new(level)
+        .map(|_| level.to_owned())
+        .map_err(|e| e.to_string())
+}
+
+fn parse_size(size: &str) -> Result<(u16, u16), String> {
+    size.split_once('x')
+        .and_then(|(rows, cols)| Some((rows.parse().ok()?, cols.parse().ok()?)))
+        .filter(|&(rows, cols)| rows > 0 && cols > 0)
+        .ok_or_else(|| format!("'{size}' is not <rows>x<cols>"))
+}
+
+fn parse_prefix_key(key: &str) -> Result<u8, String> {
+    match key_bytes(key).as_slice() {
+        &[b] => Ok(b),
+        _ => Err(format!("'{key}' is not a single key")),
+    }
+}
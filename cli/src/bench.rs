@@ -0,0 +1,422 @@
+// `remux bench` - synthetic sessions against a live daemon. every pane runs `remux bench-generate`,
+// which prints lines tagged with when they were written, and each session's client times how long
+// the tags take to come back in the rendered output
+
+use std::{
+    collections::HashSet,
+    io::Write,
+    path::PathBuf,
+    sync::Arc,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use bytes::Bytes;
+use remux_core::{
+    colors::ColorDepth,
+    comm,
+    events::{CliEvent, DaemonEvent},
+    messages::{
+        RequestBuilder,
+        request::{self, ClientEnvironment},
+    },
+};
+use tokio::{
+    net::{UnixStream, unix::OwnedReadHalf},
+    sync::watch,
+    time::Instant,
+};
+use uuid::Uuid;
+
+use crate::prelude::*;
+
+const MARKER_START: &[u8] = b"#rx";
+const MARKER_MAX_LEN: usize = 64;
+const FILLER: &str = "abcdefghijklmnopqrstuvwxyz0123456789";
+const STARTUP_TIMEOUT: Duration = Duration::from_secs(15); // shells can take a while with a heavy rc file
+const DRAIN_GRACE: Duration = Duration::from_secs(2); // quiet this long after the last marker and it's over
+const GENERATE_TICK: Duration = Duration::from_millis(10);
+
+#[derive(Debug, Clone)]
+pub struct BenchConfig {
+    pub sessions: usize,
+    pub panes: usize,         // output generating panes per session
+    pub rate: u32,            // lines per second from each pane
+    pub duration: u64,        // seconds
+    pub input_rate: u32,      // lines per second typed into an extra pane of each session, 0 for none
+    pub size: (u16, u16),     // rows, cols of every client
+    pub socket_path: PathBuf, // the daemon picked with `-L`
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+enum MarkerKind {
+    Output, // printed by a generator
+    Input,  // typed by the bench client and echoed back by the pane's shell
+}
+
+/// `#rx<o|i>_<stream>_<seq>_<micros>#`, no spaces so the renderer can't split it up
+#[derive(Debug, PartialEq, Eq)]
+struct Marker {
+    kind: MarkerKind,
+    stream: usize, // the generating pane, 0 for input
+    seq: u64,
+    micros: u64, // when it was written, since the epoch
+}
+
+impl Marker {
+    fn encode(&self) -> String {
+        let kind = match self.kind {
+            MarkerKind::Output => 'o',
+            MarkerKind::Input => 'i',
+        };
+        format!("#rx{kind}_{}_{}_{}#", self.stream, self.seq, self.micros)
+    }
+
+    fn parse(text: &str) -> Option<Self> {
+        let text = text.strip_prefix("#rx")?.strip_suffix('#')?;
+        let mut parts = text.split('_');
+        let kind = match parts.next()? {
+            "o" => MarkerKind::Output,
+            "i" => MarkerKind::Input,
+            _ => return None,
+        };
+        let marker = Self {
+            kind,
+            stream: parts.next()?.parse().ok()?,
+            seq: parts.next()?.parse().ok()?,
+            micros: parts.next()?.parse().ok()?,
+        };
+        parts.next().is_none().then_some(marker)
+    }
+}
+
+/// finds markers in rendered output, which can split one across two reads
+#[derive(Default)]
+struct MarkerScanner {
+    tail: Vec<u8>,
+}
+
+impl MarkerScanner {
+    fn feed(&mut self, bytes: &[u8]) -> Vec<Marker> {
+        let mut data = std::mem::take(&mut self.tail);
+        data.extend_from_slice(bytes);
+        let mut markers = Vec::new();
+        let mut pos = 0;
+        while let Some(start) = find(&data[pos..], MARKER_START).map(|i| i + pos) {
+            let Some(len) = data[start + 1..].iter().position(|&b| b == b'#') else {
+                if data.len() - start <= MARKER_MAX_LEN {
+                    self.tail = data[start..].to_vec();
+                }
+                return markers;
+            };
+            let end = start + len + 2;
+            match std::str::from_utf8(&data[start..end]).ok().and_then(Marker::parse) {
+                Some(marker) => {
+                    markers.push(marker);
+                    pos = end;
+                }
+                // the closing `#` may have been the start of the next one
+                None => pos = start + 1,
+            }
+        }
+        // a marker cut off right after its `#`
+        let keep = (1..MARKER_START.len())
+            .rev()
+            .find(|&n| data.len() >= pos + n && data.ends_with(&MARKER_START[..n]))
+            .unwrap_or(0);
+        self.tail = data[data.len() - keep..].to_vec();
+        markers
+    }
+}
+
+fn find(haystack: &[u8], needle: &[u8]) -> Option<usize> {
+    haystack.windows(needle.len()).position(|window| window == needle)
+}
+
+fn now_micros() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_micros() as u64
+}
+
+/// what one session's client saw
+#[derive(Default)]
+struct SessionStats {
+    seen: HashSet<(MarkerKind, usize, u64)>,
+    output_latencies: Vec<u64>, // micros, first time each line was drawn
+    input_latencies: Vec<u64>,
+    input_sent: u64,
+    frames: u64,
+    bytes: u64,
+    receiving: Option<(Instant, Instant)>, // first and last frame, for the rates
+}
+
+impl SessionStats {
+    fn record(&mut self, marker: Marker, received: u64) {
+        // panes are redrawn whole, later frames repeat lines that are still on screen
+        if !self.seen.insert((marker.kind, marker.stream, marker.seq)) {
+            return;
+        }
+        let latency = received.saturating_sub(marker.micros);
+        match marker.kind {
+            MarkerKind::Output => self.output_latencies.push(latency),
+            MarkerKind::Input => self.input_latencies.push(latency),
+        }
+    }
+
+    fn merge(&mut self, other: SessionStats) {
+        self.output_latencies.extend(other.output_latencies);
+        self.input_latencies.extend(other.input_latencies);
+        self.input_sent += other.input_sent;
+        self.frames += other.frames;
+        self.bytes += other.bytes;
+        self.receiving = match (self.receiving, other.receiving) {
+            (Some((first, last)), Some((other_first, other_last))) => {
+                Some((first.min(other_first), last.max(other_last)))
+            }
+            (receiving, other_receiving) => receiving.or(other_receiving),
+        };
+    }
+}
+
+/// the hidden `bench-generate` command run in each pane - `rate` lines a second for `duration`
+pub fn generate(stream: usize, rate: u32, duration: u64) -> Result<()> {
+    let start = std::time::Instant::now();
+    let total = rate as u64 * duration;
+    let mut stdout = std::io::stdout().lock();
+    let mut seq = 0;
+    while seq < total {
+        // catch up rather than drift when a write blocked on a full pty
+        let due = ((start.elapsed().as_secs_f64() * rate as f64) as u64).min(total);
+        while seq < due {
+            let marker = Marker {
+                kind: MarkerKind::Output,
+                stream,
+                seq,
+                micros: now_micros(),
+            };
+            writeln!(stdout, "{} {FILLER}", marker.encode())?;
+            seq += 1;
+        }
+        stdout.flush()?;
+        std::thread::sleep(GENERATE_TICK);
+    }
+    Ok(())
+}
+
+/// runs every session at once and prints the report, the exit code is non-zero if nothing came back
+pub async fn run(config: BenchConfig) -> Result<i32> {
+    let exe = std::env::current_exe()?.display().to_string();
+    let config = Arc::new(config);
+    let tasks: Vec<_> = (0..config.sessions)
+        .map(|index| tokio::spawn(run_session(index, config.clone(), exe.clone())))
+        .collect();
+    let mut stats = SessionStats::default();
+    for task in tasks {
+        stats.merge(task.await??);
+    }
+    print!("{}", report(&config, &stats));
+    Ok(if stats.output_latencies.is_empty() { 1 } else { 0 })
+}
+
+async fn run_session(index: usize, config: Arc<BenchConfig>, exe: String) -> Result<SessionStats> {
+    let mut stream = UnixStream::connect(&config.socket_path).await?;
+    let attach = RequestBuilder::default()
+        .body(request::Attach {
+            id: Uuid::new_v4(),
+            session_name: format!("bench-{}-{index}", std::process::id()),
+            create: true,
+            color_depth: ColorDepth::default(),
+            environment: ClientEnvironment::current(),
+            command: None,
+            argv: vec![],
+            control: false,
+            detach_others: false,
+        })
+        .build();
+    comm::send_and_recv_message(&mut stream, &attach).await?;
+    let (reader, mut writer) = stream.into_split();
+    let (rows, cols) = config.size;
+    comm::send_event(&mut writer, CliEvent::TerminalResize { rows, cols }).await?;
+
+    let (started_tx, mut started_rx) = watch::channel(false);
+    let receiver = tokio::spawn(receive(reader, started_tx));
+
+    let input = config.input_rate > 0;
+    let panes = config.panes + input as usize;
+    for i in 1..panes {
+        let split = if i % 2 == 1 { "split-window -h" } else { "split-window" };
+        comm::send_event(&mut writer, CliEvent::RunCommand(split.to_owned())).await?;
+    }
+    // the newest pane is active, cycling from it visits the rest in order. generators aren't
+    // `exec`ed, panes whose shell exits cleanly don't come back out of the window
+    let quoted_exe = format!("'{}'", exe.replace('\'', r"'\''"));
+    for stream in 0..config.panes {
+        let command = format!(
+            "{quoted_exe} bench-generate --stream {stream} --rate {} --duration {}\r",
+            config.rate, config.duration
+        );
+        comm::send_event(&mut writer, CliEvent::NextPane).await?;
+        comm::send_event(&mut writer, CliEvent::Raw(Bytes::from(command))).await?;
+    }
+    if input {
+        // back to the newest pane, where the shell echoes what's typed. without a prompt in front
+        // the echoed markers don't wrap in narrow panes
+        comm::send_event(&mut writer, CliEvent::NextPane).await?;
+        comm::send_event(&mut writer, CliEvent::Raw(Bytes::from_static(b"PS1= PS2=\r"))).await?;
+    }
+
+    let mut input_sent = 0;
+    // typing before the shells are up would time the tty's echo, not the shell's
+    if input && started_rx.wait_for(|started| *started).await.is_ok() {
+        let mut interval = tokio::time::interval(Duration::from_secs_f64(1.0 / config.input_rate as f64));
+        let input_end = Instant::now() + Duration::from_secs(config.duration);
+        while interval.tick().await < input_end {
+            let marker = Marker {
+                kind: MarkerKind::Input,
+                stream: 0,
+                seq: input_sent,
+                micros: now_micros(),
+            };
+            // starts with `#`, so the shell takes the line as a comment
+            let line = format!("{}\r", marker.encode());
+            comm::send_event(&mut writer, CliEvent::Raw(Bytes::from(line))).await?;
+            input_sent += 1;
+        }
+    }
+
+    let mut stats = receiver.await??;
+    stats.input_sent = input_sent;
+    // the session goes away with this client
+    let destroy = "set-option -s destroy-unattached on".to_owned();
+    comm::send_event(&mut writer, CliEvent::RunCommand(destroy)).await?;
+    comm::send_event(&mut writer, CliEvent::Detach).await?;
+    Ok(stats)
+}
+
+/// reads until the markers stop coming, `started` is set once the first generator's output shows up
+async fn receive(mut reader: OwnedReadHalf, started: watch::Sender<bool>) -> Result<SessionStats> {
+    let mut stats = SessionStats::default();
+    let mut scanner = MarkerScanner::default();
+    let mut deadline = Instant::now() + STARTUP_TIMEOUT;
+    while let Ok(event) = tokio::time::timeout_at(deadline, comm::recv_daemon_event(&mut reader)).await {
+        match event? {
+            DaemonEvent::Raw(bytes) => {
+                let (now, received) = (Instant::now(), now_micros());
+                stats.frames += 1;
+                stats.bytes += bytes.len() as u64;
+                stats.receiving = Some((stats.receiving.map_or(now, |(first, _)| first), now));
+                for marker in scanner.feed(&bytes) {
+                    if marker.kind == MarkerKind::Output {
+                        started.send_replace(true);
+                    }
+                    stats.record(marker, received);
+                    deadline = now + DRAIN_GRACE;
+                }
+            }
+            DaemonEvent::Disconnected(..) => break,
+            _ => {}
+        }
+    }
+    Ok(stats)
+}
+
+fn percentile(sorted: &[u64], p: f64) -> u64 {
+    if sorted.is_empty() {
+        return 0;
+    }
+    sorted[((sorted.len() - 1) as f64 * p).round() as usize]
+}
+
+fn format_micros(micros: u64) -> String {
+    format!("{:.2}ms", micros as f64 / 1000.0)
+}
+
+fn latency_line(latencies: &mut [u64]) -> String {
+    latencies.sort_unstable();
+    format!(
+        "p50 {}  p90 {}  p99 {}  max {}",
+        format_micros(percentile(latencies, 0.5)),
+        format_micros(percentile(latencies, 0.9)),
+        format_micros(percentile(latencies, 0.99)),
+        format_micros(latencies.last().copied().unwrap_or_default()),
+    )
+}
+
+fn report(config: &BenchConfig, stats: &SessionStats) -> String {
+    let mut out = format!(
+        "{} session(s), {} pane(s) each, {} lines/s per pane for {}s\n",
+        config.sessions, config.panes, config.rate, config.duration
+    );
+    let expected = (config.sessions * config.panes) as u64 * config.rate as u64 * config.duration;
+    let drawn = stats.output_latencies.len() as u64;
+    // lines that scrolled past between two renders were never on screen
+    let dropped = expected.saturating_sub(drawn);
+    out += &format!(
+        "output:  {expected} lines written, {drawn} drawn, {dropped} dropped ({:.1}%)\n",
+        dropped as f64 * 100.0 / expected.max(1) as f64
+    );
+    out += &format!("render:  {}\n", latency_line(&mut stats.output_latencies.clone()));
+    if stats.input_sent > 0 {
+        out += &format!(
+            "input:   {} lines typed, {} echoed, {}\n",
+            stats.input_sent,
+            stats.input_latencies.len(),
+            latency_line(&mut stats.input_latencies.clone())
+        );
+    }
+    let seconds = stats
+        .receiving
+        .map_or(0.0, |(first, last)| (last - first).as_secs_f64())
+        .max(f64::EPSILON);
+    out += &format!(
+        "clients: {} frames ({:.1}/s), {:.2} MB ({:.2} MB/s)\n",
+        stats.frames,
+        stats.frames as f64 / seconds,
+        stats.bytes as f64 / 1e6,
+        stats.bytes as f64 / 1e6 / seconds
+    );
+    out
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn marker(seq: u64) -> Marker {
+        Marker {
+            kind: MarkerKind::Output,
+            stream: 1,
+            seq,
+            micros: 1_000,
+        }
+    }
+
+    #[test]
+    fn test_marker_round_trip() {
+        assert_eq!(Marker::parse(&marker(7).encode()), Some(marker(7)));
+        assert_eq!(Marker::parse("#rxo_1_2#"), None);
+        assert_eq!(Marker::parse("#rxx_1_2_3#"), None);
+    }
+
+    #[test]
+    fn test_scanner_across_reads() {
+        let mut scanner = MarkerScanner::default();
+        let (first, second) = (marker(1).encode(), marker(2).encode());
+        let rendered = format!("\x1b[2;1H{first} abc\x1b[3;1H{second}# x #rx");
+        let (a, b) = rendered.split_at(20);
+        let mut found = scanner.feed(a.as_bytes());
+        found.extend(scanner.feed(b.as_bytes()));
+        assert_eq!(found, vec![marker(1), marker(2)]);
+        // the trailing `#rx` was kept for the next read
+        assert_eq!(scanner.feed(b"o_1_3_1000#"), vec![marker(3)]);
+    }
+
+    #[test]
+    fn test_percentile() {
+        let sorted: Vec<u64> = (1..=100).collect();
+        assert_eq!(percentile(&sorted, 0.5), 51);
+        assert_eq!(percentile(&sorted, 0.99), 99);
+        assert_eq!(percentile(&[], 0.5), 0);
+    }
+}
@@ -0,0 +1,75 @@
+// `remux attach --control` - newline delimited json instead of the tui, so tests and external uis
+// can drive a real daemon: one `CliEvent` per line on stdin, one `DaemonEvent` per line on stdout.
+// the attach asks for control mode, so panes' raw output arrives as `PaneOutput` (base64 in json) rather
+// than frames drawn for a terminal
+
+use remux_core::{
+    comm,
+    events::{CliEvent, DaemonEvent},
+    states::DaemonState,
+};
+use serde::Serialize;
+use tokio::{
+    io::{AsyncBufReadExt, AsyncWriteExt, BufReader, Stdout},
+    net::UnixStream,
+    sync::mpsc,
+};
+
+use crate::prelude::*;
+
+/// the first line written is the initial daemon state, stdin closing detaches the client
+pub async fn run(mut stream: UnixStream, initial_state: DaemonState) -> Result<()> {
+    let mut stdout = tokio::io::stdout();
+    write_line(&mut stdout, &initial_state).await?;
+
+    let (tx, mut rx) = mpsc::channel(10);
+    let _stdin_task = start_event_reader(tx);
+    let mut stdin_open = true;
+    loop {
+        tokio::select! {
+            event = rx.recv(), if stdin_open => {
+                let event = event.unwrap_or_else(|| {
+                    // keep reading until the daemon confirms the detach
+                    stdin_open = false;
+                    CliEvent::Detach
+                });
+                debug!(event=?event, "sending event");
+                comm::send_event(&mut stream, event).await?;
+            }
+            res = comm::recv_daemon_event(&mut stream) => {
+                let event = res?;
+                write_line(&mut stdout, &event).await?;
+                if matches!(event, DaemonEvent::Disconnected(..)) {
+                    break;
+                }
+            }
+        }
+    }
+    debug!("Headless client terminated");
+    Ok(())
+}
+
+/// parses stdin lines into events, lines that aren't an event are reported on stderr and skipped
+fn start_event_reader(tx: mpsc::Sender<CliEvent>) -> CliTask {
+    tokio::spawn(async move {
+        let mut lines = BufReader::new(tokio::io::stdin()).lines();
+        while let Some(line) = lines.next_line().await? {
+            if line.trim().is_empty() {
+                continue;
+            }
+            match serde_json::from_str(&line) {
+                Ok(event) => tx.send(event).await?,
+                Err(e) => eprintln!("invalid event {line:?}: {e}"),
+            }
+        }
+        Ok(())
+    })
+}
+
+async fn write_line(stdout: &mut Stdout, value: &impl Serialize) -> Result<()> {
+    let mut line = serde_json::to_vec(value)?;
+    line.push(b'\n');
+    stdout.write_all(&line).await?;
+    stdout.flush().await?;
+    Ok(())
+}
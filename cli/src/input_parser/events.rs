@@ -1,10 +1,6 @@
-use remux_core::events::CliEvent;
+use remux_core::{events::CliEvent, key_bindings::ClientAction};
 
 pub enum ParsedEvent {
-    LocalAction(Action),
+    LocalAction(ClientAction),
     DaemonAction(CliEvent),
 }
-
-pub enum Action {
-    SwitchSession,
-}
@@ -1,5 +1,5 @@
 mod events;
 mod parser;
 
-pub use events::{Action, ParsedEvent};
+pub use events::ParsedEvent;
 pub use parser::InputParser;
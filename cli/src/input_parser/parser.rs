@@ -1,37 +1,83 @@
-use bytes::Bytes;
-use remux_core::events::CliEvent;
+use std::borrow::Cow;
 
-use crate::{
-    input_parser::events::{Action, ParsedEvent},
-    prelude::*,
+use bytes::Bytes;
+use remux_core::{
+    events::CliEvent,
+    key_bindings::{KeyAction, KeyBindings},
+    keys::{self, legacy_key},
 };
 
-#[allow(unused)]
-const CTRL_SPACE: u8 = 0x00;
-const CTRL_B: u8 = 0x02;
-const PERCENT: u8 = 0x25;
-const DOUBLE_QUOTE: u8 = 0x22;
-const N: u8 = 0x6E;
-const P: u8 = 0x70;
-const S: u8 = 0x73;
-const X: u8 = 0x78;
-const D: u8 = 0x64;
-
-#[derive(Debug, Default)]
+use crate::{input_parser::events::ParsedEvent, prelude::*};
+
+const ESC: u8 = 0x1B;
+const PASTE_START: &[u8] = b"\x1b[200~";
+const PASTE_END: &[u8] = b"\x1b[201~";
+
+#[derive(Debug)]
 pub struct InputParser {
     buf: Vec<u8>,
+    bindings: KeyBindings,     // the daemon's table, keys pressed after the prefix
+    second_prefix: Option<u8>, // also accepted as the prefix, e.g. when remux runs inside tmux
+}
+
+impl Default for InputParser {
+    fn default() -> Self {
+        Self::new(KeyBindings::default(), None)
+    }
 }
 
 impl InputParser {
+    pub fn new(bindings: KeyBindings, second_prefix: Option<u8>) -> Self {
+        Self {
+            buf: Vec::new(),
+            bindings,
+            second_prefix,
+        }
+    }
+
+    /// `bind-key`, `unbind-key` or `set prefix` ran on the daemon
+    pub fn set_bindings(&mut self, bindings: KeyBindings) {
+        self.bindings = bindings;
+    }
+
+    fn is_prefix(&self, b: u8) -> bool {
+        b == self.bindings.prefix || Some(b) == self.second_prefix
+    }
+
     pub fn process(&mut self, input: &[u8]) -> Vec<ParsedEvent> {
         use ParsedEvent::{DaemonAction, LocalAction};
+        // with the kitty keyboard protocol on the prefix arrives as `CSI u`, match it like the legacy byte
+        let input = keys::rewrite_keys(input, |code, modifiers| {
+            legacy_key(code, modifiers).filter(|key| matches!(key.as_slice(), &[b] if self.is_prefix(b)))
+        });
         self.buf.extend(input);
         let mut events = vec![];
         let mut i = 0;
         while i < self.buf.len() {
             let b = self.buf[i];
             match b {
-                CTRL_B => {
+                // a paste goes through whole, the prefix inside of it is just text
+                ESC if self.buf[i..].starts_with(PASTE_START) => {
+                    let start = i + PASTE_START.len();
+                    let Some(len) = self.buf[start..]
+                        .windows(PASTE_END.len())
+                        .position(|window| window == PASTE_END)
+                    else {
+                        // held back until the end marker is read
+                        break;
+                    };
+                    if i > 0 {
+                        let old: Vec<u8> = self.buf.drain(..i).collect();
+                        events.push(DaemonAction(CliEvent::Raw(Bytes::from(old))));
+                    }
+                    let pasted: Vec<u8> = self.buf.drain(..PASTE_START.len() + len + PASTE_END.len()).collect();
+                    let text = &pasted[PASTE_START.len()..PASTE_START.len() + len];
+                    events.push(DaemonAction(CliEvent::Paste(Bytes::copy_from_slice(text))));
+                    i = 0;
+                }
+                // the start marker split across reads, shorter than this could be a key instead
+                ESC if self.buf.len() - i >= 3 && PASTE_START.starts_with(&self.buf[i..]) => break,
+                b if self.is_prefix(b) => {
                     trace!("prefix detected");
                     if (i + 1) < self.buf.len() {
                         let b_next = self.buf[i + 1];
@@ -41,36 +87,28 @@ impl InputParser {
                             i = 0;
                         }
                         match b_next {
-                            PERCENT => {
-                                events.push(DaemonAction(CliEvent::SplitPaneVertical));
-                                self.buf.drain(..2);
-                            }
-                            DOUBLE_QUOTE => {
-                                events.push(DaemonAction(CliEvent::SplitPaneHorizontal));
-                                self.buf.drain(..2);
-                            }
-                            N => {
-                                events.push(DaemonAction(CliEvent::NextPane));
-                                self.buf.drain(..2);
-                            }
-                            P => {
-                                events.push(DaemonAction(CliEvent::PrevPane));
-                                self.buf.drain(..2);
-                            }
-                            X => {
-                                events.push(DaemonAction(CliEvent::KillPane));
-                                self.buf.drain(..2);
-                            }
-                            D => {
-                                events.push(DaemonAction(CliEvent::Detach));
-                                self.buf.drain(..2);
-                            }
-                            S => {
-                                events.push(LocalAction(Action::SwitchSession));
+                            // send-prefix: pressing the prefix twice passes it through to the pane
+                            b_next if self.is_prefix(b_next) => {
+                                events.push(DaemonAction(CliEvent::Raw(Bytes::from(vec![b_next]))));
                                 self.buf.drain(..2);
                             }
                             _ => {
-                                self.buf.drain(..=i);
+                                let key = cursor_keys_as_csi(&self.buf[1..]);
+                                match self.bindings.lookup(&key) {
+                                    Some((action, len)) => {
+                                        events.push(match action.clone() {
+                                            KeyAction::Command(line) => DaemonAction(CliEvent::RunCommand(line)),
+                                            KeyAction::Client(action) => LocalAction(action),
+                                        });
+                                        self.buf.drain(..=len);
+                                    }
+                                    // the rest of the key hasn't been read yet
+                                    None if self.bindings.is_partial(&key) => break,
+                                    // unbound keys go through to the pane, only the prefix is dropped
+                                    None => {
+                                        self.buf.drain(..=i);
+                                    }
+                                }
                             }
                         }
                         i = 0;
@@ -94,3 +132,80 @@ impl InputParser {
         events
     }
 }
+
+// arrows arrive as `CSI` or `SS3` depending on the cursor key mode, bindings are written with `CSI`
+fn cursor_keys_as_csi(key: &[u8]) -> Cow<'_, [u8]> {
+    match key {
+        [ESC, b'O', rest @ ..] if matches!(rest.first(), None | Some(b'A'..=b'D')) => {
+            let mut key = key.to_vec();
+            key[1] = b'[';
+            Cow::Owned(key)
+        }
+        _ => Cow::Borrowed(key),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    #![allow(clippy::unwrap_used)]
+    use remux_core::key_bindings::ClientAction;
+
+    use super::*;
+
+    fn commands(events: Vec<ParsedEvent>) -> Vec<String> {
+        events
+            .into_iter()
+            .map(|event| match event {
+                ParsedEvent::DaemonAction(CliEvent::RunCommand(line)) => line,
+                ParsedEvent::DaemonAction(CliEvent::Raw(bytes)) => format!("raw {bytes:?}"),
+                ParsedEvent::DaemonAction(CliEvent::Paste(bytes)) => format!("paste {bytes:?}"),
+                ParsedEvent::DaemonAction(event) => format!("{event:?}"),
+                ParsedEvent::LocalAction(action) => format!("{action:?}"),
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_default_bindings() {
+        let mut parser = InputParser::default();
+        assert_eq!(
+            commands(parser.process(b"ls\x02%")),
+            vec!["raw b\"ls\"", "split-window -h"]
+        );
+        assert_eq!(commands(parser.process(b"\x02:")), vec!["CommandPrompt"]);
+        // split reads, the arrow only resolves once the whole sequence is in
+        assert!(parser.process(b"\x02\x1bO").is_empty());
+        assert_eq!(commands(parser.process(b"A")), vec!["resize-pane -U 5"]);
+        assert_eq!(commands(parser.process(b"\x02y")), vec!["raw b\"y\""]);
+    }
+
+    #[test]
+    fn test_rebound_prefix() {
+        let mut bindings = KeyBindings {
+            prefix: 0x01,
+            ..KeyBindings::default()
+        };
+        bindings.bind("v", KeyAction::Command("split-window -h".to_owned()));
+        bindings.bind("S", KeyAction::Client(ClientAction::ChooseSession));
+        let mut parser = InputParser::new(bindings, None);
+        assert_eq!(commands(parser.process(b"\x02v")), vec!["raw b\"\\x02v\""]);
+        assert_eq!(
+            commands(parser.process(b"\x01v\x01S")),
+            vec!["split-window -h", "ChooseSession"]
+        );
+        assert_eq!(commands(parser.process(b"\x01\x01")), vec!["raw b\"\\x01\""]);
+    }
+
+    #[test]
+    fn test_paste() {
+        let mut parser = InputParser::default();
+        assert_eq!(
+            commands(parser.process(b"ls \x1b[200~a\x02%b\x1b[201~\x02%")),
+            vec!["raw b\"ls \"", "paste b\"a\\x02%b\"", "split-window -h"]
+        );
+        // the whole paste is sent once its end marker arrives
+        assert!(parser.process(b"\x1b[20").is_empty());
+        assert!(parser.process(b"0~\x02x").is_empty());
+        assert_eq!(commands(parser.process(b"\x1b[201~")), vec!["paste b\"\\x02x\""]);
+    }
+}
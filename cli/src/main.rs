@@ -1,64 +1,104 @@
 mod app;
 mod args;
+mod bench;
+mod headless;
 mod input_parser;
 mod prelude;
 mod states;
 mod tasks;
 mod ui;
 
-use std::fs::File;
+use std::{
+    fs::{File, create_dir_all},
+    io::ErrorKind,
+    os::unix::process::CommandExt,
+    path::{Path, PathBuf},
+    process::Stdio,
+    time::{Duration, SystemTime},
+};
 
 use clap::Parser;
+use color_eyre::eyre::eyre;
 use ratatui::crossterm::terminal::disable_raw_mode;
 use remux_core::{
+    colors::ColorDepth,
     comm,
-    daemon_utils::get_sock_path,
+    daemon_utils::{get_sock_path, get_state_dir, is_daemon_running},
+    events::DaemonEvent,
     messages::{
         CliRequestMessage, RequestBuilder,
-        request::{self, Attach},
+        request::{self, Attach, ClientEnvironment, ControlAttach, Exec},
+        response::{self, SessionListing},
     },
 };
-use tokio::net::UnixStream;
+use tokio::{io::AsyncWriteExt, net::UnixStream};
 use uuid::Uuid;
 
 use crate::{
     app::App,
-    args::{Args, Commands},
+    args::{Args, Commands, LogArgs, SessionCommands},
+    input_parser::InputParser,
     prelude::*,
 };
 
+const LOG_FILE: &str = "remux-cli.log";
+const DAEMON_BIN: &str = "remux-daemon";
+const DAEMON_START_TIMEOUT: Duration = Duration::from_secs(5);
+const DAEMON_POLL_INTERVAL: Duration = Duration::from_millis(20);
+
 #[tokio::main]
 async fn main() {
     color_eyre::install().unwrap();
     let cli = Args::parse();
-    match setup_logging() {
-        Ok(_guard) => {
-            if let Err(e) = run(cli.command).await {
-                eprintln!("{e}");
-                std::process::exit(1);
-            }
-        }
+    let guard = setup_logging(&cli.logging);
+    let code = match run(cli.command, cli.socket_name).await {
+        Ok(code) => code,
         Err(e) => {
-            if let Err(e) = disable_raw_mode() {
-                eprintln!("error disabling raw mode: {e}");
-                eprintln!("terminal may still be in raw mode!!! You can run 'stty sane' to reset it.");
-            }
             eprintln!("{e}");
-            std::process::exit(1);
+            1
         }
     };
+    // `exit` skips destructors, flush the log first
+    drop(guard);
+    std::process::exit(code);
 }
 
-fn setup_logging() -> Result<tracing_appender::non_blocking::WorkerGuard> {
+/// a log that can't be opened only disables logging, it shouldn't stop remux from starting
+fn setup_logging(args: &LogArgs) -> Option<tracing_appender::non_blocking::WorkerGuard> {
+    if args.no_log {
+        return None;
+    }
+    match try_setup_logging(args) {
+        Ok(guard) => Some(guard),
+        Err(e) => {
+            // printed before the tui takes over the terminal
+            eprintln!("remux: logging disabled: {e}");
+            None
+        }
+    }
+}
+
+fn try_setup_logging(args: &LogArgs) -> Result<tracing_appender::non_blocking::WorkerGuard> {
     use tracing_appender::non_blocking;
     use tracing_error::ErrorLayer;
     use tracing_subscriber::{EnvFilter, FmtSubscriber, fmt::format::FmtSpan, layer::SubscriberExt};
     // Create the log file
-    let file = File::create("./logs/remux-cli.log")?;
+    let path = match &args.log_file {
+        Some(path) => path.clone(),
+        None => get_state_dir()?.join("logs").join(LOG_FILE),
+    };
+    let file = path
+        .parent()
+        .map_or(Ok(()), create_dir_all)
+        .and_then(|_| File::create(&path))
+        .map_err(|e| eyre!("{}: {e}", path.display()))?;
     let (non_blocking_writer, guard) = non_blocking(file);
 
-    // Environment filter
-    let filter = EnvFilter::try_from_default_env().unwrap_or_else(|_| EnvFilter::new("debug"));
+    // --log-level, then RUST_LOG
+    let filter = match &args.log_level {
+        Some(level) => EnvFilter::new(level),
+        None => EnvFilter::try_from_default_env().unwrap_or_else(|_| EnvFilter::new("debug")),
+    };
 
     // Build the subscriber
     let subscriber = FmtSubscriber::builder()
@@ -78,45 +118,429 @@ fn setup_logging() -> Result<tracing_appender::non_blocking::WorkerGuard> {
     Ok(guard)
 }
 
+/// `autostart` starts a daemon when none is listening, for the commands that would create a session anyway
 #[instrument]
-async fn connect() -> Result<UnixStream> {
-    let socket_path = get_sock_path()?;
+async fn connect(socket_name: &str, autostart: bool) -> Result<UnixStream> {
+    let socket_path = get_sock_path(socket_name)?;
     debug!(path=?socket_path, "Connecting to unix socket");
-    let stream = UnixStream::connect(socket_path.clone()).await?;
-    Ok(stream)
+    match UnixStream::connect(&socket_path).await {
+        Ok(stream) => Ok(stream),
+        // nothing is listening yet, start a daemon and connect once it is
+        Err(e) if autostart && matches!(e.kind(), ErrorKind::NotFound | ErrorKind::ConnectionRefused) => {
+            // one may already be on its way up, e.g. started by another cli a moment ago
+            if !is_daemon_running(socket_name) {
+                start_daemon(socket_name)?;
+            }
+            wait_for_daemon(&socket_path).await
+        }
+        Err(e) => Err(e.into()),
+    }
+}
+
+/// spawns the daemon in its own session so it has no controlling terminal and outlives this cli
+fn start_daemon(socket_name: &str) -> Result<()> {
+    // installed next to the cli, otherwise looked up on the PATH
+    let program = std::env::current_exe()
+        .ok()
+        .and_then(|exe| Some(exe.parent()?.join(DAEMON_BIN)))
+        .filter(|path| path.exists())
+        .unwrap_or_else(|| PathBuf::from(DAEMON_BIN));
+    info!(program=?program, "Starting daemon");
+    let mut command = std::process::Command::new(&program);
+    command.arg("-L").arg(socket_name);
+    command.stdin(Stdio::null()).stdout(Stdio::null()).stderr(Stdio::null());
+    unsafe {
+        command.pre_exec(|| {
+            nix::unistd::setsid()?;
+            Ok(())
+        });
+    }
+    command
+        .spawn()
+        .map_err(|e| eyre!("failed to start {}: {e}", program.display()))?;
+    Ok(())
 }
 
+async fn wait_for_daemon(socket_path: &Path) -> Result<UnixStream> {
+    let deadline = tokio::time::Instant::now() + DAEMON_START_TIMEOUT;
+    loop {
+        match UnixStream::connect(socket_path).await {
+            Ok(stream) => return Ok(stream),
+            Err(e) if tokio::time::Instant::now() >= deadline => {
+                return Err(eyre!(
+                    "daemon did not start listening on {}: {e}",
+                    socket_path.display()
+                ));
+            }
+            Err(_) => tokio::time::sleep(DAEMON_POLL_INTERVAL).await,
+        }
+    }
+}
+
+/// returns the exit code for the process
 #[instrument]
-async fn run(command: Commands) -> Result<()> {
-    let stream = connect().await?;
+async fn run(command: Commands, socket_name: String) -> Result<i32> {
+    let autostart = matches!(
+        command,
+        Commands::Attach { .. } | Commands::New { .. } | Commands::Control { .. } | Commands::Exec { .. }
+    );
+    // the bench commands make their own connections, or none at all
+    let daemon = || connect(&socket_name, autostart);
     debug!("Running command");
     match command {
-        Commands::Attach { session_name } => {
+        Commands::Bench {
+            sessions,
+            panes,
+            rate,
+            duration,
+            input_rate,
+            size,
+        } => {
+            let config = bench::BenchConfig {
+                sessions,
+                panes,
+                rate,
+                duration,
+                input_rate,
+                size,
+                socket_path: get_sock_path(&socket_name)?,
+            };
+            bench::run(config).await
+        }
+        Commands::BenchGenerate { stream, rate, duration } => {
+            bench::generate(stream, rate, duration)?;
+            Ok(0)
+        }
+        Commands::Attach {
+            session_name,
+            second_prefix,
+            opener,
+            extended_keys,
+            control,
+            detach_others,
+        } => {
             attach(
-                stream,
+                daemon().await?,
+                get_sock_path(&socket_name)?,
                 RequestBuilder::default()
                     .body(request::Attach {
                         id: Uuid::new_v4(),
                         session_name,
                         create: true,
+                        color_depth: ColorDepth::detect(
+                            std::env::var("TERM").ok().as_deref(),
+                            std::env::var("COLORTERM").ok().as_deref(),
+                        ),
+                        environment: ClientEnvironment::current(),
+                        command: None,
+                        argv: vec![],
+                        control,
+                        detach_others,
+                    })
+                    .build(),
+                second_prefix,
+                opener,
+                extended_keys,
+                control,
+            )
+            .await?;
+            Ok(0)
+        }
+        Commands::New {
+            session_name,
+            detached,
+            command,
+            group_with,
+            argv,
+        } => {
+            let session = new_session(daemon().await?, session_name, command.clone(), argv.clone(), group_with).await?;
+            if detached {
+                println!("{}", session.name);
+                return Ok(0);
+            }
+            // the daemon closes a connection once it has answered a one-shot request
+            let stream = connect(&socket_name, false).await?;
+            attach(
+                stream,
+                get_sock_path(&socket_name)?,
+                RequestBuilder::default()
+                    .body(request::Attach {
+                        id: Uuid::new_v4(),
+                        session_name: session.name,
+                        create: true,
+                        color_depth: ColorDepth::detect(
+                            std::env::var("TERM").ok().as_deref(),
+                            std::env::var("COLORTERM").ok().as_deref(),
+                        ),
+                        environment: ClientEnvironment::current(),
+                        command,
+                        argv,
+                        control: false,
+                        detach_others: false,
+                    })
+                    .build(),
+                None,
+                None,
+                false,
+                false,
+            )
+            .await?;
+            Ok(0)
+        }
+        Commands::Control { session_name } => {
+            control(
+                daemon().await?,
+                RequestBuilder::default()
+                    .body(request::ControlAttach {
+                        id: Uuid::new_v4(),
+                        control_session_name: session_name,
+                    })
+                    .build(),
+            )
+            .await?;
+            Ok(0)
+        }
+        Commands::Exec { session_name, command } => {
+            exec(
+                daemon().await?,
+                RequestBuilder::default()
+                    .body(request::Exec {
+                        id: Uuid::new_v4(),
+                        exec_session_name: session_name,
+                        command,
                     })
                     .build(),
             )
             .await
         }
-        _ => todo!(),
+        Commands::Ls
+        | Commands::Session {
+            action: SessionCommands::List,
+        } => {
+            list_sessions(daemon().await?).await?;
+            Ok(0)
+        }
+        Commands::KillSession { name } => {
+            kill_session(daemon().await?, name).await?;
+            Ok(0)
+        }
+        Commands::RenameSession { target, name } => {
+            run_command_once(daemon().await?, vec!["rename-session".to_owned(), name], Some(target)).await?;
+            Ok(0)
+        }
+        Commands::SetBuffer { name, data } => {
+            let mut args = vec!["set-buffer".to_owned()];
+            if let Some(name) = name {
+                args.extend(["-b".to_owned(), name]);
+            }
+            args.push(data);
+            run_command_once(daemon().await?, args, None).await?;
+            Ok(0)
+        }
+        Commands::ShowBuffer { buffer } => {
+            let mut args = vec!["show-buffer".to_owned()];
+            if let Some(buffer) = buffer {
+                args.extend(["-b".to_owned(), buffer]);
+            }
+            run_command_once(daemon().await?, args, None).await?;
+            Ok(0)
+        }
+        Commands::PasteBuffer {
+            session_name,
+            buffer,
+            bracketed,
+        } => {
+            let mut args = vec!["paste-buffer".to_owned()];
+            if let Some(buffer) = buffer {
+                args.extend(["-b".to_owned(), buffer]);
+            }
+            if bracketed {
+                args.push("-p".to_owned());
+            }
+            run_command_once(daemon().await?, args, Some(session_name)).await?;
+            Ok(0)
+        }
+        Commands::SourceFile { paths } => {
+            source_file(daemon().await?, paths).await?;
+            Ok(0)
+        }
+    }
+}
+
+/// prints one line per session, like `tmux ls`
+#[instrument(skip(stream))]
+async fn list_sessions(mut stream: UnixStream) -> Result<()> {
+    let request = RequestBuilder::default().body(request::ListSessions {}).build();
+    let res = comm::send_and_recv_message(&mut stream, &request).await?;
+    let now = SystemTime::now();
+    for session in &res.sessions {
+        println!("{}", format_listing(session, now));
+    }
+    Ok(())
+}
+
+#[instrument(skip(stream))]
+async fn new_session(
+    mut stream: UnixStream,
+    new_session_name: Option<String>,
+    command: Option<String>,
+    argv: Vec<String>,
+    group_with: Option<String>,
+) -> Result<response::NewSession> {
+    let request = RequestBuilder::default()
+        .body(request::NewSession {
+            new_session_name,
+            command,
+            argv,
+            group_with,
+        })
+        .build();
+    Ok(comm::send_and_recv_message(&mut stream, &request).await?)
+}
+
+#[instrument(skip(stream))]
+async fn kill_session(mut stream: UnixStream, name_or_id: String) -> Result<()> {
+    let request = RequestBuilder::default()
+        .body(request::KillSession { name_or_id })
+        .build();
+    comm::send_and_recv_message(&mut stream, &request).await?;
+    Ok(())
+}
+
+/// runs a command without attaching and prints what it printed, e.g. a buffer for `show-buffer`
+#[instrument(skip(stream))]
+async fn run_command_once(
+    mut stream: UnixStream,
+    command_args: Vec<String>,
+    target_session: Option<String>,
+) -> Result<()> {
+    let request = RequestBuilder::default()
+        .body(request::RunCommand {
+            command_args,
+            target_session,
+        })
+        .build();
+    let res = comm::send_and_recv_message(&mut stream, &request).await?;
+    if let Some(output) = res.output {
+        let mut stdout = tokio::io::stdout();
+        stdout.write_all(output.as_bytes()).await?;
+        stdout.flush().await?;
     }
+    Ok(())
+}
+
+#[instrument(skip(stream))]
+async fn source_file(mut stream: UnixStream, paths: Vec<PathBuf>) -> Result<()> {
+    // the daemon's working directory isn't ours
+    let cwd = std::env::current_dir()?;
+    let config_paths = paths.into_iter().map(|path| cwd.join(path)).collect();
+    let request = RequestBuilder::default()
+        .body(request::SourceFile { config_paths })
+        .build();
+    comm::send_and_recv_message(&mut stream, &request).await?;
+    Ok(())
+}
+
+fn format_listing(session: &SessionListing, now: SystemTime) -> String {
+    let plural = |n: usize, what: &str| format!("{n} {what}{}", if n == 1 { "" } else { "s" });
+    let age = now.duration_since(session.created).unwrap_or_default().as_secs();
+    let age = match age {
+        0..60 => format!("{age}s"),
+        60..3600 => format!("{}m", age / 60),
+        3600..86400 => format!("{}h", age / 3600),
+        _ => format!("{}d", age / 86400),
+    };
+    let mut line = format!(
+        "{}: {}, {} (id {}, created {age} ago)",
+        session.name,
+        plural(session.windows, "window"),
+        plural(session.panes, "pane"),
+        session.id
+    );
+    if session.clients > 0 {
+        line.push_str(&format!(" ({} attached)", session.clients));
+    }
+    line
+}
+
+/// bridges stdin/stdout to the daemon's control mode protocol
+#[instrument(skip(stream))]
+async fn control(mut stream: UnixStream, control_request: CliRequestMessage<ControlAttach>) -> Result<()> {
+    debug!("Sending control attach request");
+    comm::send_and_recv_message(&mut stream, &control_request).await?;
+
+    let (mut reader, mut writer) = stream.into_split();
+    let mut stdin = tokio::io::stdin();
+    let mut stdout = tokio::io::stdout();
+    let download = tokio::io::copy(&mut reader, &mut stdout);
+    tokio::pin!(download);
+    tokio::select! {
+        res = &mut download => {
+            res?;
+        }
+        res = tokio::io::copy(&mut stdin, &mut writer) => {
+            // stdin closed - let the daemon detach us and drain the remaining notifications
+            res?;
+            writer.shutdown().await?;
+            download.await?;
+        }
+    }
+    debug!("Control mode terminated");
+    Ok(())
 }
 
+/// copies the command's output to stdout until the daemon reports its exit code
 #[instrument(skip(stream))]
-async fn attach(mut stream: UnixStream, attach_request: CliRequestMessage<Attach>) -> Result<()> {
+async fn exec(mut stream: UnixStream, exec_request: CliRequestMessage<Exec>) -> Result<i32> {
+    debug!("Sending exec request");
+    comm::send_and_recv_message(&mut stream, &exec_request).await?;
+
+    let mut stdout = tokio::io::stdout();
+    loop {
+        match comm::recv_daemon_event(&mut stream).await? {
+            DaemonEvent::Raw(bytes) => {
+                stdout.write_all(&bytes).await?;
+                stdout.flush().await?;
+            }
+            DaemonEvent::Exited(code) => {
+                debug!(code, "Exec'd command finished");
+                return Ok(code);
+            }
+            DaemonEvent::Disconnected(..) => return Err(eyre!("session was closed before the command finished")),
+            _ => {}
+        }
+    }
+}
+
+#[instrument(skip(stream))]
+async fn attach(
+    mut stream: UnixStream,
+    socket_path: PathBuf, // reconnected to if the connection breaks
+    attach_request: CliRequestMessage<Attach>,
+    second_prefix: Option<u8>,
+    opener: Option<String>,
+    extended_keys: bool,
+    headless: bool,
+) -> Result<()> {
     debug!("Sending attach request");
     let res = comm::send_and_recv_message(&mut stream, &attach_request).await?;
     debug!(response=?res, "Recieved attach response");
     debug!(daemon_state=?res.initial_daemon_state, "Recieved initial daemon state");
+    if headless {
+        return headless::run(stream, res.initial_daemon_state).await;
+    }
 
     debug!("Starting app");
-    let mut app = App::new(attach_request.body.id, stream, res.initial_daemon_state);
+    let input_parser = InputParser::new(res.initial_daemon_state.key_bindings.clone(), second_prefix);
+    let mut app = App::new(
+        attach_request.body,
+        stream,
+        socket_path,
+        res.initial_daemon_state,
+        input_parser,
+        opener,
+        extended_keys,
+    );
     app.run().await?;
     debug!("App terminated");
     disable_raw_mode()?;
@@ -0,0 +1,44 @@
+use remux_core::commands::{RemuxCommand, parse_sequence};
+
+/// a yes/no question over the middle of the screen, `command` only goes to the daemon once it's answered yes
+#[derive(Debug, Clone)]
+pub struct ConfirmState {
+    pub message: String,
+    pub command: String,
+    pub yes: bool, // the highlighted answer, starts on no so a stray Enter doesn't kill anything
+}
+
+impl ConfirmState {
+    /// a question for a key bound command line that kills something, `None` if it can just run
+    pub fn for_command(line: &str) -> Option<Self> {
+        let commands = parse_sequence(line).ok()?;
+        let message = commands.iter().find_map(|command| match command {
+            RemuxCommand::KillPane => Some("kill pane?".to_owned()),
+            RemuxCommand::KillSession { target: Some(target) } => Some(format!("kill session {target}?")),
+            RemuxCommand::KillSession { target: None } => Some("kill session?".to_owned()),
+            _ => None,
+        })?;
+        Some(Self {
+            message,
+            command: line.to_owned(),
+            yes: false,
+        })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    #![allow(clippy::unwrap_used)]
+    use super::*;
+
+    #[test]
+    fn test_for_command() {
+        let confirm = ConfirmState::for_command("kill-pane").unwrap();
+        assert_eq!(confirm.message, "kill pane?");
+        assert!(!confirm.yes);
+        let confirm = ConfirmState::for_command("select-pane -n ; kill-session -t dev").unwrap();
+        assert_eq!(confirm.message, "kill session dev?");
+        assert_eq!(confirm.command, "select-pane -n ; kill-session -t dev");
+        assert!(ConfirmState::for_command("split-window -h").is_none());
+    }
+}
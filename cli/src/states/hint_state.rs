@@ -0,0 +1,88 @@
+// home row first so the most common labels are the easiest to type
+const HINT_ALPHABET: &str = "asdfghjklqwertyuiopzxcvbnm";
+
+#[derive(Debug)]
+pub enum HintInput<T> {
+    Pending, // typed text is a prefix of some labels
+    NoMatch,
+    Chosen { item: T, uppercase: bool },
+}
+
+/// labels overlaid on screen items, an item is picked by typing its label
+#[derive(Debug)]
+pub struct HintState<T> {
+    pub hints: Vec<(String, T)>,
+    pub typed: String,
+}
+
+impl<T: Clone> HintState<T> {
+    pub fn new(items: Vec<T>) -> Self {
+        Self {
+            hints: hint_labels(items.len()).into_iter().zip(items).collect(),
+            typed: String::new(),
+        }
+    }
+
+    /// hints still reachable with what has been typed so far
+    pub fn visible_hints(&self) -> impl Iterator<Item = &(String, T)> {
+        self.hints.iter().filter(|(label, _)| label.starts_with(&self.typed))
+    }
+
+    /// feeds a typed character, labels match case insensitively but the case of the last key is reported
+    pub fn input(&mut self, c: char) -> HintInput<T> {
+        self.typed.push(c.to_ascii_lowercase());
+        if let Some((_, item)) = self.hints.iter().find(|(label, _)| *label == self.typed) {
+            return HintInput::Chosen {
+                item: item.clone(),
+                uppercase: c.is_ascii_uppercase(),
+            };
+        }
+        match self.visible_hints().next() {
+            Some(_) => HintInput::Pending,
+            None => HintInput::NoMatch,
+        }
+    }
+}
+
+/// one letter labels while they suffice, otherwise two letters for every hint so no label is a prefix of another
+pub fn hint_labels(count: usize) -> Vec<String> {
+    let letters: Vec<char> = HINT_ALPHABET.chars().collect();
+    if count <= letters.len() {
+        return letters.iter().take(count).map(char::to_string).collect();
+    }
+    letters
+        .iter()
+        .flat_map(|a| letters.iter().map(move |b| format!("{a}{b}")))
+        .take(count)
+        .collect()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_hint_labels() {
+        assert_eq!(hint_labels(3), vec!["a", "s", "d"]);
+        let labels = hint_labels(30);
+        assert_eq!(labels.len(), 30);
+        assert_eq!(labels[0], "aa");
+        assert!(labels.iter().all(|label| label.len() == 2));
+    }
+
+    #[test]
+    fn test_input() {
+        let mut state = HintState::new((0..30).collect());
+        assert!(matches!(state.input('a'), HintInput::Pending));
+        assert!(matches!(
+            state.input('S'),
+            HintInput::Chosen {
+                item: 1,
+                uppercase: true
+            }
+        ));
+
+        let mut state = HintState::new(vec![0, 1]);
+        assert!(matches!(state.input('z'), HintInput::NoMatch));
+    }
+}
@@ -1 +1,7 @@
+pub mod confirm_state;
+pub mod hint_state;
+pub mod notification_state;
+pub mod prompt_history;
+pub mod prompt_state;
 pub mod status_line_state;
+pub mod tree_state;
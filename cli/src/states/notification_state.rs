@@ -0,0 +1,33 @@
+use std::time::{Duration, Instant};
+
+use remux_core::events::{Message, MessageLevel};
+
+const NOTIFICATION_TIMEOUT: Duration = Duration::from_secs(3);
+
+#[derive(Debug, Clone)]
+pub struct NotificationState {
+    pub message: String,
+    pub level: MessageLevel,
+    timeout: Duration,
+    received_at: Instant,
+}
+
+impl NotificationState {
+    pub fn new(message: String) -> Self {
+        Self::from_message(Message::info(message))
+    }
+
+    /// shown for the default time when the daemon didn't say how long
+    pub fn from_message(message: Message) -> Self {
+        Self {
+            message: message.text,
+            level: message.level,
+            timeout: message.duration.unwrap_or(NOTIFICATION_TIMEOUT),
+            received_at: Instant::now(),
+        }
+    }
+
+    pub fn is_expired(&self) -> bool {
+        self.received_at.elapsed() >= self.timeout
+    }
+}
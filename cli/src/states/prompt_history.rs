@@ -0,0 +1,58 @@
+use std::path::PathBuf;
+
+use remux_core::daemon_utils::get_state_dir;
+
+use crate::prelude::*;
+
+const HISTORY_FILE: &str = "prompt_history";
+const MAX_ENTRIES: usize = 1000;
+
+/// commands entered at the `:` prompt, oldest first, kept in the state dir across restarts
+#[derive(Debug, Default)]
+pub struct PromptHistory {
+    pub entries: Vec<String>,
+    path: Option<PathBuf>,
+}
+
+impl PromptHistory {
+    pub fn load() -> Self {
+        let path = match get_state_dir() {
+            Ok(dir) => dir.join(HISTORY_FILE),
+            Err(e) => {
+                warn!(error=%e, "no state dir, prompt history won't be saved");
+                return Self::default();
+            }
+        };
+        let entries = std::fs::read_to_string(&path)
+            .map(|contents| contents.lines().map(str::to_owned).collect())
+            .unwrap_or_default();
+        Self {
+            entries,
+            path: Some(path),
+        }
+    }
+
+    pub fn push(&mut self, entry: &str) {
+        // a repeated command moves to the end instead of showing up twice when scrolling back
+        self.entries.retain(|e| e != entry);
+        self.entries.push(entry.to_owned());
+        if self.entries.len() > MAX_ENTRIES {
+            self.entries.drain(..self.entries.len() - MAX_ENTRIES);
+        }
+        if let Err(e) = self.save() {
+            warn!(error=%e, "failed to save prompt history");
+        }
+    }
+
+    fn save(&self) -> std::io::Result<()> {
+        let Some(path) = &self.path else {
+            return Ok(());
+        };
+        if let Some(dir) = path.parent() {
+            std::fs::create_dir_all(dir)?;
+        }
+        let mut contents = self.entries.join("\n");
+        contents.push('\n');
+        std::fs::write(path, contents)
+    }
+}
@@ -0,0 +1,236 @@
+use terminput::{Event, KeyCode, KeyModifiers};
+
+#[derive(Debug)]
+pub enum PromptInput {
+    Pending,
+    Submitted(String),
+    Cancelled,
+}
+
+/// single line text prompt shown in place of the status line
+#[derive(Debug, Clone)]
+pub struct PromptState {
+    pub label: String,
+    pub input: String,
+    history_index: Option<usize>, // the entry shown while scrolling with Up/Down
+    draft: String,                // what was typed before scrolling, restored past the newest entry
+    pub search: Option<ReverseSearch>,
+}
+
+/// Ctrl-R search through the history, the current match is shown as the input
+#[derive(Debug, Clone)]
+pub struct ReverseSearch {
+    pub query: String,
+    matched: Option<usize>,
+    original: String,
+}
+
+impl PromptState {
+    pub fn new(label: &str) -> Self {
+        Self {
+            label: label.to_owned(),
+            input: String::new(),
+            history_index: None,
+            draft: String::new(),
+            search: None,
+        }
+    }
+
+    /// the text drawn before the input
+    pub fn display_label(&self) -> String {
+        match &self.search {
+            Some(search) => format!("(reverse-i-search)`{}': ", search.query),
+            None => self.label.clone(),
+        }
+    }
+
+    /// `history` is oldest first, pass an empty slice for prompts without one
+    pub fn handle_event(&mut self, event: Event, history: &[String]) -> PromptInput {
+        let Event::Key(key_event) = event else {
+            return PromptInput::Pending;
+        };
+        let ctrl = key_event.modifiers.contains(KeyModifiers::CTRL);
+        if self.search.is_some() {
+            return self.handle_search_key(key_event.code, ctrl, history);
+        }
+        match key_event.code {
+            KeyCode::Enter => PromptInput::Submitted(std::mem::take(&mut self.input)),
+            KeyCode::Esc => PromptInput::Cancelled,
+            KeyCode::Char('c') if ctrl => PromptInput::Cancelled,
+            KeyCode::Char('r') if ctrl => {
+                self.search = Some(ReverseSearch {
+                    query: String::new(),
+                    matched: None,
+                    original: self.input.clone(),
+                });
+                PromptInput::Pending
+            }
+            KeyCode::Up => {
+                self.history_back(history);
+                PromptInput::Pending
+            }
+            KeyCode::Down => {
+                self.history_forward(history);
+                PromptInput::Pending
+            }
+            KeyCode::Backspace => {
+                self.input.pop();
+                PromptInput::Pending
+            }
+            KeyCode::Char(c) => {
+                self.input.push(c);
+                PromptInput::Pending
+            }
+            _ => PromptInput::Pending,
+        }
+    }
+
+    fn history_back(&mut self, history: &[String]) {
+        let index = match self.history_index {
+            Some(0) => return,
+            Some(index) => index - 1,
+            None if history.is_empty() => return,
+            None => {
+                self.draft = self.input.clone();
+                history.len() - 1
+            }
+        };
+        self.history_index = Some(index);
+        self.input = history[index].clone();
+    }
+
+    fn history_forward(&mut self, history: &[String]) {
+        let Some(index) = self.history_index else {
+            return;
+        };
+        if index + 1 < history.len() {
+            self.history_index = Some(index + 1);
+            self.input = history[index + 1].clone();
+        } else {
+            self.history_index = None;
+            self.input = std::mem::take(&mut self.draft);
+        }
+    }
+
+    fn handle_search_key(&mut self, code: KeyCode, ctrl: bool, history: &[String]) -> PromptInput {
+        let Some(search) = self.search.as_mut() else {
+            return PromptInput::Pending;
+        };
+        match code {
+            KeyCode::Enter => {
+                self.search = None;
+                return PromptInput::Submitted(std::mem::take(&mut self.input));
+            }
+            // give up on the search and go back to what was typed before it
+            KeyCode::Char('c' | 'g') if ctrl => {
+                self.input = std::mem::take(&mut search.original);
+                self.search = None;
+            }
+            // the next older match
+            KeyCode::Char('r') if ctrl => {
+                let before = search.matched.unwrap_or(history.len());
+                if let Some(index) = find_before(history, &search.query, before) {
+                    search.matched = Some(index);
+                }
+            }
+            KeyCode::Backspace => {
+                search.query.pop();
+                search.matched = find_before(history, &search.query, history.len());
+            }
+            KeyCode::Char(c) => {
+                search.query.push(c);
+                // keep the current match while it still matches, like readline
+                let from = search.matched.map_or(history.len(), |index| index + 1);
+                search.matched = find_before(history, &search.query, from);
+            }
+            // anything else leaves the match in the input to be edited
+            _ => {
+                self.history_index = None;
+                self.search = None;
+                return PromptInput::Pending;
+            }
+        }
+        if let Some(search) = &self.search {
+            self.input = match search.matched {
+                Some(index) => history[index].clone(),
+                None => String::new(),
+            };
+        }
+        PromptInput::Pending
+    }
+}
+
+/// the newest entry before `before` containing `query`
+fn find_before(history: &[String], query: &str, before: usize) -> Option<usize> {
+    if query.is_empty() {
+        return None;
+    }
+    history[..before.min(history.len())]
+        .iter()
+        .rposition(|entry| entry.contains(query))
+}
+
+#[cfg(test)]
+mod test {
+    use terminput::KeyEvent;
+
+    use super::*;
+
+    fn key(code: KeyCode) -> Event {
+        Event::Key(KeyEvent::new(code))
+    }
+
+    fn ctrl(c: char) -> Event {
+        Event::Key(KeyEvent::new(KeyCode::Char(c)).modifiers(KeyModifiers::CTRL))
+    }
+
+    fn history() -> Vec<String> {
+        ["new-session -s work", "kill-pane", "new-window", "kill-session -t work"]
+            .map(str::to_owned)
+            .to_vec()
+    }
+
+    #[test]
+    fn test_history_navigation() {
+        let history = history();
+        let mut prompt = PromptState::new(":");
+        prompt.handle_event(key(KeyCode::Char('x')), &history);
+        prompt.handle_event(key(KeyCode::Up), &history);
+        assert_eq!(prompt.input, "kill-session -t work");
+        prompt.handle_event(key(KeyCode::Up), &history);
+        assert_eq!(prompt.input, "new-window");
+        prompt.handle_event(key(KeyCode::Down), &history);
+        prompt.handle_event(key(KeyCode::Down), &history);
+        assert_eq!(prompt.input, "x");
+
+        for _ in 0..10 {
+            prompt.handle_event(key(KeyCode::Up), &history);
+        }
+        assert_eq!(prompt.input, "new-session -s work");
+    }
+
+    #[test]
+    fn test_reverse_search() {
+        let history = history();
+        let mut prompt = PromptState::new(":");
+        prompt.handle_event(ctrl('r'), &history);
+        for c in "kill".chars() {
+            prompt.handle_event(key(KeyCode::Char(c)), &history);
+        }
+        assert_eq!(prompt.input, "kill-session -t work");
+        prompt.handle_event(ctrl('r'), &history);
+        assert_eq!(prompt.input, "kill-pane");
+        assert_eq!(prompt.display_label(), "(reverse-i-search)`kill': ");
+
+        prompt.handle_event(ctrl('g'), &history);
+        assert!(prompt.search.is_none());
+        assert_eq!(prompt.input, "");
+
+        prompt.handle_event(ctrl('r'), &history);
+        prompt.handle_event(key(KeyCode::Char('w')), &history);
+        assert!(matches!(
+            prompt.handle_event(key(KeyCode::Enter), &history),
+            PromptInput::Submitted(input) if input == "kill-session -t work"
+        ));
+    }
+}
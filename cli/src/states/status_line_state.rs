@@ -1,3 +1,5 @@
+use remux_core::states::{Alert, WindowInfo};
+
 use crate::app::AppState;
 
 #[derive(Debug, Clone)]
@@ -6,6 +8,8 @@ pub struct StatusLineState {
     pub a: Vec<String>,
     pub b: Vec<String>,
     pub c: Vec<String>,
+    pub window_bar: WindowBarState,
+    pub confirm_before_kill: bool, // `ui.confirm_before_kill`, key bound kills ask first
 }
 
 impl Default for StatusLineState {
@@ -15,9 +19,65 @@ impl Default for StatusLineState {
             a: Default::default(),
             b: Default::default(),
             c: Default::default(),
+            window_bar: Default::default(),
+            confirm_before_kill: true,
+        }
+    }
+}
+
+/// `ui.window_bar` - the line of windows above the status line, formats take tmux's
+/// `#I` (index), `#W` (name), `#F` (flags, `*` current and `!` activity) and `#P` (pane count)
+#[derive(Debug, Clone)]
+pub struct WindowBarState {
+    pub enabled: bool,
+    pub current: String, // format of the active window
+    pub other: String,
+    pub separator: String,
+}
+
+impl Default for WindowBarState {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            current: "#I:#W#F".to_owned(),
+            other: "#I:#W#F".to_owned(),
+            separator: " ".to_owned(),
         }
     }
 }
+
+impl WindowBarState {
+    pub fn format(&self, window: &WindowInfo, current: bool) -> String {
+        let format = if current { &self.current } else { &self.other };
+        let mut formatted = String::new();
+        let mut chars = format.chars();
+        while let Some(c) = chars.next() {
+            if c != '#' {
+                formatted.push(c);
+                continue;
+            }
+            match chars.next() {
+                Some('I') => formatted.push_str(&window.index.to_string()),
+                Some('W') => formatted.push_str(&window.name),
+                Some('P') => formatted.push_str(&window.panes.to_string()),
+                Some('F') => {
+                    if current {
+                        formatted.push('*');
+                    }
+                    if window.activity {
+                        formatted.push('!');
+                    }
+                }
+                Some('#') | None => formatted.push('#'),
+                Some(c) => {
+                    formatted.push('#');
+                    formatted.push(c);
+                }
+            }
+        }
+        formatted
+    }
+}
 impl StatusLineState {
     pub fn disabled() -> Self {
         Self {
@@ -26,6 +86,18 @@ impl StatusLineState {
         }
     }
 
+    /// drops powerline and other icon font glyphs, they live in the private use areas and screen
+    /// readers either skip them or spell out their code points
+    pub fn strip_glyphs(&mut self) {
+        for item in self.a.iter_mut().chain(self.b.iter_mut()).chain(self.c.iter_mut()) {
+            item.retain(|c| !is_private_use(c));
+            *item = item.trim().to_owned();
+        }
+        for section in [&mut self.a, &mut self.b, &mut self.c] {
+            section.retain(|item| !item.is_empty());
+        }
+    }
+
     pub fn apply_built_ins(&mut self, state: &AppState) {
         for item in self.a.iter_mut().chain(self.b.iter_mut()).chain(self.c.iter_mut()) {
             if item.as_str() == "active-session" {
@@ -41,7 +113,75 @@ impl StatusLineState {
                 } else {
                     *item = "".to_owned();
                 }
+            } else if item.as_str() == "last-session" {
+                // `-` as in tmux's flag for the last window, where `switch-client -l` goes
+                *item = state
+                    .daemon
+                    .last_session()
+                    .map(|session_info| format!("{}-", session_info.name))
+                    .unwrap_or_default();
+            } else if item.as_str() == "alerts" {
+                // `!` for activity and `~` for silence like tmux's window flags
+                *item = state
+                    .daemon
+                    .sessions
+                    .iter()
+                    .filter_map(|session_info| {
+                        let flag = match session_info.alert? {
+                            Alert::Activity => '!',
+                            Alert::Silence => '~',
+                        };
+                        Some(format!("{}{flag}", session_info.name))
+                    })
+                    .collect::<Vec<_>>()
+                    .join(" ");
+            } else if item.as_str() == "clients" {
+                // only worth showing when the session is shared
+                *item = match state.daemon.clients.len() {
+                    0 | 1 => "".to_owned(),
+                    n => format!("{n} clients"),
+                };
             }
         }
     }
 }
+
+fn is_private_use(c: char) -> bool {
+    matches!(c, '\u{e000}'..='\u{f8ff}' | '\u{f0000}'..='\u{10ffff}')
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_strip_glyphs() {
+        let mut state = StatusLineState {
+            a: vec!["\u{e0a0} main".to_owned(), "\u{e0b0}".to_owned()],
+            c: vec!["12:00 \u{f017}".to_owned()],
+            ..Default::default()
+        };
+        state.strip_glyphs();
+        assert_eq!(state.a, vec!["main"]);
+        assert_eq!(state.c, vec!["12:00"]);
+    }
+
+    #[test]
+    fn test_window_bar_format() {
+        let window_bar = WindowBarState {
+            current: "[#I #W#F] ##".to_owned(),
+            ..Default::default()
+        };
+        let window = WindowInfo {
+            id: 4,
+            index: 1,
+            name: "vim".to_owned(),
+            panes: 2,
+            active_pane: 3,
+            activity: true,
+            bell: false,
+        };
+        assert_eq!(window_bar.format(&window, false), "1:vim!");
+        assert_eq!(window_bar.format(&window, true), "[1 vim*!] #");
+    }
+}
@@ -0,0 +1,263 @@
+use std::collections::HashSet;
+
+use derivative::Derivative;
+use ratatui::widgets::ListState;
+use remux_core::{events::CliEvent, states::TreeSession};
+use vt100::Parser;
+
+/// a line of the choose-tree list, as indexes into the sessions, their windows and the windows' panes
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum TreeRow {
+    Session(usize),
+    Window(usize, usize),
+    Pane(usize, usize, usize),
+}
+
+/// sessions -> windows -> panes, collapsed and expanded with the selected pane's screen shown alongside
+#[derive(Derivative)]
+#[derivative(Debug)]
+pub struct TreeState {
+    pub sessions: Vec<TreeSession>,
+    pub list_state: ListState,
+    expanded_sessions: HashSet<u32>,
+    expanded_windows: HashSet<(u32, usize)>,
+    #[derivative(Debug = "ignore")]
+    pub preview: Parser,
+    pub previewing: Option<(u32, usize, usize)>, // the (session id, window index, pane id) last asked for
+}
+
+impl TreeState {
+    /// starts on the active window of the client's session, or the session itself when only sessions show
+    pub fn new(sessions: Vec<TreeSession>, sessions_only: bool, active_session: Option<u32>) -> Self {
+        let expanded_sessions = if sessions_only {
+            HashSet::new()
+        } else {
+            sessions.iter().map(|session| session.id).collect()
+        };
+        let mut tree = Self {
+            sessions,
+            list_state: ListState::default(),
+            expanded_sessions,
+            expanded_windows: HashSet::new(),
+            preview: Parser::default(),
+            previewing: None,
+        };
+        let rows = tree.rows();
+        let selected = tree
+            .sessions
+            .iter()
+            .position(|session| Some(session.id) == active_session)
+            .and_then(|s| {
+                let window = tree.sessions[s].windows.iter().position(|window| window.active);
+                rows.iter().position(|row| match (row, window) {
+                    (TreeRow::Window(i, w), Some(active)) => (*i, *w) == (s, active),
+                    (TreeRow::Session(i), _) => *i == s && sessions_only,
+                    _ => false,
+                })
+            });
+        tree.list_state.select(Some(selected.unwrap_or(0)));
+        tree
+    }
+
+    pub fn rows(&self) -> Vec<TreeRow> {
+        let mut rows = Vec::new();
+        for (s, session) in self.sessions.iter().enumerate() {
+            rows.push(TreeRow::Session(s));
+            if !self.expanded_sessions.contains(&session.id) {
+                continue;
+            }
+            for (w, window) in session.windows.iter().enumerate() {
+                rows.push(TreeRow::Window(s, w));
+                if self.expanded_windows.contains(&(session.id, window.index)) {
+                    rows.extend((0..window.panes.len()).map(|p| TreeRow::Pane(s, w, p)));
+                }
+            }
+        }
+        rows
+    }
+
+    pub fn label(&self, row: TreeRow) -> String {
+        match row {
+            TreeRow::Session(s) => {
+                let session = &self.sessions[s];
+                let marker = if self.expanded_sessions.contains(&session.id) {
+                    "-"
+                } else {
+                    "+"
+                };
+                format!("{marker} {}: {} windows", session.name, session.windows.len())
+            }
+            TreeRow::Window(s, w) => {
+                let window = &self.sessions[s].windows[w];
+                let expanded = self.expanded_windows.contains(&(self.sessions[s].id, window.index));
+                let marker = if expanded { "-" } else { "+" };
+                let active = if window.active { "*" } else { "" };
+                format!(
+                    "  {marker} {}: {}{active} ({} panes)",
+                    window.index,
+                    window.name,
+                    window.panes.len()
+                )
+            }
+            TreeRow::Pane(s, w, p) => {
+                let window = &self.sessions[s].windows[w];
+                let active = if window.panes[p] == window.active_pane { "*" } else { "" };
+                format!("      %{}{active}", window.panes[p])
+            }
+        }
+    }
+
+    pub fn selected(&self) -> Option<TreeRow> {
+        self.rows().get(self.list_state.selected()?).copied()
+    }
+
+    pub fn move_selection(&mut self, down: bool) {
+        let last = self.rows().len().saturating_sub(1);
+        let i = self.list_state.selected().unwrap_or(0);
+        let i = if down { (i + 1).min(last) } else { i.saturating_sub(1) };
+        self.list_state.select(Some(i));
+    }
+
+    /// shows the selected session's windows or the selected window's panes
+    pub fn expand(&mut self) {
+        match self.selected() {
+            Some(TreeRow::Session(s)) => {
+                self.expanded_sessions.insert(self.sessions[s].id);
+            }
+            Some(TreeRow::Window(s, w)) => {
+                let session = &self.sessions[s];
+                self.expanded_windows.insert((session.id, session.windows[w].index));
+            }
+            Some(TreeRow::Pane(..)) | None => {}
+        }
+    }
+
+    /// hides the children of the selected row, or of its parent with the parent then selected
+    pub fn collapse(&mut self) {
+        let parent = match self.selected() {
+            Some(TreeRow::Session(s)) => {
+                self.expanded_sessions.remove(&self.sessions[s].id);
+                TreeRow::Session(s)
+            }
+            Some(TreeRow::Window(s, w)) => {
+                let session = &self.sessions[s];
+                if self.expanded_windows.remove(&(session.id, session.windows[w].index)) {
+                    TreeRow::Window(s, w)
+                } else {
+                    self.expanded_sessions.remove(&session.id);
+                    TreeRow::Session(s)
+                }
+            }
+            Some(TreeRow::Pane(s, w, _)) => {
+                let session = &self.sessions[s];
+                self.expanded_windows.remove(&(session.id, session.windows[w].index));
+                TreeRow::Window(s, w)
+            }
+            None => return,
+        };
+        let i = self.rows().iter().position(|row| *row == parent);
+        self.list_state.select(i);
+    }
+
+    /// (session id, window index, pane id) of the pane shown for the selected row, a session shows its active
+    /// window and a window its active pane
+    pub fn preview_target(&self) -> Option<(u32, usize, usize)> {
+        let (s, w, pane) = match self.selected()? {
+            TreeRow::Session(s) => {
+                let window = self.sessions[s].windows.iter().find(|window| window.active)?;
+                (s, window.index, window.active_pane)
+            }
+            TreeRow::Window(s, w) => {
+                let window = &self.sessions[s].windows[w];
+                (s, window.index, window.active_pane)
+            }
+            TreeRow::Pane(s, w, p) => {
+                let window = &self.sessions[s].windows[w];
+                (s, window.index, window.panes[p])
+            }
+        };
+        Some((self.sessions[s].id, w, pane))
+    }
+
+    /// the events switching the client to the selected row, a session keeps whichever window was active
+    pub fn switch_events(&self) -> Vec<CliEvent> {
+        let Some(row) = self.selected() else {
+            return Vec::new();
+        };
+        let (TreeRow::Session(s) | TreeRow::Window(s, _) | TreeRow::Pane(s, _, _)) = row;
+        let mut events = vec![CliEvent::SwitchSession(self.sessions[s].name.clone())];
+        if let TreeRow::Window(s, w) | TreeRow::Pane(s, w, _) = row {
+            events.push(CliEvent::SelectWindow(self.sessions[s].windows[w].index));
+        }
+        if let TreeRow::Pane(s, w, p) = row {
+            events.push(CliEvent::SelectPane(self.sessions[s].windows[w].panes[p]));
+        }
+        events
+    }
+
+    /// a capture of the previewed pane arrived, drawn at the pane's own size
+    pub fn set_preview(&mut self, rows: u16, cols: u16, contents: &[u8]) {
+        self.preview = Parser::new(rows, cols, 0);
+        self.preview.process(contents);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    #![allow(clippy::unwrap_used)]
+    use remux_core::states::TreeWindow;
+
+    use super::*;
+
+    fn window(index: usize, panes: Vec<usize>, active: bool) -> TreeWindow {
+        TreeWindow {
+            index,
+            name: format!("w{index}"),
+            active_pane: panes[0],
+            panes,
+            active,
+        }
+    }
+
+    fn sessions() -> Vec<TreeSession> {
+        vec![
+            TreeSession {
+                id: 1,
+                name: "main".to_owned(),
+                windows: vec![window(0, vec![0], true)],
+            },
+            TreeSession {
+                id: 4,
+                name: "dev".to_owned(),
+                windows: vec![window(0, vec![1], false), window(1, vec![2, 3], true)],
+            },
+        ]
+    }
+
+    #[test]
+    fn test_tree_navigation() {
+        let mut tree = TreeState::new(sessions(), false, Some(4));
+        assert_eq!(tree.selected(), Some(TreeRow::Window(1, 1)));
+        assert_eq!(tree.preview_target(), Some((4, 1, 2)));
+
+        tree.expand();
+        tree.move_selection(true);
+        tree.move_selection(true);
+        assert_eq!(tree.selected(), Some(TreeRow::Pane(1, 1, 1)));
+        assert_eq!(tree.preview_target(), Some((4, 1, 3)));
+        assert!(matches!(
+            tree.switch_events().as_slice(),
+            [CliEvent::SwitchSession(name), CliEvent::SelectWindow(1), CliEvent::SelectPane(3)] if name == "dev"
+        ));
+
+        tree.collapse();
+        assert_eq!(tree.selected(), Some(TreeRow::Window(1, 1)));
+        tree.collapse();
+        assert_eq!(tree.selected(), Some(TreeRow::Session(1)));
+        assert_eq!(tree.rows().len(), 3);
+
+        let tree = TreeState::new(sessions(), true, Some(1));
+        assert_eq!(tree.rows(), vec![TreeRow::Session(0), TreeRow::Session(1)]);
+        assert!(matches!(tree.switch_events().as_slice(), [CliEvent::SwitchSession(name)] if name == "main"));
+    }
+}
@@ -1,12 +1,23 @@
 use std::{fs, time::Duration};
 
 use color_eyre::eyre;
-use mlua::Lua;
-use tokio::sync::broadcast;
+use mlua::{Lua, LuaSerdeExt};
+use remux_core::{commands::HookEvent, states::DaemonState};
+use tokio::sync::{broadcast, mpsc, watch};
 
-use crate::{prelude::*, states::status_line_state::StatusLineState};
+use crate::{
+    prelude::*,
+    states::status_line_state::{StatusLineState, WindowBarState},
+};
 
-fn initialize_lua_state(lua: &mut Lua) -> Result<()> {
+// registry key of the table of `remux.on` callbacks, keyed by hook name
+const HOOK_CALLBACKS: &str = "remux_hook_callbacks";
+
+fn initialize_lua_state(
+    lua: &mut Lua,
+    command_tx: mpsc::Sender<String>,
+    daemon_state: watch::Receiver<DaemonState>,
+) -> Result<()> {
     info!("Initializing lua state");
     let sections_table = lua.create_table()?;
     let section_a = lua.create_table()?;
@@ -18,34 +29,99 @@ fn initialize_lua_state(lua: &mut Lua) -> Result<()> {
     let status_line_table = lua.create_table()?;
     status_line_table.set("sections", sections_table)?;
     status_line_table.set("enabled", true)?;
+    let window_bar_table = lua.create_table()?;
+    window_bar_table.set("enabled", true)?;
     let ui_table = lua.create_table()?;
     ui_table.set("status_line", status_line_table)?;
+    ui_table.set("window_bar", window_bar_table)?;
+    ui_table.set("confirm_before_kill", true)?;
     lua.globals().set("ui", ui_table)?;
+
+    // `remux.command("log-pane on")` runs a command line in the daemon like the `:` prompt
+    let remux_table = lua.create_table()?;
+    let command =
+        lua.create_function(move |_, line: String| command_tx.try_send(line).map_err(mlua::Error::external))?;
+    remux_table.set("command", command)?;
+
+    // `remux.on("session-created", function(hook) ... end)` runs when the daemon fires the hook, the callback
+    // gets `hook.event` and `hook.session`. registering again replaces the callback since the config is re-run
+    lua.set_named_registry_value(HOOK_CALLBACKS, lua.create_table()?)?;
+    let on = lua.create_function(|lua, (event, callback): (String, mlua::Function)| {
+        let event = event.parse::<HookEvent>().map_err(mlua::Error::external)?;
+        let callbacks: mlua::Table = lua.named_registry_value(HOOK_CALLBACKS)?;
+        callbacks.set(event.name(), callback)
+    })?;
+    remux_table.set("on", on)?;
+
+    // the daemon's view of its sessions and the active session, e.g. `#remux.get_windows()` for a window count
+    let state = daemon_state.clone();
+    let get_windows = lua.create_function(move |lua, ()| lua.to_value(&state.borrow().windows))?;
+    remux_table.set("get_windows", get_windows)?;
+    let state = daemon_state.clone();
+    let get_active_window = lua.create_function(move |lua, ()| lua.to_value(&state.borrow().active_window()))?;
+    remux_table.set("get_active_window", get_active_window)?;
+    let state = daemon_state.clone();
+    let get_active_pane =
+        lua.create_function(move |_, ()| Ok(state.borrow().active_window().map(|window| window.active_pane)))?;
+    remux_table.set("get_active_pane", get_active_pane)?;
+    let state = daemon_state.clone();
+    let get_sessions = lua.create_function(move |lua, ()| lua.to_value(&state.borrow().sessions))?;
+    remux_table.set("get_sessions", get_sessions)?;
+    let state = daemon_state.clone();
+    let get_last_session = lua.create_function(move |lua, ()| lua.to_value(&state.borrow().last_session()))?;
+    remux_table.set("get_last_session", get_last_session)?;
+    let state = daemon_state;
+    let get_client_count = lua.create_function(move |_, ()| Ok(state.borrow().clients.len()))?;
+    remux_table.set("get_client_count", get_client_count)?;
+    lua.globals().set("remux", remux_table)?;
     Ok(())
 }
 
-pub fn start_status_line_task(tx: broadcast::Sender<StatusLineState>) -> Result<CliTask> {
+pub fn start_status_line_task(
+    tx: broadcast::Sender<StatusLineState>,
+    command_tx: mpsc::Sender<String>,
+    mut hook_rx: mpsc::Receiver<(HookEvent, Option<String>)>,
+    daemon_state: watch::Receiver<DaemonState>,
+) -> Result<CliTask> {
     let mut lua = Lua::default();
-    initialize_lua_state(&mut lua)?;
+    initialize_lua_state(&mut lua, command_tx, daemon_state)?;
     let code = fs::read_to_string("defaults/statusbar.lua")?;
 
     info!("Starting lua status line task");
     let task: CliTask = tokio::spawn({
         async move {
             loop {
-                tokio::time::sleep(Duration::from_millis(500)).await;
+                tokio::select! {
+                    _ = tokio::time::sleep(Duration::from_millis(500)) => {}
+                    Some((event, session_name)) = hook_rx.recv() => {
+                        if let Err(e) = run_hook_callback(&lua, event, session_name) {
+                            warn!(hook=%event, error=%e, "Lua hook callback failed");
+                        }
+                        continue;
+                    }
+                }
                 lua.load(&code).exec().unwrap();
                 let ui_table: mlua::Table = lua.globals().get("ui")?;
                 let status_line_config: mlua::Table = ui_table.get("status_line")?;
                 let sections_config: mlua::Table = status_line_config.get("sections")?;
                 let enabled: mlua::Value = status_line_config.get("enabled")?;
+                let window_bar = window_bar_state(&ui_table)?;
+                let confirm_before_kill = ui_table.get::<Option<bool>>("confirm_before_kill")?.unwrap_or(true);
 
                 if let mlua::Value::Boolean(false) = enabled {
-                    let _ = tx.send(StatusLineState::disabled());
+                    let _ = tx.send(StatusLineState {
+                        window_bar,
+                        confirm_before_kill,
+                        ..StatusLineState::disabled()
+                    });
                     continue;
                 }
 
-                let mut status_line_state = StatusLineState::default();
+                let mut status_line_state = StatusLineState {
+                    window_bar,
+                    confirm_before_kill,
+                    ..StatusLineState::default()
+                };
                 for pair in sections_config.pairs::<String, mlua::Table>() {
                     let (key, val) = pair?;
                     for pair in val.pairs::<mlua::Value, mlua::Value>() {
@@ -90,3 +166,35 @@ pub fn start_status_line_task(tx: broadcast::Sender<StatusLineState>) -> Result<
 
     eyre::Ok(task)
 }
+
+fn run_hook_callback(lua: &Lua, event: HookEvent, session_name: Option<String>) -> mlua::Result<()> {
+    let callbacks: mlua::Table = lua.named_registry_value(HOOK_CALLBACKS)?;
+    let Some(callback) = callbacks.get::<Option<mlua::Function>>(event.name())? else {
+        return Ok(());
+    };
+    let hook = lua.create_table()?;
+    hook.set("event", event.name())?;
+    hook.set("session", session_name)?;
+    callback.call(hook)
+}
+
+/// `ui.window_bar`, anything left out keeps its default
+fn window_bar_state(ui_table: &mlua::Table) -> mlua::Result<WindowBarState> {
+    let mut state = WindowBarState::default();
+    let Some(config) = ui_table.get::<Option<mlua::Table>>("window_bar")? else {
+        return Ok(state);
+    };
+    if let Some(enabled) = config.get::<Option<bool>>("enabled")? {
+        state.enabled = enabled;
+    }
+    if let Some(current) = config.get::<Option<String>>("current")? {
+        state.current = current;
+    }
+    if let Some(other) = config.get::<Option<String>>("other")? {
+        state.other = other;
+    }
+    if let Some(separator) = config.get::<Option<String>>("separator")? {
+        state.separator = separator;
+    }
+    Ok(state)
+}
@@ -1,4 +1,4 @@
-use ratatui::widgets::StatefulWidget;
+use ratatui::{symbols::border, widgets::StatefulWidget};
 
 use crate::{
     app::SelectorState,
@@ -6,8 +6,29 @@ use crate::{
     ui::traits::{Selection, SelectorStatefulWidget},
 };
 
+/// borders for screen reader users, box drawing characters get read out
+pub const ASCII_BORDER: border::Set = border::Set {
+    top_left: "+",
+    top_right: "+",
+    bottom_left: "+",
+    bottom_right: "+",
+    vertical_left: "|",
+    vertical_right: "|",
+    horizontal_top: "-",
+    horizontal_bottom: "-",
+};
+
 #[derive(Debug, Default)]
-pub struct BasicSelectorWidget {}
+pub struct BasicSelectorWidget {
+    ascii_borders: bool,
+}
+
+impl BasicSelectorWidget {
+    pub fn ascii_borders(mut self, ascii: bool) -> Self {
+        self.ascii_borders = ascii;
+        self
+    }
+}
 
 impl StatefulWidget for BasicSelectorWidget {
     type State = SelectorState;
@@ -21,6 +42,11 @@ impl StatefulWidget for BasicSelectorWidget {
         };
         let list_state = &mut state.list_state;
         let list = &state.list;
+        let border_set = if self.ascii_borders {
+            ASCII_BORDER
+        } else {
+            border::PLAIN
+        };
 
         // Calculate popup size (width and height)
         let width = (area.width / 2).min(50); // max width 50
@@ -32,6 +58,7 @@ impl StatefulWidget for BasicSelectorWidget {
             .block(
                 Block::default()
                     .borders(Borders::ALL)
+                    .border_set(border_set)
                     .border_style(Style::default().bold())
                     .title("Select")
                     .title_alignment(ratatui::layout::Alignment::Center),
@@ -0,0 +1,59 @@
+use ratatui::{
+    layout::Rect,
+    style::{Color, Style},
+    widgets::Widget,
+};
+
+/// per-client colors, picked by the client's place in the session's client list
+const CLIENT_COLORS: [Color; 6] = [
+    Color::Magenta,
+    Color::Cyan,
+    Color::Green,
+    Color::Blue,
+    Color::Red,
+    Color::Yellow,
+];
+
+/// ghost cursor on the cell another client of the session last clicked, labelled with its name
+pub struct ClientCursorWidget<'a> {
+    name: &'a str,
+    row: u16,
+    col: u16,
+    color: Color,
+}
+
+impl<'a> ClientCursorWidget<'a> {
+    pub fn new(name: &'a str, row: u16, col: u16, index: usize) -> Self {
+        Self {
+            name,
+            row,
+            col,
+            color: CLIENT_COLORS[index % CLIENT_COLORS.len()],
+        }
+    }
+}
+
+impl Widget for ClientCursorWidget<'_> {
+    fn render(self, area: Rect, buf: &mut ratatui::prelude::Buffer) {
+        if self.row >= area.height || self.col >= area.width {
+            return;
+        }
+        let (x, y) = (area.x + self.col, area.y + self.row);
+        // keep the character underneath readable
+        buf[(x, y)].set_style(Style::default().fg(Color::Black).bg(self.color));
+        // the label goes after the cursor, or before it when it would run off the edge
+        let width = self.name.chars().count() as u16;
+        let label_x = if self.col + 1 + width <= area.width {
+            x + 1
+        } else {
+            x.saturating_sub(width).max(area.x)
+        };
+        buf.set_stringn(
+            label_x,
+            y,
+            self.name,
+            (area.right() - label_x) as usize,
+            Style::default().fg(self.color),
+        );
+    }
+}
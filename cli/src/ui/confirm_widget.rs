@@ -0,0 +1,91 @@
+use ratatui::{
+    layout::{Alignment, Rect},
+    prelude::Stylize,
+    style::{Color, Modifier, Style},
+    symbols::border,
+    text::{Line, Span},
+    widgets::{Block, Borders, Clear, Paragraph, StatefulWidget, Widget},
+};
+
+use crate::{
+    states::confirm_state::ConfirmState,
+    ui::{
+        basic_selector_widget::ASCII_BORDER,
+        traits::{Selection, SelectorStatefulWidget},
+    },
+};
+
+/// yes/no popup over the middle of the screen, answering yes is `Selection::Index(0)`
+#[derive(Debug, Default)]
+pub struct ConfirmWidget {
+    ascii_borders: bool,
+}
+
+impl ConfirmWidget {
+    pub fn ascii_borders(mut self, ascii: bool) -> Self {
+        self.ascii_borders = ascii;
+        self
+    }
+}
+
+impl StatefulWidget for ConfirmWidget {
+    type State = ConfirmState;
+
+    fn render(self, area: Rect, buf: &mut ratatui::prelude::Buffer, state: &mut Self::State) {
+        let border_set = if self.ascii_borders {
+            ASCII_BORDER
+        } else {
+            border::PLAIN
+        };
+        let highlighted = Style::default().fg(Color::Green).add_modifier(Modifier::BOLD);
+        let answer = |label: &'static str, selected: bool| {
+            if selected {
+                Span::styled(format!("[{label}]"), highlighted)
+            } else {
+                Span::raw(format!(" {label} "))
+            }
+        };
+        let answers = Line::from(vec![
+            answer("yes", state.yes),
+            Span::raw("  "),
+            answer("no", !state.yes),
+        ]);
+
+        let width = (state.message.chars().count().max(answers.width()) as u16 + 4).min(area.width);
+        let height = 4u16.min(area.height);
+        let x = area.x + area.width.saturating_sub(width) / 2;
+        let y = area.y + area.height.saturating_sub(height) / 2;
+        let rect = Rect::new(x, y, width, height);
+        Clear.render(rect, buf);
+        Paragraph::new(vec![Line::from(state.message.as_str()), answers])
+            .alignment(Alignment::Center)
+            .block(
+                Block::default()
+                    .borders(Borders::ALL)
+                    .border_set(border_set)
+                    .border_style(Style::default().bold()),
+            )
+            .render(rect, buf);
+    }
+}
+
+impl SelectorStatefulWidget for ConfirmWidget {
+    fn input(event: terminput::Event, state: &mut Self::State) -> Option<Selection> {
+        use terminput::KeyCode::*;
+
+        let terminput::Event::Key(key_event) = event else {
+            return None;
+        };
+        match key_event.code {
+            Char('y' | 'Y') => Some(Selection::Index(0)),
+            Char('n' | 'N' | 'q') | Esc => Some(Selection::Cancelled),
+            Left | Right | Tab | Char('h' | 'l') => {
+                state.yes = !state.yes;
+                None
+            }
+            Enter if state.yes => Some(Selection::Index(0)),
+            Enter => Some(Selection::Cancelled),
+            _ => None,
+        }
+    }
+}
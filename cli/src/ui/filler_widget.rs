@@ -0,0 +1,31 @@
+use ratatui::{
+    layout::Rect,
+    style::{Color, Style},
+    widgets::Widget,
+};
+
+/// dotted fill for the part of the terminal the session's window doesn't cover, happens when a
+/// smaller client is viewing the same session
+pub struct FillerWidget {
+    rows: u16,
+    cols: u16,
+}
+
+impl FillerWidget {
+    pub fn new(rows: u16, cols: u16) -> Self {
+        Self { rows, cols }
+    }
+}
+
+impl Widget for FillerWidget {
+    fn render(self, area: Rect, buf: &mut ratatui::prelude::Buffer) {
+        let style = Style::default().fg(Color::DarkGray);
+        for y in area.top()..area.bottom() {
+            for x in area.left()..area.right() {
+                if y - area.y >= self.rows || x - area.x >= self.cols {
+                    buf[(x, y)].set_symbol("·").set_style(style);
+                }
+            }
+        }
+    }
+}
@@ -1,14 +1,29 @@
 use fuzzy_matcher::{FuzzyMatcher, skim::SkimMatcherV2};
-use ratatui::widgets::{Padding, StatefulWidget};
+use ratatui::{
+    symbols::border,
+    widgets::{Padding, StatefulWidget},
+};
 
 use crate::{
     app::{IndexedItem, SelectorState},
     prelude::*,
-    ui::traits::{Selection, SelectorStatefulWidget},
+    ui::{
+        basic_selector_widget::ASCII_BORDER,
+        traits::{Selection, SelectorStatefulWidget},
+    },
 };
 
 #[derive(Debug, Default)]
-pub struct FuzzySelectorWidget {}
+pub struct FuzzySelectorWidget {
+    ascii_borders: bool,
+}
+
+impl FuzzySelectorWidget {
+    pub fn ascii_borders(mut self, ascii: bool) -> Self {
+        self.ascii_borders = ascii;
+        self
+    }
+}
 
 impl StatefulWidget for FuzzySelectorWidget {
     type State = SelectorState;
@@ -27,6 +42,11 @@ impl StatefulWidget for FuzzySelectorWidget {
             .map(|pair| pair.item.clone())
             .collect::<Vec<String>>();
         let query = &state.query;
+        let border_set = if self.ascii_borders {
+            ASCII_BORDER
+        } else {
+            border::PLAIN
+        };
 
         let width = area.width / 2;
         let height = area.height / 2;
@@ -40,7 +60,7 @@ impl StatefulWidget for FuzzySelectorWidget {
             .split(rect);
 
         let max_height = chunks[0].height;
-        let items_height = filtered_items.len() as u16 + 2;
+        let items_height = (filtered_items.len() as u16 + 2).min(max_height);
         let subchunks = Layout::default()
             .direction(Direction::Vertical)
             .constraints([
@@ -60,6 +80,7 @@ impl StatefulWidget for FuzzySelectorWidget {
 
         let display_block = Block::default()
             .borders(Borders::ALL)
+            .border_set(border_set)
             .border_style(Style::default().bold())
             .title("Selecting")
             .title_alignment(ratatui::layout::Alignment::Center);
@@ -67,6 +88,7 @@ impl StatefulWidget for FuzzySelectorWidget {
         let display_query = Paragraph::new(query.clone()).block(
             Block::default()
                 .borders(Borders::ALL)
+                .border_set(border_set)
                 .border_style(Style::default().bold())
                 .padding(Padding::horizontal(1)),
         );
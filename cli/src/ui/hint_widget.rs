@@ -0,0 +1,37 @@
+use ratatui::{
+    layout::Rect,
+    style::{Color, Modifier, Style},
+    widgets::{Paragraph, Widget},
+};
+
+/// label drawn over the terminal output at a cell position, used to pick something on screen
+pub struct HintWidget<'a> {
+    label: &'a str,
+    row: u16,
+    col: u16,
+}
+
+impl<'a> HintWidget<'a> {
+    pub fn new(label: &'a str, row: u16, col: u16) -> Self {
+        Self { label, row, col }
+    }
+}
+
+impl Widget for HintWidget<'_> {
+    fn render(self, area: Rect, buf: &mut ratatui::prelude::Buffer) {
+        if self.row >= area.height || self.col >= area.width {
+            return;
+        }
+        let hint_area = Rect {
+            x: area.x + self.col,
+            y: area.y + self.row,
+            width: (self.label.len() as u16).min(area.width - self.col),
+            height: 1,
+        };
+        let style = Style::default()
+            .fg(Color::Black)
+            .bg(Color::Yellow)
+            .add_modifier(Modifier::BOLD);
+        Paragraph::new(self.label).style(style).render(hint_area, buf);
+    }
+}
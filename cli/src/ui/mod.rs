@@ -1,7 +1,17 @@
 pub mod basic_selector_widget;
+mod client_cursor_widget;
+pub mod confirm_widget;
+mod filler_widget;
 pub mod fuzzy_selector_widget;
+mod hint_widget;
+mod notification_widget;
+mod pane_preview_widget;
+mod prompt_widget;
+mod reconnecting_widget;
 mod status_line_widget;
 pub mod traits;
+pub mod tree_widget;
 mod ui;
+mod window_bar_widget;
 
 pub use ui::draw;
@@ -0,0 +1,32 @@
+use ratatui::{
+    style::{Color, Modifier, Style},
+    widgets::{Paragraph, Widget},
+};
+use remux_core::events::MessageLevel;
+
+use crate::states::notification_state::NotificationState;
+
+pub struct NotificationWidget {
+    state: NotificationState,
+}
+
+impl NotificationWidget {
+    pub fn new(state: NotificationState) -> Self {
+        Self { state }
+    }
+}
+
+impl Widget for NotificationWidget {
+    fn render(self, area: ratatui::prelude::Rect, buf: &mut ratatui::prelude::Buffer) {
+        if area.height < 1 {
+            return;
+        }
+
+        let (fg, bg) = match self.state.level {
+            MessageLevel::Info => (Color::Black, Color::Yellow),
+            MessageLevel::Error => (Color::White, Color::Red),
+        };
+        let style = Style::default().fg(fg).bg(bg).add_modifier(Modifier::BOLD);
+        Paragraph::new(self.state.message).style(style).render(area, buf);
+    }
+}
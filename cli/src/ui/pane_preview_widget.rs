@@ -0,0 +1,83 @@
+use ratatui::{
+    layout::{Alignment, Rect},
+    prelude::Stylize,
+    style::{Color, Modifier, Style},
+    symbols::border,
+    text::{Line, Span},
+    widgets::{Block, Borders, Clear, Paragraph, Widget},
+};
+use remux_core::messages::response::{PreviewPane, TextColor, TextStyle};
+
+use crate::ui::basic_selector_widget::ASCII_BORDER;
+
+/// read-only snapshot of a session's active pane, drawn beside the session switcher
+pub struct PanePreviewWidget<'a> {
+    title: &'a str,
+    preview: &'a PreviewPane,
+    ascii_borders: bool,
+}
+
+impl<'a> PanePreviewWidget<'a> {
+    pub fn new(title: &'a str, preview: &'a PreviewPane, ascii_borders: bool) -> Self {
+        Self {
+            title,
+            preview,
+            ascii_borders,
+        }
+    }
+}
+
+fn color(color: TextColor) -> Color {
+    match color {
+        TextColor::Default => Color::Reset,
+        TextColor::Indexed(i) => Color::Indexed(i),
+        TextColor::Rgb(r, g, b) => Color::Rgb(r, g, b),
+    }
+}
+
+fn style(style: TextStyle) -> Style {
+    let mut modifier = Modifier::empty();
+    for (on, flag) in [
+        (style.bold, Modifier::BOLD),
+        (style.italic, Modifier::ITALIC),
+        (style.underline, Modifier::UNDERLINED),
+        (style.inverse, Modifier::REVERSED),
+    ] {
+        modifier.set(flag, on);
+    }
+    Style::default()
+        .fg(color(style.fg))
+        .bg(color(style.bg))
+        .add_modifier(modifier)
+}
+
+impl Widget for PanePreviewWidget<'_> {
+    fn render(self, area: Rect, buf: &mut ratatui::prelude::Buffer) {
+        let border_set = if self.ascii_borders {
+            ASCII_BORDER
+        } else {
+            border::PLAIN
+        };
+        let lines: Vec<Line> = self
+            .preview
+            .lines
+            .iter()
+            .map(|line| {
+                line.iter()
+                    .map(|text| Span::styled(text.text.as_str(), style(text.style)))
+                    .collect()
+            })
+            .collect();
+        Clear.render(area, buf);
+        Paragraph::new(lines)
+            .block(
+                Block::default()
+                    .borders(Borders::ALL)
+                    .border_set(border_set)
+                    .border_style(Style::default().bold())
+                    .title(self.title)
+                    .title_alignment(Alignment::Center),
+            )
+            .render(area, buf);
+    }
+}
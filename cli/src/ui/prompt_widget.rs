@@ -0,0 +1,29 @@
+use ratatui::{
+    style::{Color, Style},
+    widgets::{Paragraph, Widget},
+};
+
+use crate::states::prompt_state::PromptState;
+
+pub struct PromptWidget<'a> {
+    state: &'a PromptState,
+}
+
+impl<'a> PromptWidget<'a> {
+    pub fn new(state: &'a PromptState) -> Self {
+        Self { state }
+    }
+}
+
+impl Widget for PromptWidget<'_> {
+    fn render(self, area: ratatui::prelude::Rect, buf: &mut ratatui::prelude::Buffer) {
+        if area.height < 1 {
+            return;
+        }
+
+        let style = Style::default().fg(Color::Black).bg(Color::Yellow);
+        Paragraph::new(format!("{}{}", self.state.display_label(), self.state.input))
+            .style(style)
+            .render(area, buf);
+    }
+}
@@ -0,0 +1,52 @@
+use ratatui::{
+    layout::{Alignment, Rect},
+    prelude::Stylize,
+    style::Style,
+    symbols::border,
+    widgets::{Block, Borders, Clear, Paragraph, Widget},
+};
+
+use crate::ui::basic_selector_widget::ASCII_BORDER;
+
+/// popup over the middle of the screen while the connection to the daemon is being re-established
+pub struct ReconnectingWidget {
+    attempt: u32,
+    ascii_borders: bool,
+}
+
+impl ReconnectingWidget {
+    pub fn new(attempt: u32, ascii_borders: bool) -> Self {
+        Self { attempt, ascii_borders }
+    }
+}
+
+impl Widget for ReconnectingWidget {
+    fn render(self, area: Rect, buf: &mut ratatui::prelude::Buffer) {
+        let message = if self.ascii_borders {
+            format!("reconnecting... (attempt {})", self.attempt)
+        } else {
+            format!("reconnecting… (attempt {})", self.attempt)
+        };
+        let border_set = if self.ascii_borders {
+            ASCII_BORDER
+        } else {
+            border::PLAIN
+        };
+
+        let width = (message.chars().count() as u16 + 4).min(area.width);
+        let height = 3u16.min(area.height);
+        let x = area.x + area.width.saturating_sub(width) / 2;
+        let y = area.y + area.height.saturating_sub(height) / 2;
+        let rect = Rect::new(x, y, width, height);
+        Clear.render(rect, buf);
+        Paragraph::new(message)
+            .alignment(Alignment::Center)
+            .block(
+                Block::default()
+                    .borders(Borders::ALL)
+                    .border_set(border_set)
+                    .border_style(Style::default().bold()),
+            )
+            .render(rect, buf);
+    }
+}
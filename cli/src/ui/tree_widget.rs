@@ -0,0 +1,95 @@
+use ratatui::{
+    layout::{Alignment, Constraint, Direction, Layout, Rect},
+    prelude::Stylize,
+    style::{Color, Modifier, Style},
+    symbols::border,
+    widgets::{Block, Borders, Clear, List, StatefulWidget, Widget},
+};
+use tui_term::widget::PseudoTerminal;
+
+use crate::{
+    states::tree_state::TreeState,
+    ui::{
+        basic_selector_widget::ASCII_BORDER,
+        traits::{Selection, SelectorStatefulWidget},
+    },
+};
+
+/// choose-tree popup, the tree on the left and the selected pane's screen on the right. picking a row is
+/// `Selection::Index` of the row
+#[derive(Debug, Default)]
+pub struct TreeWidget {
+    ascii_borders: bool,
+}
+
+impl TreeWidget {
+    pub fn ascii_borders(mut self, ascii: bool) -> Self {
+        self.ascii_borders = ascii;
+        self
+    }
+}
+
+impl StatefulWidget for TreeWidget {
+    type State = TreeState;
+
+    fn render(self, area: Rect, buf: &mut ratatui::prelude::Buffer, state: &mut Self::State) {
+        let border_set = if self.ascii_borders {
+            ASCII_BORDER
+        } else {
+            border::PLAIN
+        };
+        let block = |title: String| {
+            Block::default()
+                .borders(Borders::ALL)
+                .border_set(border_set)
+                .border_style(Style::default().bold())
+                .title(title)
+                .title_alignment(Alignment::Center)
+        };
+
+        let width = (area.width * 4 / 5).max(area.width.min(40));
+        let height = (area.height * 4 / 5).max(area.height.min(10));
+        let x = area.x + area.width.saturating_sub(width) / 2;
+        let y = area.y + area.height.saturating_sub(height) / 2;
+        let rect = Rect::new(x, y, width, height);
+        Clear.render(rect, buf);
+        let chunks = Layout::default()
+            .direction(Direction::Horizontal)
+            .constraints([Constraint::Percentage(35), Constraint::Percentage(65)])
+            .split(rect);
+
+        let items: Vec<String> = state.rows().into_iter().map(|row| state.label(row)).collect();
+        let list = List::new(items)
+            .block(block("Sessions".to_owned()))
+            .highlight_style(Style::default().fg(Color::Green).add_modifier(Modifier::BOLD));
+        StatefulWidget::render(list, chunks[0], buf, &mut state.list_state);
+
+        let title = match state.previewing {
+            Some((_, window, pane)) => format!("window {window} pane %{pane}"),
+            None => "Preview".to_owned(),
+        };
+        PseudoTerminal::new(state.preview.screen())
+            .block(block(title))
+            .render(chunks[1], buf);
+    }
+}
+
+impl SelectorStatefulWidget for TreeWidget {
+    fn input(event: terminput::Event, state: &mut Self::State) -> Option<Selection> {
+        use terminput::KeyCode::*;
+
+        let terminput::Event::Key(key_event) = event else {
+            return None;
+        };
+        match key_event.code {
+            Up | Char('k') => state.move_selection(false),
+            Down | Char('j') => state.move_selection(true),
+            Right | Char('l' | '+') => state.expand(),
+            Left | Char('h' | '-') => state.collapse(),
+            Enter => return state.list_state.selected().map(Selection::Index),
+            Esc | Char('q') => return Some(Selection::Cancelled),
+            _ => {}
+        }
+        None
+    }
+}
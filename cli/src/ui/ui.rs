@@ -1,6 +1,6 @@
 use ratatui::{
     Frame,
-    layout::{Constraint, Direction, Layout},
+    layout::{Constraint, Direction, Layout, Rect},
 };
 use tui_term::widget::PseudoTerminal;
 
@@ -8,42 +8,141 @@ use crate::{
     app::{AppMode, AppState},
     prelude::*,
     ui::{
-        basic_selector_widget::BasicSelectorWidget, fuzzy_selector_widget::FuzzySelectorWidget,
-        status_line_widget::StatusLineWidget,
+        basic_selector_widget::BasicSelectorWidget, client_cursor_widget::ClientCursorWidget,
+        confirm_widget::ConfirmWidget, filler_widget::FillerWidget, fuzzy_selector_widget::FuzzySelectorWidget,
+        hint_widget::HintWidget, notification_widget::NotificationWidget, pane_preview_widget::PanePreviewWidget,
+        prompt_widget::PromptWidget, reconnecting_widget::ReconnectingWidget, status_line_widget::StatusLineWidget,
+        tree_widget::TreeWidget, window_bar_widget::WindowBarWidget,
     },
 };
 
 #[instrument(skip(f))]
 pub fn draw(f: &mut Frame, state: &mut AppState) {
+    let status_height = if state.ui.status_visible { 1 } else { 0 };
+    // kept below the panes so mouse rows line up with the daemon's
+    let window_bar_height = if state.ui.status_visible && state.ui.status_line.window_bar.enabled {
+        1
+    } else {
+        0
+    };
     let chunks = Layout::default()
         .direction(Direction::Vertical)
         .constraints([
-            Constraint::Min(1),    // pseudo terminal takes everything else
-            Constraint::Length(1), // bottom status bar
+            Constraint::Min(1),                    // pseudo terminal takes everything else
+            Constraint::Length(window_bar_height), // window list
+            Constraint::Length(status_height),     // bottom status bar
         ])
         .split(f.area());
 
     // render the normal terminal output
     let term_area = chunks[0];
-    state.terminal.size = (term_area.height, term_area.width);
+    let size = (term_area.height, term_area.width);
+    if size != state.terminal.size {
+        // the status line was shown or hidden
+        state.terminal.needs_resize = true;
+    }
+    state.terminal.size = size;
     trace!("rendering terminal into rect: {term_area}");
     let term_ui = PseudoTerminal::new(state.terminal.emulator.screen());
     f.render_widget(term_ui, term_area);
+    if let Some((rows, cols)) = state.terminal.window_size
+        && (rows < term_area.height || cols < term_area.width)
+    {
+        f.render_widget(FillerWidget::new(rows, cols), term_area);
+    }
+    // the daemon only sends pointers with `client-cursors` on
+    if !state.ui.screen_reader {
+        for (i, client) in state.daemon.clients.iter().enumerate() {
+            if let (false, Some((row, col))) = (client.current, client.pointer) {
+                f.render_widget(ClientCursorWidget::new(&client.name, row, col, i), term_area);
+            }
+        }
+    }
+
+    if let (AppMode::QuickOpen, Some(quick_open)) = (&state.mode, &state.ui.quick_open) {
+        for (label, link) in quick_open.visible_hints() {
+            f.render_widget(HintWidget::new(label, link.row, link.col), term_area);
+        }
+    }
+    if let (AppMode::PaneJump, Some(pane_jump)) = (&state.mode, &state.ui.pane_jump) {
+        for (label, pane) in pane_jump.visible_hints() {
+            let row = pane.row + pane.height / 2;
+            let col = pane.col + (pane.width / 2).saturating_sub(label.len() as u16 / 2);
+            f.render_widget(HintWidget::new(label, row, col), term_area);
+        }
+    }
 
-    // render the status bar
-    let status_line = StatusLineWidget::new(state.ui.status_line.clone());
-    f.render_widget(status_line, chunks[1]);
+    let window_bar = WindowBarWidget::new(
+        &state.daemon.windows,
+        state.daemon.active_window,
+        &state.ui.status_line.window_bar,
+    );
+    f.render_widget(window_bar, chunks[1]);
 
-    if let AppMode::SelectingSession = state.mode {
+    // render the status bar - notifications temporarily take its place
+    if state.ui.notification.as_ref().is_some_and(|n| n.is_expired()) {
+        state.ui.notification = None;
+    }
+    // without a status line prompts and notifications cover the bottom row of the panes instead
+    let bottom_row = if state.ui.status_visible {
+        chunks[2]
+    } else {
+        Rect::new(term_area.x, term_area.bottom().saturating_sub(1), term_area.width, 1)
+    };
+    if let Some(prompt) = &state.ui.prompt {
+        f.render_widget(PromptWidget::new(prompt), bottom_row);
+    } else if let Some(notification) = &state.ui.notification {
+        f.render_widget(NotificationWidget::new(notification.clone()), bottom_row);
+    } else if state.ui.status_visible {
+        let status_line = StatusLineWidget::new(state.ui.status_line.clone());
+        f.render_widget(status_line, chunks[2]);
+    }
+
+    if let AppMode::SelectingSession | AppMode::ChoosingBuffer | AppMode::ChoosingSearchMatch = state.mode {
+        // the session switcher moves over to the left half, the highlighted session's pane takes the right
+        let mut selector_area = f.area();
+        if let (AppMode::SelectingSession, Some((session, preview))) = (&state.mode, &state.ui.session_preview) {
+            let halves = Layout::default()
+                .direction(Direction::Horizontal)
+                .constraints([Constraint::Percentage(50), Constraint::Percentage(50)])
+                .split(f.area());
+            selector_area = halves[0];
+            let panel = halves[1];
+            let height = (panel.height * 3 / 4).max(panel.height.min(5));
+            let panel = Rect::new(
+                panel.x,
+                panel.y + (panel.height - height) / 2,
+                panel.width.saturating_sub(1),
+                height,
+            );
+            f.render_widget(PanePreviewWidget::new(session, preview, state.ui.screen_reader), panel);
+        }
         match state.ui.selector.selector_type {
             crate::app::SelectorType::Basic => {
-                let popup = BasicSelectorWidget::default();
-                f.render_stateful_widget(popup, f.area(), &mut state.ui.selector);
+                let popup = BasicSelectorWidget::default().ascii_borders(state.ui.screen_reader);
+                f.render_stateful_widget(popup, selector_area, &mut state.ui.selector);
             }
             crate::app::SelectorType::Fuzzy => {
-                let popup = FuzzySelectorWidget::default();
-                f.render_stateful_widget(popup, f.area(), &mut state.ui.selector);
+                let popup = FuzzySelectorWidget::default().ascii_borders(state.ui.screen_reader);
+                f.render_stateful_widget(popup, selector_area, &mut state.ui.selector);
             }
         }
     }
+
+    if let (AppMode::Confirming, Some(confirm)) = (&state.mode, &mut state.ui.confirm) {
+        let popup = ConfirmWidget::default().ascii_borders(state.ui.screen_reader);
+        f.render_stateful_widget(popup, f.area(), confirm);
+    }
+
+    if let (AppMode::ChoosingTree, Some(tree)) = (&state.mode, &mut state.ui.tree) {
+        let popup = TreeWidget::default().ascii_borders(state.ui.screen_reader);
+        f.render_stateful_widget(popup, f.area(), tree);
+    }
+
+    if let Some(reconnecting) = &state.ui.reconnecting {
+        f.render_widget(
+            ReconnectingWidget::new(reconnecting.attempt, state.ui.screen_reader),
+            term_area,
+        );
+    }
 }
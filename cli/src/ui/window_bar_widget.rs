@@ -0,0 +1,49 @@
+use ratatui::{
+    style::{Color, Modifier, Style},
+    text::{Line, Span},
+    widgets::{Paragraph, Widget},
+};
+use remux_core::states::WindowInfo;
+
+use crate::states::status_line_state::WindowBarState;
+
+/// the active session's windows in a row like tmux's window list, the current one highlighted and any
+/// that rang the bell in red
+pub struct WindowBarWidget<'a> {
+    windows: &'a [WindowInfo],
+    active: Option<usize>,
+    state: &'a WindowBarState,
+}
+
+impl<'a> WindowBarWidget<'a> {
+    pub fn new(windows: &'a [WindowInfo], active: Option<usize>, state: &'a WindowBarState) -> Self {
+        Self { windows, active, state }
+    }
+}
+
+impl Widget for WindowBarWidget<'_> {
+    fn render(self, area: ratatui::prelude::Rect, buf: &mut ratatui::prelude::Buffer) {
+        if area.height < 1 {
+            return;
+        }
+
+        let mut spans = Vec::new();
+        for window in self.windows {
+            if !spans.is_empty() {
+                spans.push(Span::raw(self.state.separator.as_str()));
+            }
+            let current = Some(window.index) == self.active;
+            let mut style = if current {
+                Style::default().add_modifier(Modifier::REVERSED)
+            } else {
+                Style::default()
+            };
+            // rang while in the background, stays highlighted until it's selected
+            if window.bell {
+                style = style.fg(Color::Red).add_modifier(Modifier::BOLD);
+            }
+            spans.push(Span::styled(self.state.format(window, current), style));
+        }
+        Paragraph::new(Line::from(spans)).render(area, buf);
+    }
+}
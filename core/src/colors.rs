@@ -0,0 +1,285 @@
+// downgrading SGR colors for clients whose terminal can't show as many colors as the pane's program uses
+
+use serde::{Deserialize, Serialize};
+
+const CSI: &[u8] = b"\x1b[";
+const MAX_PENDING_PARAMS: usize = 64;
+const CUBE_LEVELS: [u8; 6] = [0, 95, 135, 175, 215, 255];
+// xterm's default palette for the 16 basic colors
+const BASIC_COLORS: [(u8, u8, u8); 16] = [
+    (0, 0, 0),
+    (205, 0, 0),
+    (0, 205, 0),
+    (205, 205, 0),
+    (0, 0, 238),
+    (205, 0, 205),
+    (0, 205, 205),
+    (229, 229, 229),
+    (127, 127, 127),
+    (255, 0, 0),
+    (0, 255, 0),
+    (255, 255, 0),
+    (92, 92, 255),
+    (255, 0, 255),
+    (0, 255, 255),
+    (255, 255, 255),
+];
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum ColorDepth {
+    #[default]
+    TrueColor,
+    Colors256,
+    Colors16,
+}
+
+impl ColorDepth {
+    /// what a terminal supports going by its `TERM` and `COLORTERM`
+    pub fn detect(term: Option<&str>, colorterm: Option<&str>) -> Self {
+        if matches!(colorterm, Some("truecolor" | "24bit")) {
+            return Self::TrueColor;
+        }
+        match term {
+            Some(term) if term.contains("direct") => Self::TrueColor,
+            Some(term) if term.contains("256color") => Self::Colors256,
+            _ => Self::Colors16,
+        }
+    }
+}
+
+/// rewrites the colors in SGR sequences to fit `depth`, sequences split across writes are held back
+#[derive(Debug, Default)]
+pub struct ColorDowngrade {
+    depth: ColorDepth,
+    pending: Vec<u8>,
+}
+
+impl ColorDowngrade {
+    pub fn new(depth: ColorDepth) -> Self {
+        Self {
+            depth,
+            pending: Vec::new(),
+        }
+    }
+
+    pub fn apply(&mut self, bytes: &[u8]) -> Vec<u8> {
+        if self.depth == ColorDepth::TrueColor {
+            return bytes.to_vec();
+        }
+        let mut data = std::mem::take(&mut self.pending);
+        data.extend_from_slice(bytes);
+
+        let mut output = Vec::with_capacity(data.len());
+        let mut rest = data.as_slice();
+        while let Some(start) = rest.iter().position(|&b| b == 0x1b) {
+            output.extend_from_slice(&rest[..start]);
+            rest = &rest[start..];
+            if rest.len() < CSI.len() {
+                self.pending = rest.to_vec();
+                return output;
+            }
+            let Some(params) = rest.strip_prefix(CSI) else {
+                output.push(0x1b);
+                rest = &rest[1..];
+                continue;
+            };
+            match params
+                .iter()
+                .position(|&b| !(b.is_ascii_digit() || b == b';' || b == b':'))
+            {
+                Some(end) if params[end] == b'm' => {
+                    output.extend_from_slice(CSI);
+                    output.extend_from_slice(self.downgrade_sgr(&params[..end]).as_bytes());
+                    output.push(b'm');
+                    rest = &params[end + 1..];
+                }
+                Some(end) => {
+                    output.extend_from_slice(&rest[..CSI.len() + end + 1]);
+                    rest = &params[end + 1..];
+                }
+                None if params.len() < MAX_PENDING_PARAMS => {
+                    self.pending = rest.to_vec();
+                    return output;
+                }
+                None => {
+                    output.extend_from_slice(rest);
+                    rest = &[];
+                }
+            }
+        }
+        output.extend_from_slice(rest);
+        output
+    }
+
+    fn downgrade_sgr(&self, params: &[u8]) -> String {
+        let params = String::from_utf8_lossy(params);
+        let mut fields = params.split(';').peekable();
+        let mut output: Vec<String> = Vec::new();
+        while let Some(field) = fields.next() {
+            // `38;2;r;g;b` and `38;5;n`, or the same joined with colons (`38:2::r:g:b`)
+            let (kind, color) = if field.contains(':') {
+                let mut parts = field.split(':');
+                let kind = parts.next().unwrap_or_default();
+                let color = match parts.next() {
+                    Some("2") => {
+                        let rgb: Vec<&str> = parts.collect();
+                        // the color space id before r:g:b is optional
+                        Color::rgb(&rgb[rgb.len().saturating_sub(3)..])
+                    }
+                    Some("5") => parts.next().and_then(|n| n.parse().ok()).map(Color::Indexed),
+                    _ => None,
+                };
+                (kind, color)
+            } else if matches!(field, "38" | "48" | "58") {
+                let color = match fields.peek().copied() {
+                    Some("2") => {
+                        fields.next();
+                        let rgb: Vec<&str> = (0..3).filter_map(|_| fields.next()).collect();
+                        Color::rgb(&rgb)
+                    }
+                    Some("5") => {
+                        fields.next();
+                        fields.next().and_then(|n| n.parse().ok()).map(Color::Indexed)
+                    }
+                    _ => None,
+                };
+                (field, color)
+            } else {
+                output.push(field.to_owned());
+                continue;
+            };
+            match color {
+                Some(color) => output.extend(self.downgrade_color(kind, color)),
+                None => output.push(field.to_owned()),
+            }
+        }
+        output.join(";")
+    }
+
+    /// the params for a color at this depth, underline colors have no 16 color form so they're dropped
+    fn downgrade_color(&self, kind: &str, color: Color) -> Option<String> {
+        match (self.depth, color) {
+            (ColorDepth::TrueColor, Color::Rgb(r, g, b)) => Some(format!("{kind};2;{r};{g};{b}")),
+            (ColorDepth::TrueColor | ColorDepth::Colors256, Color::Indexed(n)) => Some(format!("{kind};5;{n}")),
+            (ColorDepth::Colors256, Color::Rgb(r, g, b)) => Some(format!("{kind};5;{}", nearest_256(r, g, b))),
+            (ColorDepth::Colors16, color) => {
+                let basic = match color {
+                    Color::Indexed(n) if n < 16 => n,
+                    Color::Indexed(n) => {
+                        let (r, g, b) = indexed_rgb(n);
+                        nearest_basic(r, g, b)
+                    }
+                    Color::Rgb(r, g, b) => nearest_basic(r, g, b),
+                };
+                let base = match kind {
+                    "38" => 30,
+                    "48" => 40,
+                    _ => return None,
+                };
+                let bright = if basic >= 8 { 60 } else { 0 };
+                Some((base + bright + basic % 8).to_string())
+            }
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+enum Color {
+    Rgb(u8, u8, u8),
+    Indexed(u8),
+}
+
+impl Color {
+    fn rgb(fields: &[&str]) -> Option<Self> {
+        match fields {
+            [r, g, b] => Some(Self::Rgb(r.parse().ok()?, g.parse().ok()?, b.parse().ok()?)),
+            _ => None,
+        }
+    }
+}
+
+fn distance((r1, g1, b1): (u8, u8, u8), (r2, g2, b2): (u8, u8, u8)) -> u32 {
+    let d = |a: u8, b: u8| (i32::from(a) - i32::from(b)).pow(2) as u32;
+    d(r1, r2) + d(g1, g2) + d(b1, b2)
+}
+
+/// the closest of the 6x6x6 cube and the grayscale ramp, the first 16 depend on the terminal's palette
+fn nearest_256(r: u8, g: u8, b: u8) -> u8 {
+    let level = |c: u8| {
+        (0..CUBE_LEVELS.len())
+            .min_by_key(|&i| CUBE_LEVELS[i].abs_diff(c))
+            .unwrap_or_default() as u8
+    };
+    let (ri, gi, bi) = (level(r), level(g), level(b));
+    let cube = 16 + 36 * ri + 6 * gi + bi;
+
+    let average = (u16::from(r) + u16::from(g) + u16::from(b)) / 3;
+    let gray_step = (average.saturating_sub(3) / 10).min(23) as u8;
+    let gray = 232 + gray_step;
+
+    if distance((r, g, b), indexed_rgb(gray)) < distance((r, g, b), indexed_rgb(cube)) {
+        gray
+    } else {
+        cube
+    }
+}
+
+fn nearest_basic(r: u8, g: u8, b: u8) -> u8 {
+    (0..BASIC_COLORS.len())
+        .min_by_key(|&i| distance((r, g, b), BASIC_COLORS[i]))
+        .unwrap_or_default() as u8
+}
+
+fn indexed_rgb(n: u8) -> (u8, u8, u8) {
+    match n {
+        0..16 => BASIC_COLORS[n as usize],
+        16..232 => {
+            let n = n - 16;
+            (
+                CUBE_LEVELS[(n / 36) as usize],
+                CUBE_LEVELS[(n / 6 % 6) as usize],
+                CUBE_LEVELS[(n % 6) as usize],
+            )
+        }
+        _ => {
+            let level = 8 + 10 * (n - 232);
+            (level, level, level)
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_downgrade_256() {
+        let mut downgrade = ColorDowngrade::new(ColorDepth::Colors256);
+        assert_eq!(
+            downgrade.apply(b"\x1b[1;38;2;255;0;0mred\x1b[48:2::0:0:0m"),
+            b"\x1b[1;38;5;196mred\x1b[48;5;16m"
+        );
+        // grays land on the grayscale ramp
+        assert_eq!(downgrade.apply(b"\x1b[38;2;128;128;128m"), b"\x1b[38;5;244m");
+        // split across writes, other sequences pass through
+        assert_eq!(downgrade.apply(b"\x1b[2J\x1b[38;2;0;0"), b"\x1b[2J");
+        assert_eq!(downgrade.apply(b";255mx"), b"\x1b[38;5;21mx");
+    }
+
+    #[test]
+    fn test_downgrade_16() {
+        let mut downgrade = ColorDowngrade::new(ColorDepth::Colors16);
+        assert_eq!(downgrade.apply(b"\x1b[38;2;250;10;10;48;5;21;58;5;1m"), b"\x1b[91;44m");
+        assert_eq!(downgrade.apply(b"\x1b[38;5;8;4m"), b"\x1b[90;4m");
+    }
+
+    #[test]
+    fn test_detect() {
+        assert_eq!(
+            ColorDepth::detect(Some("xterm-256color"), Some("truecolor")),
+            ColorDepth::TrueColor
+        );
+        assert_eq!(ColorDepth::detect(Some("screen-256color"), None), ColorDepth::Colors256);
+        assert_eq!(ColorDepth::detect(Some("linux"), None), ColorDepth::Colors16);
+    }
+}
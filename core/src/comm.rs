@@ -1,60 +1,160 @@
+use bytes::{Buf, Bytes, BytesMut};
 use serde::{Deserialize, Serialize, de::DeserializeOwned};
 use tokio::{
-    io::{AsyncReadExt, AsyncWriteExt},
+    io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt},
     net::UnixStream,
 };
 
 use crate::{
     error::ResponseError,
     events::{CliEvent, DaemonEvent},
-    messages::{CliRequestMessage, Message, RequestBody, ResponseMessage, ResponseResult},
+    messages::{
+        CliRequestMessage, Message, RequestBody, ResponseMessage, ResponseResult, VERSION, protocol::is_compatible,
+        request::DaemonRequestMessage,
+    },
     prelude::*,
 };
 
-pub async fn send_event<E: Serialize>(stream: &mut UnixStream, event: E) -> Result<()> {
-    let bytes = serde_json::to_vec(&event)?;
-    let num_bytes = bytes.len() as u32;
-    let _written = stream.write(&num_bytes.to_be_bytes()).await?;
-    let _written = stream.write(&bytes).await?;
+/// leads every frame, bumped whenever the framing or an encoding changes so a client and daemon
+/// from different builds refuse each other instead of misreading frames
+pub const PROTOCOL_VERSION: u8 = 3;
+
+// the payload is a serialized event or message
+const FRAME_ENCODED: u8 = 0;
+// the payload is the bytes of a `Raw` event as they are, terminal output and keypresses skip encoding
+const FRAME_RAW: u8 = 1;
+
+// a frame is the version byte, the frame kind, the payload length as a big endian u32, then the payload
+// the header and payload go out in one vectored write so the payload is never copied into a buffer
+async fn write_frame(stream: &mut (impl AsyncWrite + Unpin), kind: u8, payload: impl Buf) -> Result<()> {
+    let mut header = [PROTOCOL_VERSION, kind, 0, 0, 0, 0];
+    header[2..].copy_from_slice(&(payload.remaining() as u32).to_be_bytes());
+    stream
+        .write_all_buf(&mut Buf::chain(header.as_slice(), payload))
+        .await?;
     Ok(())
 }
 
-pub async fn recv_cli_event(stream: &mut UnixStream) -> Result<CliEvent> {
-    recv_event(stream).await
+async fn read_frame(stream: &mut (impl AsyncRead + Unpin)) -> Result<(u8, Bytes)> {
+    let mut header = [0u8; 6];
+    stream.read_exact(&mut header).await?;
+    // frames from before the version byte start with the high byte of their length, always 0
+    if header[0] != PROTOCOL_VERSION {
+        return Err(Error::ProtocolVersion {
+            expected: PROTOCOL_VERSION,
+            actual: header[0],
+        });
+    }
+    let num_bytes = u32::from_be_bytes([header[2], header[3], header[4], header[5]]);
+    let mut payload = BytesMut::zeroed(num_bytes as usize);
+    stream.read_exact(&mut payload).await?;
+    Ok((header[1], payload.freeze()))
 }
 
-pub async fn recv_daemon_event(stream: &mut UnixStream) -> Result<DaemonEvent> {
-    recv_event(stream).await
+async fn read_encoded_frame(stream: &mut (impl AsyncRead + Unpin)) -> Result<Bytes> {
+    match read_frame(stream).await? {
+        (FRAME_ENCODED, payload) => Ok(payload),
+        (kind, _) => Err(Error::FrameKind(kind)),
+    }
 }
 
-async fn recv_event<E: DeserializeOwned>(stream: &mut UnixStream) -> Result<E> {
-    let mut num_bytes = [0u8; 4];
-    stream.read_exact(&mut num_bytes).await?;
-    let num_bytes = u32::from_be_bytes(num_bytes);
+/// an event sent between a client and the daemon, its `Raw` variant goes out in a raw frame
+pub trait Event: Serialize + DeserializeOwned {
+    fn into_raw(self) -> std::result::Result<Bytes, Self>;
+    fn from_raw(bytes: Bytes) -> Self;
+}
 
-    let mut message_bytes = vec![0u8; num_bytes as usize];
-    stream.read_exact(&mut message_bytes).await?;
+impl Event for CliEvent {
+    fn into_raw(self) -> std::result::Result<Bytes, Self> {
+        match self {
+            CliEvent::Raw(bytes) => Ok(bytes),
+            event => Err(event),
+        }
+    }
 
-    Ok(serde_json::from_slice(&message_bytes)?)
+    fn from_raw(bytes: Bytes) -> Self {
+        CliEvent::Raw(bytes)
+    }
 }
 
+impl Event for DaemonEvent {
+    fn into_raw(self) -> std::result::Result<Bytes, Self> {
+        match self {
+            DaemonEvent::Raw(bytes) => Ok(bytes),
+            event => Err(event),
+        }
+    }
+
+    fn from_raw(bytes: Bytes) -> Self {
+        DaemonEvent::Raw(bytes)
+    }
+}
+
+// events are generic over the stream so either half of a split connection works
+// they carry every keystroke and output chunk so they're encoded with bincode rather than json
+pub async fn send_event<E: Event>(stream: &mut (impl AsyncWrite + Unpin), event: E) -> Result<()> {
+    match event.into_raw() {
+        Ok(bytes) => write_frame(stream, FRAME_RAW, bytes).await,
+        Err(event) => {
+            let bytes = bincode::serde::encode_to_vec(&event, bincode::config::standard())?;
+            write_frame(stream, FRAME_ENCODED, bytes.as_slice()).await
+        }
+    }
+}
+
+pub async fn recv_cli_event(stream: &mut (impl AsyncRead + Unpin)) -> Result<CliEvent> {
+    recv_event(stream).await
+}
+
+pub async fn recv_daemon_event(stream: &mut (impl AsyncRead + Unpin)) -> Result<DaemonEvent> {
+    recv_event(stream).await
+}
+
+async fn recv_event<E: Event>(stream: &mut (impl AsyncRead + Unpin)) -> Result<E> {
+    match read_frame(stream).await? {
+        (FRAME_RAW, payload) => Ok(E::from_raw(payload)),
+        (FRAME_ENCODED, payload) => {
+            let (event, _) = bincode::serde::decode_from_slice(&payload, bincode::config::standard())?;
+            Ok(event)
+        }
+        (kind, _) => Err(Error::FrameKind(kind)),
+    }
+}
+
+// messages stay json, the request body is untagged which only a self describing format can decode
 pub async fn send_message(stream: &mut UnixStream, message: &impl Message) -> Result<()> {
     let bytes = serde_json::to_vec(message)?;
-    let num_bytes = bytes.len() as u32;
-
-    let _written = stream.write(&num_bytes.to_be_bytes()).await?;
-    let _written = stream.write(&bytes).await?;
-    Ok(())
+    write_frame(stream, FRAME_ENCODED, bytes.as_slice()).await
 }
 
 pub async fn read_message<M: Message>(stream: &mut UnixStream) -> Result<M> {
-    let mut num_bytes = [0u8; 4];
-    stream.read_exact(&mut num_bytes).await?;
-    let num_bytes = u32::from_be_bytes(num_bytes);
-    let mut message_bytes = vec![0u8; num_bytes as usize];
-    stream.read_exact(&mut message_bytes).await?;
-    let res = serde_json::from_slice(&message_bytes)?;
-    Ok(res)
+    let payload = read_encoded_frame(stream).await?;
+    Ok(serde_json::from_slice(&payload)?)
+}
+
+/// the part of a message every build agrees on, looked at before the rest is decoded
+#[derive(Deserialize)]
+struct Envelope {
+    #[serde(default)]
+    version: String,
+}
+
+fn peer_version(payload: &[u8]) -> Result<String> {
+    let envelope: Envelope = serde_json::from_slice(payload)?;
+    Ok(envelope.version)
+}
+
+/// read by the daemon, a client from an incompatible build is turned away before its request is decoded
+pub async fn read_request(stream: &mut UnixStream) -> Result<DaemonRequestMessage> {
+    let payload = read_encoded_frame(stream).await?;
+    let client = peer_version(&payload)?;
+    if !is_compatible(VERSION, &client) {
+        return Err(Error::IncompatibleVersion {
+            daemon: VERSION.to_owned(),
+            client,
+        });
+    }
+    Ok(serde_json::from_slice(&payload)?)
 }
 
 pub async fn send_and_recv_message<B>(stream: &mut UnixStream, req: &CliRequestMessage<B>) -> Result<B::ResponseBody>
@@ -63,7 +163,15 @@ where
 {
     // let req_id = req.id;
     send_message(stream, req).await?;
-    let res: ResponseMessage<B::ResponseBody> = read_message(stream).await?;
+    let payload = read_encoded_frame(stream).await?;
+    let daemon = peer_version(&payload)?;
+    if !is_compatible(VERSION, &daemon) {
+        return Err(Error::IncompatibleVersion {
+            daemon,
+            client: VERSION.to_owned(),
+        });
+    }
+    let res: ResponseMessage<B::ResponseBody> = serde_json::from_slice(&payload)?;
     // let res_id = res.id;
     // if req_id != res_id {
     //     return Err(Error::Response(ResponseError::UnexpectedId { expected: req_id, actual: res_id }));
@@ -85,9 +193,10 @@ mod test {
     use super::*;
     use crate::{
         constants::TEMP_SOCK_DIR,
+        events::StateDelta,
         messages::{
-            RequestBuilder, ResponseBuilder,
-            request::{self, DaemonRequestMessage, DaemonRequestMessageBody},
+            Features, RequestBuilder, ResponseBuilder,
+            request::{self, DaemonRequestMessageBody},
             response,
         },
         states::DaemonState,
@@ -108,15 +217,24 @@ mod test {
             id: Uuid::new_v4(),
             session_name: "session".to_owned(),
             create: true,
+            color_depth: Default::default(),
+            environment: Default::default(),
+            command: None,
+            argv: vec![],
+            control: false,
+            detach_others: false,
         };
         let cli_req = RequestBuilder::default().body(attach.clone()).build();
         let daemon_req = DaemonRequestMessage {
             id: cli_req.id,
+            version: VERSION.to_owned(),
+            features: Features::SUPPORTED,
             body: DaemonRequestMessageBody::Attach(attach),
         };
 
         let attach_response = response::Attach {
             initial_daemon_state: DaemonState::default(),
+            features: Features::SUPPORTED,
         };
         let res = ResponseBuilder::default()
             .result(ResponseResult::Success(attach_response.clone()))
@@ -127,7 +245,7 @@ mod test {
             let res = res.clone();
             async move {
                 let (mut socket, _) = listener.accept().await?;
-                let msg1 = read_message::<DaemonRequestMessage>(&mut socket).await.unwrap();
+                let msg1 = read_request(&mut socket).await.unwrap();
                 assert_eq!(msg1, daemon_req);
                 send_message(&mut socket, &res).await.unwrap();
                 Ok(())
@@ -141,4 +259,117 @@ mod test {
         server.await.unwrap()?;
         Ok(())
     }
+
+    #[tokio::test]
+    async fn test_incompatible_version() -> Result<()> {
+        let (mut client, mut daemon) = UnixStream::pair()?;
+        let kill = || {
+            RequestBuilder::default()
+                .body(request::KillSession {
+                    name_or_id: "main".to_owned(),
+                })
+                .build()
+        };
+
+        // a client from before versioning is turned away by the daemon
+        let mut old_request = kill();
+        old_request.version = String::new();
+        send_message(&mut client, &old_request).await?;
+        assert!(matches!(
+            read_request(&mut daemon).await,
+            Err(Error::IncompatibleVersion { client, .. }) if client.is_empty()
+        ));
+
+        // and a daemon from another release by the client
+        let server = tokio::spawn(async move {
+            read_request(&mut daemon).await.unwrap();
+            let mut res = ResponseBuilder::default().result(ResponseResult::Success(())).build();
+            res.version = "0.0.1".to_owned();
+            send_message(&mut daemon, &res).await.unwrap();
+        });
+        let err = send_and_recv_message(&mut client, &kill()).await.unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            format!("daemon is v0.0.1, client is v{VERSION} - restart the daemon so both run the same version")
+        );
+        server.await.unwrap();
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_event_round_trip() -> Result<()> {
+        let (mut client, mut daemon) = tokio::io::duplex(1024);
+        send_event(&mut client, CliEvent::Raw(vec![b'l', b's', b'\r'].into())).await?;
+        send_event(&mut client, CliEvent::SelectPane(3)).await?;
+        assert!(matches!(recv_cli_event(&mut daemon).await?, CliEvent::Raw(bytes) if bytes[..] == b"ls\r"[..]));
+        assert!(matches!(recv_cli_event(&mut daemon).await?, CliEvent::SelectPane(3)));
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_raw_frame() -> Result<()> {
+        let (mut daemon, mut client) = tokio::io::duplex(1024);
+        send_event(&mut daemon, DaemonEvent::Raw(Bytes::from_static(b"\x1b[Hhi"))).await?;
+        let mut frame = [0u8; 11];
+        client.read_exact(&mut frame).await?;
+        assert_eq!(
+            frame,
+            [PROTOCOL_VERSION, FRAME_RAW, 0, 0, 0, 5, 0x1b, b'[', b'H', b'h', b'i']
+        );
+
+        send_event(&mut daemon, DaemonEvent::Raw(Bytes::from_static(b"\r\n"))).await?;
+        send_event(&mut daemon, DaemonEvent::Raw(Bytes::new())).await?;
+        send_event(&mut daemon, DaemonEvent::Bell { session: 1, window: 0 }).await?;
+        assert!(matches!(recv_daemon_event(&mut client).await?, DaemonEvent::Raw(bytes) if bytes[..] == b"\r\n"[..]));
+        assert!(matches!(recv_daemon_event(&mut client).await?, DaemonEvent::Raw(bytes) if bytes.is_empty()));
+        assert!(matches!(
+            recv_daemon_event(&mut client).await?,
+            DaemonEvent::Bell { session: 1, window: 0 }
+        ));
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_pane_output_encoding() -> Result<()> {
+        let (mut daemon, mut client) = tokio::io::duplex(1024);
+        send_event(&mut daemon, DaemonEvent::PaneOutput(2, b"ls\r\n".to_vec().into())).await?;
+        assert!(
+            matches!(recv_daemon_event(&mut client).await?, DaemonEvent::PaneOutput(2, bytes) if bytes[..] == b"ls\r\n"[..])
+        );
+        // control mode clients see it as base64
+        let json = serde_json::to_string(&DaemonEvent::PaneOutput(2, b"ls\r\n".to_vec().into()))?;
+        assert_eq!(json, r#"{"PaneOutput":[2,"bHMNCg=="]}"#);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_state_delta_encoding() -> Result<()> {
+        let (mut daemon, mut client) = tokio::io::duplex(1024);
+        let delta = StateDelta::SessionRenamed {
+            id: 1,
+            name: "work".to_owned(),
+        };
+        send_event(&mut daemon, DaemonEvent::StateDelta(delta.clone())).await?;
+        assert!(matches!(recv_daemon_event(&mut client).await?, DaemonEvent::StateDelta(d) if d == delta));
+        let json = serde_json::to_string(&delta)?;
+        assert_eq!(json, r#"{"SessionRenamed":{"id":1,"name":"work"}}"#);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_protocol_version_mismatch() -> Result<()> {
+        let (mut client, mut daemon) = tokio::io::duplex(1024);
+        // an unversioned json frame as older builds sent it
+        let json = serde_json::to_vec(&CliEvent::KillPane)?;
+        client.write_all(&(json.len() as u32).to_be_bytes()).await?;
+        client.write_all(&json).await?;
+        assert!(matches!(
+            recv_cli_event(&mut daemon).await,
+            Err(Error::ProtocolVersion {
+                expected: PROTOCOL_VERSION,
+                actual: 0
+            })
+        ));
+        Ok(())
+    }
 }
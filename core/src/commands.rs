@@ -0,0 +1,1292 @@
+/// textual remux commands - shared by the config file, hooks and anything else that drives the daemon with text
+use std::{fmt::Display, str::FromStr};
+
+use serde::{Deserialize, Serialize};
+
+use crate::{key_bindings::KeyAction, prelude::*};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum HookEvent {
+    SessionCreated,
+    SessionClosed,
+    SessionRenamed,
+    ClientAttached,
+    ClientDetached,
+    PaneDied,
+    WindowChanged, // the session's active window changed
+    WindowRenamed,
+    AlertActivity,
+    AlertSilence,
+}
+
+impl HookEvent {
+    const ALL: [HookEvent; 10] = [
+        HookEvent::SessionCreated,
+        HookEvent::SessionClosed,
+        HookEvent::SessionRenamed,
+        HookEvent::ClientAttached,
+        HookEvent::ClientDetached,
+        HookEvent::PaneDied,
+        HookEvent::WindowChanged,
+        HookEvent::WindowRenamed,
+        HookEvent::AlertActivity,
+        HookEvent::AlertSilence,
+    ];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            HookEvent::SessionCreated => "session-created",
+            HookEvent::SessionClosed => "session-closed",
+            HookEvent::SessionRenamed => "session-renamed",
+            HookEvent::ClientAttached => "client-attached",
+            HookEvent::ClientDetached => "client-detached",
+            HookEvent::PaneDied => "pane-died",
+            HookEvent::WindowChanged => "window-changed",
+            HookEvent::WindowRenamed => "window-renamed",
+            HookEvent::AlertActivity => "alert-activity",
+            HookEvent::AlertSilence => "alert-silence",
+        }
+    }
+}
+
+impl Display for HookEvent {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+impl FromStr for HookEvent {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        HookEvent::ALL
+            .into_iter()
+            .find(|hook| hook.name() == s)
+            .ok_or_else(|| Error::CommandParse(format!("unknown hook: {s}")))
+    }
+}
+
+/// which way `resize-pane` moves the active pane's border
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum PaneDirection {
+    Left,
+    Right,
+    Up,
+    Down,
+}
+
+/// which neighbour in layout order `swap-pane` exchanges the active pane with
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum SwapWith {
+    Prev, // `-U`
+    Next, // `-D`
+}
+
+/// the arrangements `select-layout` rebuilds a window's panes into, in layout order
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum LayoutPreset {
+    EvenHorizontal, // side by side
+    EvenVertical,   // stacked
+    Tiled,          // rows of as many columns as it takes to keep the grid square
+}
+
+impl FromStr for LayoutPreset {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "even-horizontal" => Ok(Self::EvenHorizontal),
+            "even-vertical" => Ok(Self::EvenVertical),
+            "tiled" => Ok(Self::Tiled),
+            _ => Err(Error::CommandParse(format!("select-layout: unknown layout: {s}"))),
+        }
+    }
+}
+
+/// where `switch-client` takes the client
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum SwitchTarget {
+    Session(String), // `-t`
+    Last,            // `-l`, the session the client was attached to before this one
+    Next,            // `-n`, in order of creation and wrapping around
+    Prev,            // `-p`
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub enum RemuxCommand {
+    /// `vertical` places the new pane beside the active one (tmux `-h`), otherwise below it (tmux `-v`),
+    /// `environment` (`-e NAME=value`, repeatable) is set for the new pane only, over the session's table
+    SplitWindow {
+        vertical: bool,
+        environment: Vec<(String, String)>,
+    },
+    /// focuses pane `target` (tmux `-t %<id>`) if given, otherwise the next/previous pane
+    SelectPane {
+        is_next: bool,
+        target: Option<usize>,
+    },
+    /// `select-pane -m` marks pane `target` (or the active one), marking it again clears the mark,
+    /// `-M` clears it wherever it is - there's one marked pane for the whole server
+    MarkPane {
+        target: Option<usize>,
+        clear: bool,
+    },
+    /// `select-pane -t {marked}` (or `~`) - switches to the marked pane's session and focuses it
+    SelectMarkedPane,
+    /// `select-pane -T` - titles pane `target` (or the active one) in the `pane-titles` header,
+    /// an empty title goes back to the program's own
+    SetPaneTitle {
+        target: Option<usize>,
+        title: String,
+    },
+    KillPane,
+    /// moves the border of the active pane's innermost split in that direction by `amount` cells
+    ResizePane {
+        direction: PaneDirection,
+        amount: u16,
+    },
+    /// `resize-pane -Z` - toggles the active pane filling the whole window
+    ZoomPane,
+    /// moves the active pane to its neighbour's spot and the neighbour to its spot, both keep running
+    SwapPane {
+        with: SwapWith,
+    },
+    /// moves every pane of the window one spot along, towards the end (`-D`) or the start (`-U`, the default)
+    RotateWindow {
+        downward: bool,
+    },
+    SelectLayout {
+        layout: LayoutPreset,
+    },
+    /// moves pane `source` (`-s`, the active pane by default) out of its window into a new one named `name`
+    /// (`-n`), which becomes the active window unless `detached` (`-d`) - the pane's program keeps running
+    BreakPane {
+        source: Option<usize>,
+        name: Option<String>,
+        detached: bool,
+    },
+    /// moves pane `source` (`-s`) next to the active pane of window `target` (`-t`, the active window by default),
+    /// `vertical` places it beside the pane (tmux `-h`), otherwise below it
+    JoinPane {
+        source: usize,
+        target: Option<usize>,
+        vertical: bool,
+    },
+    /// opens a window in the session and switches to it, it's named after the shell unless `name` (`-n`) is given
+    NewWindow {
+        name: Option<String>,
+    },
+    NextWindow,
+    PreviousWindow,
+    /// windows are indexed from 0 in the order they were created
+    SelectWindow {
+        index: usize,
+    },
+    RenameWindow {
+        name: String,
+    },
+    /// shows the active pane of the `source` session read-only in the `target` session's window
+    MirrorPane {
+        source: String,
+        target: Option<String>,
+        vertical: bool,
+    },
+    /// shows/hides the window's floating pane, sizes are percentages of the window
+    ToggleFloating {
+        width_percent: Option<u16>,
+        height_percent: Option<u16>,
+    },
+    /// the first pane runs `command` (`-c`) through the shell instead of starting the shell itself,
+    /// or with `group` (`-t`) the session shares the windows of the session it names
+    NewSession {
+        name: Option<String>,
+        command: Option<String>,
+        group: Option<String>,
+    },
+    /// `target` is a session name or id, defaulting to the current session
+    KillSession {
+        target: Option<String>,
+    },
+    /// `target` as for `KillSession`, names stay unique
+    RenameSession {
+        target: Option<String>,
+        name: String,
+    },
+    SwitchClient {
+        target: SwitchTarget,
+    },
+    /// `others` (`-a`) detaches every other client of the session instead
+    DetachClient {
+        others: bool,
+    },
+    SendPrefix,
+    SendKeys {
+        keys: Vec<String>,
+    },
+    RunShell {
+        command: String,
+    },
+    /// paste buffers are named `buffer0`, `buffer1`, ... unless `-b` names them and are indexed newest first,
+    /// `buffer` (`-b`) takes a name or an index and defaults to the newest buffer -
+    /// setting a named buffer replaces it
+    SetBuffer {
+        name: Option<String>,
+        data: String,
+    },
+    /// `bracketed` (`-p`) wraps the text in bracketed paste markers when the program asked for them
+    PasteBuffer {
+        buffer: Option<String>,
+        bracketed: bool,
+    },
+    DeleteBuffer {
+        buffer: Option<String>,
+    },
+    /// shows `text` in place of the client's status line for `duration` milliseconds (`-d`), the client's
+    /// default without it. every client of the session gets it when the command has no client
+    DisplayMessage {
+        text: String,
+        duration: Option<u64>,
+    },
+    /// shows the buffer's text to the client, or prints it for `remux show-buffer`
+    ShowBuffer {
+        buffer: Option<String>,
+    },
+    ChooseBuffer,
+    /// browses every session, window and pane in the client, starting with only the sessions (`-s`) or
+    /// with their windows showing (`-w`, the default)
+    ChooseTree {
+        sessions_only: bool,
+    },
+    /// writes `buffer` to a file, `-a` appends to it, relative paths are from the client's working directory
+    SaveBuffer {
+        buffer: Option<String>,
+        path: String,
+        append: bool,
+    },
+    /// reads a file into a new buffer
+    LoadBuffer {
+        path: String,
+    },
+    /// lists the urls/paths on the active pane's screen so the client can open or copy one
+    QuickOpen,
+    /// searches the scrollback of every pane in the session, matches are sent to the client to choose from
+    SearchPanes {
+        pattern: String,
+    },
+    /// `-s <regex>` searches the active pane's scrollback, matches are highlighted and `n`/`N` step through them
+    CopyMode {
+        search: Option<String>,
+    },
+    /// sends the client the pane locations of its window so it can overlay jump labels
+    DisplayPanes,
+    /// writes the active pane's output as timestamped text lines to a log file, `None` toggles
+    LogPane {
+        enabled: Option<bool>,
+    },
+    /// server wide option by default, `-p`/`-w` set one of the options panes can override for the active
+    /// pane or every pane in the window, `-g` is accepted for tmux compatibility
+    SetOption {
+        name: String,
+        value: String,
+        scope: OptionScope,
+    },
+    /// changes the environment new panes start with, `global` applies to every session,
+    /// otherwise `session_name` (defaulting to the client's session) whose entries win over global ones
+    SetEnvironment {
+        global: bool,
+        session_name: Option<String>,
+        name: String,
+        change: EnvironmentChange,
+    },
+    ShowEnvironment {
+        global: bool,
+        session_name: Option<String>,
+    },
+    /// types `export`/`unset` lines for the `update-environment` variables into the active pane,
+    /// for shells started before the client reattached (e.g. to pick up a new `SSH_AUTH_SOCK`)
+    RefreshEnvironment,
+    /// `session_name` restricts the hook to a single session, no `commands` unsets the hook
+    SetHook {
+        event: HookEvent,
+        session_name: Option<String>,
+        commands: Vec<RemuxCommand>,
+    },
+    /// `key` is pressed after the prefix, named like `send-keys` takes them
+    BindKey {
+        key: String,
+        action: KeyAction,
+    },
+    UnbindKey {
+        key: String,
+    },
+    /// runs the commands of config files, the default ones when no `paths` are given,
+    /// relative paths are from the client's working directory
+    SourceFile {
+        paths: Vec<String>,
+    },
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub enum OptionScope {
+    Global,
+    Session,
+    Window,
+    Pane,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub enum EnvironmentChange {
+    Set(String),
+    Remove, // `-r` - unset in new panes even if the daemon has it
+    Unset,  // `-u` - forget the entry, new panes inherit the variable again
+}
+
+impl FromStr for RemuxCommand {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let args = tokenize(s)?;
+        RemuxCommand::from_args(&args)
+    }
+}
+
+impl RemuxCommand {
+    pub fn from_args(args: &[String]) -> Result<Self> {
+        let (name, args) = args
+            .split_first()
+            .ok_or_else(|| Error::CommandParse("empty command".to_owned()))?;
+        let mut args = Args::new(name, args);
+
+        let command = match name.as_str() {
+            "split-window" | "splitw" => {
+                let vertical = args.flag("-h");
+                args.flag("-v");
+                let mut environment = Vec::new();
+                while let Some(var) = args.option("-e")? {
+                    let (name, value) = var
+                        .split_once('=')
+                        .ok_or_else(|| Error::CommandParse(format!("split-window: expected NAME=value, got {var}")))?;
+                    environment.push((name.to_owned(), value.to_owned()));
+                }
+                RemuxCommand::SplitWindow { vertical, environment }
+            }
+            "select-pane" | "selectp" => {
+                let is_next = !args.flag("-p");
+                args.flag("-n");
+                let (mark, clear) = (args.flag("-m"), args.flag("-M"));
+                let target = args.option("-t")?;
+                if let Some("{marked}" | "~") = target.as_deref() {
+                    args.finish()?;
+                    return Ok(RemuxCommand::SelectMarkedPane);
+                }
+                let target = target.map(|target| pane_id("select-pane", &target)).transpose()?;
+                match args.option("-T")? {
+                    Some(title) => RemuxCommand::SetPaneTitle { target, title },
+                    None if mark || clear => RemuxCommand::MarkPane { target, clear },
+                    None => RemuxCommand::SelectPane { is_next, target },
+                }
+            }
+            "kill-pane" | "killp" => RemuxCommand::KillPane,
+            "resize-pane" | "resizep" => {
+                if args.flag("-Z") {
+                    args.finish()?;
+                    return Ok(RemuxCommand::ZoomPane);
+                }
+                let direction = [
+                    ("-L", PaneDirection::Left),
+                    ("-R", PaneDirection::Right),
+                    ("-U", PaneDirection::Up),
+                    ("-D", PaneDirection::Down),
+                ]
+                .into_iter()
+                .filter(|(flag, _)| args.flag(flag))
+                .map(|(_, direction)| direction)
+                .last()
+                .ok_or_else(|| Error::CommandParse("resize-pane: missing -L, -R, -U or -D".to_owned()))?;
+                let amount = match args.rest().as_slice() {
+                    [] => 1,
+                    [amount] => amount
+                        .parse::<u16>()
+                        .ok()
+                        .filter(|&amount| amount > 0)
+                        .ok_or_else(|| Error::CommandParse(format!("resize-pane: invalid adjustment {amount}")))?,
+                    extra => {
+                        return Err(Error::CommandParse(format!(
+                            "resize-pane: unexpected arguments: {}",
+                            extra.join(" ")
+                        )));
+                    }
+                };
+                RemuxCommand::ResizePane { direction, amount }
+            }
+            "swap-pane" | "swapp" => {
+                let with = [("-U", SwapWith::Prev), ("-D", SwapWith::Next)]
+                    .into_iter()
+                    .filter(|(flag, _)| args.flag(flag))
+                    .map(|(_, with)| with)
+                    .last()
+                    .ok_or_else(|| Error::CommandParse("swap-pane: missing -U or -D".to_owned()))?;
+                RemuxCommand::SwapPane { with }
+            }
+            "rotate-window" | "rotatew" => {
+                args.flag("-U");
+                RemuxCommand::RotateWindow {
+                    downward: args.flag("-D"),
+                }
+            }
+            "select-layout" | "selectl" => RemuxCommand::SelectLayout {
+                layout: args.rest().join(" ").parse()?,
+            },
+            "break-pane" | "breakp" => RemuxCommand::BreakPane {
+                source: args.pane_option("-s")?,
+                name: args.option("-n")?,
+                detached: args.flag("-d"),
+            },
+            "join-pane" | "joinp" => {
+                let vertical = args.flag("-h");
+                args.flag("-v");
+                RemuxCommand::JoinPane {
+                    source: args
+                        .pane_option("-s")?
+                        .ok_or_else(|| Error::CommandParse("join-pane: missing -s".to_owned()))?,
+                    target: args.index_option("-t")?,
+                    vertical,
+                }
+            }
+            "new-window" | "neww" => RemuxCommand::NewWindow {
+                name: args.option("-n")?,
+            },
+            "next-window" | "next" => RemuxCommand::NextWindow,
+            "previous-window" | "prev" => RemuxCommand::PreviousWindow,
+            "select-window" | "selectw" => RemuxCommand::SelectWindow {
+                index: args
+                    .index_option("-t")?
+                    .ok_or_else(|| Error::CommandParse("select-window: missing -t".to_owned()))?,
+            },
+            "rename-window" | "renamew" => {
+                let name = args.rest().join(" ");
+                if name.is_empty() {
+                    return Err(Error::CommandParse("rename-window: missing name".to_owned()));
+                }
+                RemuxCommand::RenameWindow { name }
+            }
+            "mirror-pane" => RemuxCommand::MirrorPane {
+                source: args.required_option("-s")?,
+                target: args.option("-t")?,
+                vertical: args.flag("-h"),
+            },
+            "toggle-floating" => RemuxCommand::ToggleFloating {
+                width_percent: args.percent_option("-w")?,
+                height_percent: args.percent_option("-h")?,
+            },
+            "new-session" | "new" => {
+                let name = args.option("-s")?;
+                let command = args.option("-c")?;
+                let group = args.option("-t")?;
+                if command.is_some() && group.is_some() {
+                    return Err(Error::CommandParse(
+                        "new-session: -c can't be used with -t, a grouped session shares its windows".to_owned(),
+                    ));
+                }
+                RemuxCommand::NewSession { name, command, group }
+            }
+            "kill-session" => RemuxCommand::KillSession {
+                target: args.option("-t")?,
+            },
+            "rename-session" | "rename" => {
+                let target = args.option("-t")?;
+                let name = args.rest().join(" ");
+                if name.is_empty() {
+                    return Err(Error::CommandParse("rename-session: missing name".to_owned()));
+                }
+                RemuxCommand::RenameSession { target, name }
+            }
+            "switch-client" | "switchc" => {
+                let target = if args.flag("-l") {
+                    SwitchTarget::Last
+                } else if args.flag("-n") {
+                    SwitchTarget::Next
+                } else if args.flag("-p") {
+                    SwitchTarget::Prev
+                } else {
+                    SwitchTarget::Session(args.required_option("-t")?)
+                };
+                RemuxCommand::SwitchClient { target }
+            }
+            "detach-client" | "detach" => RemuxCommand::DetachClient {
+                others: args.flag("-a"),
+            },
+            "send-prefix" => RemuxCommand::SendPrefix,
+            "send-keys" | "send" => RemuxCommand::SendKeys { keys: args.rest() },
+            "run-shell" | "run" => RemuxCommand::RunShell {
+                command: args.rest().join(" "),
+            },
+            "set-buffer" | "setb" => RemuxCommand::SetBuffer {
+                name: args.option("-b")?,
+                data: args.rest().join(" "),
+            },
+            "paste-buffer" | "pasteb" => RemuxCommand::PasteBuffer {
+                bracketed: args.flag("-p"),
+                buffer: args.option("-b")?,
+            },
+            "delete-buffer" | "deleteb" => RemuxCommand::DeleteBuffer {
+                buffer: args.option("-b")?,
+            },
+            "display-message" | "display" => {
+                let duration = args.millis_option("-d")?;
+                let text = args.rest().join(" ");
+                if text.is_empty() {
+                    return Err(Error::CommandParse("display-message: missing message".to_owned()));
+                }
+                RemuxCommand::DisplayMessage { text, duration }
+            }
+            "show-buffer" | "showb" => RemuxCommand::ShowBuffer {
+                buffer: args.option("-b")?,
+            },
+            "choose-buffer" => RemuxCommand::ChooseBuffer,
+            "choose-tree" => {
+                args.flag("-w");
+                RemuxCommand::ChooseTree {
+                    sessions_only: args.flag("-s"),
+                }
+            }
+            "save-buffer" | "saveb" => {
+                let append = args.flag("-a");
+                let buffer = args.option("-b")?;
+                match args.rest().as_slice() {
+                    [path] => RemuxCommand::SaveBuffer {
+                        buffer,
+                        path: path.clone(),
+                        append,
+                    },
+                    _ => return Err(Error::CommandParse("save-buffer: expected a path".to_owned())),
+                }
+            }
+            "load-buffer" | "loadb" => match args.rest().as_slice() {
+                [path] => RemuxCommand::LoadBuffer { path: path.clone() },
+                _ => return Err(Error::CommandParse("load-buffer: expected a path".to_owned())),
+            },
+            "display-panes" | "displayp" => RemuxCommand::DisplayPanes,
+            "quick-open" => RemuxCommand::QuickOpen,
+            "search-panes" => {
+                let pattern = args.rest().join(" ");
+                if pattern.is_empty() {
+                    return Err(Error::CommandParse("search-panes: missing pattern".to_owned()));
+                }
+                RemuxCommand::SearchPanes { pattern }
+            }
+            "copy-mode" => RemuxCommand::CopyMode {
+                search: args.option("-s")?,
+            },
+            "log-pane" => {
+                let enabled = match args.rest().as_slice() {
+                    [] => None,
+                    [state] if state == "on" => Some(true),
+                    [state] if state == "off" => Some(false),
+                    _ => return Err(Error::CommandParse("log-pane: expected [on|off]".to_owned())),
+                };
+                RemuxCommand::LogPane { enabled }
+            }
+            "set-option" | "set" => {
+                args.flag("-g");
+                let scope = match (args.flag("-s"), args.flag("-w"), args.flag("-p")) {
+                    (_, _, true) => OptionScope::Pane,
+                    (_, true, false) => OptionScope::Window,
+                    (true, false, false) => OptionScope::Session,
+                    (false, false, false) => OptionScope::Global,
+                };
+                let mut rest = args.rest();
+                if rest.len() != 2 {
+                    return Err(Error::CommandParse("set-option: expected <option> <value>".to_owned()));
+                }
+                let value = rest.remove(1);
+                RemuxCommand::SetOption {
+                    name: rest.remove(0),
+                    value,
+                    scope,
+                }
+            }
+            "set-environment" | "setenv" => {
+                let global = args.flag("-g");
+                let unset = args.flag("-u");
+                let remove = args.flag("-r");
+                let session_name = args.option("-t")?;
+                let mut rest = args.rest();
+                let change = match (unset, remove, rest.len()) {
+                    (false, false, 2) => EnvironmentChange::Set(rest.remove(1)),
+                    (true, false, 1) => EnvironmentChange::Unset,
+                    (false, true, 1) => EnvironmentChange::Remove,
+                    _ => {
+                        return Err(Error::CommandParse(
+                            "set-environment: expected [-u|-r] <name> [value]".to_owned(),
+                        ));
+                    }
+                };
+                RemuxCommand::SetEnvironment {
+                    global,
+                    session_name,
+                    name: rest.remove(0),
+                    change,
+                }
+            }
+            "show-environment" | "showenv" => RemuxCommand::ShowEnvironment {
+                global: args.flag("-g"),
+                session_name: args.option("-t")?,
+            },
+            "refresh-environment" => RemuxCommand::RefreshEnvironment,
+            "set-hook" => {
+                // flags only come before the hook name so they can't clash with the hooked command's flags
+                let mut rest = args.rest();
+                let mut unset = false;
+                let mut session_name = None;
+                while let Some(flag) = rest.first().filter(|arg| arg.starts_with('-')).cloned() {
+                    rest.remove(0);
+                    match flag.as_str() {
+                        "-u" => unset = true,
+                        "-t" if !rest.is_empty() => session_name = Some(rest.remove(0)),
+                        _ => return Err(Error::CommandParse(format!("set-hook: invalid flag {flag}"))),
+                    }
+                }
+                let (event, command) = rest
+                    .split_first()
+                    .ok_or_else(|| Error::CommandParse("set-hook: missing hook name".to_owned()))?;
+                let commands = match (unset, command) {
+                    (true, []) => vec![],
+                    (true, _) => return Err(Error::CommandParse("set-hook: -u takes no command".to_owned())),
+                    (false, []) => return Err(Error::CommandParse("set-hook: missing command".to_owned())),
+                    // a single argument holds the whole (quoted) command sequence
+                    (false, [command]) => parse_sequence(command)?,
+                    (false, command) => sequence_from_args(command)?,
+                };
+                RemuxCommand::SetHook {
+                    event: event.parse()?,
+                    session_name,
+                    commands,
+                }
+            }
+            "bind-key" | "bind" => {
+                let rest = args.rest();
+                let (key, command) = rest
+                    .split_first()
+                    .ok_or_else(|| Error::CommandParse("bind-key: missing key".to_owned()))?;
+                let action = match command {
+                    [] => return Err(Error::CommandParse("bind-key: missing command".to_owned())),
+                    // a single argument holds the whole (quoted) command sequence
+                    [command] => KeyAction::parse(command)?,
+                    command => KeyAction::parse(&command.iter().map(|arg| quote(arg)).collect::<Vec<_>>().join(" "))?,
+                };
+                RemuxCommand::BindKey {
+                    key: key.clone(),
+                    action,
+                }
+            }
+            "unbind-key" | "unbind" => match args.rest().as_slice() {
+                [key] => RemuxCommand::UnbindKey { key: key.clone() },
+                _ => return Err(Error::CommandParse("unbind-key: expected a key".to_owned())),
+            },
+            "source-file" | "source" => RemuxCommand::SourceFile { paths: args.rest() },
+            _ => return Err(Error::CommandParse(format!("unknown command: {name}"))),
+        };
+        args.finish()?;
+        Ok(command)
+    }
+}
+
+/// parses a line of `;` separated commands
+pub fn parse_sequence(line: &str) -> Result<Vec<RemuxCommand>> {
+    sequence_from_args(&tokenize(line)?)
+}
+
+fn sequence_from_args(args: &[String]) -> Result<Vec<RemuxCommand>> {
+    args.split(|arg| arg == ";")
+        .filter(|command| !command.is_empty())
+        .map(RemuxCommand::from_args)
+        .collect()
+}
+
+/// converts a `send-keys` key name into the bytes a terminal would send for it,
+/// anything that isn't a known key name is sent literally
+pub fn key_bytes(key: &str) -> Vec<u8> {
+    match key {
+        "Enter" => b"\r".to_vec(),
+        "Tab" => b"\t".to_vec(),
+        "Escape" => b"\x1b".to_vec(),
+        "Space" => b" ".to_vec(),
+        "BSpace" => b"\x7f".to_vec(),
+        "Up" => b"\x1b[A".to_vec(),
+        "Down" => b"\x1b[B".to_vec(),
+        "Right" => b"\x1b[C".to_vec(),
+        "Left" => b"\x1b[D".to_vec(),
+        _ => match key.strip_prefix("C-").map(str::as_bytes) {
+            Some(&[c]) if c.is_ascii_alphabetic() => vec![c.to_ascii_lowercase() - b'a' + 1],
+            _ => key.as_bytes().to_vec(),
+        },
+    }
+}
+
+/// panes are named by id, optionally written tmux style as `%<id>`
+fn pane_id(command: &str, value: &str) -> Result<usize> {
+    value
+        .trim_start_matches('%')
+        .parse::<usize>()
+        .map_err(|_| Error::CommandParse(format!("{command}: invalid pane {value}")))
+}
+
+/// the inverse of `tokenize` for a single word
+fn quote(word: &str) -> String {
+    if !word.is_empty() && !word.contains(|c: char| c.is_whitespace() || matches!(c, '\'' | '"' | '\\')) {
+        return word.to_owned();
+    }
+    let escaped = word.replace('\\', "\\\\").replace('"', "\\\"");
+    format!("\"{escaped}\"")
+}
+
+/// minimal flag/option parser over the arguments of a single command
+struct Args<'a> {
+    command: &'a str,
+    args: Vec<String>,
+}
+
+impl<'a> Args<'a> {
+    fn new(command: &'a str, args: &[String]) -> Self {
+        Self {
+            command,
+            args: args.to_vec(),
+        }
+    }
+
+    fn flag(&mut self, flag: &str) -> bool {
+        match self.args.iter().position(|arg| arg == flag) {
+            Some(i) => {
+                self.args.remove(i);
+                true
+            }
+            None => false,
+        }
+    }
+
+    fn option(&mut self, flag: &str) -> Result<Option<String>> {
+        match self.args.iter().position(|arg| arg == flag) {
+            Some(i) if i + 1 < self.args.len() => {
+                let value = self.args.remove(i + 1);
+                self.args.remove(i);
+                Ok(Some(value))
+            }
+            Some(_) => Err(Error::CommandParse(format!(
+                "{}: {flag} requires a value",
+                self.command
+            ))),
+            None => Ok(None),
+        }
+    }
+
+    fn percent_option(&mut self, flag: &str) -> Result<Option<u16>> {
+        self.option(flag)?
+            .map(|value| {
+                value
+                    .trim_end_matches('%')
+                    .parse::<u16>()
+                    .ok()
+                    .filter(|percent| (1..=100).contains(percent))
+                    .ok_or_else(|| Error::CommandParse(format!("{}: invalid percentage {value}", self.command)))
+            })
+            .transpose()
+    }
+
+    fn millis_option(&mut self, flag: &str) -> Result<Option<u64>> {
+        self.option(flag)?
+            .map(|value| {
+                value
+                    .parse::<u64>()
+                    .map_err(|_| Error::CommandParse(format!("{}: invalid duration {value}", self.command)))
+            })
+            .transpose()
+    }
+
+    fn index_option(&mut self, flag: &str) -> Result<Option<usize>> {
+        self.option(flag)?
+            .map(|value| {
+                value
+                    .parse::<usize>()
+                    .map_err(|_| Error::CommandParse(format!("{}: invalid index {value}", self.command)))
+            })
+            .transpose()
+    }
+
+    fn pane_option(&mut self, flag: &str) -> Result<Option<usize>> {
+        self.option(flag)?
+            .map(|value| pane_id(self.command, &value))
+            .transpose()
+    }
+
+    fn required_option(&mut self, flag: &str) -> Result<String> {
+        self.option(flag)?
+            .ok_or_else(|| Error::CommandParse(format!("{}: missing {flag}", self.command)))
+    }
+
+    fn rest(&mut self) -> Vec<String> {
+        std::mem::take(&mut self.args)
+    }
+
+    fn finish(self) -> Result<()> {
+        if self.args.is_empty() {
+            Ok(())
+        } else {
+            Err(Error::CommandParse(format!(
+                "{}: unexpected arguments: {}",
+                self.command,
+                self.args.join(" ")
+            )))
+        }
+    }
+}
+
+/// splits a command line into words, honouring single quotes, double quotes and backslash escapes
+pub fn tokenize(line: &str) -> Result<Vec<String>> {
+    let mut words = Vec::new();
+    let mut word = String::new();
+    let mut in_word = false;
+    let mut quote: Option<char> = None;
+    let mut chars = line.chars();
+
+    while let Some(c) = chars.next() {
+        match (quote, c) {
+            (Some(q), c) if c == q => quote = None,
+            (Some('\''), c) => word.push(c),
+            (_, '\\') => {
+                let escaped = chars
+                    .next()
+                    .ok_or_else(|| Error::CommandParse("trailing backslash".to_owned()))?;
+                word.push(escaped);
+                in_word = true;
+            }
+            (Some(_), c) => word.push(c),
+            (None, '\'' | '"') => {
+                quote = Some(c);
+                in_word = true;
+            }
+            (None, c) if c.is_whitespace() => {
+                if in_word {
+                    words.push(std::mem::take(&mut word));
+                    in_word = false;
+                }
+            }
+            (None, c) => {
+                word.push(c);
+                in_word = true;
+            }
+        }
+    }
+
+    if quote.is_some() {
+        return Err(Error::CommandParse("unterminated quote".to_owned()));
+    }
+    if in_word {
+        words.push(word);
+    }
+    Ok(words)
+}
+
+#[cfg(test)]
+mod test {
+    #![allow(clippy::unwrap_used)]
+    use super::*;
+    use crate::key_bindings::ClientAction;
+
+    #[test]
+    fn test_tokenize_quotes() {
+        let words = tokenize(r#"set-hook session-created "split-window -h" 'a b' c\ d"#).unwrap();
+        assert_eq!(
+            words,
+            vec!["set-hook", "session-created", "split-window -h", "a b", "c d"]
+        );
+        assert!(tokenize("\"unterminated").is_err());
+    }
+
+    #[test]
+    fn test_parse_commands() {
+        assert_eq!(
+            "split-window -h".parse::<RemuxCommand>().unwrap(),
+            RemuxCommand::SplitWindow {
+                vertical: true,
+                environment: vec![],
+            }
+        );
+        assert_eq!(
+            "split-window -e DOCKER_HOST=tcp://dev:2375 -e EMPTY="
+                .parse::<RemuxCommand>()
+                .unwrap(),
+            RemuxCommand::SplitWindow {
+                vertical: false,
+                environment: vec![
+                    ("DOCKER_HOST".to_owned(), "tcp://dev:2375".to_owned()),
+                    ("EMPTY".to_owned(), String::new()),
+                ],
+            }
+        );
+        assert!("split-window -e DOCKER_HOST".parse::<RemuxCommand>().is_err());
+        assert_eq!(
+            r"copy-mode -s 'err(or)? \d+'".parse::<RemuxCommand>().unwrap(),
+            RemuxCommand::CopyMode {
+                search: Some(r"err(or)? \d+".to_owned())
+            }
+        );
+        assert_eq!(
+            "copy-mode".parse::<RemuxCommand>().unwrap(),
+            RemuxCommand::CopyMode { search: None }
+        );
+        assert_eq!(
+            "neww -n logs".parse::<RemuxCommand>().unwrap(),
+            RemuxCommand::NewWindow {
+                name: Some("logs".to_owned())
+            }
+        );
+        assert_eq!(
+            "select-window -t 2".parse::<RemuxCommand>().unwrap(),
+            RemuxCommand::SelectWindow { index: 2 }
+        );
+        assert!("select-window".parse::<RemuxCommand>().is_err());
+        assert_eq!(
+            "rename-window build logs".parse::<RemuxCommand>().unwrap(),
+            RemuxCommand::RenameWindow {
+                name: "build logs".to_owned()
+            }
+        );
+        assert_eq!(
+            "new-session -s dev".parse::<RemuxCommand>().unwrap(),
+            RemuxCommand::NewSession {
+                name: Some("dev".to_owned()),
+                command: None,
+                group: None,
+            }
+        );
+        assert_eq!(
+            "new -s build -c 'cargo watch'".parse::<RemuxCommand>().unwrap(),
+            RemuxCommand::NewSession {
+                name: Some("build".to_owned()),
+                command: Some("cargo watch".to_owned()),
+                group: None,
+            }
+        );
+        assert_eq!(
+            "new -s view -t dev".parse::<RemuxCommand>().unwrap(),
+            RemuxCommand::NewSession {
+                name: Some("view".to_owned()),
+                command: None,
+                group: Some("dev".to_owned()),
+            }
+        );
+        assert!("new -t dev -c htop".parse::<RemuxCommand>().is_err());
+        assert_eq!(
+            "resize-pane -L 5".parse::<RemuxCommand>().unwrap(),
+            RemuxCommand::ResizePane {
+                direction: PaneDirection::Left,
+                amount: 5
+            }
+        );
+        assert_eq!(
+            "resizep -D".parse::<RemuxCommand>().unwrap(),
+            RemuxCommand::ResizePane {
+                direction: PaneDirection::Down,
+                amount: 1
+            }
+        );
+        assert!("resize-pane 5".parse::<RemuxCommand>().is_err());
+        assert_eq!(
+            "resize-pane -Z".parse::<RemuxCommand>().unwrap(),
+            RemuxCommand::ZoomPane
+        );
+        assert!("resize-pane -U 0".parse::<RemuxCommand>().is_err());
+        assert_eq!(
+            "swap-pane -D".parse::<RemuxCommand>().unwrap(),
+            RemuxCommand::SwapPane { with: SwapWith::Next }
+        );
+        assert!("swapp".parse::<RemuxCommand>().is_err());
+        assert_eq!(
+            "break-pane -d -n logs".parse::<RemuxCommand>().unwrap(),
+            RemuxCommand::BreakPane {
+                source: None,
+                name: Some("logs".to_owned()),
+                detached: true,
+            }
+        );
+        assert_eq!(
+            "joinp -h -s %3 -t 1".parse::<RemuxCommand>().unwrap(),
+            RemuxCommand::JoinPane {
+                source: 3,
+                target: Some(1),
+                vertical: true,
+            }
+        );
+        assert!("join-pane -t 1".parse::<RemuxCommand>().is_err());
+        assert_eq!(
+            "rotate-window".parse::<RemuxCommand>().unwrap(),
+            RemuxCommand::RotateWindow { downward: false }
+        );
+        assert_eq!(
+            "select-layout tiled".parse::<RemuxCommand>().unwrap(),
+            RemuxCommand::SelectLayout {
+                layout: LayoutPreset::Tiled
+            }
+        );
+        assert!("select-layout".parse::<RemuxCommand>().is_err());
+        assert_eq!(
+            "display-message -d 500 build done".parse::<RemuxCommand>().unwrap(),
+            RemuxCommand::DisplayMessage {
+                text: "build done".to_owned(),
+                duration: Some(500),
+            }
+        );
+        assert!("display -d soon hi".parse::<RemuxCommand>().is_err());
+        assert_eq!(
+            "choose-tree -s".parse::<RemuxCommand>().unwrap(),
+            RemuxCommand::ChooseTree { sessions_only: true }
+        );
+        assert_eq!(
+            "kill-session -t dev".parse::<RemuxCommand>().unwrap(),
+            RemuxCommand::KillSession {
+                target: Some("dev".to_owned())
+            }
+        );
+        assert_eq!(
+            "kill-session".parse::<RemuxCommand>().unwrap(),
+            RemuxCommand::KillSession { target: None }
+        );
+        assert_eq!(
+            "rename-session -t 0 dev box".parse::<RemuxCommand>().unwrap(),
+            RemuxCommand::RenameSession {
+                target: Some("0".to_owned()),
+                name: "dev box".to_owned(),
+            }
+        );
+        assert!("rename-session -t dev".parse::<RemuxCommand>().is_err());
+        assert_eq!(
+            "mirror-pane -s logs -h".parse::<RemuxCommand>().unwrap(),
+            RemuxCommand::MirrorPane {
+                source: "logs".to_owned(),
+                target: None,
+                vertical: true,
+            }
+        );
+        assert_eq!(
+            "paste-buffer -b 2".parse::<RemuxCommand>().unwrap(),
+            RemuxCommand::PasteBuffer {
+                buffer: Some("2".to_owned()),
+                bracketed: false,
+            }
+        );
+        assert_eq!(
+            "paste-buffer -p".parse::<RemuxCommand>().unwrap(),
+            RemuxCommand::PasteBuffer {
+                buffer: None,
+                bracketed: true,
+            }
+        );
+        assert_eq!(
+            "save-buffer -a -b 1 ~/snippets.txt".parse::<RemuxCommand>().unwrap(),
+            RemuxCommand::SaveBuffer {
+                buffer: Some("1".to_owned()),
+                path: "~/snippets.txt".to_owned(),
+                append: true,
+            }
+        );
+        assert!("load-buffer".parse::<RemuxCommand>().is_err());
+        assert_eq!(
+            "setb -b notes some text".parse::<RemuxCommand>().unwrap(),
+            RemuxCommand::SetBuffer {
+                name: Some("notes".to_owned()),
+                data: "some text".to_owned(),
+            }
+        );
+        assert_eq!(
+            "log-pane on".parse::<RemuxCommand>().unwrap(),
+            RemuxCommand::LogPane { enabled: Some(true) }
+        );
+        assert!("log-pane maybe".parse::<RemuxCommand>().is_err());
+        assert_eq!(
+            "select-pane -m".parse::<RemuxCommand>().unwrap(),
+            RemuxCommand::MarkPane {
+                target: None,
+                clear: false,
+            }
+        );
+        assert_eq!(
+            "select-pane -t '{marked}'".parse::<RemuxCommand>().unwrap(),
+            RemuxCommand::SelectMarkedPane
+        );
+        assert_eq!(
+            "select-pane -t %1 -T 'build logs'".parse::<RemuxCommand>().unwrap(),
+            RemuxCommand::SetPaneTitle {
+                target: Some(1),
+                title: "build logs".to_owned(),
+            }
+        );
+        assert_eq!(
+            "set -g scrollbar on".parse::<RemuxCommand>().unwrap(),
+            RemuxCommand::SetOption {
+                name: "scrollbar".to_owned(),
+                value: "on".to_owned(),
+                scope: OptionScope::Global,
+            }
+        );
+        assert_eq!(
+            "set -p auto-respawn on".parse::<RemuxCommand>().unwrap(),
+            RemuxCommand::SetOption {
+                name: "auto-respawn".to_owned(),
+                value: "on".to_owned(),
+                scope: OptionScope::Pane,
+            }
+        );
+        assert_eq!(
+            "set -s destroy-unattached on".parse::<RemuxCommand>().unwrap(),
+            RemuxCommand::SetOption {
+                name: "destroy-unattached".to_owned(),
+                value: "on".to_owned(),
+                scope: OptionScope::Session,
+            }
+        );
+        assert_eq!(
+            "setenv -t dev DOCKER_HOST tcp://dev:2375"
+                .parse::<RemuxCommand>()
+                .unwrap(),
+            RemuxCommand::SetEnvironment {
+                global: false,
+                session_name: Some("dev".to_owned()),
+                name: "DOCKER_HOST".to_owned(),
+                change: EnvironmentChange::Set("tcp://dev:2375".to_owned()),
+            }
+        );
+        assert_eq!(
+            "set-environment -g -r EDITOR".parse::<RemuxCommand>().unwrap(),
+            RemuxCommand::SetEnvironment {
+                global: true,
+                session_name: None,
+                name: "EDITOR".to_owned(),
+                change: EnvironmentChange::Remove,
+            }
+        );
+        assert!("set-environment -u EDITOR vim".parse::<RemuxCommand>().is_err());
+        assert!("switch-client".parse::<RemuxCommand>().is_err());
+        assert_eq!(
+            "switchc -t work".parse::<RemuxCommand>().unwrap(),
+            RemuxCommand::SwitchClient {
+                target: SwitchTarget::Session("work".to_owned())
+            }
+        );
+        assert_eq!(
+            "switch-client -l".parse::<RemuxCommand>().unwrap(),
+            RemuxCommand::SwitchClient {
+                target: SwitchTarget::Last
+            }
+        );
+        assert!("switch-client -n extra".parse::<RemuxCommand>().is_err());
+        assert_eq!(
+            "detach -a".parse::<RemuxCommand>().unwrap(),
+            RemuxCommand::DetachClient { others: true }
+        );
+        assert!("kill-pane extra".parse::<RemuxCommand>().is_err());
+        assert!("not-a-command".parse::<RemuxCommand>().is_err());
+        assert_eq!(
+            "source-file ~/dev.conf".parse::<RemuxCommand>().unwrap(),
+            RemuxCommand::SourceFile {
+                paths: vec!["~/dev.conf".to_owned()]
+            }
+        );
+        assert_eq!(
+            "source".parse::<RemuxCommand>().unwrap(),
+            RemuxCommand::SourceFile { paths: vec![] }
+        );
+    }
+
+    #[test]
+    fn test_key_bytes() {
+        assert_eq!(key_bytes("Enter"), b"\r");
+        assert_eq!(key_bytes("C-c"), vec![0x03]);
+        assert_eq!(key_bytes("ls"), b"ls");
+        assert_eq!(key_bytes("Up"), b"\x1b[A");
+    }
+
+    #[test]
+    fn test_parse_set_hook() {
+        assert_eq!(
+            "set-hook -t dev session-created 'split-window -h'"
+                .parse::<RemuxCommand>()
+                .unwrap(),
+            RemuxCommand::SetHook {
+                event: HookEvent::SessionCreated,
+                session_name: Some("dev".to_owned()),
+                commands: vec![RemuxCommand::SplitWindow {
+                    vertical: true,
+                    environment: vec![],
+                }],
+            }
+        );
+        assert_eq!(
+            "set-hook session-created 'split-window -h ; split-window'"
+                .parse::<RemuxCommand>()
+                .unwrap(),
+            RemuxCommand::SetHook {
+                event: HookEvent::SessionCreated,
+                session_name: None,
+                commands: vec![
+                    RemuxCommand::SplitWindow {
+                        vertical: true,
+                        environment: vec![],
+                    },
+                    RemuxCommand::SplitWindow {
+                        vertical: false,
+                        environment: vec![],
+                    }
+                ],
+            }
+        );
+        assert_eq!(
+            "set-hook -u pane-died".parse::<RemuxCommand>().unwrap(),
+            RemuxCommand::SetHook {
+                event: HookEvent::PaneDied,
+                session_name: None,
+                commands: vec![],
+            }
+        );
+        assert_eq!(
+            "set-hook -u session-closed".parse::<RemuxCommand>().unwrap(),
+            RemuxCommand::SetHook {
+                event: HookEvent::SessionClosed,
+                session_name: None,
+                commands: vec![],
+            }
+        );
+        assert_eq!("window-changed".parse::<HookEvent>().unwrap(), HookEvent::WindowChanged);
+    }
+
+    #[test]
+    fn test_parse_bind_key() {
+        assert_eq!(
+            "bind-key C-k kill-pane".parse::<RemuxCommand>().unwrap(),
+            RemuxCommand::BindKey {
+                key: "C-k".to_owned(),
+                action: KeyAction::Command("kill-pane".to_owned()),
+            }
+        );
+        assert_eq!(
+            "bind v set-buffer 'a b'".parse::<RemuxCommand>().unwrap(),
+            RemuxCommand::BindKey {
+                key: "v".to_owned(),
+                action: KeyAction::Command("set-buffer \"a b\"".to_owned()),
+            }
+        );
+        assert_eq!(
+            "bind S choose-session".parse::<RemuxCommand>().unwrap(),
+            RemuxCommand::BindKey {
+                key: "S".to_owned(),
+                action: KeyAction::Client(ClientAction::ChooseSession),
+            }
+        );
+        assert!("bind-key x".parse::<RemuxCommand>().is_err());
+        assert!("bind-key x not-a-command".parse::<RemuxCommand>().is_err());
+        assert_eq!(
+            "unbind x".parse::<RemuxCommand>().unwrap(),
+            RemuxCommand::UnbindKey { key: "x".to_owned() }
+        );
+    }
+}
@@ -1,5 +1,8 @@
+pub const DEFAULT_PREFIX: u8 = 0x02; // C-b
 pub const PORT: u16 = 12345;
-pub const DAEMON_LOCK_FILE: &str = "/tmp/remux-daemon.lock";
 pub const TEMP_SOCK_DIR: &str = "/tmp/remux.sock";
+pub const DEFAULT_SOCKET_NAME: &str = "default";
 pub const RUNTIME_DIR: &str = "XDG_RUNTIME_DIR";
 pub const HOME_DIR: &str = "HOME";
+pub const CONFIG_HOME_DIR: &str = "XDG_CONFIG_HOME";
+pub const STATE_HOME_DIR: &str = "XDG_STATE_HOME";
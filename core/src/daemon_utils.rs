@@ -1,51 +1,90 @@
 use std::{
     env::var,
-    fs::{File, create_dir_all},
-    path::PathBuf,
+    fs::{self, DirBuilder, File, Permissions},
+    os::unix::fs::{DirBuilderExt, PermissionsExt},
+    path::{Path, PathBuf},
 };
 
 use fs2::FileExt;
+use nix::unistd::getuid;
 
 use crate::{
-    constants::{self, HOME_DIR, RUNTIME_DIR},
+    constants::{CONFIG_HOME_DIR, HOME_DIR, RUNTIME_DIR, STATE_HOME_DIR},
     error::{Error, Result},
 };
 
 /// if we can't lock the daemon file then the daemon
 /// process must be running
-pub fn is_daemon_running() -> bool {
-    lock_daemon_file().is_err()
+pub fn is_daemon_running(socket_name: &str) -> bool {
+    lock_daemon_file(socket_name).is_err()
 }
 
-pub fn get_daemon_file() -> Result<File> {
-    let file = File::create(constants::DAEMON_LOCK_FILE)?;
+/// kept next to the socket so every `-L` name gets its own daemon
+pub fn get_daemon_file(socket_name: &str) -> Result<File> {
+    let file = File::create(get_sock_path(socket_name)?.with_extension("lock"))?;
     Ok(file)
 }
 
-pub fn lock_daemon_file() -> Result<File> {
-    let file = get_daemon_file()?;
+pub fn lock_daemon_file(socket_name: &str) -> Result<File> {
+    let file = get_daemon_file(socket_name)?;
     file.try_lock_exclusive()?;
     Ok(file)
 }
 
-pub fn get_sock_path() -> Result<PathBuf> {
-    // For linux systems
-    if let Ok(runtime_dir) = var(RUNTIME_DIR) {
-        return Ok(PathBuf::from(runtime_dir).join("remux.sock"));
+/// `$XDG_RUNTIME_DIR/remux/<uid>/<socket name>.sock`, or under `~/.remux/run` when there's no runtime dir
+pub fn get_sock_path(socket_name: &str) -> Result<PathBuf> {
+    if socket_name.is_empty() || socket_name.contains('/') {
+        return Err(Error::Custom(format!("Invalid socket name: {socket_name:?}")));
+    }
+
+    let base = if let Ok(runtime_dir) = var(RUNTIME_DIR) {
+        PathBuf::from(runtime_dir).join("remux")
+    } else if let Ok(home_dir) = var(HOME_DIR) {
+        PathBuf::from(home_dir).join(".remux/run")
+    } else {
+        return Err(Error::Custom(
+            "Could not determine socket path: neither XDG_RUNTIME_DIR nor HOME are set".to_string(),
+        ));
+    };
+
+    let dir = base.join(getuid().to_string());
+    create_private_dir(&dir)?;
+    Ok(dir.join(format!("{socket_name}.sock")))
+}
+
+/// only the user can list or connect to the sockets inside, an existing directory is tightened too
+fn create_private_dir(dir: &Path) -> Result<()> {
+    DirBuilder::new().recursive(true).mode(0o700).create(dir)?;
+    fs::set_permissions(dir, Permissions::from_mode(0o700))?;
+    Ok(())
+}
+
+pub fn get_config_dir() -> Result<PathBuf> {
+    if let Ok(config_dir) = var(CONFIG_HOME_DIR) {
+        return Ok(PathBuf::from(config_dir).join("remux"));
     }
 
     if let Ok(home_dir) = var(HOME_DIR) {
-        let path = PathBuf::from(home_dir).join(".remux/run/remux.sock");
+        return Ok(PathBuf::from(home_dir).join(".config/remux"));
+    }
 
-        if let Some(parent) = path.parent() {
-            create_dir_all(parent)?;
-        }
+    Err(Error::Custom(
+        "Could not determine config path: neither XDG_CONFIG_HOME nor HOME are set".to_string(),
+    ))
+}
 
-        return Ok(path);
+/// where the daemon keeps data worth keeping around, e.g. pane logs
+pub fn get_state_dir() -> Result<PathBuf> {
+    if let Ok(state_dir) = var(STATE_HOME_DIR) {
+        return Ok(PathBuf::from(state_dir).join("remux"));
+    }
+
+    if let Ok(home_dir) = var(HOME_DIR) {
+        return Ok(PathBuf::from(home_dir).join(".local/state/remux"));
     }
 
     Err(Error::Custom(
-        "Could not determine socket path: neither XDG_RUNTIME_DIR nor HOME are set".to_string(),
+        "Could not determine state path: neither XDG_STATE_HOME nor HOME are set".to_string(),
     ))
 }
 
@@ -58,24 +97,46 @@ mod test {
 
     static TEST_MUTEX: LazyLock<Mutex<()>> = LazyLock::new(|| Mutex::new(()));
 
+    #[test]
+    fn test_create_private_dir() -> Result<()> {
+        let dir = std::env::temp_dir().join(format!("remux-test-{}", std::process::id()));
+        DirBuilder::new().mode(0o755).create(&dir)?;
+        create_private_dir(&dir.join("1000"))?;
+        create_private_dir(&dir)?;
+        for path in [dir.join("1000"), dir.clone()] {
+            assert_eq!(fs::metadata(path)?.permissions().mode() & 0o777, 0o700);
+        }
+        fs::remove_dir_all(&dir)?;
+        Ok(())
+    }
+
+    #[test]
+    fn test_sock_path() {
+        assert!(get_sock_path("work").unwrap().ends_with("work.sock"));
+        assert!(get_sock_path("").is_err());
+        assert!(get_sock_path("../work").is_err());
+    }
+
     #[test]
     fn test_get_daemon_file() {
-        get_daemon_file().unwrap();
+        get_daemon_file("test").unwrap();
     }
 
     // following tests operate on the shared lock file resource
     #[test]
     fn test_lock_daemon_file_success() -> Result<()> {
         let _lock = TEST_MUTEX.lock();
-        lock_daemon_file()?;
+        lock_daemon_file("test")?;
         Ok(())
     }
 
     #[test]
     fn test_lock_daemon_file_failure() -> Result<()> {
         let _lock = TEST_MUTEX.lock();
-        let _locked_file = lock_daemon_file()?;
-        assert!(lock_daemon_file().is_err());
+        let _locked_file = lock_daemon_file("test")?;
+        assert!(lock_daemon_file("test").is_err());
+        // other socket names run their own daemon
+        assert!(lock_daemon_file("other").is_ok());
         Ok(())
     }
 }
@@ -13,10 +13,40 @@ pub enum Error {
     #[error("Serialization error: {0}")]
     SerializationError(#[from] serde_json::Error),
 
+    #[error("Encode error: {0}")]
+    Encode(#[from] bincode::error::EncodeError),
+
+    #[error("Decode error: {0}")]
+    Decode(#[from] bincode::error::DecodeError),
+
+    #[error("Protocol version mismatch: expected {expected}, got {actual} (restart the daemon after upgrading remux)")]
+    ProtocolVersion { expected: u8, actual: u8 },
+
+    #[error("Unexpected frame kind {0}")]
+    FrameKind(u8),
+
+    #[error(
+        "daemon is {}, client is {} - restart the daemon so both run the same version",
+        describe_version(.daemon),
+        describe_version(.client)
+    )]
+    IncompatibleVersion { daemon: String, client: String },
+
+    #[error("Command parse error: {0}")]
+    CommandParse(String),
+
     #[error("Response Error: {0}")]
     Response(ResponseError),
 }
 
+fn describe_version(version: &str) -> String {
+    if version.is_empty() {
+        "an older build".to_owned()
+    } else {
+        format!("v{version}")
+    }
+}
+
 #[derive(Error, Debug)]
 pub enum ResponseError {
     #[error("UnexpectedId: expected({expected}) actual({actual})")]
@@ -1,9 +1,35 @@
+use std::time::Duration;
+
 use bytes::Bytes;
 use serde::{Deserialize, Serialize};
 
+use crate::{
+    commands::{HookEvent, PaneDirection, SwapWith},
+    key_bindings::KeyBindings,
+    links::LinkMatch,
+    states::{Alert, ClientInfo, PaneLocation, SearchMatch, TreeSession, WindowInfo},
+};
+
+/// left button mouse actions and the wheel, other buttons aren't forwarded
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum MouseKind {
+    Down,
+    Drag,
+    Up,
+    ScrollUp,
+    ScrollDown,
+}
+
+impl MouseKind {
+    pub fn is_scroll(self) -> bool {
+        matches!(self, Self::ScrollUp | Self::ScrollDown)
+    }
+}
+
 #[derive(Debug, Serialize, Deserialize)]
 pub enum CliEvent {
-    Raw(Bytes), // raw user keypresses
+    Raw(Bytes),   // raw user keypresses
+    Paste(Bytes), // text the outer terminal pasted, without the bracketed paste markers
 
     // pane related
     KillPane,
@@ -11,10 +37,48 @@ pub enum CliEvent {
     SplitPaneVertical,
     SplitPaneHorizontal,
     PrevPane,
+    ToggleFloatingPane,
+    DisplayPanes,      // asks the daemon where each pane is for jump labels
+    SelectPane(usize), // focus a pane by id
+    ResizePane { direction: PaneDirection, amount: u16 }, // moves the active pane's border by `amount` cells
+    ZoomPane,          // toggles the active pane filling the window
+    SwapPane { with: SwapWith }, // exchanges the active pane with its neighbour in the layout
+    RotateWindow,      // moves every pane one spot along in the layout
+
+    // window related
+    NewWindow,
+    NextWindow,
+    PrevWindow,
+    SelectWindow(usize), // by index, in the order the windows were created
+    RenameWindow(String),
+
+    // paste buffers
+    ChooseBuffer,       // asks the daemon for the buffer list
+    PasteBuffer(usize), // paste the buffer at this index into the active pane
+    SetBuffer(String),  // store text as the newest paste buffer
+
+    CapturePane { session: u32, window: usize, pane: usize }, // a snapshot of any pane's screen, for choose-tree
+
+    QuickOpen, // asks the daemon for the links on the active pane's screen
+
+    // session wide search
+    SearchPanes(String), // search every pane of the session for a pattern
+    JumpToSearchMatch { pane_id: usize, line: usize }, // focus the pane in copy mode at the line
+    CopyModeSearch(String), // regex searched for in the active pane's scrollback from copy mode
 
     SwitchSession(String), // switch session - does nothing if session does not exist
+    RenameSession(String), // renames the client's session
+    LastSession,           // back to the session the client was attached to before this one
+    NextSession,
+    PrevSession,
+
+    RunCommand(String), // command line typed at the prompt or sent from lua, parsed by the daemon
 
     TerminalResize { rows: u16, cols: u16 },
+    Mouse { kind: MouseKind, row: u16, col: u16 }, // 0 based terminal cells
+    ScrollUp { row: u16, col: u16 },               // mouse wheel over a pane, scrolls back through its history
+    ScrollDown { row: u16, col: u16 },
+    Focus(bool), // the outer terminal gained/lost focus
 
     Detach,
 }
@@ -24,10 +88,150 @@ pub enum DaemonEvent {
     Raw(Bytes), // raw response - ansii control chars
 
     // session events
-    CurrentSessions(Vec<u32>),
     ActiveSession(u32),
-    NewSession(u32, String),
-    DeletedSession(u32),
+    LastSession(Option<u32>), // the session this client was attached to before the active one
+    StateDelta(StateDelta),   // a change to the `DaemonState` the client was sent when it attached
+    SessionAlert { session_id: u32, alert: Alert, bell: bool }, // a background session needs attention
+    WindowSize { rows: u16, cols: u16 }, // the session's window size, smaller than the client when shared
+    ScreenReader(bool),       // the `screen-reader` option, the client drops decorations too
+    StatusLine(bool),         // the `status` option, the client shows or hides its status line
+    SessionClients(Vec<ClientInfo>), // the clients attached to the active session changed
+    SessionWindows { windows: Vec<WindowInfo>, active: usize }, // all of the active session's windows, on attach
+    KeyBindings(KeyBindings), // `bind-key`, `unbind-key` or the prefix changed
+    HookFired(HookEvent, Option<String>), // with the session's name, runs the client's `remux.on` callbacks
+    Bell { session: u32, window: usize }, // a pane rang the bell and `bell-action` lets it through
+    PaneOutput(usize, #[serde(with = "base64_bytes")] Bytes), // a pane's raw output, for `attach --control` clients
+
+    Message(Message),      // shown in place of the status line for a while
+    Buffers(Vec<String>),  // paste buffer previews to choose from, newest first
+    Links(Vec<LinkMatch>), // links on the active pane's screen for quick-open
+    SearchResults(Vec<SearchMatch>),
+    PaneLocations(Vec<PaneLocation>), // panes of the active window for jump labels
+    Exited(i32),                      // the command of a `remux exec` finished with this exit code
+
+    Tree(Vec<TreeSession>, bool), // every session for `choose-tree`, only the sessions showing if true
+    PaneCapture { rows: u16, cols: u16, contents: Bytes }, // a pane's screen as escape sequences
     // TODO: for window id
-    Disconnected,
+    Disconnected(DetachReason), // nothing more follows
+}
+
+/// changes to a `DaemonState`, applied in the order they arrive. windows are named by id so a delta the
+/// client already saw in a full window list changes nothing. externally tagged, bincode can't read the other kinds
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub enum StateDelta {
+    SessionAdded {
+        id: u32,
+        name: String,
+    },
+    SessionRemoved {
+        id: u32,
+    },
+    SessionRenamed {
+        id: u32,
+        name: String,
+    },
+    ClientCountChanged {
+        id: u32,
+        clients: usize,
+    }, // clients attached to the session
+
+    // windows of the active session
+    WindowAdded(WindowInfo), // inserted at its index
+    WindowRemoved {
+        id: usize,
+    },
+    WindowSelected {
+        id: usize,
+    },
+    WindowRenamed {
+        id: usize,
+        name: String,
+    },
+    WindowActivity {
+        id: usize,
+        activity: bool,
+        bell: bool,
+    },
+    PaneCountChanged {
+        id: usize,
+        panes: usize,
+        active_pane: usize,
+    },
+}
+
+/// shown in place of the client's status line, from `display-message`, failed commands and the like
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Message {
+    pub text: String,
+    pub level: MessageLevel,
+    pub duration: Option<Duration>, // the client's default without one
+}
+
+impl Message {
+    pub fn info(text: String) -> Self {
+        Self {
+            text,
+            level: MessageLevel::Info,
+            duration: None,
+        }
+    }
+
+    pub fn error(text: String) -> Self {
+        Self {
+            text,
+            level: MessageLevel::Error,
+            duration: None,
+        }
+    }
+}
+
+/// how loudly a `Message` is shown
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum MessageLevel {
+    Info,
+    Error, // a command or an actor failed, the daemon carried on
+}
+
+/// why the daemon let go of a client, the cli says so on its way out
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum DetachReason {
+    Detached,              // `detach-client` or the client's own detach key
+    Replaced,              // another client attached with `-d` or ran `detach-client -a`
+    SessionKilled(String), // the attached session was killed or destroyed
+    ServerExited,
+}
+
+impl std::fmt::Display for DetachReason {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Detached => f.write_str("detached"),
+            Self::Replaced => f.write_str("detached by another client"),
+            Self::SessionKilled(name) => write!(f, "session {name} killed"),
+            Self::ServerExited => f.write_str("server exited"),
+        }
+    }
+}
+
+/// bytes go out as base64 in json so control mode output stays one readable string, bincode keeps them raw
+mod base64_bytes {
+    use base64::{Engine, engine::general_purpose::STANDARD};
+    use bytes::Bytes;
+    use serde::{Deserialize, Deserializer, Serializer, de::Error};
+
+    pub fn serialize<S: Serializer>(bytes: &Bytes, serializer: S) -> Result<S::Ok, S::Error> {
+        if serializer.is_human_readable() {
+            serializer.serialize_str(&STANDARD.encode(bytes))
+        } else {
+            serializer.serialize_bytes(bytes)
+        }
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Bytes, D::Error> {
+        if deserializer.is_human_readable() {
+            let encoded = String::deserialize(deserializer)?;
+            STANDARD.decode(encoded).map(Bytes::from).map_err(D::Error::custom)
+        } else {
+            Bytes::deserialize(deserializer)
+        }
+    }
 }
@@ -0,0 +1,174 @@
+// inline image escape sequences (sixel, kitty graphics, iTerm2) - vt100 can't draw them,
+// so they're split out of the output stream and passed through to the real terminal
+
+const ST: &[u8] = b"\x1b\\";
+const BEL: u8 = 0x07;
+const ITERM_START: &[u8] = b"\x1b]1337;File=";
+const MAX_IMAGE_LEN: usize = 32 * 1024 * 1024; // larger images are dropped instead of buffered
+const MAX_DCS_PARAMS: usize = 16;
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum Chunk {
+    Text(Vec<u8>),
+    Image(Vec<u8>),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum ImageKind {
+    Sixel, // DCS <params> q ... ST
+    Kitty, // APC G ... ST
+    Iterm, // OSC 1337 ; File= ... BEL/ST
+}
+
+enum Start {
+    Image(ImageKind),
+    Partial, // could still become an image once more bytes arrive
+    NotImage,
+}
+
+/// what the escape sequence at the start of `bytes` is
+fn image_start(bytes: &[u8]) -> Start {
+    match bytes.get(1) {
+        None => Start::Partial,
+        Some(b'P') => {
+            let params = &bytes[2..];
+            match params.iter().position(|&b| !(b.is_ascii_digit() || b == b';')) {
+                Some(end) if params[end] == b'q' => Start::Image(ImageKind::Sixel),
+                None if params.len() < MAX_DCS_PARAMS => Start::Partial,
+                _ => Start::NotImage,
+            }
+        }
+        Some(b'_') => match bytes.get(2) {
+            None => Start::Partial,
+            Some(b'G') => Start::Image(ImageKind::Kitty),
+            Some(_) => Start::NotImage,
+        },
+        Some(b']') if bytes.len() < ITERM_START.len() && ITERM_START.starts_with(bytes) => Start::Partial,
+        Some(b']') if bytes.starts_with(ITERM_START) => Start::Image(ImageKind::Iterm),
+        Some(_) => Start::NotImage,
+    }
+}
+
+/// end (exclusive) of the image's terminator, searching from `from`
+fn image_end(kind: ImageKind, bytes: &[u8], from: usize) -> Option<usize> {
+    bytes[from..].iter().enumerate().find_map(|(i, &b)| match b {
+        BEL if kind == ImageKind::Iterm => Some(from + i + 1),
+        0x1b if bytes[from + i..].starts_with(ST) => Some(from + i + ST.len()),
+        _ => None,
+    })
+}
+
+/// splits output into text and images in order, images split across reads are held back until complete
+#[derive(Debug, Default)]
+pub struct GraphicsScanner {
+    pending: Vec<u8>,
+    searched: usize, // how much of a pending image has been searched for its terminator
+    discarding: Option<ImageKind>,
+}
+
+impl GraphicsScanner {
+    pub fn split(&mut self, bytes: &[u8]) -> Vec<Chunk> {
+        let mut chunks = Vec::new();
+        let mut data = std::mem::take(&mut self.pending);
+        data.extend_from_slice(bytes);
+
+        let mut text_start = 0;
+        if let Some(kind) = self.discarding {
+            match image_end(kind, &data, 0) {
+                Some(end) => {
+                    self.discarding = None;
+                    text_start = end;
+                }
+                None => return chunks,
+            }
+        }
+
+        let mut i = text_start;
+        while let Some(offset) = data[i..].iter().position(|&b| b == 0x1b) {
+            let start = i + offset;
+            match image_start(&data[start..]) {
+                Start::NotImage => i = start + 1,
+                Start::Partial => {
+                    push_text(&mut chunks, &data[text_start..start]);
+                    self.pending = data[start..].to_vec();
+                    return chunks;
+                }
+                Start::Image(kind) => {
+                    push_text(&mut chunks, &data[text_start..start]);
+                    // an image carried over from the last read has already been searched up to `searched`
+                    let from = if start == 0 { self.searched.max(2) } else { start + 2 };
+                    match image_end(kind, &data, from) {
+                        Some(end) => {
+                            chunks.push(Chunk::Image(data[start..end].to_vec()));
+                            self.searched = 0;
+                            text_start = end;
+                            i = end;
+                        }
+                        None if data.len() - start > MAX_IMAGE_LEN => {
+                            self.discarding = Some(kind);
+                            self.searched = 0;
+                            return chunks;
+                        }
+                        None => {
+                            self.pending = data[start..].to_vec();
+                            // the terminator's `ESC` may be the last byte
+                            self.searched = self.pending.len() - 1;
+                            return chunks;
+                        }
+                    }
+                }
+            }
+        }
+        push_text(&mut chunks, &data[text_start..]);
+        chunks
+    }
+}
+
+fn push_text(chunks: &mut Vec<Chunk>, text: &[u8]) {
+    if !text.is_empty() {
+        chunks.push(Chunk::Text(text.to_vec()));
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn text(s: &[u8]) -> Chunk {
+        Chunk::Text(s.to_vec())
+    }
+
+    fn image(s: &[u8]) -> Chunk {
+        Chunk::Image(s.to_vec())
+    }
+
+    #[test]
+    fn test_split_images() {
+        let mut scanner = GraphicsScanner::default();
+        assert_eq!(
+            scanner.split(b"a\x1bPq#0;2;0;0;0~-\x1b\\b\x1b_Gf=100;AAAA\x1b\\"),
+            vec![
+                text(b"a"),
+                image(b"\x1bPq#0;2;0;0;0~-\x1b\\"),
+                text(b"b"),
+                image(b"\x1b_Gf=100;AAAA\x1b\\")
+            ]
+        );
+        // other escape sequences are text
+        assert_eq!(
+            scanner.split(b"\x1b[1m\x1bP+q544e\x1b\\"),
+            vec![text(b"\x1b[1m\x1bP+q544e\x1b\\")]
+        );
+    }
+
+    #[test]
+    fn test_split_across_reads() {
+        let mut scanner = GraphicsScanner::default();
+        assert_eq!(scanner.split(b"x\x1b]13"), vec![text(b"x")]);
+        assert_eq!(scanner.split(b"37;File=inline=1:AAAA\x1b"), vec![]);
+        assert_eq!(
+            scanner.split(b"\\y"),
+            vec![image(b"\x1b]1337;File=inline=1:AAAA\x1b\\"), text(b"y")]
+        );
+    }
+}
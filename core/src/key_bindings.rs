@@ -0,0 +1,211 @@
+/// what the keys pressed after the prefix do - the daemon keeps the table, `bind-key`/`unbind-key` and
+/// `set prefix` change it and clients build their input parser from it
+use std::collections::BTreeMap;
+
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    commands::{key_bytes, parse_sequence},
+    constants::DEFAULT_PREFIX,
+    prelude::*,
+};
+
+/// what a key pressed after the prefix does
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum KeyAction {
+    Command(String),      // command line the daemon runs for the client that pressed the key
+    Client(ClientAction), // prompts and pickers the client handles itself
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum ClientAction {
+    ChooseSession,
+    SearchPrompt,     // searches every pane of the session
+    CopySearchPrompt, // searches the active pane's scrollback in copy mode
+    CommandPrompt,
+    RenamePrompt,        // renames the active window
+    RenameSessionPrompt, // starts out with the session's name
+}
+
+impl ClientAction {
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "choose-session" => Some(Self::ChooseSession),
+            "search-prompt" => Some(Self::SearchPrompt),
+            "copy-search-prompt" => Some(Self::CopySearchPrompt),
+            "command-prompt" => Some(Self::CommandPrompt),
+            "rename-prompt" => Some(Self::RenamePrompt),
+            "rename-session-prompt" => Some(Self::RenameSessionPrompt),
+            _ => None,
+        }
+    }
+}
+
+impl KeyAction {
+    /// a client action's name or a command line, checked so mistakes show up when binding rather than on the key press
+    pub fn parse(line: &str) -> Result<Self> {
+        if let Some(action) = ClientAction::from_name(line.trim()) {
+            return Ok(Self::Client(action));
+        }
+        if parse_sequence(line)?.is_empty() {
+            return Err(Error::CommandParse("bind-key: missing command".to_owned()));
+        }
+        Ok(Self::Command(line.to_owned()))
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct KeyBindings {
+    pub prefix: u8,
+    pub keys: BTreeMap<String, KeyAction>, // keyed by what the terminal sends for the key
+}
+
+impl Default for KeyBindings {
+    fn default() -> Self {
+        let mut bindings = Self {
+            prefix: DEFAULT_PREFIX,
+            keys: BTreeMap::new(),
+        };
+        let commands = [
+            ("%", "split-window -h"),
+            ("\"", "split-window -v"),
+            ("n", "select-pane -n"),
+            ("p", "select-pane -p"),
+            ("x", "kill-pane"),
+            ("z", "resize-pane -Z"),
+            ("{", "swap-pane -U"),
+            ("}", "swap-pane -D"),
+            ("C-o", "rotate-window"),
+            // n/p already cycle panes, windows get < and >
+            ("c", "new-window"),
+            (">", "next-window"),
+            ("<", "previous-window"),
+            ("d", "detach-client"),
+            ("L", "switch-client -l"),
+            ("(", "switch-client -p"),
+            (")", "switch-client -n"),
+            ("f", "toggle-floating"),
+            ("=", "choose-buffer"),
+            ("s", "choose-tree -s"),
+            ("w", "choose-tree -w"),
+            ("]", "paste-buffer -p"),
+            ("u", "quick-open"),
+            ("q", "display-panes"),
+            ("m", "select-pane -m"),
+            ("`", "select-pane -t {marked}"),
+            ("b", "set status toggle"),
+            ("Up", "resize-pane -U 5"),
+            ("Down", "resize-pane -D 5"),
+            ("Left", "resize-pane -L 5"),
+            ("Right", "resize-pane -R 5"),
+        ];
+        for (key, command) in commands {
+            bindings.bind(key, KeyAction::Command(command.to_owned()));
+        }
+        for index in 0..10 {
+            bindings.bind(
+                &index.to_string(),
+                KeyAction::Command(format!("select-window -t {index}")),
+            );
+        }
+        let client_actions = [
+            (",", ClientAction::RenamePrompt),
+            ("$", ClientAction::RenameSessionPrompt),
+            ("/", ClientAction::CopySearchPrompt),
+            ("F", ClientAction::SearchPrompt),
+            (":", ClientAction::CommandPrompt),
+        ];
+        for (key, action) in client_actions {
+            bindings.bind(key, KeyAction::Client(action));
+        }
+        bindings
+    }
+}
+
+impl KeyBindings {
+    /// `key` is a key name as `send-keys` takes them
+    pub fn bind(&mut self, key: &str, action: KeyAction) {
+        self.keys.insert(key_sequence(key), action);
+    }
+
+    pub fn unbind(&mut self, key: &str) -> Option<KeyAction> {
+        self.keys.remove(&key_sequence(key))
+    }
+
+    /// the binding `input` starts with and how many bytes its key took up
+    pub fn lookup(&self, input: &[u8]) -> Option<(&KeyAction, usize)> {
+        self.keys
+            .iter()
+            .filter(|(key, _)| input.starts_with(key.as_bytes()))
+            .max_by_key(|(key, _)| key.len())
+            .map(|(key, action)| (action, key.len()))
+    }
+
+    /// whether `input` could still become a bound key once more of it is read
+    pub fn is_partial(&self, input: &[u8]) -> bool {
+        self.keys
+            .keys()
+            .any(|key| key.len() > input.len() && key.as_bytes().starts_with(input))
+    }
+}
+
+/// the prefix has to be a single byte so it can't be confused with the start of an escape sequence
+pub fn parse_prefix(key: &str) -> Result<u8> {
+    match key_bytes(key).as_slice() {
+        &[b] if b != 0x1b => Ok(b),
+        _ => Err(Error::CommandParse(format!(
+            "prefix: expected a single key like C-a, got {key}"
+        ))),
+    }
+}
+
+// key names turn into ascii or the utf-8 the name was written in, either way a valid string
+fn key_sequence(key: &str) -> String {
+    String::from_utf8_lossy(&key_bytes(key)).into_owned()
+}
+
+#[cfg(test)]
+mod test {
+    #![allow(clippy::unwrap_used)]
+    use super::*;
+
+    #[test]
+    fn test_lookup() {
+        let mut bindings = KeyBindings::default();
+        assert_eq!(
+            bindings.lookup(b"%rest"),
+            Some((&KeyAction::Command("split-window -h".to_owned()), 1))
+        );
+        assert_eq!(
+            bindings.lookup(b"\x1b[A"),
+            Some((&KeyAction::Command("resize-pane -U 5".to_owned()), 3))
+        );
+        assert!(bindings.lookup(b"\x1b[").is_none());
+        assert!(bindings.is_partial(b"\x1b["));
+        assert!(!bindings.is_partial(b"y"));
+
+        bindings.bind("C-k", KeyAction::Command("kill-pane".to_owned()));
+        assert_eq!(
+            bindings.lookup(&[0x0b]),
+            Some((&KeyAction::Command("kill-pane".to_owned()), 1))
+        );
+        assert!(bindings.unbind("x").is_some());
+        assert!(bindings.lookup(b"x").is_none());
+    }
+
+    #[test]
+    fn test_parse() {
+        assert_eq!(
+            KeyAction::parse("command-prompt").unwrap(),
+            KeyAction::Client(ClientAction::CommandPrompt)
+        );
+        assert_eq!(
+            KeyAction::parse("split-window -h ; select-pane -n").unwrap(),
+            KeyAction::Command("split-window -h ; select-pane -n".to_owned())
+        );
+        assert!(KeyAction::parse("no-such-command").is_err());
+        assert_eq!(parse_prefix("C-a").unwrap(), 0x01);
+        assert!(parse_prefix("Up").is_err());
+        assert!(parse_prefix("ab").is_err());
+    }
+}
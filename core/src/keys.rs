@@ -0,0 +1,115 @@
+// kitty keyboard protocol keys (`CSI <code> ; <modifiers> u`) and their legacy encodings
+
+const SHIFT: u32 = 0b1;
+const ALT: u32 = 0b10;
+const CTRL: u32 = 0b100;
+const LOCKS: u32 = 0b1100_0000; // caps lock and num lock don't change what a legacy key sends
+
+/// a `CSI u` key at the start of `bytes`: (code, modifier bits, event type, sequence length)
+fn parse_csi_u(bytes: &[u8]) -> Option<(u32, u32, u32, usize)> {
+    let params = bytes.strip_prefix(b"\x1b[")?;
+    let end = params
+        .iter()
+        .position(|&b| !(b.is_ascii_digit() || b == b';' || b == b':'))?;
+    if params[end] != b'u' || end == 0 {
+        return None;
+    }
+    let params = std::str::from_utf8(&params[..end]).ok()?;
+    let mut fields = params.split(';');
+    // `code:shifted:base` and `modifiers:event`, the alternate codes aren't needed
+    let code = fields.next()?.split(':').next()?.parse().ok()?;
+    let (modifiers, event) = match fields.next() {
+        Some(field) => {
+            let mut parts = field.split(':');
+            let modifiers = parts.next().filter(|m| !m.is_empty()).map_or(Ok(1), str::parse).ok()?;
+            let event = parts.next().map_or(Ok(1), str::parse).ok()?;
+            (modifiers, event)
+        }
+        None => (1, 1),
+    };
+    Some((code, u32::saturating_sub(modifiers, 1), event, end + 3))
+}
+
+/// what a terminal without the kitty protocol sends for the key, `None` if it can't send it at all (e.g. super)
+pub fn legacy_key(code: u32, modifiers: u32) -> Option<Vec<u8>> {
+    let modifiers = modifiers & !LOCKS;
+    if modifiers & !(SHIFT | ALT | CTRL) != 0 {
+        return None;
+    }
+    let mut key = match code {
+        9 if modifiers & SHIFT != 0 => b"\x1b[Z".to_vec(),
+        13 => b"\r".to_vec(),
+        127 if modifiers & CTRL != 0 => b"\x08".to_vec(),
+        _ => {
+            let mut c = char::from_u32(code)?;
+            if modifiers & SHIFT != 0 {
+                c = c.to_ascii_uppercase();
+            }
+            match c {
+                'a'..='z' | 'A'..='Z' | '@' | '['..='_' | ' ' if modifiers & CTRL != 0 => {
+                    vec![c.to_ascii_uppercase() as u8 & 0x1f]
+                }
+                '?' if modifiers & CTRL != 0 => vec![0x7f],
+                _ => c.to_string().into_bytes(),
+            }
+        }
+    };
+    if modifiers & ALT != 0 {
+        key.insert(0, 0x1b);
+    }
+    Some(key)
+}
+
+/// replaces each `CSI u` key in `bytes` with what `rewrite(code, modifiers)` returns, keys it returns
+/// `None` for and all other bytes pass through untouched
+pub fn rewrite_keys(bytes: &[u8], mut rewrite: impl FnMut(u32, u32) -> Option<Vec<u8>>) -> Vec<u8> {
+    let mut output = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        if bytes[i] == 0x1b
+            && let Some((code, modifiers, event, len)) = parse_csi_u(&bytes[i..])
+        {
+            // key releases only exist in the kitty protocol
+            let replacement = if event == 3 {
+                Some(vec![])
+            } else {
+                rewrite(code, modifiers)
+            };
+            match replacement {
+                Some(replacement) => output.extend_from_slice(&replacement),
+                None => output.extend_from_slice(&bytes[i..i + len]),
+            }
+            i += len;
+        } else {
+            output.push(bytes[i]);
+            i += 1;
+        }
+    }
+    output
+}
+
+/// input for a program that never asked for the kitty protocol, keys without a legacy encoding are dropped
+pub fn to_legacy(bytes: &[u8]) -> Vec<u8> {
+    rewrite_keys(bytes, |code, modifiers| {
+        Some(legacy_key(code, modifiers).unwrap_or_default())
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_to_legacy() {
+        assert_eq!(to_legacy(b"\x1b[98;5u"), b"\x02");
+        assert_eq!(to_legacy(b"a\x1b[27ub"), b"a\x1bb");
+        assert_eq!(to_legacy(b"\x1b[120;3u"), b"\x1bx");
+        assert_eq!(to_legacy(b"\x1b[120;6u"), b"\x18");
+        assert_eq!(to_legacy(b"\x1b[9;2u"), b"\x1b[Z");
+        // super has no legacy encoding, releases are dropped
+        assert_eq!(to_legacy(b"\x1b[120;9u"), b"");
+        assert_eq!(to_legacy(b"\x1b[97;1:3u"), b"");
+        // other sequences are left alone
+        assert_eq!(to_legacy(b"\x1b[1;5A\x1b[?1u"), b"\x1b[1;5A\x1b[?1u");
+    }
+}
@@ -1,8 +1,14 @@
+pub mod colors;
 pub mod comm;
+pub mod commands;
 pub mod constants;
 pub mod daemon_utils;
 pub mod error;
 pub mod events;
+pub mod graphics;
+pub mod key_bindings;
+pub mod keys;
+pub mod links;
 pub mod messages;
 mod prelude;
 pub mod rand;
@@ -0,0 +1,80 @@
+/// url and file path detection for quick-open
+use serde::{Deserialize, Serialize};
+
+const URL_PREFIXES: [&str; 5] = ["https://", "http://", "file://", "ftp://", "www."];
+const PATH_PREFIXES: [&str; 4] = ["/", "~/", "./", "../"];
+// punctuation that usually surrounds a link in prose/log output rather than belonging to it
+const TRIM_CHARS: &[char] = &[
+    '(', ')', '[', ']', '<', '>', '{', '}', '"', '\'', '`', ',', '.', ';', ':', '!', '?',
+];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum LinkKind {
+    Url,
+    Path,
+}
+
+/// a link found on screen, `row`/`col` are 0 based terminal cells
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct LinkMatch {
+    pub text: String,
+    pub kind: LinkKind,
+    pub row: u16,
+    pub col: u16,
+}
+
+/// finds urls and paths in a single line of screen text, returns the column they start at
+pub fn find_links(line: &str) -> Vec<(usize, String, LinkKind)> {
+    let mut links = Vec::new();
+    let mut col = 0;
+    for word in line.split(' ') {
+        let leading = word.chars().take_while(|c| TRIM_CHARS.contains(c)).count();
+        let text = word.trim_matches(TRIM_CHARS);
+        if let Some(kind) = classify(text) {
+            links.push((col + leading, text.to_owned(), kind));
+        }
+        col += word.chars().count() + 1;
+    }
+    links
+}
+
+fn classify(text: &str) -> Option<LinkKind> {
+    if URL_PREFIXES
+        .iter()
+        .any(|prefix| text.starts_with(prefix) && text.len() > prefix.len())
+    {
+        return Some(LinkKind::Url);
+    }
+    // `dir/file` style relative paths need at least one alphanumeric segment on each side of a slash
+    let is_relative = text
+        .split_once('/')
+        .is_some_and(|(dir, file)| dir.chars().any(char::is_alphanumeric) && file.chars().any(char::is_alphanumeric));
+    let is_path = PATH_PREFIXES
+        .iter()
+        .any(|prefix| text.starts_with(prefix) && text.len() > prefix.len())
+        || is_relative;
+    (is_path && text.chars().any(char::is_alphanumeric) && !text.contains("://")).then_some(LinkKind::Path)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_find_links() {
+        let links = find_links("see (https://example.com/a?b=1), then open src/main.rs:12 or ~/notes.md.");
+        assert_eq!(
+            links,
+            vec![
+                (5, "https://example.com/a?b=1".to_owned(), LinkKind::Url),
+                (43, "src/main.rs:12".to_owned(), LinkKind::Path),
+                (61, "~/notes.md".to_owned(), LinkKind::Path),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_ignores_plain_text() {
+        assert!(find_links("a / b and/ or // nothing http://").is_empty());
+    }
+}
@@ -1,7 +1,9 @@
+pub mod protocol;
 pub mod request;
 pub mod response;
 mod traits;
 
+pub use protocol::{Features, VERSION};
 pub use request::{CliRequestMessage, RequestBuilder};
 pub use response::{ResponseBuilder, ResponseMessage, ResponseResult};
 pub use traits::{Message, RequestBody};
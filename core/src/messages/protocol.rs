@@ -0,0 +1,78 @@
+use std::ops::BitOr;
+
+use serde::{Deserialize, Serialize};
+
+/// sent with every request and response so a client and daemon from different builds notice before
+/// misreading each other's messages
+pub const VERSION: &str = env!("CARGO_PKG_VERSION");
+
+/// what an end of the connection can do, each end sends its own and both go with what they have in common
+#[derive(Clone, Copy, Default, Serialize, Deserialize, Debug, PartialEq, Eq)]
+#[serde(transparent)]
+pub struct Features(u32);
+
+impl Features {
+    pub const BINARY_FRAMES: Self = Self(1); // events are bincode encoded
+    pub const STATE_DELTAS: Self = Self(1 << 1); // state changes after the attach come as deltas
+    pub const CONTROL_MODE: Self = Self(1 << 2); // `attach --control` and control mode clients
+
+    /// everything this build can do
+    pub const SUPPORTED: Self = Self(Self::BINARY_FRAMES.0 | Self::STATE_DELTAS.0 | Self::CONTROL_MODE.0);
+    /// a client without these can't attach at all
+    pub const REQUIRED: Self = Self(Self::BINARY_FRAMES.0 | Self::STATE_DELTAS.0);
+
+    pub fn contains(self, other: Self) -> bool {
+        self.0 & other.0 == other.0
+    }
+
+    /// what both ends can do
+    pub fn negotiate(self, theirs: Self) -> Self {
+        Self(self.0 & theirs.0)
+    }
+}
+
+impl BitOr for Features {
+    type Output = Self;
+
+    fn bitor(self, rhs: Self) -> Self {
+        Self(self.0 | rhs.0)
+    }
+}
+
+/// before 1.0 a minor release may change the protocol, after it only a major one
+pub fn is_compatible(ours: &str, theirs: &str) -> bool {
+    let release = |version: &str| -> Option<(u64, u64)> {
+        let mut parts = version.split('.').map(str::parse::<u64>);
+        let major = parts.next()?.ok()?;
+        let minor = parts.next()?.ok()?;
+        Some(if major == 0 { (0, minor) } else { (major, 0) })
+    };
+    match (release(ours), release(theirs)) {
+        (Some(ours), Some(theirs)) => ours == theirs,
+        _ => false,
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_is_compatible() {
+        assert!(is_compatible("0.3.1", "0.3.0"));
+        assert!(!is_compatible("0.3.0", "0.5.0"));
+        assert!(is_compatible("1.2.0", "1.4.1"));
+        assert!(!is_compatible("1.2.0", "2.0.0"));
+        // builds from before versioning send nothing
+        assert!(!is_compatible(VERSION, ""));
+    }
+
+    #[test]
+    fn test_negotiate() {
+        let client = Features::BINARY_FRAMES | Features::STATE_DELTAS;
+        let negotiated = Features::SUPPORTED.negotiate(client);
+        assert!(negotiated.contains(Features::REQUIRED));
+        assert!(!negotiated.contains(Features::CONTROL_MODE));
+        assert!(!Features::default().contains(Features::REQUIRED));
+    }
+}
@@ -1,8 +1,16 @@
+use std::{
+    collections::HashMap,
+    fmt::{self, Debug, Formatter},
+    path::PathBuf,
+};
+
 use serde::{Deserialize, Serialize};
 use uuid::Uuid;
 
 use crate::{
+    colors::ColorDepth,
     messages::{
+        protocol::{Features, VERSION},
         response,
         traits::{Message, RequestBody},
     },
@@ -14,6 +22,8 @@ use crate::{
 #[derive(Clone, Serialize, Deserialize, Debug, PartialEq)]
 pub struct CliRequestMessage<T: RequestBody> {
     pub id: u32,
+    pub version: String,    // the client's `VERSION`
+    pub features: Features, // everything the client can do
     pub body: T,
 }
 impl<T: RequestBody + Serialize + for<'de> Deserialize<'de>> Message for CliRequestMessage<T> {}
@@ -23,12 +33,24 @@ impl<T: RequestBody + Serialize + for<'de> Deserialize<'de>> Message for CliRequ
 #[derive(Serialize, Deserialize, Debug, PartialEq)]
 pub struct DaemonRequestMessage {
     pub id: u32,
+    #[serde(default)]
+    pub version: String, // empty from builds older than versioning
+    #[serde(default)]
+    pub features: Features,
     pub body: DaemonRequestMessageBody,
 }
 #[derive(Serialize, Deserialize, Debug, PartialEq)]
 #[serde(untagged)]
 pub enum DaemonRequestMessageBody {
     Attach(Attach),
+    ControlAttach(ControlAttach),
+    Exec(Exec),
+    NewSession(NewSession),
+    KillSession(KillSession),
+    SourceFile(SourceFile),
+    RunCommand(RunCommand),
+    PreviewPane(PreviewPane),
+    ListSessions(ListSessions), // last, it matches any empty object
 }
 impl Message for DaemonRequestMessage {}
 
@@ -39,11 +61,123 @@ pub struct Attach {
     pub id: Uuid,
     pub session_name: String,
     pub create: bool,
+    #[serde(default)]
+    pub color_depth: ColorDepth, // output is downgraded to what the client's terminal can show
+    #[serde(default)]
+    pub environment: ClientEnvironment,
+    #[serde(default)]
+    pub command: Option<String>, // run through the shell by the first pane, when the attach creates the session
+    #[serde(default)]
+    pub argv: Vec<String>, // run directly by the first pane instead, from `remux new -- <command>`
+    #[serde(default)]
+    pub control: bool, // `attach --control` - raw per pane output instead of rendered frames
+    #[serde(default)]
+    pub detach_others: bool, // `attach -d` - every other client of the session is detached
 }
 impl RequestBody for Attach {
     type ResponseBody = response::Attach;
 }
 
+/// the attaching client's environment, `update-environment` copies variables from it into the session
+#[derive(Clone, Default, Serialize, Deserialize, PartialEq)]
+pub struct ClientEnvironment(pub HashMap<String, String>);
+
+impl ClientEnvironment {
+    pub fn current() -> Self {
+        Self(std::env::vars().collect())
+    }
+}
+
+// values can hold secrets, keep them out of the logs
+impl Debug for ClientEnvironment {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        f.debug_set().entries(self.0.keys()).finish()
+    }
+}
+
+/// attaches a control mode client - after the response the connection switches to a line based protocol
+#[derive(Clone, Serialize, Deserialize, Debug, PartialEq)]
+pub struct ControlAttach {
+    pub id: Uuid,
+    pub control_session_name: String,
+}
+impl RequestBody for ControlAttach {
+    type ResponseBody = response::Attach;
+}
+
+/// runs `command` in a new temporary session - after the response the connection streams its output
+/// followed by its exit code
+#[derive(Clone, Serialize, Deserialize, Debug, PartialEq)]
+pub struct Exec {
+    pub id: Uuid,
+    pub exec_session_name: Option<String>, // defaults to `exec-<session id>`
+    pub command: Vec<String>,
+}
+impl RequestBody for Exec {
+    type ResponseBody = response::Attach;
+}
+
+/// creates a session without attaching to it - the connection closes after the response
+#[derive(Clone, Serialize, Deserialize, Debug, PartialEq)]
+pub struct NewSession {
+    pub new_session_name: Option<String>, // defaults to the session's id
+    pub command: Option<String>,          // run through the shell by the first pane
+    pub argv: Vec<String>,                // run directly by the first pane instead
+    pub group_with: Option<String>,       // shares this session's windows instead of running anything
+}
+impl RequestBody for NewSession {
+    type ResponseBody = response::NewSession;
+}
+
+/// one-shot query answered with a summary of every session, the connection closes after the response
+#[derive(Clone, Serialize, Deserialize, Debug, PartialEq)]
+#[serde(deny_unknown_fields)]
+pub struct ListSessions {}
+impl RequestBody for ListSessions {
+    type ResponseBody = response::ListSessions;
+}
+
+/// kills a session by name or id, detaching its clients - the connection closes after the response
+#[derive(Clone, Serialize, Deserialize, Debug, PartialEq)]
+pub struct KillSession {
+    pub name_or_id: String,
+}
+impl RequestBody for KillSession {
+    type ResponseBody = ();
+}
+
+/// reruns the commands of config files on the live daemon - the connection closes after the response
+#[derive(Clone, Serialize, Deserialize, Debug, PartialEq)]
+pub struct SourceFile {
+    pub config_paths: Vec<PathBuf>, // absolute, the default config files when empty
+}
+impl RequestBody for SourceFile {
+    type ResponseBody = ();
+}
+
+/// runs a single command without attaching, against `target_session` when given -
+/// the connection closes after the response with whatever the command printed
+#[derive(Clone, Serialize, Deserialize, Debug, PartialEq)]
+pub struct RunCommand {
+    pub command_args: Vec<String>, // the command name and its arguments, already split
+    pub target_session: Option<String>,
+}
+impl RequestBody for RunCommand {
+    type ResponseBody = response::CommandOutput;
+}
+
+/// a snapshot of a session's active pane for the session switcher, made on a connection of its own while
+/// the attached one carries on - the connection closes after the response
+#[derive(Clone, Serialize, Deserialize, Debug, PartialEq)]
+pub struct PreviewPane {
+    pub preview_session: String, // name or id
+    pub rows: u16,               // the snapshot is cropped to the panel it's drawn in
+    pub cols: u16,
+}
+impl RequestBody for PreviewPane {
+    type ResponseBody = response::PreviewPane;
+}
+
 // --------- builder ---------  //
 
 pub struct BodyUnset;
@@ -74,7 +208,64 @@ impl<T: RequestBody> RequestBuilder<BodySet<T>> {
     pub fn build(self) -> CliRequestMessage<T> {
         CliRequestMessage {
             id: self.id,
+            version: VERSION.to_owned(),
+            features: Features::SUPPORTED,
             body: self.body,
         }
     }
 }
+
+#[cfg(test)]
+mod test {
+    #![allow(clippy::unwrap_used)]
+    use super::*;
+
+    #[test]
+    fn test_untagged_request_bodies() {
+        let list = serde_json::to_string(&ListSessions {}).unwrap();
+        assert_eq!(
+            serde_json::from_str::<DaemonRequestMessageBody>(&list).unwrap(),
+            DaemonRequestMessageBody::ListSessions(ListSessions {})
+        );
+        let control = ControlAttach {
+            id: Uuid::nil(),
+            control_session_name: "main".to_owned(),
+        };
+        assert_eq!(
+            serde_json::from_str::<DaemonRequestMessageBody>(&serde_json::to_string(&control).unwrap()).unwrap(),
+            DaemonRequestMessageBody::ControlAttach(control)
+        );
+        let source = SourceFile { config_paths: vec![] };
+        assert_eq!(
+            serde_json::from_str::<DaemonRequestMessageBody>(&serde_json::to_string(&source).unwrap()).unwrap(),
+            DaemonRequestMessageBody::SourceFile(source)
+        );
+        let run = RunCommand {
+            command_args: vec!["show-buffer".to_owned()],
+            target_session: None,
+        };
+        assert_eq!(
+            serde_json::from_str::<DaemonRequestMessageBody>(&serde_json::to_string(&run).unwrap()).unwrap(),
+            DaemonRequestMessageBody::RunCommand(run)
+        );
+        let new = NewSession {
+            new_session_name: None,
+            command: None,
+            argv: vec![],
+            group_with: Some("work".to_owned()),
+        };
+        assert_eq!(
+            serde_json::from_str::<DaemonRequestMessageBody>(&serde_json::to_string(&new).unwrap()).unwrap(),
+            DaemonRequestMessageBody::NewSession(new)
+        );
+        let preview = PreviewPane {
+            preview_session: "1".to_owned(),
+            rows: 10,
+            cols: 40,
+        };
+        assert_eq!(
+            serde_json::from_str::<DaemonRequestMessageBody>(&serde_json::to_string(&preview).unwrap()).unwrap(),
+            DaemonRequestMessageBody::PreviewPane(preview)
+        );
+    }
+}
@@ -1,16 +1,27 @@
+use std::time::SystemTime;
+
 use serde::{Deserialize, Serialize};
 
-use crate::{messages::traits::Message, rand, states::DaemonState};
+use crate::{
+    messages::{
+        protocol::{Features, VERSION},
+        traits::Message,
+    },
+    rand,
+    states::DaemonState,
+};
 
 #[derive(Clone, Serialize, Deserialize, Debug, PartialEq)]
 pub struct ResponseMessage<T> {
     pub id: u32,
+    #[serde(default)]
+    pub version: String, // the daemon's `VERSION`, empty from builds older than versioning
     pub result: ResponseResult<T>,
 }
 impl<T: Serialize + for<'de> Deserialize<'de>> Message for ResponseMessage<T> {}
 
 #[derive(Clone, Serialize, Deserialize, Debug, PartialEq)]
-#[serde(tag = "type")]
+#[serde(tag = "type", content = "body")] // adjacent, an internal tag can't hold the failure's string
 pub enum ResponseResult<T> {
     Success(T),
     Failure(String),
@@ -21,6 +32,66 @@ pub enum ResponseResult<T> {
 #[derive(Clone, Serialize, Deserialize, Debug, PartialEq)]
 pub struct Attach {
     pub initial_daemon_state: DaemonState,
+    #[serde(default)]
+    pub features: Features, // what both the client and the daemon can do
+}
+
+/// the session `remux new` created
+#[derive(Clone, Serialize, Deserialize, Debug, PartialEq)]
+pub struct NewSession {
+    pub id: u32,
+    pub name: String,
+}
+
+#[derive(Clone, Serialize, Deserialize, Debug, PartialEq)]
+pub struct CommandOutput {
+    pub output: Option<String>, // e.g. the buffer's text for `show-buffer`
+}
+
+#[derive(Clone, Serialize, Deserialize, Debug, PartialEq)]
+pub struct ListSessions {
+    pub sessions: Vec<SessionListing>, // ordered by id
+}
+
+/// a session as shown by `remux ls`
+#[derive(Clone, Serialize, Deserialize, Debug, PartialEq)]
+pub struct SessionListing {
+    pub id: u32,
+    pub name: String,
+    pub windows: usize,
+    pub panes: usize,
+    pub clients: usize, // attached clients, control mode ones included
+    pub created: SystemTime,
+}
+
+/// the rows of a pane's screen from the top, each one split into runs of equally styled text
+#[derive(Clone, Default, Serialize, Deserialize, Debug, PartialEq)]
+pub struct PreviewPane {
+    pub lines: Vec<Vec<StyledText>>,
+}
+
+#[derive(Clone, Default, Serialize, Deserialize, Debug, PartialEq)]
+pub struct StyledText {
+    pub text: String,
+    pub style: TextStyle,
+}
+
+#[derive(Clone, Copy, Default, Serialize, Deserialize, Debug, PartialEq, Eq)]
+pub struct TextStyle {
+    pub fg: TextColor,
+    pub bg: TextColor,
+    pub bold: bool,
+    pub italic: bool,
+    pub underline: bool,
+    pub inverse: bool,
+}
+
+#[derive(Clone, Copy, Default, Serialize, Deserialize, Debug, PartialEq, Eq)]
+pub enum TextColor {
+    #[default]
+    Default,
+    Indexed(u8),
+    Rgb(u8, u8, u8),
 }
 
 // --------- builder ---------  //
@@ -52,7 +123,25 @@ impl<T> ResponseBuilder<ResultSet<T>> {
     pub fn build(self) -> ResponseMessage<T> {
         ResponseMessage {
             id: self.id,
+            version: VERSION.to_owned(),
             result: self.result,
         }
     }
 }
+
+#[cfg(test)]
+mod test {
+    #![allow(clippy::unwrap_used)]
+    use super::*;
+
+    #[test]
+    fn test_response_result_round_trip() {
+        for result in [
+            ResponseResult::Success(()),
+            ResponseResult::Failure("no session".to_owned()),
+        ] {
+            let json = serde_json::to_string(&result).unwrap();
+            assert_eq!(serde_json::from_str::<ResponseResult<()>>(&json).unwrap(), result);
+        }
+    }
+}
@@ -1,47 +1,357 @@
 /// comprehensive summary of the state of the daemon
 use serde::{Deserialize, Serialize};
 
+use crate::{events::StateDelta, key_bindings::KeyBindings};
+
 #[derive(Default, Serialize, Deserialize, Debug, Clone, PartialEq)]
 pub struct SessionInfo {
     pub id: u32,
     pub name: String,
+    pub alert: Option<Alert>, // set while a monitored session hasn't been looked at
+    pub clients: usize,       // attached to it, this client included
+}
+
+/// `monitor-activity` and `monitor-silence` alerts raised by a session nobody is viewing
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Alert {
+    Activity,
+    Silence,
+}
+
+/// a line matching a session wide search, `line` counts from the oldest scrollback line of the pane
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct SearchMatch {
+    pub pane_id: usize,
+    pub line: usize,
+    pub text: String,
+}
+
+/// a client attached to the same session, `size` is `None` for control mode clients
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct ClientInfo {
+    pub name: String,
+    pub size: Option<(u16, u16)>,    // rows, cols
+    pub pointer: Option<(u16, u16)>, // the cell it last clicked, only sent with `client-cursors` on
+    pub current: bool,               // the client receiving the list
+}
+
+/// where a pane is drawn on the client's terminal, 0 based cells
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct PaneLocation {
+    pub pane_id: usize,
+    pub row: u16,
+    pub col: u16,
+    pub width: u16,
+    pub height: u16,
+}
+
+/// a session in the `choose-tree` browser, a snapshot taken when it was opened
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct TreeSession {
+    pub id: u32,
+    pub name: String,
+    pub windows: Vec<TreeWindow>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct TreeWindow {
+    pub index: usize,
+    pub name: String,
+    pub panes: Vec<usize>, // ids in layout order, the floating pane last
+    pub active_pane: usize,
+    pub active: bool, // the session's active window
+}
+
+/// a window of the active session, in the order `select-window -t` numbers them
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct WindowInfo {
+    pub id: usize, // stays the same as windows before it close, unlike `index`
+    pub index: usize,
+    pub name: String,
+    pub panes: usize,
+    pub active_pane: usize, // id of the pane receiving input
+    pub activity: bool,     // output arrived since the window was last selected
+    pub bell: bool,         // a pane rang the bell since the window was last selected
 }
 
 #[derive(Default, Serialize, Deserialize, Debug, Clone, PartialEq)]
 pub struct DaemonState {
     pub sessions: Vec<SessionInfo>,
     pub active_session: Option<u32>,
-    // window_ids: Vec<u32>,
-    // pub active_window: Option<u32>,
+    pub last_session: Option<u32>, // this client's previous session, where `switch-client -l` goes
+    pub windows: Vec<WindowInfo>,  // of the active session
+    pub active_window: Option<usize>, // index into `windows`
+    pub clients: Vec<ClientInfo>,  // everyone attached to the active session, this client included
+    pub key_bindings: KeyBindings,
 }
 
 impl DaemonState {
-    pub fn set_sessions(&mut self, sessions: Vec<(u32, String)>) {
+    /// `(id, name, clients)` of every session
+    pub fn set_sessions(&mut self, sessions: Vec<(u32, String, usize)>) {
         self.sessions = sessions
             .into_iter()
-            .map(|(id, name)| SessionInfo { id, name })
+            .map(|(id, name, clients)| SessionInfo {
+                id,
+                name,
+                alert: None,
+                clients,
+            })
             .collect();
+        self.sessions.sort_by_key(|info| info.id);
     }
     pub fn add_session(&mut self, id: u32, name: String) {
-        let i = self
-            .sessions
-            .binary_search_by_key(&id, |info| info.id)
-            .unwrap_or_else(|i| i);
-        self.sessions.insert(i, SessionInfo { id, name });
-    }
-    // pub fn remove_session(&mut self, session_id: u32) {
-    //     self.session_ids.retain(|s| s != &session_id);
-    // }
+        match self.sessions.binary_search_by_key(&id, |info| info.id) {
+            // the session came with the attach snapshot too
+            Ok(i) => self.sessions[i].name = name,
+            Err(i) => self.sessions.insert(
+                i,
+                SessionInfo {
+                    id,
+                    name,
+                    alert: None,
+                    clients: 0,
+                },
+            ),
+        }
+    }
+    pub fn remove_session(&mut self, session_id: u32) {
+        self.sessions.retain(|info| info.id != session_id);
+        if self.last_session == Some(session_id) {
+            self.last_session = None;
+        }
+        if self.active_session == Some(session_id) {
+            self.active_session = None;
+            self.clients.clear();
+            self.windows.clear();
+            self.active_window = None;
+        }
+    }
     pub fn set_active_session(&mut self, session_id: u32) {
         self.active_session = Some(session_id);
+        self.set_alert(session_id, None);
+    }
+    pub fn set_alert(&mut self, session_id: u32, alert: Option<Alert>) {
+        if let Some(info) = self.sessions.iter_mut().find(|info| info.id == session_id) {
+            info.alert = alert;
+        }
+    }
+    pub fn set_windows(&mut self, windows: Vec<WindowInfo>, active: usize) {
+        self.active_window = (active < windows.len()).then_some(active);
+        self.windows = windows;
+    }
+    pub fn active_session(&self) -> Option<&SessionInfo> {
+        let id = self.active_session?;
+        self.sessions.iter().find(|info| info.id == id)
+    }
+    pub fn last_session(&self) -> Option<&SessionInfo> {
+        let id = self.last_session?;
+        self.sessions.iter().find(|info| info.id == id)
+    }
+    pub fn active_window(&self) -> Option<&WindowInfo> {
+        self.windows.get(self.active_window?)
+    }
+    pub fn apply(&mut self, delta: StateDelta) {
+        match delta {
+            StateDelta::SessionAdded { id, name } => self.add_session(id, name),
+            StateDelta::SessionRemoved { id } => self.remove_session(id),
+            StateDelta::SessionRenamed { id, name } => {
+                if let Some(info) = self.sessions.iter_mut().find(|info| info.id == id) {
+                    info.name = name;
+                }
+            }
+            StateDelta::ClientCountChanged { id, clients } => {
+                if let Some(info) = self.sessions.iter_mut().find(|info| info.id == id) {
+                    info.clients = clients;
+                }
+            }
+            StateDelta::WindowAdded(window) => {
+                let active = self.active_window().map(|window| window.id);
+                self.windows.retain(|w| w.id != window.id);
+                let i = window.index.min(self.windows.len());
+                self.windows.insert(i, window);
+                self.renumber_windows(active);
+            }
+            StateDelta::WindowRemoved { id } => {
+                let active = self.active_window().map(|window| window.id);
+                self.windows.retain(|window| window.id != id);
+                self.renumber_windows(active);
+            }
+            StateDelta::WindowSelected { id } => self.renumber_windows(Some(id)),
+            StateDelta::WindowRenamed { id, name } => {
+                if let Some(window) = self.window_mut(id) {
+                    window.name = name;
+                }
+            }
+            StateDelta::WindowActivity { id, activity, bell } => {
+                if let Some(window) = self.window_mut(id) {
+                    (window.activity, window.bell) = (activity, bell);
+                }
+            }
+            StateDelta::PaneCountChanged { id, panes, active_pane } => {
+                if let Some(window) = self.window_mut(id) {
+                    (window.panes, window.active_pane) = (panes, active_pane);
+                }
+            }
+        }
+    }
+    fn window_mut(&mut self, id: usize) -> Option<&mut WindowInfo> {
+        self.windows.iter_mut().find(|window| window.id == id)
+    }
+    /// indexes follow the windows' positions, the active window is kept by id
+    fn renumber_windows(&mut self, active: Option<usize>) {
+        for (i, window) in self.windows.iter_mut().enumerate() {
+            window.index = i;
+        }
+        self.active_window = active.and_then(|id| self.windows.iter().position(|window| window.id == id));
+    }
+}
+
+/// the deltas taking a session's window list from `old` to `new`, `None` when windows were reordered and
+/// the list has to be sent whole
+pub fn window_deltas(
+    old: &[WindowInfo],
+    old_active: usize,
+    new: &[WindowInfo],
+    new_active: usize,
+) -> Option<Vec<StateDelta>> {
+    let kept = |windows: &[WindowInfo], others: &[WindowInfo]| -> Vec<usize> {
+        windows
+            .iter()
+            .map(|window| window.id)
+            .filter(|id| others.iter().any(|other| other.id == *id))
+            .collect()
+    };
+    if kept(old, new) != kept(new, old) {
+        return None;
+    }
+    let mut deltas = Vec::new();
+    for window in old {
+        if !new.iter().any(|w| w.id == window.id) {
+            deltas.push(StateDelta::WindowRemoved { id: window.id });
+        }
+    }
+    for window in new {
+        let Some(previous) = old.iter().find(|w| w.id == window.id) else {
+            deltas.push(StateDelta::WindowAdded(window.clone()));
+            continue;
+        };
+        let id = window.id;
+        if previous.name != window.name {
+            deltas.push(StateDelta::WindowRenamed {
+                id,
+                name: window.name.clone(),
+            });
+        }
+        if (previous.activity, previous.bell) != (window.activity, window.bell) {
+            deltas.push(StateDelta::WindowActivity {
+                id,
+                activity: window.activity,
+                bell: window.bell,
+            });
+        }
+        if (previous.panes, previous.active_pane) != (window.panes, window.active_pane) {
+            deltas.push(StateDelta::PaneCountChanged {
+                id,
+                panes: window.panes,
+                active_pane: window.active_pane,
+            });
+        }
+    }
+    let active_id = |windows: &[WindowInfo], active: usize| windows.get(active).map(|window| window.id);
+    if let Some(id) = active_id(new, new_active)
+        && active_id(old, old_active) != Some(id)
+    {
+        deltas.push(StateDelta::WindowSelected { id });
+    }
+    Some(deltas)
+}
+
+#[cfg(test)]
+mod test {
+    #![allow(clippy::unwrap_used)]
+    use super::*;
+
+    fn window(id: usize, index: usize) -> WindowInfo {
+        WindowInfo {
+            id,
+            index,
+            name: format!("window {id}"),
+            panes: 1,
+            active_pane: 0,
+            activity: false,
+            bell: false,
+        }
+    }
+
+    #[test]
+    fn test_apply() {
+        let mut state = DaemonState::default();
+        state.set_sessions(vec![(1, "b".to_owned(), 1), (0, "a".to_owned(), 0)]);
+        state.set_active_session(1);
+        state.set_windows(vec![window(0, 0), window(3, 1), window(4, 2)], 1);
+
+        state.apply(StateDelta::SessionAdded {
+            id: 2,
+            name: "c".to_owned(),
+        });
+        // already known from the snapshot
+        state.apply(StateDelta::SessionAdded {
+            id: 2,
+            name: "c".to_owned(),
+        });
+        state.apply(StateDelta::ClientCountChanged { id: 0, clients: 2 });
+        assert_eq!(state.sessions.iter().map(|info| info.id).collect::<Vec<_>>(), [0, 1, 2]);
+        assert_eq!(state.sessions[0].clients, 2);
+
+        // the active window keeps its id as the ones before it go
+        state.apply(StateDelta::WindowRemoved { id: 0 });
+        assert_eq!(state.active_window().unwrap().id, 3);
+        assert_eq!(state.active_window, Some(0));
+        state.apply(StateDelta::WindowAdded(window(5, 2)));
+        state.apply(StateDelta::WindowAdded(window(5, 2)));
+        state.apply(StateDelta::WindowSelected { id: 5 });
+        state.apply(StateDelta::PaneCountChanged {
+            id: 5,
+            panes: 2,
+            active_pane: 7,
+        });
+        assert_eq!(
+            state.windows.iter().map(|w| (w.id, w.index)).collect::<Vec<_>>(),
+            [(3, 0), (4, 1), (5, 2)]
+        );
+        assert_eq!(state.active_window().unwrap().active_pane, 7);
+
+        state.last_session = Some(0);
+        state.apply(StateDelta::SessionRemoved { id: 0 });
+        assert_eq!(state.last_session(), None);
+        assert_eq!(state.active_session().unwrap().id, 1);
+
+        state.apply(StateDelta::SessionRemoved { id: 1 });
+        assert!(state.windows.is_empty());
+        assert_eq!(state.active_window, None);
+    }
+
+    #[test]
+    fn test_window_deltas() {
+        let old = vec![window(0, 0), window(1, 1), window(2, 2)];
+        let mut new = vec![window(0, 0), window(2, 1), window(3, 2)];
+        new[0].name = "vim".to_owned();
+        new[1].panes = 2;
+        new[1].bell = true;
+        let deltas = window_deltas(&old, 1, &new, 2).unwrap();
+        assert_eq!(deltas.len(), 6);
+
+        let mut state = DaemonState::default();
+        state.set_windows(old.clone(), 1);
+        for delta in deltas {
+            state.apply(delta);
+        }
+        assert_eq!(state.windows, new);
+        assert_eq!(state.active_window, Some(2));
+        assert_eq!(window_deltas(&new, 2, &new, 2), Some(vec![]));
+
+        // swapped windows can't be described by deltas
+        let swapped = vec![window(1, 0), window(0, 1), window(2, 2)];
+        assert_eq!(window_deltas(&old, 0, &swapped, 0), None);
     }
-    // pub fn add_window(&mut self, window_id: u32) {
-    //     todo!()
-    // }
-    // pub fn remove_window(&mut self, window_id: u32) {
-    //     todo!()
-    // }
-    // pub fn set_active_window(&mut self, window_id: u32) {
-    //     todo!()
-    // }
 }
@@ -0,0 +1,115 @@
+use handle_macro::Handle;
+use tokio::sync::{mpsc, oneshot};
+use tracing::Span;
+
+use crate::{actors::message_span, paste_buffers::PasteBuffers, prelude::*};
+
+#[derive(Handle, Debug)]
+#[handle(spawn, error = Error)]
+pub enum BufferManagerEvent {
+    Push(String), // becomes the newest buffer under an automatic name
+    Set {
+        name: String,
+        text: String,
+    }, // replaces the buffer called `name`
+    // by name or index, the newest buffer if `None`
+    #[reply(Option<String>)]
+    Get {
+        buffer: Option<String>,
+        reply: oneshot::Sender<Option<String>>,
+    },
+    // false if there was no such buffer
+    #[reply(bool)]
+    Delete {
+        buffer: Option<String>,
+        reply: oneshot::Sender<bool>,
+    },
+    // for `choose-buffer`, newest first
+    #[reply(Vec<String>)]
+    Previews {
+        reply: oneshot::Sender<Vec<String>>,
+    },
+}
+use BufferManagerEvent::*;
+
+/// owns the paste buffers every session copies into and pastes from
+pub struct BufferManager {
+    rx: mpsc::Receiver<(BufferManagerEvent, Span)>,
+    buffers: PasteBuffers,
+}
+
+impl BufferManager {
+    pub fn spawn() -> BufferManagerHandle {
+        let (handle, rx) = BufferManagerHandle::spawn(10);
+        let buffer_manager = Self {
+            rx,
+            buffers: PasteBuffers::default(),
+        };
+        buffer_manager.run();
+        handle
+    }
+
+    fn run(mut self) {
+        let _task = tokio::spawn(
+            async move {
+                while let Some((event, cause)) = self.rx.recv().await {
+                    let _span = message_span(&cause).entered();
+                    trace!(event=?event);
+                    self.handle_event(event);
+                }
+            }
+            .instrument(error_span!(parent: None, "Buffer Manager")),
+        );
+    }
+
+    fn handle_event(&mut self, event: BufferManagerEvent) {
+        match event {
+            Push(text) => self.buffers.push(text),
+            Set { name, text } => self.buffers.set(name, text),
+            Get { buffer, reply } => {
+                let text = self
+                    .buffers
+                    .find(buffer.as_deref())
+                    .and_then(|index| self.buffers.get(index))
+                    .map(str::to_owned);
+                let _ = reply.send(text);
+            }
+            Delete { buffer, reply } => {
+                let removed = self
+                    .buffers
+                    .find(buffer.as_deref())
+                    .and_then(|index| self.buffers.remove(index));
+                let _ = reply.send(removed.is_some());
+            }
+            Previews { reply } => {
+                let _ = reply.send(self.buffers.previews());
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_buffer_manager() {
+        let buffers = BufferManager::spawn();
+        buffers.push("first".to_owned()).await.unwrap();
+        buffers.set("named".to_owned(), "second".to_owned()).await.unwrap();
+        assert_eq!(buffers.get(None).await.unwrap().as_deref(), Some("second"));
+        assert_eq!(
+            buffers.get(Some("buffer0".to_owned())).await.unwrap().as_deref(),
+            Some("first")
+        );
+        assert_eq!(
+            buffers.get(Some("1".to_owned())).await.unwrap().as_deref(),
+            Some("first")
+        );
+
+        assert!(!buffers.delete(Some("missing".to_owned())).await.unwrap());
+        assert!(buffers.delete(None).await.unwrap());
+        assert_eq!(buffers.previews().await.unwrap().len(), 1);
+        assert_eq!(buffers.get(Some("named".to_owned())).await.unwrap(), None);
+    }
+}
@@ -1,33 +1,87 @@
 use bytes::Bytes;
 use handle_macro::Handle;
 use remux_core::{
+    colors::{ColorDepth, ColorDowngrade},
     comm,
-    events::DaemonEvent,
-    messages::{ResponseBuilder, ResponseResult, response},
-    states::DaemonState,
+    commands::{HookEvent, RemuxCommand, SwitchTarget, parse_sequence},
+    events::{self, CliEvent, DaemonEvent, DetachReason, MouseKind, StateDelta},
+    key_bindings::KeyBindings,
+    links::LinkMatch,
+    messages::{
+        Features, ResponseBuilder, ResponseResult,
+        request::{self, ClientEnvironment},
+        response,
+    },
+    states::{Alert, ClientInfo, DaemonState, PaneLocation, SearchMatch, TreeSession, WindowInfo},
 };
 use tokio::{net::UnixStream, sync::mpsc};
+use tracing::Span;
 use uuid::Uuid;
 
-use crate::{actors::session_manager::SessionManagerHandle, layout::SplitDirection, prelude::*};
+use crate::{
+    actors::{
+        continue_trace,
+        session_manager::{CommandContext, NewSessionArgs, SessionManagerHandle},
+        start_trace,
+    },
+    layout::SplitDirection,
+    output_batch::OutputCredit,
+    prelude::*,
+};
 
 #[allow(unused)]
 #[derive(Handle, Debug)]
+#[handle(capacity = 10, error = Error)] // `channel()` is shared with the control mode and exec clients
 pub enum ClientConnectionEvent {
     // AttachToSession(u32),
     SuccessAttachToSession(u32),
+    LastSession(Option<u32>), // sent after every switch, see `switch-client -l`
     FailedAttachToSession(u32),
     DetachFromSession(u32),
-    SessionOutput(Bytes),
-    Disconnect,
-
-    // client side state update events
-    NewSession(u32, String),
+    SessionOutput(Bytes, OutputCredit), // the credit is released once the bytes are on the socket
+    PaneOutput {
+        pane_id: usize,
+        bytes: Bytes,
+        credit: OutputCredit,
+    }, // raw pane output for control mode clients
+    Notify(String),                     // shorthand for an info message
+    Message(events::Message),
+    Buffers(Vec<String>),  // paste buffer previews for choose-buffer
+    Links(Vec<LinkMatch>), // links on the active pane's screen for quick-open
+    SearchResults(Vec<SearchMatch>),
+    PaneLocations(Vec<PaneLocation>), // pane rects for jump labels
+    Tree(Vec<TreeSession>, bool),     // every session for choose-tree, only the sessions showing if true
+    PaneCapture {
+        rows: u16,
+        cols: u16,
+        contents: Bytes,
+    }, // a pane's screen for the choose-tree preview
+    SessionAlert {
+        session_id: u32,
+        alert: Alert,
+        bell: bool,
+    },
+    Bell(u32, usize), // (session id, window index)
+    WindowSize {
+        rows: u16,
+        cols: u16,
+    }, // the session's window can be smaller than the client's terminal
+    SessionClients(Vec<ClientInfo>),
+    SessionWindows {
+        windows: Vec<WindowInfo>,
+        active: usize,
+    },
+    ExecFinished(i32), // the command of a `remux exec` session exited with this code
+    CommandFinished(u32, Result<Option<String>>), // a control mode command, by number, and what it printed
+    ScreenReader(bool),
+    StatusLine(bool),
+    KeyBindings(KeyBindings),
+    HookFired(HookEvent, Option<String>),
+    StateDelta(StateDelta), // sessions or the attached session's windows changed
+    Disconnect(DetachReason),
 
     // variants related to initialization phase
     InitialAttach(u32), // invoked directly by the daemon
-    // this variant is unique in that it responds to client by sending a message not an event
-    InitialAttachResult(Result<DaemonState>),
 }
 use ClientConnectionEvent::*;
 
@@ -42,23 +96,49 @@ pub struct ClientConnection {
     id: Uuid,
     stream: UnixStream,
     handle: ClientConnectionHandle,
-    rx: mpsc::Receiver<ClientConnectionEvent>,
+    rx: mpsc::Receiver<(ClientConnectionEvent, Span)>,
     session_manager_handle: SessionManagerHandle,
     state: ClientConnectionState,
+    colors: ColorDowngrade,
+    control: bool,      // `attach --control`, gets raw pane output instead of rendered frames
+    features: Features, // negotiated with the cli, passed back in the attach response
 }
 impl ClientConnection {
     pub fn spawn(
-        id: Uuid,
         stream: UnixStream,
         session_manager_handle: SessionManagerHandle,
-        initial_session_name: &str,
+        attach: request::Attach,
+        features: Features,
     ) -> Result<ClientConnectionHandle> {
-        let client = Self::new(id, stream, session_manager_handle);
-        client.run(initial_session_name)
+        let client = Self::new(
+            attach.id,
+            stream,
+            session_manager_handle,
+            attach.color_depth,
+            attach.control,
+            features,
+        );
+        let new_session = attach.create.then_some(NewSessionArgs {
+            command: attach.command,
+            argv: attach.argv,
+            group_with: None,
+        });
+        client.run(
+            &attach.session_name,
+            attach.environment,
+            new_session,
+            attach.detach_others,
+        )
     }
-    fn new(id: Uuid, stream: UnixStream, session_manager_handle: SessionManagerHandle) -> Self {
-        let (tx, rx) = mpsc::channel(10);
-        let handle = ClientConnectionHandle { tx };
+    fn new(
+        id: Uuid,
+        stream: UnixStream,
+        session_manager_handle: SessionManagerHandle,
+        color_depth: ColorDepth,
+        control: bool,
+        features: Features,
+    ) -> Self {
+        let (handle, rx) = ClientConnectionHandle::channel();
 
         Self {
             id,
@@ -67,124 +147,426 @@ impl ClientConnection {
             rx,
             session_manager_handle,
             state: ClientConnectionState::Unattached,
+            colors: ColorDowngrade::new(color_depth),
+            control,
+            features,
+        }
+    }
+    /// commands from this client run against whatever session it is attached to
+    fn command_context(&self) -> CommandContext {
+        CommandContext {
+            client_id: Some(self.id),
+            session_id: None,
         }
     }
-    fn run(mut self, initial_session_name: &str) -> Result<ClientConnectionHandle> {
+    async fn switch_client(&self, target: SwitchTarget) -> Result<()> {
+        self.session_manager_handle
+            .run_command(self.command_context(), RemuxCommand::SwitchClient { target })
+            .await
+    }
+    fn run(
+        mut self,
+        initial_session_name: &str,
+        environment: ClientEnvironment,
+        new_session: Option<NewSessionArgs>, // `None` fails the attach when the session doesn't exist
+        detach_others: bool,                 // `attach -d`
+    ) -> Result<ClientConnectionHandle> {
         let handle_clone = self.handle.clone();
         let session_name = initial_session_name.to_owned();
+        let id = self.id;
         let _task = tokio::spawn(
             async move {
                 let handle = self.handle.clone();
-                self.session_manager_handle.client_connect(self.id, handle.clone(), Some(session_name), true).await?;
+                let result = self
+                    .session_manager_handle
+                    .client_connect(self.id, handle.clone(), Some(session_name), new_session, self.control, environment)
+                    .await
+                    .and_then(|result| result);
+                if !self.send_attach_result(result).await? {
+                    return Ok(());
+                }
+                if detach_others {
+                    self.session_manager_handle
+                        .run_command(self.command_context(), RemuxCommand::DetachClient { others: true })
+                        .await?;
+                }
                 loop {
-                    use remux_core::events::CliEvent;
                     tokio::select! {
-                        Some(event) = self.rx.recv() => {
+                        Some((event, cause)) = self.rx.recv() => {
                             let span = error_span!("Recieved Client Connection Event");
+                            continue_trace(&span, &cause);
                             let _guard = span.enter();
                             match &event {
-                                SessionOutput(bytes) => {
+                                SessionOutput(bytes, _) => {
                                     trace!(event=?event, num_bytes=bytes.len());
                                 }
                                 _ => {
                                     info!(event=?event);
                                 }
                             }
-                            match event {
-                                InitialAttachResult(result) if matches!(self.state, ClientConnectionState::Unattached) => {
-                                    match result {
-                                        Ok(daemon_state) => {
-                                            let res = ResponseBuilder::default().result(ResponseResult::Success(response::Attach{initial_daemon_state: daemon_state})).build();
-                                            info!(respnse=?res, "Sending response");
-                                            comm::send_message(&mut self.stream, &res).await.unwrap();
-                                            self.state = ClientConnectionState::Attached;
-                                        }
-                                        Err(e) => {
-                                            comm::send_message(&mut self.stream, &ResponseBuilder::default().result(ResponseResult::Failure::<()>(e.to_string())).build()).await.unwrap();
-                                        }
-                                    }
-                                }
-                                SuccessAttachToSession(session_id) => {
-                                    self.state = ClientConnectionState::Attached;
-                                    comm::send_event(&mut self.stream, DaemonEvent::ActiveSession(session_id)).await.unwrap();
-                                }
-                                FailedAttachToSession(..) => {
-                                    comm::send_event(&mut self.stream, DaemonEvent::Disconnected).await.unwrap();
-                                }
-                                DetachFromSession(..) => {
-                                    self.state = ClientConnectionState::Unattached;
-                                }
-                                Disconnect => {
-                                    comm::send_event(&mut self.stream, DaemonEvent::Disconnected).await.unwrap();
-                                }
-                                SessionOutput(bytes) => {
-                                    comm::send_event(&mut self.stream, DaemonEvent::Raw(bytes)).await.unwrap();
-                                }
-                                NewSession(session_id, session_name) => {
-                                    comm::send_event(&mut self.stream, DaemonEvent::NewSession(session_id, session_name)).await.unwrap();
-                                }
-                                _ => {
-                                    error!(event=?event, state=?self.state, "Unhandled or invalid event for current state");
-                                }
+                            // the cli exits once it's told why it was disconnected
+                            let disconnected = matches!(event, Disconnect(..));
+                            // the socket is gone, there's no one left to send anything to
+                            if let Err(e) = self.handle_event(event).await {
+                                debug!("Client disconnected because of error sending daemon event: {e}");
+                                let _ = self.session_manager_handle.client_disconnect(self.id).await;
+                                break;
+                            }
+                            if disconnected {
+                                break;
                             }
                         },
                         res = comm::recv_cli_event(&mut self.stream), if matches!(self.state, ClientConnectionState::Attached) => {
-                            match res {
+                            let res = match res {
                                 Ok(event) => {
+                                    // each keypress is a trace of its own, followed through every actor it reaches
                                     let span = error_span!("Recieved Cli Event", event=?event);
+                                    start_trace(&span);
                                     let _guard = span.enter();
                                     match &event {
-                                        CliEvent::Raw(..) => {
+                                        CliEvent::Raw(..) | CliEvent::Paste(..) => {
                                             trace!(event=?event);
                                         }
                                         _ => {
                                             info!(event=?event);
                                         }
                                     }
-                                    match event {
-                                        CliEvent::Raw(bytes) => {
-                                            self.session_manager_handle.user_input(self.id, bytes).await.unwrap();
-                                        },
-                                        CliEvent::TerminalResize{rows, cols} => {
-                                            self.session_manager_handle.terminal_resize(rows, cols).await.unwrap();
-                                        },
-                                        CliEvent::Detach => {
-                                            self.session_manager_handle.client_disconnect(self.id).await.unwrap();
-                                        },
-                                        CliEvent::KillPane => {
-                                            self.session_manager_handle.user_kill_pane(self.id).await.unwrap();
-                                        },
-                                        CliEvent::SplitPaneHorizontal => {
-                                            self.session_manager_handle.user_split_pane(self.id, SplitDirection::Horizontal).await.unwrap();
-                                        },
-                                        CliEvent::SplitPaneVertical => {
-                                            self.session_manager_handle.user_split_pane(self.id, SplitDirection::Vertical).await.unwrap();
-                                        },
-                                        CliEvent::NextPane => {
-                                            self.session_manager_handle.user_iterate_pane(self.id, true).await.unwrap();
-                                        },
-                                        CliEvent::PrevPane => {
-                                            self.session_manager_handle.user_iterate_pane(self.id, false).await.unwrap();
-                                        },
-                                        CliEvent::SwitchSession(session_name) => {
-                                            self.session_manager_handle.client_switch_session(self.id, session_name).await.unwrap();
-                                        }
-                                    }
-                                }
-                                Err(e) => {
-                                    // client disconnected
-                                    debug!("Client disconnected because of error recieving cli event: {e}");
-                                    self.session_manager_handle.client_disconnect(self.id).await.unwrap();
-                                    break;
+                                    self.handle_cli_event(event).await
                                 }
+                                Err(e) => Err(e.into()),
+                            };
+                            if let Err(e) = res {
+                                // client disconnected, or the session manager is gone
+                                debug!("Client disconnected because of error handling cli event: {e}");
+                                let _ = self.session_manager_handle.client_disconnect(self.id).await;
+                                break;
                             }
                         }
                     }
                 }
             Ok::<(), Error>(())
-            }.instrument(error_span!(parent: None, "Client Actor", id=?self.id))
+            }.instrument(error_span!(parent: None, "Client Actor", id=?id))
         );
 
         Ok(handle_clone)
     }
+
+    /// answers the attach request, returns false if the client couldn't attach
+    async fn send_attach_result(&mut self, result: Result<DaemonState>) -> Result<bool> {
+        match result {
+            Ok(daemon_state) => {
+                let res = ResponseBuilder::default()
+                    .result(ResponseResult::Success(response::Attach {
+                        initial_daemon_state: daemon_state,
+                        features: self.features,
+                    }))
+                    .build();
+                info!(respnse=?res, "Sending response");
+                comm::send_message(&mut self.stream, &res).await?;
+                self.state = ClientConnectionState::Attached;
+                Ok(true)
+            }
+            Err(e) => {
+                comm::send_message(
+                    &mut self.stream,
+                    &ResponseBuilder::default()
+                        .result(ResponseResult::Failure::<()>(e.to_string()))
+                        .build(),
+                )
+                .await?;
+                Ok(false)
+            }
+        }
+    }
+
+    async fn handle_event(&mut self, event: ClientConnectionEvent) -> Result<()> {
+        match event {
+            SuccessAttachToSession(session_id) => {
+                self.state = ClientConnectionState::Attached;
+                comm::send_event(&mut self.stream, DaemonEvent::ActiveSession(session_id)).await?;
+            }
+            LastSession(session_id) => {
+                comm::send_event(&mut self.stream, DaemonEvent::LastSession(session_id)).await?;
+            }
+            FailedAttachToSession(..) => {
+                comm::send_event(&mut self.stream, DaemonEvent::Disconnected(DetachReason::Detached)).await?;
+            }
+            DetachFromSession(..) => {
+                self.state = ClientConnectionState::Unattached;
+            }
+            Disconnect(reason) => {
+                comm::send_event(&mut self.stream, DaemonEvent::Disconnected(reason)).await?;
+            }
+            SessionOutput(bytes, _credit) => {
+                let bytes = Bytes::from(self.colors.apply(&bytes));
+                comm::send_event(&mut self.stream, DaemonEvent::Raw(bytes)).await?;
+            }
+            PaneOutput {
+                pane_id,
+                bytes,
+                credit: _credit,
+            } => {
+                comm::send_event(&mut self.stream, DaemonEvent::PaneOutput(pane_id, bytes)).await?;
+            }
+            Notify(text) => {
+                comm::send_event(&mut self.stream, DaemonEvent::Message(events::Message::info(text))).await?;
+            }
+            Message(message) => {
+                comm::send_event(&mut self.stream, DaemonEvent::Message(message)).await?;
+            }
+            Buffers(previews) => {
+                comm::send_event(&mut self.stream, DaemonEvent::Buffers(previews)).await?;
+            }
+            Links(links) => {
+                comm::send_event(&mut self.stream, DaemonEvent::Links(links)).await?;
+            }
+            SearchResults(matches) => {
+                comm::send_event(&mut self.stream, DaemonEvent::SearchResults(matches)).await?;
+            }
+            PaneLocations(locations) => {
+                comm::send_event(&mut self.stream, DaemonEvent::PaneLocations(locations)).await?;
+            }
+            Tree(sessions, sessions_only) => {
+                comm::send_event(&mut self.stream, DaemonEvent::Tree(sessions, sessions_only)).await?;
+            }
+            PaneCapture { rows, cols, contents } => {
+                comm::send_event(&mut self.stream, DaemonEvent::PaneCapture { rows, cols, contents }).await?;
+            }
+            SessionAlert {
+                session_id,
+                alert,
+                bell,
+            } => {
+                comm::send_event(
+                    &mut self.stream,
+                    DaemonEvent::SessionAlert {
+                        session_id,
+                        alert,
+                        bell,
+                    },
+                )
+                .await?;
+            }
+            Bell(session, window) => {
+                comm::send_event(&mut self.stream, DaemonEvent::Bell { session, window }).await?;
+            }
+            WindowSize { rows, cols } => {
+                comm::send_event(&mut self.stream, DaemonEvent::WindowSize { rows, cols }).await?;
+            }
+            SessionClients(clients) => {
+                comm::send_event(&mut self.stream, DaemonEvent::SessionClients(clients)).await?;
+            }
+            SessionWindows { windows, active } => {
+                comm::send_event(&mut self.stream, DaemonEvent::SessionWindows { windows, active }).await?;
+            }
+            HookFired(event, session_name) => {
+                comm::send_event(&mut self.stream, DaemonEvent::HookFired(event, session_name)).await?;
+            }
+            StateDelta(delta) => {
+                comm::send_event(&mut self.stream, DaemonEvent::StateDelta(delta)).await?;
+            }
+            ScreenReader(enabled) => {
+                comm::send_event(&mut self.stream, DaemonEvent::ScreenReader(enabled)).await?;
+            }
+            StatusLine(visible) => {
+                comm::send_event(&mut self.stream, DaemonEvent::StatusLine(visible)).await?;
+            }
+            KeyBindings(bindings) => {
+                comm::send_event(&mut self.stream, DaemonEvent::KeyBindings(bindings)).await?;
+            }
+            _ => {
+                error!(event=?event, state=?self.state, "Unhandled or invalid event for current state");
+            }
+        }
+        Ok(())
+    }
+
+    async fn handle_cli_event(&mut self, event: CliEvent) -> Result<()> {
+        match event {
+            CliEvent::Raw(bytes) => {
+                self.session_manager_handle.user_input(self.id, bytes).await?;
+            }
+            CliEvent::Paste(text) => {
+                self.session_manager_handle.user_paste(self.id, text).await?;
+            }
+            CliEvent::Mouse { kind, row, col } => {
+                self.session_manager_handle.user_mouse(self.id, kind, row, col).await?;
+            }
+            CliEvent::ScrollUp { row, col } => {
+                self.session_manager_handle
+                    .user_mouse(self.id, MouseKind::ScrollUp, row, col)
+                    .await?;
+            }
+            CliEvent::ScrollDown { row, col } => {
+                self.session_manager_handle
+                    .user_mouse(self.id, MouseKind::ScrollDown, row, col)
+                    .await?;
+            }
+            CliEvent::Focus(focused) => {
+                self.session_manager_handle.user_focus(self.id, focused).await?;
+            }
+            CliEvent::TerminalResize { rows, cols } => {
+                self.session_manager_handle.terminal_resize(self.id, rows, cols).await?;
+            }
+            CliEvent::Detach => {
+                self.session_manager_handle.client_disconnect(self.id).await?;
+            }
+            CliEvent::KillPane => {
+                self.session_manager_handle.user_kill_pane(self.id).await?;
+            }
+            CliEvent::SplitPaneHorizontal => {
+                self.session_manager_handle
+                    .user_split_pane(self.id, SplitDirection::Horizontal)
+                    .await?;
+            }
+            CliEvent::SplitPaneVertical => {
+                self.session_manager_handle
+                    .user_split_pane(self.id, SplitDirection::Vertical)
+                    .await?;
+            }
+            CliEvent::ToggleFloatingPane => {
+                self.session_manager_handle.user_toggle_floating_pane(self.id).await?;
+            }
+            CliEvent::NextPane => {
+                self.session_manager_handle.user_iterate_pane(self.id, true).await?;
+            }
+            CliEvent::PrevPane => {
+                self.session_manager_handle.user_iterate_pane(self.id, false).await?;
+            }
+            CliEvent::SwitchSession(session_name) => {
+                self.session_manager_handle
+                    .client_switch_session(self.id, session_name)
+                    .await?;
+            }
+            CliEvent::LastSession => self.switch_client(SwitchTarget::Last).await?,
+            CliEvent::NextSession => self.switch_client(SwitchTarget::Next).await?,
+            CliEvent::PrevSession => self.switch_client(SwitchTarget::Prev).await?,
+            CliEvent::ChooseBuffer => {
+                self.session_manager_handle
+                    .run_command(self.command_context(), RemuxCommand::ChooseBuffer)
+                    .await?;
+            }
+            CliEvent::SetBuffer(data) => {
+                self.session_manager_handle
+                    .run_command(self.command_context(), RemuxCommand::SetBuffer { name: None, data })
+                    .await?;
+            }
+            CliEvent::QuickOpen => {
+                self.session_manager_handle
+                    .run_command(self.command_context(), RemuxCommand::QuickOpen)
+                    .await?;
+            }
+            CliEvent::DisplayPanes => {
+                self.session_manager_handle
+                    .run_command(self.command_context(), RemuxCommand::DisplayPanes)
+                    .await?;
+            }
+            CliEvent::NewWindow => {
+                self.session_manager_handle
+                    .run_command(self.command_context(), RemuxCommand::NewWindow { name: None })
+                    .await?;
+            }
+            CliEvent::ResizePane { direction, amount } => {
+                self.session_manager_handle
+                    .run_command(self.command_context(), RemuxCommand::ResizePane { direction, amount })
+                    .await?;
+            }
+            CliEvent::ZoomPane => {
+                self.session_manager_handle
+                    .run_command(self.command_context(), RemuxCommand::ZoomPane)
+                    .await?;
+            }
+            CliEvent::SwapPane { with } => {
+                self.session_manager_handle
+                    .run_command(self.command_context(), RemuxCommand::SwapPane { with })
+                    .await?;
+            }
+            CliEvent::RotateWindow => {
+                self.session_manager_handle
+                    .run_command(self.command_context(), RemuxCommand::RotateWindow { downward: false })
+                    .await?;
+            }
+            CliEvent::NextWindow => {
+                self.session_manager_handle
+                    .run_command(self.command_context(), RemuxCommand::NextWindow)
+                    .await?;
+            }
+            CliEvent::PrevWindow => {
+                self.session_manager_handle
+                    .run_command(self.command_context(), RemuxCommand::PreviousWindow)
+                    .await?;
+            }
+            CliEvent::SelectWindow(index) => {
+                self.session_manager_handle
+                    .run_command(self.command_context(), RemuxCommand::SelectWindow { index })
+                    .await?;
+            }
+            CliEvent::RenameSession(name) => {
+                self.session_manager_handle
+                    .run_command(
+                        self.command_context(),
+                        RemuxCommand::RenameSession { target: None, name },
+                    )
+                    .await?;
+            }
+            CliEvent::RenameWindow(name) => {
+                self.session_manager_handle
+                    .run_command(self.command_context(), RemuxCommand::RenameWindow { name })
+                    .await?;
+            }
+            CliEvent::SelectPane(pane_id) => {
+                let command = RemuxCommand::SelectPane {
+                    is_next: true,
+                    target: Some(pane_id),
+                };
+                self.session_manager_handle
+                    .run_command(self.command_context(), command)
+                    .await?;
+            }
+            CliEvent::SearchPanes(pattern) => {
+                self.session_manager_handle
+                    .run_command(self.command_context(), RemuxCommand::SearchPanes { pattern })
+                    .await?;
+            }
+            CliEvent::CopyModeSearch(pattern) => {
+                self.session_manager_handle
+                    .run_command(self.command_context(), RemuxCommand::CopyMode { search: Some(pattern) })
+                    .await?;
+            }
+            CliEvent::CapturePane { session, window, pane } => {
+                self.session_manager_handle
+                    .user_capture_pane(self.id, session, window, pane)
+                    .await?;
+            }
+            CliEvent::JumpToSearchMatch { pane_id, line } => {
+                self.session_manager_handle
+                    .user_jump_to_line(self.id, pane_id, line)
+                    .await?;
+            }
+            CliEvent::RunCommand(line) => match parse_sequence(&line) {
+                Ok(commands) => {
+                    for command in commands {
+                        self.session_manager_handle
+                            .run_command(self.command_context(), command)
+                            .await?;
+                    }
+                }
+                Err(e) => {
+                    let message = events::Message::error(e.to_string());
+                    comm::send_event(&mut self.stream, DaemonEvent::Message(message)).await?;
+                }
+            },
+            CliEvent::PasteBuffer(index) => {
+                // like tmux's `prefix-]`, editors and shells get to tell pasted text from typing
+                let command = RemuxCommand::PasteBuffer {
+                    buffer: Some(index.to_string()),
+                    bracketed: true,
+                };
+                self.session_manager_handle
+                    .run_command(self.command_context(), command)
+                    .await?;
+            }
+        }
+        Ok(())
+    }
 }
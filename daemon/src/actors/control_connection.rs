@@ -0,0 +1,280 @@
+use std::collections::HashMap;
+
+use remux_core::{
+    comm,
+    commands::RemuxCommand,
+    events,
+    messages::{Features, ResponseBuilder, ResponseResult, request::ClientEnvironment, response},
+    states::DaemonState,
+};
+use tokio::{
+    io::{AsyncReadExt, AsyncWriteExt},
+    net::UnixStream,
+    sync::mpsc,
+};
+use tracing::Span;
+use uuid::Uuid;
+
+use crate::{
+    actors::{
+        client_connection::{ClientConnectionEvent, ClientConnectionHandle},
+        message_span,
+        session_manager::{NewSessionArgs, SessionManagerHandle},
+    },
+    control_mode::ControlNotification,
+    prelude::*,
+};
+
+/// client actor for control mode - shares the client event type with `ClientConnection` so the
+/// session manager does not need to know which kind of client it is talking to
+pub struct ControlConnection {
+    id: Uuid,
+    stream: UnixStream,
+    handle: ClientConnectionHandle,
+    rx: mpsc::Receiver<(ClientConnectionEvent, Span)>,
+    session_manager_handle: SessionManagerHandle,
+    features: Features, // negotiated with the cli, passed back in the attach response
+    sessions: HashMap<u32, String>,
+    attached: bool,
+    command_number: u32,
+    command_task: Option<DaemonTask>, // waiting on the last command's result
+    line_buf: Vec<u8>,
+}
+impl ControlConnection {
+    pub fn spawn(
+        id: Uuid,
+        stream: UnixStream,
+        session_manager_handle: SessionManagerHandle,
+        initial_session_name: &str,
+        features: Features,
+    ) -> Result<ClientConnectionHandle> {
+        let (handle, rx) = ClientConnectionHandle::channel();
+        let connection = Self {
+            id,
+            stream,
+            handle,
+            rx,
+            session_manager_handle,
+            features,
+            sessions: HashMap::new(),
+            attached: false,
+            command_number: 0,
+            command_task: None,
+            line_buf: Vec::new(),
+        };
+        connection.run(initial_session_name)
+    }
+
+    fn run(mut self, initial_session_name: &str) -> Result<ClientConnectionHandle> {
+        let handle_clone = self.handle.clone();
+        let session_name = initial_session_name.to_owned();
+        let span = error_span!(parent: None, "Control Client Actor", id=?self.id);
+        let _task: DaemonTask = tokio::spawn(
+            async move {
+                let result = self
+                    .session_manager_handle
+                    .client_connect(
+                        self.id,
+                        self.handle.clone(),
+                        Some(session_name),
+                        Some(NewSessionArgs::default()),
+                        true,
+                        ClientEnvironment::default(),
+                    )
+                    .await
+                    .and_then(|result| result);
+                if !self.send_attach_result(result).await? {
+                    return Ok(());
+                }
+                let mut buf = [0u8; 1024];
+                loop {
+                    tokio::select! {
+                        Some((event, cause)) = self.rx.recv() => {
+                            if !self.handle_event(event).instrument(message_span(&cause)).await? {
+                                break;
+                            }
+                        }
+                        res = self.stream.read(&mut buf), if self.attached => {
+                            match res {
+                                Ok(n) if n > 0 => self.handle_input(&buf[..n]).await?,
+                                _ => {
+                                    // wait for the session manager to confirm the disconnect before stopping
+                                    debug!("Control client hung up");
+                                    self.attached = false;
+                                    self.session_manager_handle.client_disconnect(self.id).await?;
+                                }
+                            }
+                        }
+                    }
+                }
+                Ok(())
+            }
+            .instrument(span),
+        );
+
+        Ok(handle_clone)
+    }
+
+    /// answers the attach request, returns false if the client couldn't attach
+    async fn send_attach_result(&mut self, result: Result<DaemonState>) -> Result<bool> {
+        match result {
+            Ok(daemon_state) => {
+                self.sessions = daemon_state
+                    .sessions
+                    .iter()
+                    .map(|info| (info.id, info.name.clone()))
+                    .collect();
+                let res = ResponseBuilder::default()
+                    .result(ResponseResult::Success(response::Attach {
+                        initial_daemon_state: daemon_state,
+                        features: self.features,
+                    }))
+                    .build();
+                comm::send_message(&mut self.stream, &res).await?;
+                self.attached = true;
+                Ok(true)
+            }
+            Err(e) => {
+                let res = ResponseBuilder::default()
+                    .result(ResponseResult::Failure::<()>(e.to_string()))
+                    .build();
+                comm::send_message(&mut self.stream, &res).await?;
+                Ok(false)
+            }
+        }
+    }
+
+    /// returns false once the connection should be closed
+    async fn handle_event(&mut self, event: ClientConnectionEvent) -> Result<bool> {
+        use ClientConnectionEvent::*;
+        use ControlNotification::*;
+        match event {
+            SuccessAttachToSession(session_id) => {
+                let name = self.sessions.get(&session_id).cloned().unwrap_or_default();
+                self.write(SessionChanged {
+                    session_id,
+                    name: &name,
+                })
+                .await?;
+            }
+            StateDelta(events::StateDelta::SessionAdded { id, name }) => {
+                self.sessions.insert(id, name);
+                self.write(SessionsChanged).await?;
+                // every session currently owns exactly one window which shares the session's id
+                self.write(WindowAdd { window_id: id }).await?;
+            }
+            StateDelta(events::StateDelta::SessionRemoved { id }) => {
+                self.sessions.remove(&id);
+                self.write(SessionsChanged).await?;
+                self.write(WindowClose { window_id: id }).await?;
+            }
+            StateDelta(events::StateDelta::SessionRenamed { id, name }) => {
+                self.sessions.insert(id, name);
+                self.write(SessionsChanged).await?;
+            }
+            PaneOutput {
+                pane_id,
+                bytes,
+                credit: _credit,
+            } => self.write(Output { pane_id, bytes: &bytes }).await?,
+            CommandFinished(number, Ok(output)) => {
+                let output = output.unwrap_or_default();
+                self.write(Done {
+                    number,
+                    output: &output,
+                })
+                .await?;
+            }
+            CommandFinished(number, Err(e)) => {
+                let message = e.to_string();
+                self.write(ControlNotification::Error {
+                    number,
+                    message: &message,
+                })
+                .await?;
+            }
+            Notify(text) | ClientConnectionEvent::Message(events::Message { text, .. }) => {
+                self.write(ControlNotification::Message(&text)).await?
+            }
+            Disconnect(reason) => {
+                self.write(Exit(Some(&reason.to_string()))).await?;
+                return Ok(false);
+            }
+            FailedAttachToSession(..) => {
+                self.write(Exit(None)).await?;
+                return Ok(false);
+            }
+            SessionOutput(..)
+            | DetachFromSession(..)
+            | InitialAttach(..)
+            | Buffers(..)
+            | Links(..)
+            | SearchResults(..)
+            | PaneLocations(..)
+            | Tree(..)
+            | PaneCapture { .. }
+            | SessionAlert { .. }
+            | WindowSize { .. }
+            | SessionClients(..)
+            | SessionWindows { .. }
+            | LastSession(..)
+            | StateDelta(..)
+            | ExecFinished(..)
+            | ScreenReader(..)
+            | StatusLine(..)
+            | KeyBindings(..)
+            | HookFired(..)
+            | Bell(..) => {}
+        }
+        Ok(true)
+    }
+
+    /// input is a stream of newline terminated commands, an empty line detaches the client
+    async fn handle_input(&mut self, bytes: &[u8]) -> Result<()> {
+        self.line_buf.extend_from_slice(bytes);
+        while let Some(i) = self.line_buf.iter().position(|&b| b == b'\n') {
+            let line: Vec<u8> = self.line_buf.drain(..=i).collect();
+            let line = String::from_utf8_lossy(&line).trim_end_matches(['\r', '\n']).to_owned();
+            if line.is_empty() {
+                self.session_manager_handle.client_disconnect(self.id).await?;
+                continue;
+            }
+
+            let number = self.command_number;
+            self.command_number += 1;
+            self.run_command(number, line.parse::<RemuxCommand>().map_err(Error::from));
+        }
+        Ok(())
+    }
+
+    /// the block is written once the command has run, off the event loop since the session manager may be
+    /// waiting on this client to take its output. one that didn't parse still waits its turn
+    fn run_command(&mut self, number: u32, command: Result<RemuxCommand>) {
+        let session_manager = self.session_manager_handle.clone();
+        let handle = self.handle.clone();
+        let client_id = self.id;
+        let previous = self.command_task.take();
+        self.command_task = Some(tokio::spawn(
+            async move {
+                // results come back in the order the commands were sent
+                if let Some(previous) = previous {
+                    let _ = previous.await;
+                }
+                let result = match command {
+                    Ok(command) => session_manager
+                        .run_command_once(command, None, Some(client_id))
+                        .await
+                        .and_then(|result| result),
+                    Err(e) => Err(e),
+                };
+                handle.command_finished(number, result).await
+            }
+            .in_current_span(),
+        ));
+    }
+
+    async fn write(&mut self, notification: ControlNotification<'_>) -> Result<()> {
+        self.stream.write_all(notification.to_string().as_bytes()).await?;
+        Ok(())
+    }
+}
@@ -0,0 +1,147 @@
+use remux_core::{
+    comm,
+    events::{DaemonEvent, DetachReason},
+    messages::{Features, ResponseBuilder, ResponseResult, response},
+    states::DaemonState,
+};
+use tokio::{io::AsyncReadExt, net::UnixStream, sync::mpsc};
+use tracing::Span;
+use uuid::Uuid;
+
+use crate::{
+    actors::{
+        client_connection::{ClientConnectionEvent, ClientConnectionHandle},
+        message_span,
+        session_manager::SessionManagerHandle,
+    },
+    prelude::*,
+};
+
+/// the command always runs in the first pane of its session
+const EXEC_PANE_ID: usize = 0;
+
+/// client actor for `remux exec` - streams the output of the command's pane and then its exit code,
+/// the session it runs in goes away with the command or when the cli hangs up
+pub struct ExecConnection {
+    id: Uuid,
+    stream: UnixStream,
+    handle: ClientConnectionHandle,
+    rx: mpsc::Receiver<(ClientConnectionEvent, Span)>,
+    session_manager_handle: SessionManagerHandle,
+    features: Features, // negotiated with the cli, passed back in the attach response
+    attached: bool,
+}
+impl ExecConnection {
+    pub fn spawn(
+        id: Uuid,
+        stream: UnixStream,
+        session_manager_handle: SessionManagerHandle,
+        session_name: Option<String>,
+        command: Vec<String>,
+        features: Features,
+    ) -> Result<ClientConnectionHandle> {
+        let (handle, rx) = ClientConnectionHandle::channel();
+        let connection = Self {
+            id,
+            stream,
+            handle,
+            rx,
+            session_manager_handle,
+            features,
+            attached: false,
+        };
+        connection.run(session_name, command)
+    }
+
+    fn run(mut self, session_name: Option<String>, command: Vec<String>) -> Result<ClientConnectionHandle> {
+        let handle_clone = self.handle.clone();
+        let span = error_span!(parent: None, "Exec Client Actor", id=?self.id);
+        let _task: DaemonTask = tokio::spawn(
+            async move {
+                let result = self
+                    .session_manager_handle
+                    .exec_connect(self.id, self.handle.clone(), session_name, command)
+                    .await
+                    .and_then(|result| result);
+                if !self.send_attach_result(result).await? {
+                    return Ok(());
+                }
+                let mut buf = [0u8; 64];
+                loop {
+                    tokio::select! {
+                        Some((event, cause)) = self.rx.recv() => {
+                            if !self.handle_event(event).instrument(message_span(&cause)).await? {
+                                break;
+                            }
+                        }
+                        // the cli never writes, reading only notices it going away
+                        res = self.stream.read(&mut buf), if self.attached => {
+                            if !matches!(res, Ok(n) if n > 0) {
+                                debug!("Exec client hung up");
+                                self.attached = false;
+                                self.session_manager_handle.client_disconnect(self.id).await?;
+                            }
+                        }
+                    }
+                }
+                Ok(())
+            }
+            .instrument(span),
+        );
+
+        Ok(handle_clone)
+    }
+
+    /// answers the attach request, returns false if the command couldn't be started
+    async fn send_attach_result(&mut self, result: Result<DaemonState>) -> Result<bool> {
+        match result {
+            Ok(daemon_state) => {
+                let res = ResponseBuilder::default()
+                    .result(ResponseResult::Success(response::Attach {
+                        initial_daemon_state: daemon_state,
+                        features: self.features,
+                    }))
+                    .build();
+                comm::send_message(&mut self.stream, &res).await?;
+                self.attached = true;
+                Ok(true)
+            }
+            Err(e) => {
+                let res = ResponseBuilder::default()
+                    .result(ResponseResult::Failure::<()>(e.to_string()))
+                    .build();
+                comm::send_message(&mut self.stream, &res).await?;
+                Ok(false)
+            }
+        }
+    }
+
+    /// returns false once the connection should be closed
+    async fn handle_event(&mut self, event: ClientConnectionEvent) -> Result<bool> {
+        use ClientConnectionEvent::*;
+        match event {
+            PaneOutput {
+                pane_id,
+                bytes,
+                credit: _credit,
+            } if pane_id == EXEC_PANE_ID => {
+                comm::send_event(&mut self.stream, DaemonEvent::Raw(bytes)).await?;
+            }
+            ExecFinished(code) => {
+                comm::send_event(&mut self.stream, DaemonEvent::Exited(code)).await?;
+                return Ok(false);
+            }
+            Disconnect(reason) => {
+                // the cli may already be gone
+                let _ = comm::send_event(&mut self.stream, DaemonEvent::Disconnected(reason)).await;
+                return Ok(false);
+            }
+            FailedAttachToSession(..) => {
+                let _ = comm::send_event(&mut self.stream, DaemonEvent::Disconnected(DetachReason::Detached)).await;
+                return Ok(false);
+            }
+            _ => {}
+        }
+        Ok(true)
+    }
+}
@@ -1,6 +1,47 @@
+pub mod buffer_manager;
 pub mod client_connection;
+pub mod control_connection;
+pub mod exec_connection;
 pub mod pane;
 pub mod pty;
 pub mod session;
 pub mod session_manager;
 pub mod window;
+pub mod window_group;
+
+use tracing::Span;
+
+/// the span an actor handles a message sent from `cause` in. with `--features otel` it's a span of its own
+/// continuing `cause`'s trace, so a keypress can be followed from the client to the pty and out to the
+/// frame it drew, without it the actor's own span is enough
+pub fn message_span(cause: &Span) -> Span {
+    #[cfg(feature = "otel")]
+    {
+        let span = tracing::debug_span!("message");
+        continue_trace(&span, cause);
+        span
+    }
+    #[cfg(not(feature = "otel"))]
+    {
+        let _ = cause;
+        Span::current()
+    }
+}
+
+/// makes `span` part of `cause`'s trace instead of its parent's when spans are exported, in the logs it
+/// stays under its parent
+pub fn continue_trace(span: &Span, cause: &Span) {
+    #[cfg(feature = "otel")]
+    {
+        use tracing_opentelemetry::OpenTelemetrySpanExt;
+        // fails only for a span that's disabled or already started, neither has a trace to join
+        let _ = span.set_parent(cause.context());
+    }
+    #[cfg(not(feature = "otel"))]
+    let _ = (span, cause);
+}
+
+/// makes `span` the root of a trace of its own when spans are exported, see `otel.rs` for which are kept
+pub fn start_trace(span: &Span) {
+    continue_trace(span, &Span::none());
+}
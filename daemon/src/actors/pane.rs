@@ -1,120 +1,325 @@
+use std::{
+    collections::HashMap,
+    path::PathBuf,
+    time::{Duration, Instant},
+};
+
 use bytes::Bytes;
 use handle_macro::Handle;
-use tokio::sync::mpsc;
-use tracing::Instrument;
+use nix::unistd::Pid;
+use remux_core::{
+    events::MouseKind,
+    graphics::{Chunk, GraphicsScanner},
+    keys::to_legacy,
+    links::{LinkMatch, find_links},
+    messages::response::{PreviewPane, StyledText, TextColor, TextStyle},
+    states::Alert,
+};
+use tokio::sync::{mpsc, oneshot, watch};
+use tracing::{Instrument, Span};
 
 use crate::{
     actors::{
-        pty::{Pty, PtyHandle},
-        window::WindowHandle,
+        client_connection::ClientConnectionHandle,
+        message_span,
+        pty::{ExitStatus, Pty, PtyHandle, ReadCredit, pane_term},
+        window::{WindowHandle, WindowHandleError},
     },
+    cell::CellGrid,
+    copy_mode::{
+        CopyModeAction, CopySearch, CopySearchState, RowCell, Selection, SelectionUnit, copy_mode_action,
+        offset_for_line, position_indicator, row_text, scrollbar_thumb, word_bounds,
+    },
+    environment::SessionEnvironment,
+    frame_scheduler::FrameScheduler,
     layout::Rect,
+    options::{OptionsReceiver, PaneOption},
+    pane_log::PaneLog,
+    pane_title::{ForegroundProcess, TitleVars, format_title},
+    paste_buffers::{Osc52Scanner, bracketed_paste},
+    platform,
     prelude::*,
+    supervision::{Supervisor, Verdict, wait_for_children},
+    terminal_modes::TerminalModes,
 };
 
 #[derive(Handle, Debug)]
+#[handle(spawn, try_send, error = Error)]
 pub enum PaneEvent {
     UserInput(Bytes),
-    PtyOutput(Bytes),
-    PtyDied,
-    Render,   // uses the diff from prev state to get to desired state (falls back to rerender if no prev state)
+    Paste {
+        text: Bytes,
+        bracketed: bool, // wrapped in bracketed paste markers if the program turned them on
+    },
+    PtyOutput(Bytes, ReadCredit), // the credit goes back to the pty once the output has been passed on
+    PtyDied(ExitStatus),
+    Render, // a frame held back by `frame-rate`, drawn as a diff from the last one (a full rerender if there's none)
     Rerender, // full rerender
-    Resize { rect: Rect },
+    Resize {
+        rect: Rect,
+    },
     Hide,
     Reveal,
     Kill,
+    MoveToWindow {
+        window_handle: WindowHandle,
+    }, // `break-pane`/`join-pane` gave the pane to another window, a resize to its new rect follows
+    // read-only copies of this pane drawn in other windows
+    AddMirror {
+        mirror_id: u32,
+        window_handle: WindowHandle,
+        id: usize,
+        rect: Rect,
+    },
+    ResizeMirror {
+        mirror_id: u32,
+        rect: Rect,
+    },
+    RemoveMirror {
+        mirror_id: u32,
+    },
+    ScanLinks {
+        client: ClientConnectionHandle,
+    }, // reply to the client with the links on screen
+    Capture {
+        client: ClientConnectionHandle,
+    }, // reply to the client with the screen as it's drawn, for the choose-tree preview
+    #[reply(Vec<(usize, String)>)]
+    Search {
+        pattern: String,
+        reply: oneshot::Sender<Vec<(usize, String)>>,
+    }, // matching (line, text) pairs from scrollback and screen, lines counted from the oldest
+    EnterCopyMode {
+        line: Option<usize>,
+        search: Option<String>,
+    }, // optionally scrolled so `line` is on the top row, or to the first match of a `/` search
+    Mouse {
+        kind: MouseKind,
+        row: u16,
+        col: u16,
+    }, // pane relative cell
+    Tick,                   // sent every second for `monitor-silence` and `automatic-rename`
+    Focus(bool),            // the pane gained/lost focus, passed on if the program asked for focus reports
+    PauseOutput(bool),      // the clients watching the pane are behind, the pty isn't read until they catch up
+    ForwardRawOutput(bool), // whether a control mode client is watching, only then is the raw output sent up
+    SetLogging {
+        enabled: Option<bool>, // toggles when None
+        session_name: String,
+        client: Option<ClientConnectionHandle>,
+    },
+    SetOption(PaneOption),
+    SetTitle(Option<String>), // `select-pane -T`, `None` goes back to the program's own title
+    SetMarked(bool),
+    Respawn, // sent `RESPAWN_DELAY` after a clean exit with auto-respawn on, or `r` pressed in a dead pane
+    #[reply(PreviewPane)]
+    Preview {
+        rows: u16,
+        cols: u16,
+        reply: oneshot::Sender<PreviewPane>,
+    }, // the top left of the screen for the session switcher
+    #[reply(Option<PathBuf>)]
+    CurrentDir {
+        reply: oneshot::Sender<Option<PathBuf>>,
+    }, // where the pane's shell is, panes split from this one start there
 }
 use PaneEvent::*;
 
+const WHEEL_LINES: usize = 3;
+const MULTI_CLICK_INTERVAL: Duration = Duration::from_millis(400);
+const TICK_INTERVAL: Duration = Duration::from_secs(1);
+const RESPAWN_DELAY: Duration = Duration::from_secs(1);
+const RESPAWN_MIN_UPTIME: Duration = Duration::from_secs(10); // exiting sooner counts towards a crash loop
+const MAX_QUICK_RESPAWNS: u32 = 5;
+const ACTIVITY_ALERT_INTERVAL: Duration = Duration::from_secs(1); // the manager dedupes, this keeps chatty panes quiet
+
 pub enum PaneState {
     Visible,
     Hidden,
 }
 
+/// where a mirror of this pane is drawn, `id` is the pane id inside the mirroring window
+struct PaneMirror {
+    window_handle: WindowHandle,
+    id: usize,
+    rect: Rect,
+}
+
 pub struct Pane {
     id: usize,
     handle: PaneHandle,
     window_handle: WindowHandle,
-    rx: mpsc::Receiver<PaneEvent>,
+    rx: mpsc::Receiver<(PaneEvent, Span)>,
+    supervisor: Supervisor,
     pane_state: PaneState,
     pty_handle: PtyHandle,
+    shell_pid: Pid,
+    exit_status: Option<ExitStatus>, // set once the pty has died, the pane stays dead until respawned or closed
+    mirrors: HashMap<u32, PaneMirror>,
+    osc52: Osc52Scanner,
+    modes: TerminalModes,
+    alternate_screen: bool, // whether the program was on its alternate screen after the last output
+    bells: usize,           // the vte's bell count after the last output, any increase rang the bell
+    graphics: GraphicsScanner,
+    copy_mode: bool,
+    selection: Option<Selection>,                         // highlighted while in copy mode
+    search: Option<CopySearchState>,                      // `/` in copy mode, dropped when copy mode ends
+    selection_anchor: Option<(Selection, SelectionUnit)>, // what the mouse press selected, drags extend from it
+    last_click: Option<(Instant, (u16, u16), u8)>,        // for counting double/triple clicks
+    options: OptionsReceiver,
+    environment: SessionEnvironment, // read again when the shell is respawned
+    command: Option<Vec<String>>,    // `remux exec` panes run this instead of the shell
+    cwd: Option<PathBuf>,            // where the process starts, again when respawned
+    auto_respawn: Option<bool>,      // `set-option -p/-w auto-respawn`, overrides the global option
+    spawned_at: Instant,
+    quick_respawns: u32,  // respawns in a row of shells that exited soon after starting
+    log: Option<PaneLog>, // `log-pane on`
+    last_output: Instant,
+    frames: FrameScheduler, // output is drawn at most `frame-rate` times a second
+    last_activity_alert: Option<Instant>,
+    silence_alerted: bool,              // only alert once per silence
+    reported_command: Option<String>,   // the foreground program the window was last told about
+    title: Option<String>,              // `select-pane -T`, shown instead of the program's title
+    marked: bool,                       // `select-pane -m`
+    output_paused: watch::Sender<bool>, // read by the pty, a respawned one included
+    raw_output: bool,                   // what the pty reads is also sent up as it is, until the window says otherwise
+    echo_cause: Span,                   // the last input, the output answering it is handled in its trace
     // vte related
     vte: vt100::Parser,
-    prev_screen_state: Option<vt100::Screen>,
-    rect: Rect,
+    prev_cells: Option<CellGrid>, // what clients were last sent of the screen, `None` forces a full redraw
+    outer: Rect,                  // given by the window, includes the `pane-titles` header
+    rect: Rect,                   // what the program gets
 }
 impl Pane {
-    #[instrument(skip(window_handle, rect), name = "Pane")]
-    pub fn spawn(window_handle: WindowHandle, id: usize, rect: Rect) -> Result<PaneHandle> {
-        let pane = Pane::new(window_handle, id, rect)?;
+    #[instrument(skip(window_handle, rect, options, environment), name = "Pane")]
+    pub fn spawn(
+        window_handle: WindowHandle,
+        id: usize,
+        rect: Rect,
+        options: OptionsReceiver,
+        environment: SessionEnvironment,
+        command: Option<Vec<String>>,
+        cwd: Option<PathBuf>,
+    ) -> Result<PaneHandle> {
+        let pane = Pane::new(window_handle, id, rect, options, environment, command, cwd)?;
         pane.run()
     }
-    fn new(window_handle: WindowHandle, id: usize, rect: Rect) -> Result<Self> {
-        let (tx, rx) = mpsc::channel(10);
-        let handle = PaneHandle { tx };
+    fn new(
+        window_handle: WindowHandle,
+        id: usize,
+        rect: Rect,
+        options: OptionsReceiver,
+        environment: SessionEnvironment,
+        command: Option<Vec<String>>,
+        cwd: Option<PathBuf>,
+    ) -> Result<Self> {
+        let (handle, rx) = PaneHandle::spawn(10);
 
-        let vte = vt100::Parser::new(rect.height, rect.width, 0);
-        let pty_handle = Pty::spawn(handle.clone(), rect)?;
+        let outer = rect;
+        let rect = content_rect(outer, options.borrow().pane_titles);
+        let vte = vt100::Parser::new(rect.height, rect.width, options.borrow().history_limit);
+        let output_paused = watch::Sender::new(false);
+        let (pty_handle, shell_pid) = spawn_pty(
+            &handle,
+            rect,
+            &options,
+            &environment,
+            command.clone(),
+            cwd.clone(),
+            &output_paused,
+        )?;
         Ok(Self {
             id,
             handle,
             window_handle,
             pty_handle,
+            shell_pid,
             rx,
+            supervisor: Supervisor::default(),
             vte,
             pane_state: PaneState::Visible,
-            prev_screen_state: None,
+            exit_status: None,
+            mirrors: HashMap::new(),
+            osc52: Osc52Scanner::default(),
+            modes: TerminalModes::default(),
+            alternate_screen: false,
+            bells: 0,
+            graphics: GraphicsScanner::default(),
+            copy_mode: false,
+            search: None,
+            selection: None,
+            selection_anchor: None,
+            last_click: None,
+            options,
+            environment,
+            command,
+            cwd,
+            auto_respawn: None,
+            spawned_at: Instant::now(),
+            quick_respawns: 0,
+            log: None,
+            last_output: Instant::now(),
+            frames: FrameScheduler::default(),
+            last_activity_alert: None,
+            silence_alerted: false,
+            reported_command: None,
+            title: None,
+            marked: false,
+            output_paused,
+            raw_output: true,
+            echo_cause: Span::none(),
+            prev_cells: None,
+            outer,
             rect,
         })
     }
     fn run(mut self) -> Result<PaneHandle> {
         let handle_clone = self.handle.clone();
+        let tick_handle = self.handle.clone();
+        let _tick_task = tokio::spawn(async move {
+            let mut ticker = tokio::time::interval(TICK_INTERVAL);
+            loop {
+                ticker.tick().await;
+                // a busy pane skips a check rather than queueing them up, it only fails for good once the pane has exited
+                if tick_handle.try_tick().is_err() && tick_handle.is_closed() {
+                    break;
+                }
+            }
+        });
         let _task = tokio::spawn(
             async move {
-                loop {
-                    if let Some(event) = self.rx.recv().await {
-                        match &event {
-                            UserInput(..) | PtyOutput(..) => {
-                                trace!(event=?event);
-                            }
-                            _ => {
-                                info!(event=?event);
-                            }
+                while let Some((event, cause)) = self.rx.recv().await {
+                    match &event {
+                        UserInput(..) | PtyOutput(..) | Render | Mouse { .. } | Tick => {
+                            trace!(event=?event);
                         }
-                        match event {
-                            UserInput(bytes) => {
-                                self.handle_input(bytes).await.unwrap();
-                            }
-                            PtyOutput(bytes) => {
-                                if let Err(e) = self.handle_pty_output(bytes).await {
-                                    error!("Error while handling PTY output: {}", e);
-                                }
-                            }
-                            PtyDied => {
-                                break;
-                            }
-                            Kill => {
-                                self.pty_handle.kill().await.unwrap();
+                        _ => {
+                            info!(event=?event);
+                        }
+                    }
+                    // the pty's answer to a keypress is drawn in the keypress's trace rather than the pty's
+                    let span = match &event {
+                        PtyOutput(..) if !self.echo_cause.is_none() => {
+                            message_span(&std::mem::replace(&mut self.echo_cause, Span::none()))
+                        }
+                        _ => message_span(&cause),
+                    };
+                    if matches!(event, UserInput(..)) {
+                        self.echo_cause = span.clone();
+                    }
+                    match self.handle_event(event).instrument(span).await {
+                        Ok(true) => {}
+                        Ok(false) => break,
+                        Err(e) => match self.supervisor.check(&e, WindowHandleError::Closed) {
+                            Verdict::Continue => {}
+                            Verdict::ParentGone => break,
+                            Verdict::GiveUp => {
+                                let _ = self.window_handle.pane_failed(self.id, e.to_string()).await;
                                 break;
                             }
-                            Render => {
-                                self.handle_render().await.unwrap();
-                            }
-                            Rerender => {
-                                self.handle_rerender().await.unwrap();
-                            }
-                            Resize { rect } => {
-                                self.handle_resize(rect).await.unwrap();
-                            }
-                            Hide => {
-                                self.pane_state = PaneState::Hidden;
-                            }
-                            Reveal => {
-                                self.pane_state = PaneState::Visible;
-                            }
-                        }
+                        },
                     }
                 }
+                self.stop().await;
             }
             .in_current_span(),
         );
@@ -122,71 +327,1096 @@ impl Pane {
         Ok(handle_clone)
     }
 
+    /// returns false once the pane should stop
+    async fn handle_event(&mut self, event: PaneEvent) -> Result<bool> {
+        match event {
+            UserInput(bytes) => {
+                self.handle_input(bytes).await?;
+            }
+            Paste { text, bracketed } => {
+                self.handle_paste(text, bracketed).await?;
+            }
+            PtyOutput(bytes, _credit) => {
+                if let Err(e) = self.handle_pty_output(bytes).await {
+                    error!("Error while handling PTY output: {}", e);
+                }
+            }
+            PtyDied(status) if self.command.is_none() && !status.is_failure() && self.should_respawn() => {
+                self.schedule_respawn(status).await?;
+            }
+            PtyDied(status) => {
+                // the pane stays with its last screen and the exit status until it's respawned or closed
+                self.handle_pty_died(status).await?;
+            }
+            Kill => return Ok(false),
+            // the frame may have been drawn already, e.g. to put images on screen
+            Render if self.frames.is_scheduled() => {
+                self.handle_render().await?;
+            }
+            Render => {}
+            Rerender => {
+                self.handle_rerender().await?;
+            }
+            Resize { rect } => {
+                self.handle_resize(rect).await?;
+            }
+            Hide => {
+                self.pane_state = PaneState::Hidden;
+            }
+            MoveToWindow { window_handle } => {
+                self.window_handle = window_handle;
+                self.prev_cells = None;
+                self.reported_command = None;
+            }
+            Reveal => {
+                self.pane_state = PaneState::Visible;
+            }
+            AddMirror {
+                mirror_id,
+                window_handle,
+                id,
+                rect,
+            } => {
+                self.mirrors.insert(
+                    mirror_id,
+                    PaneMirror {
+                        window_handle,
+                        id,
+                        rect,
+                    },
+                );
+                self.handle_rerender().await?;
+            }
+            ResizeMirror { mirror_id, rect } => {
+                if let Some(mirror) = self.mirrors.get_mut(&mirror_id) {
+                    mirror.rect = rect;
+                }
+                self.handle_rerender().await?;
+            }
+            RemoveMirror { mirror_id } => {
+                self.mirrors.remove(&mirror_id);
+            }
+            ScanLinks { client } => {
+                self.handle_scan_links(client).await?;
+            }
+            Capture { client } => {
+                let screen = self.vte.screen();
+                let (rows, cols) = screen.size();
+                client
+                    .pane_capture(rows, cols, Bytes::from(screen.contents_formatted()))
+                    .await?;
+            }
+            Search { pattern, reply } => {
+                let _ = reply.send(self.search(&pattern));
+            }
+            Preview { rows, cols, reply } => {
+                let _ = reply.send(preview(self.vte.screen(), rows, cols));
+            }
+            CurrentDir { reply } => {
+                let _ = reply.send(platform::process_cwd(self.shell_pid));
+            }
+            EnterCopyMode { line, search } => {
+                self.handle_enter_copy_mode(line, search).await?;
+            }
+            Mouse { kind, row, col } => {
+                self.handle_mouse(kind, row, col).await?;
+            }
+            Tick => {
+                self.handle_check_silence().await?;
+                self.report_command().await?;
+            }
+            Focus(focused) => {
+                self.handle_focus(focused).await?;
+            }
+            PauseOutput(paused) => {
+                self.output_paused.send_replace(paused);
+            }
+            ForwardRawOutput(forward) => {
+                self.raw_output = forward;
+            }
+            SetLogging {
+                enabled,
+                session_name,
+                client,
+            } => {
+                self.handle_set_logging(enabled, session_name, client).await?;
+            }
+            SetOption(option) => match option {
+                PaneOption::AutoRespawn(enabled) => self.auto_respawn = Some(enabled),
+            },
+            SetTitle(title) => {
+                self.title = title;
+                self.handle_rerender().await?;
+            }
+            SetMarked(marked) => {
+                self.marked = marked;
+                self.handle_rerender().await?;
+            }
+            Respawn => {
+                self.handle_respawn().await?;
+            }
+        }
+        Ok(true)
+    }
+
+    /// the pty and mirrors go with the pane however it stopped
+    /// done once the pty has reaped its process
+    async fn stop(&mut self) {
+        let pty_handle = &self.pty_handle;
+        wait_for_children(&mut self.rx, async {
+            // the pty may have hung up on its own already
+            let _ = pty_handle.kill().await;
+            pty_handle.closed().await;
+        })
+        .await;
+        self.close_mirrors().await;
+    }
+
     async fn handle_input(&mut self, bytes: Bytes) -> Result<()> {
-        self.pty_handle.input(bytes).await.unwrap();
+        // the client may have the kitty keyboard protocol on, only programs that asked for it get its keys
+        let bytes = if self.copy_mode || self.modes.keyboard_flags() == 0 {
+            Bytes::from(to_legacy(&bytes))
+        } else {
+            bytes
+        };
+        if self.copy_mode {
+            return self.handle_copy_mode_input(&bytes).await;
+        }
+        if self.exit_status.is_some() {
+            return match &bytes[..] {
+                b"r" => self.handle_respawn().await,
+                b"x" => self.window_handle.close_pane(self.id).await,
+                _ => {
+                    trace!("ignoring input for dead pane");
+                    Ok(())
+                }
+            };
+        }
+        self.pty_handle.input(bytes).await
+    }
+
+    async fn handle_paste(&mut self, text: Bytes, bracketed: bool) -> Result<()> {
+        if self.copy_mode || self.exit_status.is_some() {
+            return Ok(());
+        }
+        let text = if bracketed && self.vte.screen().bracketed_paste() {
+            Bytes::from(bracketed_paste(&text))
+        } else {
+            text
+        };
+        self.pty_handle.input(text).await
+    }
+
+    async fn handle_enter_copy_mode(&mut self, line: Option<usize>, search: Option<String>) -> Result<()> {
+        self.copy_mode = true;
+        self.search = None;
+        if let Some(pattern) = search {
+            return self.handle_copy_search(pattern).await;
+        }
+        if let Some(line) = line {
+            let scrollback_len = self.scrollback_len();
+            self.vte.set_scrollback(offset_for_line(line, scrollback_len));
+        }
+        self.handle_rerender().await
+    }
+
+    async fn handle_copy_mode_input(&mut self, bytes: &[u8]) -> Result<()> {
+        if let Some(CopySearchState::Typing(pattern)) = &mut self.search {
+            match bytes {
+                b"\r" => {
+                    let pattern = std::mem::take(pattern);
+                    return self.handle_copy_search(pattern).await;
+                }
+                b"\x1b" | b"\x03" => self.search = None,
+                b"\x7f" | b"\x08" => {
+                    pattern.pop();
+                }
+                _ => match std::str::from_utf8(bytes) {
+                    Ok(text) if !text.chars().any(char::is_control) => pattern.push_str(text),
+                    _ => return Ok(()),
+                },
+            }
+            return self.handle_rerender().await;
+        }
+        let offset = self.vte.screen().scrollback();
+        let new_offset = match copy_mode_action(bytes, self.rect.height as usize) {
+            CopyModeAction::Exit => {
+                self.copy_mode = false;
+                self.search = None;
+                0
+            }
+            CopyModeAction::ScrollUp(n) => offset + n,
+            CopyModeAction::ScrollDown(n) => offset.saturating_sub(n),
+            CopyModeAction::Top => usize::MAX,
+            CopyModeAction::Bottom => 0,
+            CopyModeAction::Search => {
+                self.search = Some(CopySearchState::Typing(String::new()));
+                return self.handle_rerender().await;
+            }
+            action @ (CopyModeAction::NextMatch | CopyModeAction::PrevMatch) => {
+                let Some(CopySearchState::Results(search)) = &mut self.search else {
+                    return Ok(());
+                };
+                search.step(action == CopyModeAction::NextMatch);
+                let Some(line) = search.line() else {
+                    return Ok(());
+                };
+                self.line_offset(line)
+            }
+            CopyModeAction::None => return Ok(()),
+        };
+        // selections are in screen coordinates so they don't survive scrolling
+        self.selection = None;
+        // vt100 clamps the offset to the available scrollback
+        self.vte.set_scrollback(new_offset);
+        self.handle_rerender().await
+    }
+
+    /// runs a `/` search over the scrollback and screen and scrolls to its first match
+    async fn handle_copy_search(&mut self, pattern: String) -> Result<()> {
+        self.copy_mode = true;
+        self.selection = None;
+        if pattern.is_empty() {
+            self.search = None;
+            return self.handle_rerender().await;
+        }
+        let lines = self.all_lines();
+        let first_line = self.scrollback_len() - self.vte.screen().scrollback();
+        let bottom = first_line + (self.rect.height as usize).saturating_sub(1);
+        self.search = Some(match CopySearch::new(&pattern, &lines, bottom) {
+            Ok(search) => {
+                if let Some(line) = search.line() {
+                    let offset = self.line_offset(line);
+                    self.vte.set_scrollback(offset);
+                }
+                CopySearchState::Results(search)
+            }
+            Err(e) => CopySearchState::Failed(e.to_string()),
+        });
+        self.handle_rerender().await
+    }
+
+    /// the scrollback offset showing `line`, the current one if it's already in view and otherwise
+    /// one with the line in the middle of the pane
+    fn line_offset(&mut self, line: usize) -> usize {
+        let offset = self.vte.screen().scrollback();
+        let scrollback_len = self.scrollback_len();
+        let height = self.rect.height as usize;
+        let first_line = scrollback_len - offset;
+        if (first_line..first_line + height).contains(&line) {
+            return offset;
+        }
+        offset_for_line(line.saturating_sub(height / 2), scrollback_len)
+    }
+
+    async fn handle_mouse(&mut self, kind: MouseKind, row: u16, col: u16) -> Result<()> {
+        // rows arrive relative to the outer rect, the header isn't part of the screen
+        let row = row.saturating_sub(self.rect.y - self.outer.y);
+        if !self.copy_mode && self.forward_mouse(kind, row, col).await? {
+            return Ok(());
+        }
+
+        match kind {
+            MouseKind::ScrollUp => return self.handle_scroll(true).await,
+            MouseKind::ScrollDown => return self.handle_scroll(false).await,
+            MouseKind::Down => {
+                let count = match self.last_click {
+                    Some((at, pos, count)) if pos == (row, col) && at.elapsed() < MULTI_CLICK_INTERVAL => count % 3 + 1,
+                    _ => 1,
+                };
+                self.last_click = Some((Instant::now(), (row, col), count));
+                let unit = SelectionUnit::from_click_count(count);
+                let selection = self.selection_at(row, col, unit);
+                self.selection_anchor = Some((selection, unit));
+                if unit == SelectionUnit::Char {
+                    // a single click only starts selecting once dragged
+                    self.selection = None;
+                } else {
+                    self.copy_mode = true;
+                    self.selection = Some(selection);
+                }
+            }
+            MouseKind::Drag => {
+                let Some((anchor, unit)) = self.selection_anchor else {
+                    return Ok(());
+                };
+                self.copy_mode = true;
+                self.selection = Some(anchor.union(&self.selection_at(row, col, unit)));
+            }
+            MouseKind::Up => {
+                self.selection_anchor = None;
+                if let Some(selection) = self.selection {
+                    let (start, end) = (selection.start, selection.end);
+                    let text = self.vte.screen().contents_between(start.0, start.1, end.0, end.1 + 1);
+                    if !text.is_empty() {
+                        self.window_handle.pane_copied(text).await?;
+                    }
+                }
+                return Ok(());
+            }
+        }
+        self.handle_rerender().await
+    }
+
+    /// the wheel scrolls back through the history in copy mode, scrolling back down to the live screen
+    /// leaves it again. the alternate screen has no history, programs like `less` get arrow keys instead
+    async fn handle_scroll(&mut self, up: bool) -> Result<()> {
+        let screen = self.vte.screen();
+        if screen.alternate_screen() && !self.copy_mode {
+            if self.exit_status.is_some() {
+                return Ok(());
+            }
+            let key: &[u8] = match (up, screen.application_cursor()) {
+                (true, true) => b"\x1bOA",
+                (true, false) => b"\x1b[A",
+                (false, true) => b"\x1bOB",
+                (false, false) => b"\x1b[B",
+            };
+            return self.pty_handle.input(Bytes::from(key.repeat(WHEEL_LINES))).await;
+        }
+        let offset = screen.scrollback();
+        let new_offset = if up {
+            offset + WHEEL_LINES
+        } else {
+            offset.saturating_sub(WHEEL_LINES)
+        };
+        // vt100 clamps the offset to the available scrollback
+        self.vte.set_scrollback(new_offset);
+        self.copy_mode = self.vte.screen().scrollback() > 0;
+        self.selection = None;
+        if !self.copy_mode {
+            self.search = None;
+        }
+        self.handle_rerender().await
+    }
+
+    /// programs that turned on mouse reporting get the event instead of it being used for selection,
+    /// returns whether the program owns the mouse
+    async fn forward_mouse(&mut self, kind: MouseKind, row: u16, col: u16) -> Result<bool> {
+        use vt100::{MouseProtocolEncoding, MouseProtocolMode};
+
+        let screen = self.vte.screen();
+        let wanted = match (screen.mouse_protocol_mode(), kind) {
+            (MouseProtocolMode::None, _) => return Ok(false),
+            (MouseProtocolMode::Press, MouseKind::Down | MouseKind::ScrollUp | MouseKind::ScrollDown) => true,
+            (MouseProtocolMode::Press, _) | (MouseProtocolMode::PressRelease, MouseKind::Drag) => false,
+            _ => true,
+        };
+        if !wanted || self.exit_status.is_some() {
+            return Ok(true);
+        }
+
+        let button: u8 = match kind {
+            MouseKind::Drag => 32,
+            MouseKind::ScrollUp => 64,
+            MouseKind::ScrollDown => 65,
+            _ => 0,
+        };
+        let bytes = match screen.mouse_protocol_encoding() {
+            MouseProtocolEncoding::Sgr => {
+                let final_char = if kind == MouseKind::Up { 'm' } else { 'M' };
+                format!("\x1b[<{button};{};{}{final_char}", col + 1, row + 1).into_bytes()
+            }
+            // the legacy encoding reports every release as button 3 and can't address cells past 223
+            _ => {
+                let button = if kind == MouseKind::Up { 3 } else { button };
+                let encode = |n: u16| 32 + (n + 1).min(223) as u8;
+                vec![0x1b, b'[', b'M', 32 + button, encode(col), encode(row)]
+            }
+        };
+        self.pty_handle.input(Bytes::from(bytes)).await?;
+        Ok(true)
+    }
+
+    fn selection_at(&self, row: u16, col: u16, unit: SelectionUnit) -> Selection {
+        match unit {
+            SelectionUnit::Char => Selection::new((row, col), (row, col)),
+            SelectionUnit::Word => {
+                let cells = row_cells(self.vte.screen(), row, self.rect.width);
+                let (start, end) = word_bounds(&cells, col as usize);
+                Selection::new((row, start as u16), (row, end as u16))
+            }
+            SelectionUnit::Line => Selection::new((row, 0), (row, self.rect.width.saturating_sub(1))),
+        }
+    }
+
+    /// number of lines currently held in scrollback
+    fn scrollback_len(&mut self) -> usize {
+        let offset = self.vte.screen().scrollback();
+        self.vte.set_scrollback(usize::MAX);
+        let len = self.vte.screen().scrollback();
+        self.vte.set_scrollback(offset);
+        len
+    }
+
+    /// every line of scrollback followed by the visible screen, oldest first
+    fn all_lines(&mut self) -> Vec<String> {
+        let offset = self.vte.screen().scrollback();
+        let scrollback_len = self.scrollback_len();
+        let height = self.rect.height as usize;
+        let mut lines = Vec::with_capacity(scrollback_len + height);
+
+        // page through the history, each view shows `height` lines starting `view_offset` lines back
+        let mut view_offset = scrollback_len;
+        loop {
+            self.vte.set_scrollback(view_offset);
+            let first_line = scrollback_len - view_offset;
+            for (i, row) in self.vte.screen().rows(0, self.rect.width).enumerate() {
+                if first_line + i == lines.len() {
+                    lines.push(row);
+                }
+            }
+            if view_offset == 0 {
+                break;
+            }
+            view_offset = view_offset.saturating_sub(height.max(1));
+        }
+
+        self.vte.set_scrollback(offset);
+        lines
+    }
+
+    fn search(&mut self, pattern: &str) -> Vec<(usize, String)> {
+        self.all_lines()
+            .into_iter()
+            .enumerate()
+            .filter(|(_, line)| line.contains(pattern))
+            .map(|(i, line)| (i, line.trim_end().to_owned()))
+            .collect()
+    }
+
+    fn should_respawn(&mut self) -> bool {
+        if !self.auto_respawn.unwrap_or(self.options.borrow().auto_respawn) {
+            return false;
+        }
+        if self.spawned_at.elapsed() < RESPAWN_MIN_UPTIME {
+            self.quick_respawns += 1;
+        } else {
+            self.quick_respawns = 0;
+        }
+        if self.quick_respawns >= MAX_QUICK_RESPAWNS {
+            warn!(
+                respawns = self.quick_respawns,
+                "shell keeps exiting, no longer respawning it"
+            );
+            return false;
+        }
+        true
+    }
+
+    /// the exit banner stays up until the new shell starts
+    async fn schedule_respawn(&mut self, status: ExitStatus) -> Result<()> {
+        info!(status=%status, "respawning pane process");
+        self.exit_status = Some(status);
+        self.handle_rerender().await?;
+        let handle = self.handle.clone();
+        let _task: DaemonTask = tokio::spawn(async move {
+            tokio::time::sleep(RESPAWN_DELAY).await;
+            // fails if the pane was killed in the meantime
+            let _ = handle.respawn().await;
+            Ok(())
+        });
         Ok(())
     }
 
-    async fn handle_pty_output(&mut self, bytes: Bytes) -> Result<()> {
-        self.vte.process(&bytes);
+    async fn handle_respawn(&mut self) -> Result<()> {
+        // `r` may have beaten the scheduled auto-respawn to it
+        if self.exit_status.is_none() {
+            return Ok(());
+        }
+        (self.pty_handle, self.shell_pid) = spawn_pty(
+            &self.handle,
+            self.rect,
+            &self.options,
+            &self.environment,
+            self.command.clone(),
+            self.cwd.clone(),
+            &self.output_paused,
+        )?;
+        self.exit_status = None;
+        self.spawned_at = Instant::now();
+        let history_limit = self.options.borrow().history_limit;
+        self.vte = vt100::Parser::new(self.rect.height, self.rect.width, history_limit);
+        self.modes = TerminalModes::default();
+        self.alternate_screen = false;
+        self.bells = 0;
+        self.graphics = GraphicsScanner::default();
         self.handle_rerender().await
     }
 
-    // TODO: below code is bad and unused, need better diffing solution
-    async fn handle_render(&mut self) -> Result<()> {
-        match &self.prev_screen_state {
-            Some(prev) => {
-                let cur_screen_state = self.vte.screen();
-                let diff = cur_screen_state.state_diff(prev);
-                self.prev_screen_state = Some(cur_screen_state.clone());
-                let (c_row, c_col) = cur_screen_state.cursor_position();
-                let global_x = self.rect.x + 1 + c_col;
-                let global_y = self.rect.y + 1 + c_row;
-                Ok(self
-                    .window_handle
-                    .pane_output(self.id, Bytes::copy_from_slice(&diff), Some((global_x, global_y)))
-                    .await?)
+    async fn handle_pty_died(&mut self, status: ExitStatus) -> Result<()> {
+        if status.is_failure() {
+            warn!(status=%status, "pane process failed");
+        } else {
+            info!(status=%status, "pane process finished");
+        }
+        self.exit_status = Some(status);
+        self.handle_rerender().await?;
+        self.window_handle.pane_exited(self.id, status).await
+    }
+
+    async fn handle_pty_output(&mut self, bytes: Bytes) -> Result<()> {
+        // images are drawn where the cursor was when they arrived, so text before them is processed first
+        let mut text = Vec::with_capacity(bytes.len());
+        let mut images = Vec::new();
+        for chunk in self.graphics.split(&bytes) {
+            match chunk {
+                Chunk::Text(chunk) => {
+                    self.vte.process(&chunk);
+                    text.extend_from_slice(&chunk);
+                }
+                Chunk::Image(image) => images.push((image, self.vte.screen().cursor_position())),
             }
-            None => self.handle_rerender().await,
         }
+        // the switch stays inside the pane's vte, clients only see the pane's rect redrawn from whichever
+        // screen is now showing - leaving puts the primary screen back as it was
+        let alternate_screen = self.vte.screen().alternate_screen();
+        if alternate_screen != self.alternate_screen {
+            debug!(id = self.id, alternate_screen, "pane switched screens");
+            self.alternate_screen = alternate_screen;
+            self.prev_cells = None;
+        }
+        // BEL never reaches the clients with the cells, it's passed on separately
+        let bells = self.vte.screen().audible_bell_count();
+        if bells != self.bells {
+            self.bells = bells;
+            self.window_handle.pane_bell(self.id).await?;
+        }
+        self.last_output = Instant::now();
+        self.silence_alerted = false;
+        let monitor_activity = self.options.borrow().monitor_activity;
+        if monitor_activity
+            && self
+                .last_activity_alert
+                .is_none_or(|alerted| alerted.elapsed() >= ACTIVITY_ALERT_INTERVAL)
+        {
+            self.last_activity_alert = Some(Instant::now());
+            self.window_handle.pane_alert(self.id, Alert::Activity).await?;
+        }
+        if let Some(log) = self.log.as_mut()
+            && let Err(e) = log.write(&text)
+        {
+            // a full disk shouldn't take the pane down with it
+            error!("Error while writing pane log, logging stopped: {}", e);
+            self.log = None;
+        }
+        let replies = self.modes.feed(&bytes);
+        if !replies.is_empty() {
+            self.pty_handle.input(Bytes::from(replies)).await?;
+        }
+        for text in self.osc52.feed(&bytes) {
+            self.window_handle.pane_copied(text).await?;
+        }
+        if self.raw_output {
+            self.window_handle.pane_raw_output(self.id, bytes).await?;
+        }
+        // images are drawn over the text, which has to be on screen first
+        if images.is_empty() {
+            self.schedule_render().await?;
+        } else {
+            self.handle_render().await?;
+        }
+
+        // drawn after the rerender so the text doesn't paint over them, a scrolled back view has moved on
+        if self.copy_mode || self.vte.screen().scrollback() > 0 || matches!(self.pane_state, PaneState::Hidden) {
+            return Ok(());
+        }
+        for (image, cursor) in images {
+            self.window_handle
+                .pane_image(self.id, Bytes::from(image), cursor)
+                .await?;
+        }
+        Ok(())
+    }
+
+    /// draws straight away if the last frame was long enough ago, otherwise once the frame interval is up
+    async fn schedule_render(&mut self) -> Result<()> {
+        let frame_rate = self.options.borrow().frame_rate;
+        if self.frames.output(frame_rate, &self.handle) {
+            self.handle_render().await?;
+        }
+        Ok(())
+    }
+
+    /// sends only the cells that changed since the last frame, anything drawn over the screen
+    /// (selection, scrollbar, exit banner, mark badge) or a mirror needs the full redraw
+    async fn handle_render(&mut self) -> Result<()> {
+        self.frames.drawn();
+        if matches!(self.pane_state, PaneState::Hidden) {
+            // nothing is drawn, the pane is redrawn in full once revealed
+            self.prev_cells = None;
+            return Ok(());
+        }
+        let screen = self.vte.screen();
+        let overlaid = self.copy_mode
+            || screen.scrollback() > 0
+            || self.exit_status.is_some()
+            || (self.marked && self.rect == self.outer)
+            || !self.mirrors.is_empty();
+        let cells = CellGrid::from_screen(screen, self.rect.width, self.rect.height);
+        let diff = match &self.prev_cells {
+            Some(prev) if !overlaid => cells.render_diff(prev, self.rect),
+            _ => None,
+        };
+        let Some(mut output) = diff else {
+            return self.handle_rerender().await;
+        };
+        self.prev_cells = Some(cells);
+        if self.rect != self.outer {
+            output.extend_from_slice(&self.header());
+        }
+        let cursor = global_cursor(screen, self.rect);
+        self.window_handle
+            .pane_output(self.id, Bytes::from(output), Some(cursor), screen.hide_cursor())
+            .await
     }
 
     async fn handle_rerender(&mut self) -> Result<()> {
+        // `pane-titles` may have been switched since the last render
+        self.sync_rect().await?;
+        let offset = self.vte.screen().scrollback();
+        let scroll = ScrollOverlay {
+            offset,
+            scrollback_len: self.scrollback_len(),
+            scrollbar: self.options.borrow().scrollbar,
+            ascii: self.options.borrow().screen_reader,
+        };
         let screen = self.vte.screen();
 
         trace!("RERENDER -- id: {} size {:?}", self.id, screen.size());
-        self.prev_screen_state = Some(screen.clone());
-        let mut output = Vec::new();
+        self.prev_cells = matches!(self.pane_state, PaneState::Visible)
+            .then(|| CellGrid::from_screen(screen, self.rect.width, self.rect.height));
+
+        let selection = self.selection.filter(|_| self.copy_mode);
+        let search = self
+            .search
+            .as_ref()
+            .filter(|_| self.copy_mode)
+            .map(|state| SearchOverlay {
+                state,
+                first_line: scroll.scrollback_len - offset,
+            });
+        let scroll = (offset > 0).then_some(scroll);
+        let (mut output, cursor) = render_screen(screen, self.rect, self.exit_status, selection, scroll, search);
+        if self.rect != self.outer {
+            output.extend_from_slice(&self.header());
+        } else if self.marked {
+            output.extend_from_slice(&mark_badge(self.rect));
+        }
+        // a hidden pane (another one is zoomed) keeps its screen up to date but draws nothing
+        if matches!(self.pane_state, PaneState::Visible) {
+            self.window_handle
+                .pane_output(self.id, Bytes::from(output), Some(cursor), screen.hide_cursor())
+                .await?;
+        }
 
-        for (i, row) in screen.rows_formatted(0, self.rect.width).enumerate() {
-            let cx = self.rect.x + 1;
-            let cy = self.rect.y + 1 + (i as u16);
+        // mirrors are clipped to their own rect, the pty keeps the size of the primary pane
+        for mirror in self.mirrors.values() {
+            let (output, cursor) = render_screen(screen, mirror.rect, self.exit_status, None, None, None);
+            mirror
+                .window_handle
+                .pane_output(mirror.id, Bytes::from(output), Some(cursor), screen.hide_cursor())
+                .await?;
+        }
+        Ok(())
+    }
 
-            let move_cursor = format!("\x1b[{};{}H", cy, cx);
-            output.extend_from_slice(move_cursor.as_bytes());
+    async fn handle_scan_links(&mut self, client: ClientConnectionHandle) -> Result<()> {
+        let rect = self.rect;
+        let screen = self.vte.screen();
+        let links: Vec<LinkMatch> = (0..rect.height)
+            .flat_map(|row| {
+                // wide characters take two cells but are one character of the text
+                let (line, columns) = row_text(&row_cells(screen, row, rect.width));
+                find_links(&line)
+                    .into_iter()
+                    .filter_map(|(i, text, kind)| {
+                        let (byte, _) = line.char_indices().nth(i)?;
+                        Some(LinkMatch {
+                            text,
+                            kind,
+                            row: rect.y + row,
+                            col: rect.x + columns[byte],
+                        })
+                    })
+                    .collect::<Vec<_>>()
+            })
+            .collect();
 
-            let erase_chars = format!("\x1b[{}X", self.rect.width);
-            output.extend_from_slice(erase_chars.as_bytes());
-            output.extend_from_slice(&row);
+        if links.is_empty() {
+            client.notify("no links found".to_owned()).await
+        } else {
+            client.links(links).await
         }
+    }
 
-        output.extend_from_slice(b"\x1b[0m");
+    async fn handle_focus(&mut self, focused: bool) -> Result<()> {
+        if !self.modes.focus_reporting || self.exit_status.is_some() {
+            return Ok(());
+        }
+        let report: &'static [u8] = if focused { b"\x1b[I" } else { b"\x1b[O" };
+        self.pty_handle.input(Bytes::from_static(report)).await
+    }
 
-        let (c_row, c_col) = screen.cursor_position();
-        let global_x = self.rect.x + 1 + c_col;
-        let global_y = self.rect.y + 1 + c_row;
+    async fn handle_check_silence(&mut self) -> Result<()> {
+        let secs = self.options.borrow().monitor_silence;
+        if secs == 0 || self.silence_alerted || self.exit_status.is_some() {
+            return Ok(());
+        }
+        if self.last_output.elapsed() >= Duration::from_secs(secs) {
+            self.silence_alerted = true;
+            self.window_handle.pane_alert(self.id, Alert::Silence).await?;
+        }
+        Ok(())
+    }
 
-        self.window_handle
-            .pane_output(self.id, Bytes::from(output), Some((global_x, global_y)))
-            .await
+    /// windows are named after their focused pane's foreground program, see `automatic-rename`
+    async fn report_command(&mut self) -> Result<()> {
+        if self.exit_status.is_some() {
+            return Ok(());
+        }
+        let Some(process) = ForegroundProcess::of_shell(self.shell_pid.as_raw()) else {
+            return Ok(());
+        };
+        if self.reported_command.as_ref() == Some(&process.command) {
+            return Ok(());
+        }
+        self.reported_command = Some(process.command.clone());
+        self.window_handle.pane_command(self.id, process.command).await
+    }
+
+    async fn handle_set_logging(
+        &mut self,
+        enabled: Option<bool>,
+        session_name: String,
+        client: Option<ClientConnectionHandle>,
+    ) -> Result<()> {
+        let enabled = enabled.unwrap_or(self.log.is_none());
+        let message = match (enabled, self.log.is_some()) {
+            (true, false) => {
+                let name = format!("{}-pane{}", session_name.replace('/', "_"), self.id);
+                match PaneLog::open(&name) {
+                    Ok(log) => {
+                        let message = format!("logging pane to {}", log.path().display());
+                        self.log = Some(log);
+                        message
+                    }
+                    Err(e) => {
+                        error!("Error while opening pane log: {}", e);
+                        format!("failed to open pane log: {e}")
+                    }
+                }
+            }
+            (false, true) => {
+                self.log = None; // dropping the log flushes a partial line
+                "pane logging stopped".to_owned()
+            }
+            (true, true) => "pane is already being logged".to_owned(),
+            (false, false) => "pane is not being logged".to_owned(),
+        };
+        match client {
+            Some(client) => client.notify(message).await,
+            None => Ok(()),
+        }
+    }
+
+    /// tells every mirroring window that this pane is gone
+    async fn close_mirrors(&mut self) {
+        for (_, mirror) in self.mirrors.drain() {
+            if let Err(e) = mirror.window_handle.mirror_closed(mirror.id).await {
+                warn!("Error while closing pane mirror: {}", e);
+            }
+        }
     }
 
     async fn handle_resize(&mut self, rect: Rect) -> Result<()> {
+        self.outer = rect;
+        self.handle_rerender().await?;
+        Ok(())
+    }
+
+    /// resizes the program to the outer rect minus the header, if that changed
+    async fn sync_rect(&mut self) -> Result<()> {
+        let rect = content_rect(self.outer, self.options.borrow().pane_titles);
+        if rect == self.rect {
+            return Ok(());
+        }
         self.rect = rect;
-        self.pty_handle.resize(rect).await?;
+        if self.exit_status.is_none() {
+            self.pty_handle.resize(rect).await?;
+        }
         self.vte.set_size(rect.height, rect.width);
-
-        self.handle_rerender().await?;
         Ok(())
     }
+
+    /// the `pane-titles` header on the row above the screen
+    fn header(&self) -> Vec<u8> {
+        let process = ForegroundProcess::of_shell(self.shell_pid.as_raw()).unwrap_or_default();
+        let title = self.title.as_deref().unwrap_or(self.vte.screen().title());
+        let vars = TitleVars {
+            index: self.id,
+            title,
+            process: &process,
+        };
+        let text = format_title(&self.options.borrow().pane_title_format, &vars);
+        let text = if self.marked { format!("[marked] {text}") } else { text };
+        let width = self.outer.width as usize;
+        let text: String = format!(" {text}").chars().take(width).collect();
+        let mut header = format!("\x1b[{};{}H", self.outer.y + 1, self.outer.x + 1).into_bytes();
+        // the marked pane's header stands out from the rest
+        header.extend_from_slice(if self.marked { b"\x1b[1;30;46m" } else { b"\x1b[7m" });
+        header.extend_from_slice(format!("{text:<width$}").as_bytes());
+        header.extend_from_slice(b"\x1b[0m");
+        header
+    }
+}
+
+/// where a scrolled back pane is in its history
+struct ScrollOverlay {
+    offset: usize,
+    scrollback_len: usize,
+    scrollbar: bool,
+    ascii: bool, // screen readers read box drawing characters out loud
+}
+
+/// `/` in copy mode, matches on screen are highlighted and the last row shows the prompt or results
+struct SearchOverlay<'a> {
+    state: &'a CopySearchState,
+    first_line: usize, // the line on the top row, counted from the oldest scrollback line
+}
+
+/// draws the screen into `rect`, returns the output and the global cursor position
+fn render_screen(
+    screen: &vt100::Screen,
+    rect: Rect,
+    exit_status: Option<ExitStatus>,
+    selection: Option<Selection>,
+    scroll: Option<ScrollOverlay>,
+    search: Option<SearchOverlay>,
+) -> (Vec<u8>, (u16, u16)) {
+    // every cell is drawn with its full attributes, nothing the other panes drew can bleed in
+    let mut output = CellGrid::from_screen(screen, rect.width, rect.height).render(rect);
+    output.extend_from_slice(b"\x1b[0m");
+
+    if let Some(selection) = selection {
+        output.extend_from_slice(&highlight(screen, rect, selection, "\x1b[7m"));
+    }
+
+    if let Some(scroll) = scroll {
+        output.extend_from_slice(&scroll_overlay(rect, scroll));
+    }
+
+    if let Some(search) = search {
+        output.extend_from_slice(&search_overlay(screen, rect, search));
+    }
+
+    if let Some(status) = exit_status {
+        output.extend_from_slice(&exit_banner(rect, status));
+    }
+
+    (output, global_cursor(screen, rect))
+}
+
+/// the cursor's 1 based position on the client's terminal, kept inside the rect. on the right half of a
+/// wide character it sits on the character, as the terminal would show it
+fn global_cursor(screen: &vt100::Screen, rect: Rect) -> (u16, u16) {
+    let (c_row, c_col) = screen.cursor_position();
+    let c_col = match screen.cell(c_row, c_col) {
+        Some(cell) if cell.is_wide_continuation() => c_col.saturating_sub(1),
+        _ => c_col,
+    };
+    let global_x = rect.x + 1 + c_col.min(rect.width.saturating_sub(1));
+    let global_y = rect.y + 1 + c_row.min(rect.height.saturating_sub(1));
+    (global_x, global_y)
+}
+
+/// redraws the selected cells with `sgr`
+fn highlight(screen: &vt100::Screen, rect: Rect, selection: Selection, sgr: &str) -> Vec<u8> {
+    let mut output = Vec::new();
+    for row in 0..rect.height {
+        let Some((first, last)) = selection.columns(row, rect.width) else {
+            continue;
+        };
+        // starting on the right half of a wide character would shift the rest of the row left by a cell
+        let first = match screen.cell(row, first) {
+            Some(cell) if cell.is_wide_continuation() => first.saturating_sub(1),
+            _ => first,
+        };
+        output.extend_from_slice(format!("\x1b[{};{}H{sgr}", rect.y + 1 + row, rect.x + 1 + first).as_bytes());
+        for col in first..=last {
+            match screen.cell(row, col) {
+                // the left half already drew the whole wide character
+                Some(cell) if cell.is_wide_continuation() => {}
+                // cut off by the right edge, it would spill into the neighbouring pane
+                Some(cell) if cell.is_wide() && col + 1 == rect.width => output.push(b' '),
+                Some(cell) if cell.has_contents() => output.extend_from_slice(cell.contents().as_bytes()),
+                _ => output.push(b' '),
+            }
+        }
+        output.extend_from_slice(b"\x1b[0m");
+    }
+    output
+}
+
+/// the matches on screen in yellow, the current one in reverse video, and the prompt or search
+/// status over the last row
+fn search_overlay(screen: &vt100::Screen, rect: Rect, search: SearchOverlay) -> Vec<u8> {
+    let mut output = Vec::new();
+    if let CopySearchState::Results(results) = search.state {
+        for row in 0..rect.height {
+            let cells = row_cells(screen, row, rect.width);
+            for (first, last, current) in results.row_matches(search.first_line + row as usize, &cells) {
+                let sgr = if current { "\x1b[7m" } else { "\x1b[30;43m" };
+                output.extend_from_slice(&highlight(screen, rect, Selection::new((row, first), (row, last)), sgr));
+            }
+        }
+    }
+    let status: String = search.state.status().chars().take(rect.width as usize).collect();
+    output.extend_from_slice(
+        format!(
+            "\x1b[{};{}H\x1b[30;43m{status}\x1b[0m",
+            rect.y + rect.height,
+            rect.x + 1
+        )
+        .as_bytes(),
+    );
+    output
+}
+
+/// the cells of a screen row for word selection and search
+fn row_cells(screen: &vt100::Screen, row: u16, width: u16) -> Vec<RowCell> {
+    (0..width)
+        .map(|col| {
+            let cell = screen.cell(row, col);
+            RowCell {
+                contents: cell.map(|cell| cell.contents()).unwrap_or_default(),
+                is_wide_continuation: cell.is_some_and(|cell| cell.is_wide_continuation()),
+            }
+        })
+        .collect()
+}
+
+/// the visible screen cropped to `rows` x `cols`, each row as runs of equally styled text
+fn preview(screen: &vt100::Screen, rows: u16, cols: u16) -> PreviewPane {
+    let color = |color: vt100::Color| match color {
+        vt100::Color::Default => TextColor::Default,
+        vt100::Color::Idx(i) => TextColor::Indexed(i),
+        vt100::Color::Rgb(r, g, b) => TextColor::Rgb(r, g, b),
+    };
+    let (screen_rows, screen_cols) = screen.size();
+    let lines = (0..rows.min(screen_rows))
+        .map(|row| {
+            let mut line: Vec<StyledText> = Vec::new();
+            for col in 0..cols.min(screen_cols) {
+                let Some(cell) = screen.cell(row, col) else {
+                    continue;
+                };
+                if cell.is_wide_continuation() {
+                    continue;
+                }
+                let style = TextStyle {
+                    fg: color(cell.fgcolor()),
+                    bg: color(cell.bgcolor()),
+                    bold: cell.bold(),
+                    italic: cell.italic(),
+                    underline: cell.underline(),
+                    inverse: cell.inverse(),
+                };
+                let contents = if cell.has_contents() {
+                    cell.contents()
+                } else {
+                    " ".to_owned()
+                };
+                match line.last_mut() {
+                    Some(last) if last.style == style => last.text.push_str(&contents),
+                    _ => line.push(StyledText { text: contents, style }),
+                }
+            }
+            line
+        })
+        .collect();
+    PreviewPane { lines }
+}
+
+/// position indicator in the top right corner and, if enabled, a scrollbar in the last column
+fn scroll_overlay(rect: Rect, scroll: ScrollOverlay) -> Vec<u8> {
+    let mut output = Vec::new();
+    let mut right = rect.x + rect.width;
+    if scroll.scrollbar && rect.width > 1 {
+        let (start, len) = scrollbar_thumb(scroll.offset, scroll.scrollback_len, rect.height);
+        for row in 0..rect.height {
+            let glyph = match ((start..start + len).contains(&row), scroll.ascii) {
+                (true, false) => "┃",
+                (false, false) => "│",
+                (true, true) => "#",
+                (false, true) => "|",
+            };
+            output.extend_from_slice(format!("\x1b[{};{}H\x1b[90m{glyph}", rect.y + 1 + row, right).as_bytes());
+        }
+        output.extend_from_slice(b"\x1b[0m");
+        right -= 1;
+    }
+
+    let indicator = position_indicator(scroll.offset, scroll.scrollback_len);
+    let len = indicator.len() as u16;
+    if len <= right - rect.x {
+        output.extend_from_slice(
+            format!("\x1b[{};{}H\x1b[30;43m{indicator}\x1b[0m", rect.y + 1, right - len + 1).as_bytes(),
+        );
+    }
+    output
+}
+
+/// marks the top left corner of the marked pane when there's no header to show it in
+fn mark_badge(rect: Rect) -> Vec<u8> {
+    let text: String = " M ".chars().take(rect.width as usize).collect();
+    format!("\x1b[{};{}H\x1b[1;30;46m{text}\x1b[0m", rect.y + 1, rect.x + 1).into_bytes()
+}
+
+/// the outer rect without its header row, too short a pane keeps every row for the program
+fn content_rect(outer: Rect, header: bool) -> Rect {
+    if !header || outer.height < 2 {
+        return outer;
+    }
+    Rect {
+        y: outer.y + 1,
+        height: outer.height - 1,
+        ..outer
+    }
+}
+
+fn spawn_pty(
+    handle: &PaneHandle,
+    rect: Rect,
+    options: &OptionsReceiver,
+    environment: &SessionEnvironment,
+    command: Option<Vec<String>>,
+    cwd: Option<PathBuf>,
+    paused: &watch::Sender<bool>,
+) -> Result<(PtyHandle, Pid)> {
+    // `set-environment TERM` wins over default-terminal
+    let term = pane_term(&options.borrow().default_terminal);
+    let mut env = vec![("TERM".to_owned(), Some(term))];
+    env.extend(environment.vars());
+    let command = command.unwrap_or_else(|| vec![options.borrow().shell()]);
+    Pty::spawn(handle.clone(), rect, env, command, cwd, paused.subscribe())
+}
+
+/// styled banner drawn over the last row of the pane rect describing how the process died
+fn exit_banner(rect: Rect, status: ExitStatus) -> Vec<u8> {
+    let width = rect.width as usize;
+    let text: String = format!(" [process {status} - press r to respawn, x to close] ")
+        .chars()
+        .take(width)
+        .collect();
+    let mut banner = format!("\x1b[{};{}H", rect.y + rect.height, rect.x + 1).into_bytes();
+    // failures stand out, a shell that was simply exited doesn't need to
+    banner.extend_from_slice(if status.is_failure() {
+        b"\x1b[1;37;41m"
+    } else {
+        b"\x1b[7m"
+    });
+    banner.extend_from_slice(format!("{text:<width$}").as_bytes());
+    banner.extend_from_slice(b"\x1b[0m");
+    banner
 }
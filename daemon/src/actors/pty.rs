@@ -1,30 +1,91 @@
 use std::{
-    ffi::CString,
-    os::fd::{AsRawFd, OwnedFd, RawFd},
+    collections::{BTreeMap, VecDeque},
+    ffi::{CStr, CString},
+    os::{
+        fd::{AsRawFd, RawFd},
+        unix::ffi::OsStrExt,
+    },
+    path::PathBuf,
+    sync::Arc,
 };
 
 use bytes::Bytes;
-use color_eyre::eyre;
 use handle_macro::Handle;
 use nix::{
     errno::Errno,
-    libc::{F_GETFL, F_SETFL, O_NONBLOCK, TIOCSWINSZ, fcntl, ioctl},
     pty::{
         ForkptyResult::{Child, Parent},
-        Winsize, forkpty,
+        forkpty,
     },
     sys::{
         signal::{Signal, kill},
         wait::{WaitStatus, waitpid},
     },
-    unistd::{self, Pid, execvp},
+    unistd::{self, Pid, execve},
+};
+use tokio::{
+    io::unix::AsyncFd,
+    sync::{OwnedSemaphorePermit, Semaphore, mpsc, watch},
+};
+use tracing::{Instrument, Span};
+
+use crate::{
+    actors::{continue_trace, pane::PaneHandle},
+    layout::Rect,
+    platform::{self, set_winsize},
+    prelude::*,
 };
-use tokio::{io::unix::AsyncFd, sync::mpsc};
-use tracing::Instrument;
 
-use crate::{actors::pane::PaneHandle, layout::Rect, prelude::*};
+// a pty's input buffer is around 4k, writing more at once only blocks
+const PTY_WRITE_CHUNK: usize = 4096;
+// reads handed to the pane that it hasn't processed yet, past this the pty waits for the pane to catch up
+const PTY_READ_CREDITS: usize = 4;
+const FALLBACK_TERM: &str = "screen-256color";
+const TERMINFO_DIRS: [&str; 4] = [
+    "/etc/terminfo",
+    "/lib/terminfo",
+    "/usr/share/terminfo",
+    "/usr/lib/terminfo",
+];
+
+/// how the child process running inside of a pty terminated
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ExitStatus {
+    Exited(i32),
+    Signaled(Signal),
+    Unknown,
+}
+impl ExitStatus {
+    /// non-zero exit codes and deaths by signal are treated as failures
+    pub fn is_failure(&self) -> bool {
+        match self {
+            ExitStatus::Exited(code) => *code != 0,
+            ExitStatus::Signaled(_) => true,
+            ExitStatus::Unknown => false,
+        }
+    }
+
+    /// what a shell would report as `$?`
+    pub fn code(&self) -> i32 {
+        match self {
+            ExitStatus::Exited(code) => *code,
+            ExitStatus::Signaled(signal) => 128 + *signal as i32,
+            ExitStatus::Unknown => 1,
+        }
+    }
+}
+impl std::fmt::Display for ExitStatus {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ExitStatus::Exited(code) => write!(f, "exited with code {code}"),
+            ExitStatus::Signaled(signal) => write!(f, "killed by signal {}", signal.as_str()),
+            ExitStatus::Unknown => write!(f, "exited with unknown status"),
+        }
+    }
+}
 
 #[derive(Debug, Clone, Handle)]
+#[handle(spawn, error = Error)]
 pub enum PtyEvent {
     Kill,
     Input { bytes: Bytes },
@@ -32,66 +93,123 @@ pub enum PtyEvent {
 }
 use PtyEvent::*;
 
+/// handed to the pane with each read, the pty gets it back once the pane is done with the output
+#[derive(Debug)]
+pub struct ReadCredit {
+    _permit: OwnedSemaphorePermit,
+}
+
 pub struct Pty {
     // used for sending events to the actor
-    tx: mpsc::Sender<PtyEvent>,
-    rx: mpsc::Receiver<PtyEvent>,
+    handle: PtyHandle,
+    rx: mpsc::Receiver<(PtyEvent, Span)>,
     // channels for sending to pty process -> sends into child process
     pty_tx: mpsc::UnboundedSender<Bytes>,
     pty_rx: mpsc::UnboundedReceiver<Bytes>,
     pane_handle: PaneHandle,
     rect: Rect,
+    env: Vec<(String, Option<String>)>, // set (or removed when `None`) on top of the daemon's environment
+    command: Vec<String>,               // program and its arguments
+    cwd: Option<PathBuf>,               // the daemon's working directory when `None`
+    paused: watch::Receiver<bool>,      // the clients are behind, nothing is read and the child blocks writing
 }
 impl Pty {
-    #[instrument(skip(pane_handle, rect), name = "Pty")]
-    pub fn spawn(pane_handle: PaneHandle, rect: Rect) -> Result<PtyHandle> {
-        let pty = Pty::new(pane_handle, rect);
+    #[instrument(skip(pane_handle, rect, paused), name = "Pty")]
+    pub fn spawn(
+        pane_handle: PaneHandle,
+        rect: Rect,
+        env: Vec<(String, Option<String>)>,
+        command: Vec<String>,
+        cwd: Option<PathBuf>,
+        paused: watch::Receiver<bool>,
+    ) -> Result<(PtyHandle, Pid)> {
+        let pty = Pty::new(pane_handle, rect, env, command, cwd, paused);
         pty.run()
     }
 
-    fn new(pane_handle: PaneHandle, rect: Rect) -> Self {
-        let (tx, rx) = mpsc::channel::<PtyEvent>(10);
+    fn new(
+        pane_handle: PaneHandle,
+        rect: Rect,
+        env: Vec<(String, Option<String>)>,
+        command: Vec<String>,
+        cwd: Option<PathBuf>,
+        paused: watch::Receiver<bool>,
+    ) -> Self {
+        let (handle, rx) = PtyHandle::spawn(10);
         let (pty_tx, pty_rx) = mpsc::unbounded_channel::<Bytes>();
         Self {
-            tx,
+            handle,
             rx,
             pty_tx,
             pty_rx,
             pane_handle,
             rect,
+            env,
+            command,
+            cwd,
+            paused,
         }
     }
 
-    fn run(mut self) -> Result<PtyHandle> {
+    /// returns the handle and the child's pid
+    fn run(mut self) -> Result<(PtyHandle, Pid)> {
+        // built before forking, the child shouldn't allocate
+        let env = child_env(&self.env)?;
+        let argv = child_argv(&self.command)?;
+        let cwd = match &self.cwd {
+            Some(cwd) => Some(CString::new(cwd.as_os_str().as_bytes())?),
+            None => None,
+        };
         debug!("forking and spawning child PTY process");
-        let fork_result = unsafe { forkpty(None, None)? };
+        // the child starts out at the pane's size rather than resizing once it's running
+        let winsize = platform::winsize(self.rect.height, self.rect.width);
+        let fork_result = unsafe { forkpty(Some(&winsize), None)? };
 
         match fork_result {
-            // child just goes off on its own and runs the shell
-            Child => run_child(),
+            // child just goes off on its own and runs the command
+            Child => run_child(&argv, &env, cwd.as_deref()),
             Parent { child, master } => {
                 debug!("child PID: {}", child.as_raw());
-                set_fd_nonblocking(&master)?;
-                let handle = PtyHandle { tx: self.tx.clone() };
+                platform::set_nonblocking(&master)?;
+                let handle = self.handle.clone();
                 let async_fd = AsyncFd::new(master)?;
-                set_winsize(async_fd.get_ref().as_raw_fd(), self.rect.height, self.rect.width)?;
                 let _task: DaemonTask = tokio::spawn({
-                    let handler = handle.clone();
                     async move {
+                        // input not yet written, the child may be slow to read it
+                        let mut pending: VecDeque<Bytes> = VecDeque::new();
+                        let credits = Arc::new(Semaphore::new(PTY_READ_CREDITS));
+                        let mut credit: Option<OwnedSemaphorePermit> = None; // taken before the next read
                         loop {
                             tokio::select! {
-                                // read from PTY
-                                Ok(mut guard) = async_fd.readable() => {
+                                Ok(permit) = credits.clone().acquire_owned(), if credit.is_none() => {
+                                    credit = Some(permit);
+                                },
+                                // read from PTY, only with a credit and while the clients keep up
+                                Ok(mut guard) = async_fd.readable(), if credit.is_some() && !*self.paused.borrow() => {
                                     let span = error_span!("Pty Reader");
                                     let _gard = span.enter();
                                     let mut buf = [0u8; 1024];
                                     match guard.try_io(|fd| unistd::read(fd.get_ref(), &mut buf).map_err(|e| e.into())) {
                                         Ok(Ok(n)) if n > 0 => {
                                             trace!("Pty: read {n} bytes from fd");
-                                            self.pane_handle.pty_output(Bytes::copy_from_slice(&buf[..n])).await.unwrap();
+                                            let Some(permit) = credit.take() else { continue };
+                                            let bytes = Bytes::copy_from_slice(&buf[..n]);
+                                            // nobody is left to read the output, the child goes with the pane
+                                            if let Err(e) = self.pane_handle.pty_output(bytes, ReadCredit { _permit: permit }).await {
+                                                warn!("Pane is gone, killing its process: {e}");
+                                                Self::kill_child(child);
+                                                break;
+                                            }
                                         },
                                         Ok(Ok(_)) => {
-                                            handler.kill().await.unwrap();
+                                            Self::kill_child(child);
+                                            break;
+                                        },
+                                        // linux and macOS report EIO on the master side once the child has hung up,
+                                        // the BSDs read 0
+                                        Ok(Err(e)) if e.raw_os_error() == Some(Errno::EIO as i32) => {
+                                            Self::kill_child(child);
+                                            break;
                                         },
                                         Ok(Err(e)) => {
                                             error!("Error reading: {e}");
@@ -100,38 +218,50 @@ impl Pty {
                                             continue;},
                                     }
                                 },
-                                // write to PTY
-                                data_opt = self.pty_rx.recv() => {
+                                // write to PTY, a chunk per wakeup so a large paste doesn't hold up reading output
+                                Ok(mut guard) = async_fd.writable(), if !pending.is_empty() => {
                                     let span = error_span!("Pty Writer");
                                     let _gard = span.enter();
-                                    match data_opt {
-                                        Some(data) => {
-                                            let mut guard = async_fd.writable().await.unwrap();
-                                            let _res = guard.try_io(|fd| {
-                                                match unistd::write(fd.get_ref(), &data) {
-                                                    Ok(n) if n > 0 => trace!("wrote {n} bytes to pty"),
-                                                    Ok(_) => trace!("wrote 0 bytes to pty"),
-                                                    Err(e) => error!("error writing to pty: {}", e),
-                                                };
-                                                std::result::Result::Ok(())
-                                            });
+                                    let Some(data) = pending.front_mut() else { continue };
+                                    let len = data.len().min(PTY_WRITE_CHUNK);
+                                    match guard.try_io(|fd| unistd::write(fd.get_ref(), &data[..len]).map_err(|e| e.into())) {
+                                        Ok(Ok(n)) => {
+                                            trace!("wrote {n} bytes to pty");
+                                            *data = data.slice(n..);
+                                            if data.is_empty() {
+                                                pending.pop_front();
+                                            }
+                                        },
+                                        Ok(Err(e)) => {
+                                            error!("error writing to pty: {}", e);
+                                            pending.pop_front();
                                         },
+                                        Err(_would_block) => {
+                                            continue;},
+                                    }
+                                },
+                                data_opt = self.pty_rx.recv() => {
+                                    match data_opt {
+                                        Some(data) => pending.push_back(data),
                                         None => {
                                             // None means sender closed the channel - and we need to
                                             // clean up the child process
-                                            handler.kill().await?;
+                                            Self::kill_child(child);
                                             break;
                                         },
                                     }
                                 },
+                                // the guard on reading is looked at again
+                                Ok(()) = self.paused.changed() => {},
                                 // event handler
-                                Some(event) = self.rx.recv() => {
+                                Some((event, cause)) = self.rx.recv() => {
                                     let span = error_span!("Recieved Pty Event");
+                                    continue_trace(&span, &cause);
                                     let _guard = span.enter();
                                     let res = match &event {
                                         Kill => {
                                             info!(event=?event);
-                                            Self::handle_kill(child)?;
+                                            Self::kill_child(child);
                                             break;
                                         }
                                         Input{bytes} => {
@@ -149,30 +279,39 @@ impl Pty {
                                 },
                             }
                         }
-                        match waitpid(child, None) {
+                        let exit_status = match waitpid(child, None) {
                             Ok(status) => match status {
                                 WaitStatus::Exited(child, code) => {
                                     info!("Process {} exited with code {}", child, code);
+                                    ExitStatus::Exited(code)
                                 }
                                 WaitStatus::Signaled(child, signal, _) => {
                                     info!("Process {} killed by signal {:?}", child, signal);
+                                    ExitStatus::Signaled(signal)
                                 }
                                 _ => {
                                     info!("Process {:?} changed state: {:?}", child, status);
+                                    ExitStatus::Unknown
                                 }
                             },
-                            Err(Errno::ECHILD) => info!("No such child process: {}", child),
-                            Err(err) => error!("waitpid failed: {}", err),
-                        }
+                            Err(Errno::ECHILD) => {
+                                info!("No such child process: {}", child);
+                                ExitStatus::Unknown
+                            }
+                            Err(err) => {
+                                error!("waitpid failed: {}", err);
+                                ExitStatus::Unknown
+                            }
+                        };
                         debug!("stopping PtyProcess run");
-                        if let Err(e) = self.pane_handle.pty_died().await {
+                        if let Err(e) = self.pane_handle.pty_died(exit_status).await {
                             warn!("Could not notify pane that PTY died (Pane has likely already died) {}", e);
                         }
                         Ok(())
                     }.in_current_span()
                 });
 
-                Ok(handle)
+                Ok((handle, child))
             }
         }
     }
@@ -182,10 +321,12 @@ impl Pty {
         Ok(())
     }
 
-    fn handle_kill(child: Pid) -> Result<()> {
-        kill(child, Signal::SIGKILL)?;
-        info!("killing pty child process {child}");
-        Ok(())
+    /// a failed kill is only logged, the child still has to be reaped after
+    fn kill_child(child: Pid) {
+        match kill(child, Signal::SIGKILL) {
+            Ok(()) => info!("killing pty child process {child}"),
+            Err(e) => warn!("could not kill pty child process {child}: {e}"),
+        }
     }
 
     fn handle_resize(&mut self, raw_fd: RawFd, rect: Rect) -> Result<()> {
@@ -195,34 +336,71 @@ impl Pty {
     }
 }
 
-fn set_winsize(fd: RawFd, rows: u16, cols: u16) -> Result<()> {
-    let ws = Winsize {
-        ws_row: rows,
-        ws_col: cols,
-        ws_xpixel: 0, // unused
-        ws_ypixel: 0, // unused
-    };
-    unsafe { ioctl(fd, TIOCSWINSZ, &ws) };
-    Ok(())
+/// the daemon's environment with `overrides` applied in order, as `KEY=value` strings
+fn child_env(overrides: &[(String, Option<String>)]) -> Result<Vec<CString>> {
+    let mut env: BTreeMap<String, String> = std::env::vars().collect();
+    for (key, value) in overrides {
+        match value {
+            Some(value) => env.insert(key.clone(), value.clone()),
+            None => env.remove(key),
+        };
+    }
+    env.into_iter()
+        .map(|(key, value)| Ok(CString::new(format!("{key}={value}"))?))
+        .collect()
 }
 
-fn set_fd_nonblocking(owned_fd: &OwnedFd) -> Result<()> {
-    let fd = owned_fd.as_raw_fd();
-    let flags = unsafe { fcntl(fd, F_GETFL) };
-    if flags < 0 {
-        return Err(eyre::eyre!("flag error"));
-    }
-    let res = unsafe { fcntl(fd, F_SETFL, flags | O_NONBLOCK) };
-    if res < 0 {
-        Err(eyre::eyre!("fcntl error"))
+/// `term` if its terminfo entry is installed, otherwise `screen-256color` which nearly everywhere has
+pub fn pane_term(term: &str) -> String {
+    if terminfo_installed(term) {
+        term.to_owned()
     } else {
-        Ok(())
+        debug!(term, "no terminfo entry, falling back to {FALLBACK_TERM}");
+        FALLBACK_TERM.to_owned()
+    }
+}
+
+fn terminfo_installed(term: &str) -> bool {
+    let Some(first) = term.chars().next() else {
+        return false;
+    };
+    let mut dirs: Vec<PathBuf> = Vec::new();
+    if let Some(dir) = std::env::var_os("TERMINFO") {
+        dirs.push(dir.into());
     }
+    if let Some(home) = std::env::var_os("HOME") {
+        dirs.push(PathBuf::from(home).join(".terminfo"));
+    }
+    if let Some(extra) = std::env::var_os("TERMINFO_DIRS") {
+        dirs.extend(std::env::split_paths(&extra));
+    }
+    dirs.extend(TERMINFO_DIRS.iter().map(PathBuf::from));
+    // entries are filed under their first letter, or its hex code on macOS
+    let subdirs = [first.to_string(), format!("{:x}", first as u32)];
+    dirs.iter()
+        .any(|dir| subdirs.iter().any(|subdir| dir.join(subdir).join(term).exists()))
+}
+
+/// `command` as is when its program is a path, otherwise `sh` exec'ing it so it is looked up in the child's
+/// `PATH`, which execve doesn't search
+fn child_argv(command: &[String]) -> Result<Vec<CString>> {
+    let argv = match command.first() {
+        Some(program) if program.contains('/') => command.to_vec(),
+        _ => ["/bin/sh", "-c", "exec \"$@\"", "sh"]
+            .into_iter()
+            .map(str::to_owned)
+            .chain(command.iter().cloned())
+            .collect(),
+    };
+    argv.into_iter().map(|arg| Ok(CString::new(arg)?)).collect()
 }
 
-fn run_child() -> ! {
-    let cmd = CString::new("/bin/zsh").expect("couldn't spawn shell process in PTY");
-    let _ = execvp(&cmd, std::slice::from_ref(&cmd));
-    eprintln!("failed to exec shell");
+fn run_child(argv: &[CString], env: &[CString], cwd: Option<&CStr>) -> ! {
+    // the directory may have been removed since, the daemon's is better than not starting at all
+    if let Some(cwd) = cwd {
+        let _ = unistd::chdir(cwd);
+    }
+    let _ = execve(&argv[0], argv, env);
+    eprintln!("failed to exec command");
     std::process::exit(1);
 }
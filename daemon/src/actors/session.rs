@@ -1,118 +1,304 @@
 use bytes::Bytes;
+use color_eyre::eyre::OptionExt;
+use crossterm::terminal::{self, ClearType};
 use handle_macro::Handle;
-use tokio::sync::mpsc;
+use remux_core::{
+    commands::{LayoutPreset, PaneDirection, RemuxCommand, SwapWith},
+    events::{MessageLevel, MouseKind},
+    messages::response::PreviewPane,
+    states::{Alert, TreeWindow, WindowInfo},
+};
+use tokio::sync::{mpsc, oneshot};
 use tracing::{Instrument, Span};
 
 use crate::{
     actors::{
-        session_manager::SessionManagerHandle,
-        window::{Window, WindowHandle},
+        client_connection::ClientConnectionHandle,
+        message_span,
+        pane::PaneHandle,
+        pty::ExitStatus,
+        session_manager::{CommandContext, SessionManagerHandle, SessionManagerHandleError},
+        window::WindowHandle,
+        window_group::{GroupWindow, PaneDestination, WindowGroupHandle},
     },
     layout::SplitDirection,
+    options::PaneOption,
     prelude::*,
+    supervision::{Supervisor, Verdict, wait_for_children},
 };
 
 #[allow(unused)]
 #[derive(Handle, Debug)]
+#[handle(spawn, error = Error)]
 pub enum SessionEvent {
     // user input
     UserInput(Bytes),
+    Paste {
+        text: Bytes,
+        bracketed: bool,
+    },
     // user commands
     //  - client id not needed anymore because session controls active window and
     //    window controls active pane which should be sufficient)
     UserConnection,
-    UserSplitPane { direction: SplitDirection },
-    UserIteratePane { is_next: bool },
+    UserSplitPane {
+        direction: SplitDirection,
+        environment: Vec<(String, String)>, // `split-window -e`
+    },
+    UserIteratePane {
+        is_next: bool,
+    },
     UserKillPane,
+    ResizePane {
+        direction: PaneDirection,
+        amount: u16,
+    },
+    ZoomPane,
+    SwapPane {
+        with: SwapWith,
+    },
+    RotateWindow {
+        downward: bool,
+    },
+    SelectLayout {
+        layout: LayoutPreset,
+    },
+    ToggleFloatingPane {
+        width_percent: Option<u16>,
+        height_percent: Option<u16>,
+    },
+    MirrorActivePane {
+        target: SessionHandle,
+        direction: SplitDirection,
+    },
+    AttachMirror {
+        pane: PaneHandle,
+        direction: SplitDirection,
+    },
+    BreakPane {
+        pane_id: Option<usize>, // the active window's active pane when not given
+        name: Option<String>,
+        detached: bool,
+    },
+    JoinPane {
+        pane_id: usize,
+        target: Option<usize>, // window index, the active window when not given
+        direction: SplitDirection,
+    },
+    ScanLinks {
+        client: ClientConnectionHandle,
+    },
+    SearchPanes {
+        pattern: String,
+        client: ClientConnectionHandle,
+    },
+    CapturePane {
+        window: usize, // index, not necessarily the active window
+        pane_id: usize,
+        client: ClientConnectionHandle,
+    },
+    JumpToLine {
+        pane_id: usize,
+        line: usize,
+    },
+    EnterCopyMode(Option<String>), // with the pattern of a `/` search
+    LogPane {
+        enabled: Option<bool>,
+        client: Option<ClientConnectionHandle>,
+    },
+    SetPaneOption {
+        option: PaneOption,
+        whole_window: bool, // every pane in the window rather than just the active one
+    },
+    DisplayPanes {
+        client: ClientConnectionHandle,
+    },
+    FocusPane {
+        pane_id: usize,
+    },
+    SetPaneTitle {
+        pane_id: Option<usize>, // the active pane when `None`
+        title: Option<String>,
+    },
+    MarkPane {
+        pane_id: Option<usize>,
+        clear: bool,
+    },
+    PaneMarked(Option<usize>), // the window's marked pane changed
+    Mouse {
+        kind: MouseKind,
+        row: u16,
+        col: u16,
+    },
     Redraw,
+    ClientFocus(bool),
 
     RenameSession(String),
 
+    // windows, indexed in the order they were created
+    NewWindow {
+        name: Option<String>, // named after the shell when not given
+    },
+    NextWindow,
+    PrevWindow,
+    SelectWindow {
+        index: usize,
+    },
+    RenameWindow(String), // renames the active window
+    GroupWindows {
+        windows: Vec<GroupWindow>,
+        select: Option<usize>, // id of a window to switch to
+    }, // the window list as it is in the group, whenever it changes
+    GroupFailed(String),  // the group lost its last window or gave up, the session goes with it
+    #[reply((usize, usize))]
+    CountPanes {
+        reply: oneshot::Sender<(usize, usize)>,
+    }, // (windows, panes) for `remux ls`
+    #[reply(Vec<TreeWindow>)]
+    Tree {
+        reply: oneshot::Sender<Vec<TreeWindow>>,
+    }, // every window and its panes for `choose-tree`
+    #[reply(PreviewPane)]
+    Preview {
+        rows: u16,
+        cols: u16,
+        reply: oneshot::Sender<PreviewPane>,
+    }, // the active window's focused pane for the session switcher
+
     // output
-    WindowOutput(Bytes),
-    TerminalResize { rows: u16, cols: u16 },
+    WindowOutput {
+        window_id: usize,
+        bytes: Bytes,
+    }, // only passed on to clients while the window is the active one
+    PaneOutput {
+        pane_id: usize,
+        bytes: Bytes,
+    },
+    PaneExited {
+        pane_id: usize,
+        status: ExitStatus,
+    },
+    PaneCopied {
+        text: String,
+    },
+    PaneAlert {
+        alert: Alert,
+    },
+    WindowBell {
+        window_id: usize,
+    },
+    Notify(String, MessageLevel), // status message for every client viewing the session
+    TerminalResize {
+        size: Option<(u16, u16)>, // (rows, cols) of the smallest client, `None` once there are none
+    },
+    WindowSize {
+        rows: u16,
+        cols: u16,
+    }, // what the group sized the active window to, the clients pad out the rest of their screens
     Kill,
 }
 use SessionEvent::*;
 
+/// one of the group's windows with what's particular to this session
+struct SessionWindow {
+    id: usize,
+    name: String,
+    handle: WindowHandle,
+    panes: usize,
+    active_pane: usize,
+    activity: bool, // output while in the background, cleared once selected
+    bell: bool,     // a pane rang the bell while in the background, cleared once selected
+}
+
+impl SessionWindow {
+    fn new(window: GroupWindow) -> Self {
+        Self {
+            id: window.id,
+            name: window.name,
+            handle: window.handle,
+            panes: window.panes,
+            active_pane: window.active_pane,
+            activity: false,
+            bell: false,
+        }
+    }
+}
+
 pub struct Session {
     id: u32,
     name: String,
     handle: SessionHandle,
     session_manager_handle: SessionManagerHandle,
-    rx: mpsc::Receiver<SessionEvent>,
-    window_handle: WindowHandle,
+    rx: mpsc::Receiver<(SessionEvent, Span)>,
+    supervisor: Supervisor,
+    group: WindowGroupHandle, // owns the windows, shared with the other sessions of a `new-session -t` group
+    windows: Vec<SessionWindow>, // empty until the group has sent its window list
+    active_window: usize,     // index into `windows`
+    client_focused: bool,     // whether any client viewing the session has its terminal focused
 }
 impl Session {
-    #[instrument(parent=None, skip(session_manager_handle), name="Session")]
-    pub fn spawn(id: u32, name: String, session_manager_handle: SessionManagerHandle) -> Result<SessionHandle> {
-        let session = Session::new(id, name, session_manager_handle);
+    #[instrument(parent=None, skip(session_manager_handle, group), name="Session")]
+    pub fn spawn(
+        id: u32,
+        name: String,
+        session_manager_handle: SessionManagerHandle,
+        group: WindowGroupHandle,
+    ) -> Result<SessionHandle> {
+        let session = Session::new(id, name, session_manager_handle, group);
         session.run()
     }
-    fn new(id: u32, name: String, session_manager_handle: SessionManagerHandle) -> Self {
-        let (tx, rx) = mpsc::channel(10);
-        let handle = SessionHandle { tx };
-        let window_handle = Window::spawn(handle.clone()).unwrap();
+    fn new(id: u32, name: String, session_manager_handle: SessionManagerHandle, group: WindowGroupHandle) -> Self {
+        let (handle, rx) = SessionHandle::spawn(10);
         Self {
             id,
             name,
             session_manager_handle,
             handle,
             rx,
-            window_handle,
+            supervisor: Supervisor::default(),
+            group,
+            windows: Vec::new(),
+            active_window: 0,
+            client_focused: true,
         }
     }
     fn run(mut self) -> Result<SessionHandle> {
         let handle_clone = self.handle.clone();
         let _task = tokio::spawn(
             async move {
-                loop {
-                    if let Some(event) = self.rx.recv().await {
-                        match &event {
-                            WindowOutput(..) | UserInput(..) => {
-                                trace!(event=?event);
-                            }
-                            _ => {
-                                info!(event=?event);
-                            }
+                // the group has already stopped when the session it was joined to went while this one started
+                if let Err(e) = self.group.join(self.id, self.handle.clone()).await {
+                    let _ = self.session_manager_handle.session_failed(self.id, e.to_string()).await;
+                    return;
+                }
+                while let Some((event, cause)) = self.rx.recv().await {
+                    match &event {
+                        WindowOutput { .. } | PaneOutput { .. } | UserInput(..) | Mouse { .. } | PaneAlert { .. } => {
+                            trace!(event=?event);
                         }
-                        match event {
-                            UserInput(bytes) => {
-                                self.handle_user_input(bytes).await.unwrap();
-                            }
-                            UserConnection => {
-                                self.handle_new_connection().await.unwrap();
-                            }
-                            UserSplitPane { direction } => {
-                                self.handle_split_pane(direction).await.unwrap();
-                            }
-                            UserIteratePane { is_next } => {
-                                self.handle_iterate_pane(is_next).await.unwrap();
-                            }
-                            UserKillPane => {
-                                self.handle_kill_pane().await.unwrap();
-                            }
-                            WindowOutput(bytes) => {
-                                self.handle_window_output(bytes).await.unwrap();
-                            }
-                            Redraw => {
-                                self.window_handle.redraw().await.unwrap();
+                        _ => {
+                            info!(event=?event);
+                        }
+                    }
+                    match self.handle_event(event).instrument(message_span(&cause)).await {
+                        Ok(true) => {}
+                        Ok(false) => break,
+                        Err(e) => match self.supervisor.check(&e, SessionManagerHandleError::Closed) {
+                            Verdict::Continue => {
+                                let _ = self
+                                    .session_manager_handle
+                                    .session_notify(self.id, e.to_string(), MessageLevel::Error)
+                                    .await;
                             }
-                            Kill => {
-                                self.window_handle.kill().await.unwrap();
+                            Verdict::ParentGone => break,
+                            Verdict::GiveUp => {
+                                let _ = self.session_manager_handle.session_failed(self.id, e.to_string()).await;
                                 break;
                             }
-                            TerminalResize { rows, cols } => {
-                                self.window_handle.terminal_resize(rows, cols).await.unwrap();
-                            }
-                            RenameSession(name) => {
-                                let span = Span::current();
-                                self.name = name.clone();
-                                span.record("name", name);
-                            }
-                        }
+                        },
                     }
                 }
+                self.stop().await;
             }
             .in_current_span(),
         );
@@ -120,27 +306,472 @@ impl Session {
         Ok(handle_clone)
     }
 
+    /// returns false once the session should stop
+    async fn handle_event(&mut self, event: SessionEvent) -> Result<bool> {
+        match event {
+            UserInput(bytes) => {
+                self.handle_user_input(bytes).await?;
+            }
+            Paste { text, bracketed } => {
+                self.window_handle()?.paste(text, bracketed).await?;
+            }
+            UserConnection => {
+                self.handle_new_connection().await?;
+            }
+            UserSplitPane { direction, environment } => {
+                self.handle_split_pane(direction, environment).await?;
+            }
+            UserIteratePane { is_next } => {
+                self.handle_iterate_pane(is_next).await?;
+            }
+            UserKillPane => {
+                self.handle_kill_pane().await?;
+            }
+            ResizePane { direction, amount } => {
+                self.window_handle()?.resize_pane(direction, amount).await?;
+            }
+            ZoomPane => {
+                self.window_handle()?.zoom_pane().await?;
+            }
+            SwapPane { with } => {
+                self.window_handle()?.swap_pane(with).await?;
+            }
+            RotateWindow { downward } => {
+                self.window_handle()?.rotate_window(downward).await?;
+            }
+            SelectLayout { layout } => {
+                self.window_handle()?.select_layout(layout).await?;
+            }
+            ToggleFloatingPane {
+                width_percent,
+                height_percent,
+            } => {
+                self.window_handle()?
+                    .toggle_floating_pane(width_percent, height_percent)
+                    .await?;
+            }
+            MirrorActivePane { target, direction } => {
+                self.window_handle()?.mirror_active_pane(target, direction).await?;
+            }
+            AttachMirror { pane, direction } => {
+                self.window_handle()?.attach_mirror(pane, direction).await?;
+            }
+            BreakPane {
+                pane_id,
+                name,
+                detached,
+            } => {
+                let destination = PaneDestination::NewWindow {
+                    name,
+                    detached,
+                    session_id: self.id,
+                };
+                self.release_pane(pane_id, destination).await?;
+            }
+            JoinPane {
+                pane_id,
+                target,
+                direction,
+            } => {
+                self.handle_join_pane(pane_id, target, direction).await?;
+            }
+            ScanLinks { client } => {
+                self.window_handle()?.scan_links(client).await?;
+            }
+            SearchPanes { pattern, client } => {
+                self.window_handle()?.search_panes(pattern, client).await?;
+            }
+            CapturePane {
+                window,
+                pane_id,
+                client,
+            } => {
+                let window = self.windows.get(window).ok_or_eyre("window does not exist")?;
+                window.handle.capture_pane(pane_id, client).await?;
+            }
+            JumpToLine { pane_id, line } => {
+                self.window_handle()?.jump_to_line(pane_id, line).await?;
+            }
+            EnterCopyMode(search) => {
+                self.window_handle()?.enter_copy_mode(search).await?;
+            }
+            LogPane { enabled, client } => {
+                let name = self.name.clone();
+                self.window_handle()?.log_pane(enabled, name, client).await?;
+            }
+            SetPaneOption { option, whole_window } => {
+                self.window_handle()?.set_pane_option(option, whole_window).await?;
+            }
+            DisplayPanes { client } => {
+                self.window_handle()?.display_panes(client).await?;
+            }
+            FocusPane { pane_id } => {
+                self.window_handle()?.focus_pane(pane_id).await?;
+            }
+            SetPaneTitle { pane_id, title } => {
+                self.window_handle()?.set_pane_title(pane_id, title).await?;
+            }
+            MarkPane { pane_id, clear } => {
+                self.window_handle()?.mark_pane(pane_id, clear).await?;
+            }
+            PaneMarked(pane_id) => {
+                self.session_manager_handle
+                    .session_pane_marked(self.id, pane_id)
+                    .await?;
+            }
+            Mouse { kind, row, col } => {
+                self.window_handle()?.mouse(kind, row, col).await?;
+            }
+            WindowOutput { window_id, bytes } => {
+                self.handle_window_output(window_id, bytes).await?;
+            }
+            PaneOutput { pane_id, bytes } => {
+                self.session_manager_handle
+                    .session_pane_output(self.id, pane_id, bytes)
+                    .await?;
+            }
+            PaneCopied { text } => {
+                let context = CommandContext {
+                    client_id: None,
+                    session_id: Some(self.id),
+                };
+                let message = format!("copied {} lines", text.lines().count());
+                self.session_manager_handle
+                    .run_command(context, RemuxCommand::SetBuffer { name: None, data: text })
+                    .await?;
+                self.session_manager_handle
+                    .session_notify(self.id, message, MessageLevel::Info)
+                    .await?;
+            }
+            PaneAlert { alert } => {
+                self.session_manager_handle.session_alert(self.id, alert).await?;
+            }
+            WindowBell { window_id } => {
+                self.handle_window_bell(window_id).await?;
+            }
+            GroupFailed(error) => {
+                self.session_manager_handle.session_failed(self.id, error).await?;
+                return Ok(false);
+            }
+            Notify(message, level) => {
+                self.session_manager_handle
+                    .session_notify(self.id, message, level)
+                    .await?;
+            }
+            PaneExited { pane_id, status } => {
+                self.session_manager_handle
+                    .session_pane_exited(self.id, pane_id, status)
+                    .await?;
+            }
+            Redraw => {
+                self.window_handle()?.redraw().await?;
+            }
+            ClientFocus(focused) => {
+                self.client_focused = focused;
+                self.window_handle()?.client_focus(focused).await?;
+            }
+            CountPanes { reply } => {
+                self.handle_count_panes(reply);
+            }
+            Tree { reply } => {
+                self.handle_tree(reply);
+            }
+            Preview { rows, cols, reply } => {
+                self.handle_preview(rows, cols, reply);
+            }
+            Kill => return Ok(false),
+            TerminalResize { size } => {
+                self.group.terminal_resize(self.id, size).await?;
+            }
+            WindowSize { rows, cols } => {
+                self.session_manager_handle
+                    .session_window_size(self.id, rows, cols)
+                    .await?;
+            }
+            NewWindow { name } => {
+                self.group.new_window(name, self.id).await?;
+            }
+            // nothing to cycle through until the group has sent its windows
+            NextWindow | PrevWindow if self.windows.is_empty() => {}
+            NextWindow => {
+                let index = (self.active_window + 1) % self.windows.len();
+                self.select_window(index).await?;
+            }
+            PrevWindow => {
+                let len = self.windows.len();
+                self.select_window((self.active_window + len - 1) % len).await?;
+            }
+            SelectWindow { index } => {
+                self.select_window(index).await?;
+            }
+            RenameWindow(name) => {
+                if let Some(window) = self.windows.get(self.active_window) {
+                    self.group.rename_window(window.id, name).await?;
+                }
+            }
+            GroupWindows { windows, select } => {
+                self.handle_group_windows(windows, select).await?;
+            }
+            RenameSession(name) => {
+                let span = Span::current();
+                self.name = name.clone();
+                span.record("name", name);
+            }
+        }
+        Ok(true)
+    }
+
+    /// the session leaves its group however it stopped, the last member to leave takes the windows with it
+    /// done once they have stopped
+    async fn stop(&mut self) {
+        let (group, id) = (&self.group, self.id);
+        wait_for_children(&mut self.rx, async {
+            if let Ok(true) = group.leave(id).await {
+                group.closed().await;
+            }
+        })
+        .await;
+    }
+
+    fn window_handle(&self) -> Result<&WindowHandle> {
+        self.windows
+            .get(self.active_window)
+            .map(|window| &window.handle)
+            .ok_or_eyre("session has no windows yet")
+    }
+
     async fn handle_user_input(&self, bytes: Bytes) -> Result<()> {
-        self.window_handle.user_input(bytes).await
+        self.window_handle()?.user_input(bytes).await
     }
 
-    async fn handle_window_output(&self, bytes: Bytes) -> Result<()> {
+    async fn handle_window_output(&mut self, window_id: usize, bytes: Bytes) -> Result<()> {
+        // windows in the background keep rendering, they're redrawn in full when selected
+        if self.windows.get(self.active_window).map(|window| window.id) != Some(window_id) {
+            if let Some(window) = self.windows.iter_mut().find(|window| window.id == window_id)
+                && !window.activity
+            {
+                window.activity = true;
+                return self.send_windows().await;
+            }
+            return Ok(());
+        }
         self.session_manager_handle.session_send_output(self.id, bytes).await
     }
 
+    /// flags a background window for the status line, the clients decide whether to ring
+    async fn handle_window_bell(&mut self, window_id: usize) -> Result<()> {
+        let Some(index) = self.windows.iter().position(|window| window.id == window_id) else {
+            return Ok(());
+        };
+        if index != self.active_window && !self.windows[index].bell {
+            self.windows[index].bell = true;
+            self.send_windows().await?;
+        }
+        self.session_manager_handle.session_bell(self.id, index).await
+    }
+
+    /// takes on the group's window list, keeping this session's own flags. the active window stays active
+    /// unless it's gone, then its neighbour takes its place
+    async fn handle_group_windows(&mut self, windows: Vec<GroupWindow>, select: Option<usize>) -> Result<()> {
+        let previous = self.windows.get(self.active_window).map(|window| window.id);
+        let mut old_windows = std::mem::take(&mut self.windows);
+        self.windows = windows
+            .into_iter()
+            .map(|window| {
+                let flags = old_windows
+                    .iter()
+                    .position(|old| old.id == window.id)
+                    .map(|i| old_windows.swap_remove(i));
+                let mut window = SessionWindow::new(window);
+                if let Some(old) = flags {
+                    window.activity = old.activity;
+                    window.bell = old.bell;
+                }
+                window
+            })
+            .collect();
+        if self.windows.is_empty() {
+            return Ok(());
+        }
+        let position = |id: Option<usize>| id.and_then(|id| self.windows.iter().position(|window| window.id == id));
+        let index = match (position(select), position(previous)) {
+            (Some(index), _) => index,
+            (None, Some(index)) => {
+                self.active_window = index;
+                return self.send_windows().await;
+            }
+            (None, None) => self.active_window.min(self.windows.len() - 1),
+        };
+        // the window switched away from may be gone along with its handle
+        if let Some(previous) = old_windows.iter().find(|window| Some(window.id) == previous)
+            && previous.handle.client_focus(false).await.is_err()
+        {
+            debug!("previous window has already stopped");
+        }
+        self.show_window(index).await
+    }
+
+    /// asks the window holding the pane to let go of it, pane ids are unique across the session's windows
+    /// so only its own window answers with `PaneReleased`
+    async fn release_pane(&self, pane_id: Option<usize>, destination: PaneDestination) -> Result<()> {
+        match pane_id {
+            None => self.window_handle()?.release_pane(None, destination).await,
+            Some(pane_id) => {
+                for window in &self.windows {
+                    window.handle.release_pane(Some(pane_id), destination.clone()).await?;
+                }
+                Ok(())
+            }
+        }
+    }
+
+    async fn handle_join_pane(&self, pane_id: usize, target: Option<usize>, direction: SplitDirection) -> Result<()> {
+        let index = target.unwrap_or(self.active_window);
+        let Some(window) = self.windows.get(index) else {
+            return self
+                .session_manager_handle
+                .session_notify(self.id, format!("no window {index}"), MessageLevel::Error)
+                .await;
+        };
+        let destination = PaneDestination::Window {
+            window_id: window.id,
+            direction,
+            session_id: self.id,
+        };
+        self.release_pane(Some(pane_id), destination).await
+    }
+
+    async fn select_window(&mut self, index: usize) -> Result<()> {
+        if index >= self.windows.len() {
+            return self
+                .session_manager_handle
+                .session_notify(self.id, format!("no window {index}"), MessageLevel::Error)
+                .await;
+        }
+        if index == self.active_window {
+            return Ok(());
+        }
+        self.window_handle()?.client_focus(false).await?;
+        self.show_window(index).await?;
+        let message = format!("window {index}: {}", self.windows[index].name);
+        self.session_manager_handle
+            .session_notify(self.id, message, MessageLevel::Info)
+            .await
+    }
+
+    /// makes the window at `index` the active one and puts it on the clients' screens
+    async fn show_window(&mut self, index: usize) -> Result<()> {
+        self.active_window = index;
+        self.windows[index].activity = false;
+        self.windows[index].bell = false;
+        self.send_windows().await?;
+        let window = &self.windows[index];
+        // the group sizes each window to the clients of the sessions viewing it
+        self.group.view_window(self.id, window.id).await?;
+        window.handle.client_focus(self.client_focused).await?;
+        // the previous window's panes are still on the clients' screens
+        let clear = Bytes::from(terminal::Clear(ClearType::All).to_string());
+        self.session_manager_handle.session_send_output(self.id, clear).await?;
+        window.handle.redraw().await
+    }
+
+    /// the window list clients show in their status line
+    async fn send_windows(&self) -> Result<()> {
+        let windows = self
+            .windows
+            .iter()
+            .enumerate()
+            .map(|(index, window)| WindowInfo {
+                id: window.id,
+                index,
+                name: window.name.clone(),
+                panes: window.panes,
+                active_pane: window.active_pane,
+                activity: window.activity,
+                bell: window.bell,
+            })
+            .collect();
+        self.session_manager_handle
+            .session_windows(self.id, windows, self.active_window)
+            .await
+    }
+
+    /// asks every window off the event loop, windows may be waiting on the session themselves
+    fn handle_count_panes(&self, reply: oneshot::Sender<(usize, usize)>) {
+        let windows: Vec<WindowHandle> = self.windows.iter().map(|window| window.handle.clone()).collect();
+        let _task: DaemonTask = tokio::spawn(
+            async move {
+                let mut panes = 0;
+                for window in &windows {
+                    panes += window.count_panes().await.unwrap_or_default();
+                }
+                let _ = reply.send((windows.len(), panes));
+                Ok(())
+            }
+            .in_current_span(),
+        );
+    }
+
+    /// same as `handle_count_panes`, the windows are asked off the event loop
+    fn handle_tree(&self, reply: oneshot::Sender<Vec<TreeWindow>>) {
+        let windows: Vec<(TreeWindow, WindowHandle)> = self
+            .windows
+            .iter()
+            .enumerate()
+            .map(|(index, window)| {
+                let tree = TreeWindow {
+                    index,
+                    name: window.name.clone(),
+                    panes: Vec::new(),
+                    active_pane: window.active_pane,
+                    active: index == self.active_window,
+                };
+                (tree, window.handle.clone())
+            })
+            .collect();
+        let _task: DaemonTask = tokio::spawn(
+            async move {
+                let mut trees = Vec::with_capacity(windows.len());
+                for (mut tree, handle) in windows {
+                    tree.panes = handle.list_panes().await.unwrap_or_default();
+                    trees.push(tree);
+                }
+                let _ = reply.send(trees);
+                Ok(())
+            }
+            .in_current_span(),
+        );
+    }
+
+    fn handle_preview(&self, rows: u16, cols: u16, reply: oneshot::Sender<PreviewPane>) {
+        let window = self.window_handle().ok().cloned();
+        let _task: DaemonTask = tokio::spawn(
+            async move {
+                // a session without windows yet has nothing to show
+                let preview = match window {
+                    Some(window) => window.preview(rows, cols).await.unwrap_or_default(),
+                    None => PreviewPane::default(),
+                };
+                let _ = reply.send(preview);
+                Ok(())
+            }
+            .in_current_span(),
+        );
+    }
+
     async fn handle_new_connection(&self) -> Result<()> {
-        self.window_handle.redraw().await
+        self.window_handle()?.redraw().await
     }
 
     async fn handle_iterate_pane(&self, is_next: bool) -> Result<()> {
-        self.window_handle.iterate_pane(is_next).await
+        self.window_handle()?.iterate_pane(is_next).await
     }
 
-    async fn handle_split_pane(&self, direction: SplitDirection) -> Result<()> {
-        self.window_handle.split_pane(direction).await
+    async fn handle_split_pane(&self, direction: SplitDirection, environment: Vec<(String, String)>) -> Result<()> {
+        self.window_handle()?.split_pane(direction, environment).await
     }
 
     async fn handle_kill_pane(&self) -> Result<()> {
-        self.window_handle.kill_pane().await
+        self.window_handle()?.kill_pane().await
     }
 }
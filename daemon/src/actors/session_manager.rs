@@ -1,36 +1,80 @@
-use std::collections::HashMap;
+use std::{
+    collections::{HashMap, HashSet, VecDeque},
+    path::PathBuf,
+    process::Stdio,
+    time::{Duration, SystemTime},
+};
 
 use bytes::Bytes;
-use color_eyre::eyre::{self, OptionExt, eyre};
+use color_eyre::eyre::{OptionExt, eyre};
 use handle_macro::Handle;
 use itertools::Itertools;
-use remux_core::states::DaemonState;
-use tokio::sync::mpsc;
-use tracing::Instrument;
+use remux_core::{
+    commands::{EnvironmentChange, HookEvent, OptionScope, RemuxCommand, SwitchTarget, key_bytes},
+    events::{DetachReason, Message, MessageLevel, MouseKind, StateDelta},
+    key_bindings::{KeyBindings, parse_prefix},
+    messages::{
+        request::ClientEnvironment,
+        response::{PreviewPane, SessionListing},
+    },
+    states::{Alert, ClientInfo, DaemonState, TreeSession, WindowInfo, window_deltas},
+};
+use tokio::{
+    io::AsyncWriteExt,
+    process::Command,
+    sync::{mpsc, oneshot, watch},
+    time::Instant,
+};
+use tracing::{Instrument, Span};
 use uuid::Uuid;
 
 use crate::{
     actors::{
+        buffer_manager::{BufferManager, BufferManagerHandle},
         client_connection::ClientConnectionHandle,
+        message_span,
+        pty::ExitStatus,
         session::{Session, SessionHandle},
+        window_group::{WindowGroup, WindowGroupHandle, smallest_size},
     },
+    config::{config_files, read_config},
+    environment::{Environment, SessionEnvironment, shell_exports},
+    hooks::Hooks,
     layout::SplitDirection,
+    options::{Options, PaneOption, SessionOption},
+    output_batch::{ClientOutput, OUTPUT_BATCH_WINDOW},
     prelude::*,
 };
 
+// like tmux, a config sourcing itself is stopped this many files deep
+const MAX_SOURCE_DEPTH: usize = 50;
+
 #[allow(unused)]
 #[derive(Handle, Debug)]
+#[handle(spawn, error = Error)]
 pub enum SessionManagerEvent {
     // client -> session manager events
+    #[reply(Result<DaemonState>)]
     ClientConnect {
         client_id: Uuid,
         client_handle: ClientConnectionHandle,
         session_name: Option<String>,
-        create_session: bool,
+        create_session: Option<NewSessionArgs>, // the session is created if it doesn't exist
+        control: bool,                          // control mode clients get raw per pane output
+        environment: ClientEnvironment,
+        reply: oneshot::Sender<Result<DaemonState>>,
     },
     ClientDisconnect {
         client_id: Uuid,
     },
+    #[reply(Result<DaemonState>)]
+    ExecConnect {
+        client_id: Uuid,
+        client_handle: ClientConnectionHandle,
+        session_name: Option<String>,
+        command: Vec<String>,
+        reply: oneshot::Sender<Result<DaemonState>>,
+    },
     ClientSwitchSession {
         client_id: Uuid,
         session_name: String,
@@ -41,6 +85,10 @@ pub enum SessionManagerEvent {
         client_id: Uuid,
         bytes: Bytes,
     },
+    UserPaste {
+        client_id: Uuid,
+        text: Bytes,
+    },
     UserSplitPane {
         client_id: Uuid,
         direction: SplitDirection,
@@ -52,24 +100,176 @@ pub enum SessionManagerEvent {
     UserKillPane {
         client_id: Uuid,
     },
+    UserMouse {
+        client_id: Uuid,
+        kind: MouseKind,
+        row: u16,
+        col: u16,
+    },
+    UserJumpToLine {
+        client_id: Uuid,
+        pane_id: usize,
+        line: usize,
+    },
+    UserToggleFloatingPane {
+        client_id: Uuid,
+    },
+    UserCapturePane {
+        client_id: Uuid,
+        session_id: u32,
+        window: usize,
+        pane_id: usize,
+    }, // any session's pane, for the choose-tree preview
+    UserFocus {
+        client_id: Uuid,
+        focused: bool,
+    },
 
     // session -> client events
     SessionSendOutput {
         session_id: u32,
         bytes: Bytes,
     },
+    SessionPaneOutput {
+        session_id: u32,
+        pane_id: usize,
+        bytes: Bytes,
+    },
+    FlushOutput, // sends every client the output batched since the last flush
+    SessionPaneExited {
+        session_id: u32,
+        pane_id: usize,
+        status: ExitStatus,
+    },
+    SessionAlert {
+        session_id: u32,
+        alert: Alert,
+    },
+    SessionBell {
+        session_id: u32,
+        window: usize, // index of the window it rang in
+    },
+    SessionWindowSize {
+        session_id: u32,
+        rows: u16,
+        cols: u16,
+    }, // what the session's active window is sized to, its clients pad out the rest of their screens
+    SessionNotify {
+        session_id: u32,
+        message: String,
+        level: MessageLevel,
+    },
+    SessionFailed {
+        session_id: u32,
+        error: String,
+    }, // the session gave up after its handlers kept failing and has stopped
+    SessionPaneMarked {
+        session_id: u32,
+        pane_id: Option<usize>,
+    },
+    SessionWindows {
+        session_id: u32,
+        windows: Vec<WindowInfo>,
+        active: usize,
+    },
     TerminalResize {
+        client_id: Uuid,
+        rows: u16,
+        cols: u16,
+    },
+
+    // one-shot queries from connections that never attach
+    #[reply(Vec<SessionListing>)]
+    ListSessions {
+        reply: oneshot::Sender<Vec<SessionListing>>,
+    },
+    #[reply(Result<PreviewPane>)]
+    PreviewSessionPane {
+        name_or_id: String,
         rows: u16,
         cols: u16,
+        reply: oneshot::Sender<Result<PreviewPane>>,
+    },
+    #[reply(Result<(u32, String)>)]
+    NewSession {
+        name: Option<String>, // named after its id when not given
+        args: NewSessionArgs,
+        reply: oneshot::Sender<Result<(u32, String)>>, // the new session's id and name
+    },
+    #[reply(Result<()>)]
+    KillSession {
+        name_or_id: String,
+        reply: oneshot::Sender<Result<()>>,
+    },
+    #[reply(Result<()>)]
+    SourceFile {
+        paths: Vec<PathBuf>, // the default config files when empty
+        reply: oneshot::Sender<Result<()>>,
+    },
+    #[reply(Result<Option<String>>)]
+    RunCommandOnce {
+        command: RemuxCommand,
+        target_session: Option<String>,
+        client_id: Option<Uuid>, // a control mode client, whose session the command runs in
+        reply: oneshot::Sender<Result<Option<String>>>, // what the command printed
+    },
+
+    // the daemon is exiting, replies once every session has stopped
+    #[reply(())]
+    Shutdown {
+        reply: oneshot::Sender<()>,
+    },
+
+    // textual commands from the config file, hooks, etc.
+    RunCommand {
+        context: CommandContext,
+        command: RemuxCommand,
     },
 }
 use SessionManagerEvent::*;
 
+/// how an attach creates its session when there's none by that name yet
+#[derive(Debug, Clone, Default)]
+pub struct NewSessionArgs {
+    pub command: Option<String>, // run through the shell by the first pane instead of the shell itself
+    pub argv: Vec<String>,       // run directly by the first pane, wins over `command`
+    pub group_with: Option<String>, // shares this session's windows instead, the command is ignored
+}
+
+impl NewSessionArgs {
+    /// the first pane's argv, `None` starts the shell
+    fn pane_command(self, options: &Options) -> Option<Vec<String>> {
+        match self {
+            Self { argv, .. } if !argv.is_empty() => Some(argv),
+            Self { command, .. } => command.map(|command| options.shell_command(command)),
+        }
+    }
+}
+
+/// the client and/or session a command acts on
+#[derive(Debug, Clone, Copy, Default)]
+pub struct CommandContext {
+    pub client_id: Option<Uuid>,
+    pub session_id: Option<u32>,
+}
+
+/// where a queued command came from, the commands it queues in turn inherit it
+#[derive(Debug, Clone, Copy, Default)]
+struct CommandOrigin {
+    in_hook: bool,       // run by a hook, like in tmux it fires no hooks itself
+    source_depth: usize, // how many `source-file`s deep, a file sourcing itself stops at `MAX_SOURCE_DEPTH`
+}
+
 #[derive(Debug)]
 struct SessionInfo {
     pub handle: SessionHandle,
+    pub group: WindowGroupHandle, // owns the windows, shared by every session in the group
     pub name: String,
     pub id: u32,
+    pub alert: Option<Alert>,             // cleared when a client attaches
+    pub destroy_unattached: Option<bool>, // `set-option -s`, overrides the global option
+    pub created: SystemTime,
+    pub windows: (Vec<WindowInfo>, usize), // as the session last reported them, with the active window's index
 }
 
 #[derive(Debug)]
@@ -79,20 +279,72 @@ struct SessionManagerState {
     session_to_client_mapping: HashMap<u32, Vec<Uuid>>, // support multiple clients attached to same session
     clients: HashMap<Uuid, ClientConnectionHandle>,
     client_to_session_mapping: HashMap<Uuid, u32>, // one client can only attach to one session
+    control_clients: HashSet<Uuid>,
+    exec_sessions: HashMap<u32, Uuid>, // `remux exec` sessions and the client waiting on each one's command
+    marked_pane: Option<(u32, usize)>, // (session id, pane id) of the `select-pane -m` pane
     session_id_count: u32,
     manager_handle: SessionManagerHandle,
+    hooks: Hooks,
+    buffers: BufferManagerHandle,
+    options: watch::Sender<Options>,
+    environment: watch::Sender<Environment>,
+    shutdown: watch::Sender<bool>, // tells the daemon to stop listening, see `exit-empty`
+    stopping_sessions: Vec<SessionHandle>, // killed but maybe still stopping their windows
+    client_environments: HashMap<Uuid, ClientEnvironment>,
+    client_sizes: HashMap<Uuid, (u16, u16)>, // (rows, cols) of each client's terminal area
+    client_pointers: HashMap<Uuid, (u16, u16)>, // where each client last clicked in its session
+    last_sessions: HashMap<Uuid, u32>,       // the session each client switched away from, for `switch-client -l`
+    unfocused_clients: HashSet<Uuid>,        // clients whose terminal last reported losing focus
+    session_sizes: HashMap<u32, (u16, u16)>, // the smallest client of each session, as the session was last told
+    view_sizes: HashMap<u32, (u16, u16)>,    // what the window each session views is sized to
+    pending_commands: VecDeque<(CommandContext, RemuxCommand, CommandOrigin)>, // run after the current event
+    running_origin: CommandOrigin,           // of the queued command being run
+    hook_window_changes: HashSet<u32>, // sessions a hook's command acted on, their next window report fires no hooks
+    fired_hooks: Vec<(HookEvent, Option<String>)>, // passed on to the clients' lua callbacks with the commands
+    state_deltas: Vec<(Option<u32>, StateDelta)>, // for the clients of a session or everyone, sent after the event
+    client_output: HashMap<Uuid, ClientOutput>, // session output waiting for the next flush
+    output_flush_scheduled: bool,
+    behind_sessions: HashSet<u32>, // sessions with a client too far behind, their groups' panes are paused
+    raw_output_sessions: HashSet<u32>, // sessions with a control client, their groups' panes send their raw output
+    key_bindings: KeyBindings,     // sent to clients when they attach and whenever it changes
 }
 
 impl SessionManagerState {
-    pub fn new(manager_handle: &SessionManagerHandle) -> Self {
+    pub fn new(manager_handle: &SessionManagerHandle, shutdown: watch::Sender<bool>) -> Self {
         Self {
             session_name_to_id: Default::default(),
             sessions: Default::default(),
             session_to_client_mapping: Default::default(),
             clients: Default::default(),
             client_to_session_mapping: Default::default(),
+            control_clients: Default::default(),
+            exec_sessions: Default::default(),
+            marked_pane: None,
             session_id_count: Default::default(),
             manager_handle: manager_handle.clone(),
+            hooks: Default::default(),
+            buffers: BufferManager::spawn(),
+            options: watch::Sender::new(Options::default()),
+            environment: watch::Sender::new(Environment::default()),
+            shutdown,
+            stopping_sessions: Default::default(),
+            client_environments: Default::default(),
+            client_sizes: Default::default(),
+            client_pointers: Default::default(),
+            last_sessions: Default::default(),
+            unfocused_clients: Default::default(),
+            session_sizes: Default::default(),
+            view_sizes: Default::default(),
+            pending_commands: Default::default(),
+            running_origin: Default::default(),
+            hook_window_changes: Default::default(),
+            fired_hooks: Default::default(),
+            state_deltas: Default::default(),
+            client_output: Default::default(),
+            output_flush_scheduled: false,
+            behind_sessions: Default::default(),
+            raw_output_sessions: Default::default(),
+            key_bindings: KeyBindings::default(),
         }
     }
     fn new_session_id(&mut self) -> u32 {
@@ -102,15 +354,95 @@ impl SessionManagerState {
     }
     pub fn snapshot(&self) -> DaemonState {
         let mut daemon_state = DaemonState::default();
-        daemon_state.set_sessions(self.sessions.values().map(|s| (s.id, s.name.clone())).collect_vec());
+        daemon_state.set_sessions(
+            self.sessions
+                .values()
+                .map(|s| (s.id, s.name.clone(), self.client_count(s.id)))
+                .collect_vec(),
+        );
+        daemon_state.key_bindings = self.key_bindings.clone();
         daemon_state
     }
+    fn client_count(&self, session_id: u32) -> usize {
+        self.session_to_client_mapping.get(&session_id).map_or(0, Vec::len)
+    }
+    /// `session_id` limits it to the session's clients, for changes to its windows
+    pub fn queue_delta(&mut self, session_id: Option<u32>, delta: StateDelta) {
+        self.state_deltas.push((session_id, delta));
+    }
+    fn queue_client_count(&mut self, session_id: u32) {
+        let clients = self.client_count(session_id);
+        self.queue_delta(
+            None,
+            StateDelta::ClientCountChanged {
+                id: session_id,
+                clients,
+            },
+        );
+    }
     // pub fn get_by_id(&self, id: u32) -> Option<&SessionInfo> {
     //     self.sessions.get(&id)
     // }
     pub fn get_session_by_name(&self, name: &str) -> Option<&SessionInfo> {
         self.session_name_to_id.get(name).and_then(|id| self.sessions.get(id))
     }
+    /// names win over ids, a session can be named after another one's id
+    pub fn find_session(&self, name_or_id: &str) -> Option<u32> {
+        self.session_name_to_id
+            .get(name_or_id)
+            .copied()
+            .or_else(|| name_or_id.parse().ok().filter(|id| self.sessions.contains_key(id)))
+    }
+    /// the name of the session `switch-client` takes the client to, next and previous go by id and wrap around
+    pub fn switch_target(&self, client_id: Uuid, target: SwitchTarget) -> Result<String> {
+        let session_id = match target {
+            SwitchTarget::Session(name) => return Ok(name),
+            SwitchTarget::Last => *self.last_sessions.get(&client_id).ok_or_eyre("no last session")?,
+            SwitchTarget::Next | SwitchTarget::Prev => {
+                let current = *self
+                    .client_to_session_mapping
+                    .get(&client_id)
+                    .ok_or_eyre("client is not attached")?;
+                let ids = self.sessions.keys().copied().sorted().collect_vec();
+                let i = ids.iter().position(|id| *id == current).ok_or_eyre("no such session")?;
+                let step = if target == SwitchTarget::Next { 1 } else { ids.len() - 1 };
+                ids[(i + step) % ids.len()]
+            }
+        };
+        self.sessions
+            .get(&session_id)
+            .map(|info| info.name.clone())
+            .ok_or_eyre("no such session")
+    }
+    pub fn get_session_id_for_context(&self, context: &CommandContext) -> Option<u32> {
+        context.session_id.or_else(|| {
+            context
+                .client_id
+                .and_then(|client_id| self.client_to_session_mapping.get(&client_id).copied())
+        })
+    }
+    pub fn queue_hook(&mut self, event: HookEvent, context: CommandContext) {
+        // `set-hook session-created new-session` would otherwise never stop
+        if self.running_origin.in_hook {
+            debug!(hook=%event, "Not firing a hook from a hook's command");
+            return;
+        }
+        let session_name = self
+            .get_session_id_for_context(&context)
+            .and_then(|id| self.sessions.get(&id))
+            .map(|info| info.name.clone());
+        let commands = self.hooks.commands_for(event, session_name.as_deref());
+        if !commands.is_empty() {
+            debug!(hook=%event, num_commands=commands.len(), "Queueing hook commands");
+        }
+        let origin = CommandOrigin {
+            in_hook: true,
+            ..self.running_origin
+        };
+        self.pending_commands
+            .extend(commands.into_iter().map(|command| (context, command, origin)));
+        self.fired_hooks.push((event, session_name));
+    }
     pub fn get_session_for_client(&self, client_id: &Uuid) -> Result<&SessionInfo> {
         let session_id = self
             .client_to_session_mapping
@@ -131,19 +463,72 @@ impl SessionManagerState {
             .collect_vec())
     }
 
-    pub fn create_new_session(&mut self, name: Option<&str>) -> Result<&SessionInfo> {
+    /// `raw_output` is set for the sessions of `remux exec`, whose client reads the command's raw output
+    pub fn create_new_session(
+        &mut self,
+        name: Option<&str>,
+        command: Option<Vec<String>>,
+        raw_output: bool,
+    ) -> Result<&SessionInfo> {
         if name.and_then(|n| self.get_session_by_name(n)).is_some() {
-            Err(eyre!("duplicate session"))
-        } else {
-            let id = self.new_session_id();
-            let name = name.map(|n| n.to_owned()).unwrap_or(id.to_string());
-            let handle = Session::spawn(id, name.clone(), self.manager_handle.clone())?;
-            self.session_name_to_id.insert(name.clone(), id);
-            self.sessions.insert(id, SessionInfo { handle, name, id });
-            self.sessions
-                .get(&id)
-                .ok_or(eyre!("couldn't get session info from sessions"))
+            return Err(eyre!("duplicate session"));
+        }
+        let id = self.new_session_id();
+        let environment = SessionEnvironment::new(id, self.environment.subscribe());
+        let group = WindowGroup::spawn(self.options.subscribe(), environment, command, raw_output)?;
+        self.add_session(id, name, group)
+    }
+    /// `new-session -t` - the session shares the target's windows, each picks its own active window
+    pub fn create_grouped_session(&mut self, name: Option<&str>, target: &str) -> Result<&SessionInfo> {
+        if name.and_then(|n| self.get_session_by_name(n)).is_some() {
+            return Err(eyre!("duplicate session"));
         }
+        let group = self
+            .find_session(target)
+            .and_then(|id| self.sessions.get(&id))
+            .map(|info| info.group.clone())
+            .ok_or_else(|| eyre!("no session {target}"))?;
+        let id = self.new_session_id();
+        self.add_session(id, name, group)
+    }
+    /// what `new-session` and attaching with `create` make
+    pub fn create_session(&mut self, name: Option<&str>, args: NewSessionArgs) -> Result<&SessionInfo> {
+        match args.group_with.clone() {
+            Some(target) => self.create_grouped_session(name, &target),
+            None => {
+                let command = args.pane_command(&self.options.borrow());
+                self.create_new_session(name, command, false)
+            }
+        }
+    }
+    fn add_session(&mut self, id: u32, name: Option<&str>, group: WindowGroupHandle) -> Result<&SessionInfo> {
+        let name = name.map(|n| n.to_owned()).unwrap_or(id.to_string());
+        let handle = Session::spawn(id, name.clone(), self.manager_handle.clone(), group.clone())?;
+        self.session_name_to_id.insert(name.clone(), id);
+        self.queue_delta(None, StateDelta::SessionAdded { id, name: name.clone() });
+        self.sessions.insert(
+            id,
+            SessionInfo {
+                handle,
+                group,
+                name,
+                id,
+                alert: None,
+                destroy_unattached: None,
+                created: SystemTime::now(),
+                windows: Default::default(),
+            },
+        );
+        self.queue_hook(
+            HookEvent::SessionCreated,
+            CommandContext {
+                client_id: None,
+                session_id: Some(id),
+            },
+        );
+        self.sessions
+            .get(&id)
+            .ok_or(eyre!("couldn't get session info from sessions"))
     }
 
     pub fn attach_client(
@@ -151,28 +536,56 @@ impl SessionManagerState {
         client_id: Uuid,
         client_handle: ClientConnectionHandle,
         session_name: &str,
-        create: bool,
+        create: Option<NewSessionArgs>,
     ) -> Result<()> {
         let mut id_opt = self.get_session_by_name(session_name).map(|info| info.id);
-        if id_opt.is_none() && create {
-            id_opt = Some(self.create_new_session(Some(session_name))?.id);
+        if id_opt.is_none()
+            && let Some(create) = create
+        {
+            id_opt = Some(self.create_session(Some(session_name), create)?.id);
         }
 
         if let Some(id) = id_opt {
+            if let Some(info) = self.sessions.get_mut(&id) {
+                info.alert = None;
+            }
             self.session_to_client_mapping.entry(id).or_default().push(client_id);
             self.client_to_session_mapping.insert(client_id, id);
             self.clients.insert(client_id, client_handle);
+            self.queue_client_count(id);
             Ok(())
         } else {
             Err(eyre!("no session to attach client"))
         }
     }
+    /// copies the `update-environment` variables from the client's environment into the session's,
+    /// ones the client doesn't have are removed so new panes don't get a stale value from the daemon
+    pub fn update_environment(&self, client_id: Uuid, session_id: u32) {
+        let Some(client_environment) = self.client_environments.get(&client_id) else {
+            return;
+        };
+        let names = self.options.borrow().update_environment.clone();
+        self.environment.send_modify(|environment| {
+            for name in names {
+                let change = match client_environment.0.get(&name) {
+                    Some(value) => EnvironmentChange::Set(value.clone()),
+                    None => EnvironmentChange::Remove,
+                };
+                environment.apply(Some(session_id), name, change);
+            }
+        });
+    }
     pub fn detach_client(&mut self, client_id: Uuid) -> Option<ClientConnectionHandle> {
         if self.clients.contains_key(&client_id) {
             let session_id = self.client_to_session_mapping.remove(&client_id)?;
+            self.control_clients.remove(&client_id);
+            self.client_output.remove(&client_id);
+            // the position means nothing in another session
+            self.client_pointers.remove(&client_id);
             self.session_to_client_mapping
                 .get_mut(&session_id)?
                 .retain(|x| x != &client_id);
+            self.queue_client_count(session_id);
             self.clients.remove(&client_id)
         } else {
             None
@@ -199,22 +612,22 @@ impl SessionManagerState {
 #[derive(Debug)]
 pub struct SessionManager {
     handle: SessionManagerHandle,
-    rx: mpsc::Receiver<SessionManagerEvent>,
+    rx: mpsc::Receiver<(SessionManagerEvent, Span)>,
     state: SessionManagerState,
 }
 impl SessionManager {
-    pub fn spawn() -> Result<SessionManagerHandle> {
-        let session_manager = SessionManager::new();
+    /// `shutdown` is set once the daemon should exit
+    pub fn spawn(shutdown: watch::Sender<bool>) -> Result<SessionManagerHandle> {
+        let session_manager = SessionManager::new(shutdown);
         session_manager.run()
     }
 
-    fn new() -> Self {
-        let (tx, rx) = mpsc::channel(10);
-        let handle = SessionManagerHandle { tx };
+    fn new(shutdown: watch::Sender<bool>) -> Self {
+        let (handle, rx) = SessionManagerHandle::spawn(10);
         Self {
             handle: handle.clone(),
             rx,
-            state: SessionManagerState::new(&handle),
+            state: SessionManagerState::new(&handle, shutdown),
         }
     }
 
@@ -223,65 +636,26 @@ impl SessionManager {
         let handle_clone = self.handle.clone();
         let _task = tokio::spawn({
             async move {
-                loop {
-                    if let Some(event) = self.rx.recv().await {
-                        match &event {
-                            SessionSendOutput { .. } | UserInput { .. } => {
-                                trace!(event=?event);
-                            }
-                            _ => {
-                                info!(event=?event);
-                            }
+                while let Some((event, cause)) = self.rx.recv().await {
+                    match &event {
+                        SessionSendOutput { .. }
+                        | SessionPaneOutput { .. }
+                        | FlushOutput
+                        | UserInput { .. }
+                        | UserPaste { .. }
+                        | UserMouse { .. } => {
+                            trace!(event=?event);
                         }
-                        match event {
-                            ClientConnect {
-                                client_id,
-                                client_handle,
-                                session_name,
-                                create_session,
-                            } => {
-                                self.handle_client_connect(
-                                    client_id,
-                                    client_handle,
-                                    session_name.as_deref(),
-                                    create_session,
-                                )
-                                .await
-                                .unwrap();
-                            }
-                            ClientDisconnect { client_id } => {
-                                self.handle_client_disconnect(client_id).await.unwrap();
-                            }
-                            ClientSwitchSession {
-                                client_id,
-                                session_name,
-                            } => {
-                                self.handle_client_switch_session(client_id, &session_name)
-                                    .await
-                                    .unwrap();
-                            }
-                            UserInput { client_id, bytes } => {
-                                self.handle_client_send_user_input(client_id, bytes).await.unwrap();
-                            }
-                            UserSplitPane { client_id, direction } => {
-                                self.handle_client_split_pane(client_id, direction).await.unwrap();
-                            }
-                            UserIteratePane { client_id, is_next } => {
-                                self.handle_client_iterate_pane(client_id, is_next).await.unwrap();
-                            }
-                            UserKillPane { client_id } => {
-                                self.handle_client_kill_pane(client_id).await.unwrap();
-                            }
-                            SessionSendOutput { session_id, bytes } => {
-                                self.handle_session_send_output(session_id, bytes).await.unwrap();
-                            }
-                            TerminalResize { rows, cols } => {
-                                for SessionInfo { handle, .. } in self.state.sessions.values_mut() {
-                                    handle.terminal_resize(rows, cols).await.unwrap();
-                                }
-                            }
+                        _ => {
+                            info!(event=?event);
                         }
                     }
+                    // the manager has no parent to hand errors to, it logs them and carries on
+                    if let Err(e) = self.handle_event(event).instrument(message_span(&cause)).await {
+                        error!("error handling event: {e}");
+                    }
+                    self.run_pending_commands().await;
+                    self.forward_raw_output();
                 }
             }
             .instrument(error_span!(parent: None, "Session Manager"))
@@ -290,54 +664,881 @@ impl SessionManager {
         Ok(handle_clone)
     }
 
+    async fn handle_event(&mut self, event: SessionManagerEvent) -> Result<()> {
+        match event {
+            ClientConnect {
+                client_id,
+                client_handle,
+                session_name,
+                create_session,
+                control,
+                environment,
+                reply,
+            } => {
+                let connected = self.connect_client(
+                    client_id,
+                    client_handle.clone(),
+                    session_name.as_deref(),
+                    create_session,
+                    control,
+                    environment,
+                );
+                // the client hears back before it's sent anything else
+                match connected {
+                    Ok(session_id) => {
+                        let _ = reply.send(Ok(self.state.snapshot()));
+                        self.handle_client_connect(client_id, client_handle, session_id).await?;
+                    }
+                    Err(e) => {
+                        let _ = reply.send(Err(e));
+                    }
+                }
+            }
+            ClientDisconnect { client_id } => {
+                self.handle_client_disconnect(client_id, DetachReason::Detached).await?;
+            }
+            ExecConnect {
+                client_id,
+                client_handle,
+                session_name,
+                command,
+                reply,
+            } => {
+                self.handle_exec_connect(client_id, client_handle, session_name, command, reply)
+                    .await?;
+            }
+            ClientSwitchSession {
+                client_id,
+                session_name,
+            } => {
+                self.handle_client_switch_session(client_id, &session_name).await?;
+            }
+            UserInput { client_id, bytes } => {
+                self.handle_client_send_user_input(client_id, bytes).await?;
+            }
+            UserPaste { client_id, text } => {
+                self.handle_client_paste(client_id, text).await?;
+            }
+            UserSplitPane { client_id, direction } => {
+                self.handle_client_split_pane(client_id, direction).await?;
+            }
+            UserIteratePane { client_id, is_next } => {
+                self.handle_client_iterate_pane(client_id, is_next).await?;
+            }
+            UserKillPane { client_id } => {
+                self.handle_client_kill_pane(client_id).await?;
+            }
+            UserMouse {
+                client_id,
+                kind,
+                row,
+                col,
+            } => {
+                if let Ok(session) = self.state.get_session_for_client(&client_id) {
+                    session.handle.mouse(kind, row, col).await?;
+                }
+                // scrolling isn't pointing at anything
+                if !kind.is_scroll() {
+                    self.handle_client_pointer(client_id, row, col).await?;
+                }
+            }
+            UserFocus { client_id, focused } => {
+                if focused {
+                    self.state.unfocused_clients.remove(&client_id);
+                } else {
+                    self.state.unfocused_clients.insert(client_id);
+                }
+                if let Some(&session_id) = self.state.client_to_session_mapping.get(&client_id) {
+                    self.sync_session_focus(session_id).await?;
+                }
+            }
+            UserJumpToLine {
+                client_id,
+                pane_id,
+                line,
+            } => {
+                self.state
+                    .get_session_for_client(&client_id)?
+                    .handle
+                    .jump_to_line(pane_id, line)
+                    .await?;
+            }
+            UserCapturePane {
+                client_id,
+                session_id,
+                window,
+                pane_id,
+            } => {
+                let client = self.state.clients.get(&client_id).ok_or_eyre("client does not exist")?;
+                let session = self
+                    .state
+                    .sessions
+                    .get(&session_id)
+                    .ok_or_eyre("session does not exist")?;
+                session.handle.capture_pane(window, pane_id, client.clone()).await?;
+            }
+            UserToggleFloatingPane { client_id } => {
+                self.state
+                    .get_session_for_client(&client_id)?
+                    .handle
+                    .toggle_floating_pane(None, None)
+                    .await?;
+            }
+            SessionSendOutput { session_id, bytes } => {
+                self.handle_session_send_output(session_id, &bytes);
+            }
+            FlushOutput => {
+                self.handle_flush_output().await;
+            }
+            SessionPaneOutput {
+                session_id,
+                pane_id,
+                bytes,
+            } => {
+                self.handle_session_pane_output(session_id, pane_id, &bytes);
+            }
+            SessionPaneExited {
+                session_id,
+                pane_id,
+                status,
+            } => {
+                self.handle_session_pane_exited(session_id, pane_id, status).await?;
+            }
+            SessionAlert { session_id, alert } => {
+                self.handle_session_alert(session_id, alert).await?;
+            }
+            SessionBell { session_id, window } => {
+                self.handle_session_bell(session_id, window).await?;
+            }
+            SessionWindowSize { session_id, rows, cols } => {
+                self.state.view_sizes.insert(session_id, (rows, cols));
+                for client in self.state.get_clients_for_session(&session_id).unwrap_or_default() {
+                    client.window_size(rows, cols).await?;
+                }
+            }
+            SessionNotify {
+                session_id,
+                message,
+                level,
+            } => {
+                for client in self.state.get_clients_for_session(&session_id).unwrap_or_default() {
+                    let message = Message {
+                        text: message.clone(),
+                        level,
+                        duration: None,
+                    };
+                    client.message(message).await?;
+                }
+            }
+            SessionFailed { session_id, error } => {
+                self.handle_session_failed(session_id, error).await?;
+            }
+            SessionPaneMarked { session_id, pane_id } => {
+                self.handle_session_pane_marked(session_id, pane_id).await?;
+            }
+            SessionWindows {
+                session_id,
+                windows,
+                active,
+            } => {
+                let Some(session) = self.state.sessions.get_mut(&session_id) else {
+                    return Ok(());
+                };
+                let (old_windows, old_active) = std::mem::replace(&mut session.windows, (windows, active));
+                self.queue_window_hooks(session_id, &old_windows, old_active);
+                let (windows, active) = &self.state.sessions[&session_id].windows;
+                match window_deltas(&old_windows, old_active, windows, *active) {
+                    Some(deltas) => {
+                        for delta in deltas {
+                            self.state.queue_delta(Some(session_id), delta);
+                        }
+                    }
+                    None => self.send_session_windows(session_id).await?,
+                }
+            }
+            TerminalResize { client_id, rows, cols } => {
+                self.state.client_sizes.insert(client_id, (rows, cols));
+                if let Some(&session_id) = self.state.client_to_session_mapping.get(&client_id) {
+                    self.resize_session(session_id).await?;
+                }
+            }
+            ListSessions { reply } => {
+                self.handle_list_sessions(reply);
+            }
+            PreviewSessionPane {
+                name_or_id,
+                rows,
+                cols,
+                reply,
+            } => {
+                self.handle_preview_session_pane(&name_or_id, rows, cols, reply);
+            }
+            NewSession { name, args, reply } => {
+                let res = self
+                    .state
+                    .create_session(name.as_deref(), args)
+                    .map(|info| (info.id, info.name.clone()));
+                let _ = reply.send(res);
+            }
+            KillSession { name_or_id, reply } => {
+                let res = self.handle_kill_session(&name_or_id).await;
+                let _ = reply.send(res);
+            }
+            SourceFile { paths, reply } => {
+                let _ = reply.send(self.source_files(CommandContext::default(), paths));
+            }
+            RunCommandOnce {
+                command,
+                target_session,
+                client_id,
+                reply,
+            } => {
+                let res = self.handle_run_command_once(command, target_session, client_id).await;
+                let _ = reply.send(res);
+            }
+            RunCommand { context, command } => {
+                self.state
+                    .pending_commands
+                    .push_back((context, command, CommandOrigin::default()));
+            }
+            Shutdown { reply } => {
+                self.handle_shutdown(reply);
+            }
+        }
+        Ok(())
+    }
+
     // /// creates a new session and handles updating the state and notifying clients about the update
     // async fn create_session(&mut self, session_name: Option<&str>) -> Result<&SessionInfo> {
     //     self.state.create_new_session(session_name)
     // }
 
-    async fn handle_client_connect(
+    /// attaches the client to its session, creating the session if asked to
+    fn connect_client(
         &mut self,
         client_id: Uuid,
         client_handle: ClientConnectionHandle,
         session_name: Option<&str>,
-        create_session: bool,
-    ) -> Result<()> {
+        create_session: Option<NewSessionArgs>,
+        control: bool,
+        environment: ClientEnvironment,
+    ) -> Result<u32> {
         let session_name = session_name.ok_or(eyre!("no session name"))?;
-        match self
+        self.state
+            .attach_client(client_id, client_handle, session_name, create_session)?;
+        if control {
+            self.state.control_clients.insert(client_id);
+        }
+        self.state.client_environments.insert(client_id, environment);
+        let session_id = self
             .state
-            .attach_client(client_id, client_handle.clone(), session_name, create_session)
-        {
-            Ok(_) => {
-                let session_info = self
-                    .state
-                    .get_session_by_name(session_name)
-                    .expect("session should exist here");
-                client_handle.initial_attach_result(Ok(self.state.snapshot())).await?;
-                client_handle.success_attach_to_session(session_info.id).await?;
-                session_info.handle.redraw().await?;
-            }
+            .get_session_by_name(session_name)
+            .ok_or_eyre("session should exist here")?
+            .id;
+        self.state.update_environment(client_id, session_id);
+        Ok(session_id)
+    }
+
+    /// brings a client that was just told it's attached up to date with its session
+    async fn handle_client_connect(
+        &mut self,
+        client_id: Uuid,
+        client_handle: ClientConnectionHandle,
+        session_id: u32,
+    ) -> Result<()> {
+        let session_info = self
+            .state
+            .sessions
+            .get(&session_id)
+            .ok_or_eyre("session should exist here")?;
+        client_handle.success_attach_to_session(session_id).await?;
+        let (windows, active) = session_info.windows.clone();
+        client_handle.session_windows(windows, active).await?;
+        let (screen_reader, status) = {
+            let options = self.state.options.borrow();
+            (options.screen_reader, options.status)
+        };
+        if screen_reader {
+            client_handle.screen_reader(true).await?;
+        }
+        if !status {
+            client_handle.status_line(false).await?;
+        }
+        session_info.handle.redraw().await?;
+        self.sync_session_focus(session_id).await?;
+        // sized clients are announced again once their first resize arrives
+        self.send_session_clients(session_id).await?;
+        self.state.queue_hook(
+            HookEvent::ClientAttached,
+            CommandContext {
+                client_id: Some(client_id),
+                session_id: Some(session_id),
+            },
+        );
+        Ok(())
+    }
+
+    /// starts `command` in a new session and attaches the exec client to it like a control client,
+    /// so it gets the raw output of the command's pane
+    async fn handle_exec_connect(
+        &mut self,
+        client_id: Uuid,
+        client_handle: ClientConnectionHandle,
+        session_name: Option<String>,
+        command: Vec<String>,
+        reply: oneshot::Sender<Result<DaemonState>>,
+    ) -> Result<()> {
+        let name = session_name.unwrap_or_else(|| format!("exec-{}", self.state.session_id_count));
+        let info = match self.state.create_new_session(Some(&name), Some(command), true) {
+            Ok(info) => info,
             Err(e) => {
-                client_handle.initial_attach_result(Err(eyre::eyre!(e))).await?;
+                let _ = reply.send(Err(e));
+                return Ok(());
+            }
+        };
+        let session_id = info.id;
+        self.state
+            .attach_client(client_id, client_handle.clone(), &name, None)?;
+        self.state.control_clients.insert(client_id);
+        self.state.raw_output_sessions.insert(session_id); // the group started out forwarding
+        self.state.exec_sessions.insert(session_id, client_id);
+        let _ = reply.send(Ok(self.state.snapshot()));
+        Ok(())
+    }
+
+    /// resolves `~` and relative paths the way the client's shell would, falling back to the daemon's
+    /// environment for commands without a client
+    fn client_path(&self, client_id: Option<Uuid>, path: &str) -> PathBuf {
+        let env = |name: &str| {
+            client_id
+                .and_then(|id| self.state.client_environments.get(&id))
+                .and_then(|environment| environment.0.get(name).cloned())
+                .or_else(|| std::env::var(name).ok())
+        };
+        let path = match (path.strip_prefix("~/"), env("HOME")) {
+            (Some(rest), Some(home)) => PathBuf::from(home).join(rest),
+            _ => PathBuf::from(path),
+        };
+        match env("PWD") {
+            Some(cwd) if path.is_relative() => PathBuf::from(cwd).join(path),
+            _ => path,
+        }
+    }
+
+    /// a session's window marked or unmarked a pane, only one pane is marked across all sessions
+    async fn handle_session_pane_marked(&mut self, session_id: u32, pane_id: Option<usize>) -> Result<()> {
+        match (pane_id, self.state.marked_pane) {
+            (Some(pane_id), previous) => {
+                self.state.marked_pane = Some((session_id, pane_id));
+                if let Some((previous_session, _)) = previous
+                    && previous_session != session_id
+                    && let Some(session) = self.state.sessions.get(&previous_session)
+                {
+                    session.handle.mark_pane(None, true).await?;
+                }
             }
+            (None, Some((marked_session, _))) if marked_session == session_id => self.state.marked_pane = None,
+            (None, _) => {}
         }
         Ok(())
     }
 
-    async fn handle_client_disconnect(&mut self, client_id: Uuid) -> Result<()> {
+    /// kills the session and detaches whoever was still looking at it
+    async fn remove_session(&mut self, session_id: u32) -> Result<()> {
+        // queued while the session still resolves so session specific hooks match it
+        if self.state.sessions.contains_key(&session_id) {
+            self.state.queue_hook(
+                HookEvent::SessionClosed,
+                CommandContext {
+                    client_id: None,
+                    session_id: Some(session_id),
+                },
+            );
+        }
+        self.state.exec_sessions.remove(&session_id);
+        if self.state.marked_pane.is_some_and(|(marked, _)| marked == session_id) {
+            self.state.marked_pane = None;
+        }
+        let Some(info) = self.state.sessions.remove(&session_id) else {
+            return Ok(());
+        };
+        self.state.session_name_to_id.remove(&info.name);
+        self.state.session_sizes.remove(&session_id);
+        self.state.view_sizes.remove(&session_id);
+        self.state.hook_window_changes.remove(&session_id);
+        self.state
+            .environment
+            .send_modify(|environment| environment.remove_session(session_id));
+        // the clients drop it from their own state with the delta
+        self.state.last_sessions.retain(|_, last| *last != session_id);
+        for client_id in self
+            .state
+            .session_to_client_mapping
+            .remove(&session_id)
+            .unwrap_or_default()
+        {
+            self.state.client_to_session_mapping.remove(&client_id);
+            self.state.control_clients.remove(&client_id);
+            self.state.client_environments.remove(&client_id);
+            self.state.client_sizes.remove(&client_id);
+            self.state.client_pointers.remove(&client_id);
+            self.state.last_sessions.remove(&client_id);
+            self.state.client_output.remove(&client_id);
+            if let Some(client) = self.state.clients.remove(&client_id) {
+                // an exec client has already gone away with the exit code
+                let _ = client.disconnect(DetachReason::SessionKilled(info.name.clone())).await;
+            }
+        }
+        // a failed session has already stopped
+        let _ = info.handle.kill().await;
+        self.state.stopping_sessions.retain(|session| !session.is_closed());
+        self.state.stopping_sessions.push(info.handle);
+        self.state
+            .queue_delta(None, StateDelta::SessionRemoved { id: session_id });
+        if self.state.sessions.is_empty() && self.state.options.borrow().exit_empty {
+            info!("Last session destroyed, shutting down");
+            self.state.shutdown.send_replace(true);
+        }
+        Ok(())
+    }
+
+    async fn handle_kill_session(&mut self, name_or_id: &str) -> Result<()> {
+        let session_id = self
+            .state
+            .find_session(name_or_id)
+            .ok_or_else(|| eyre!("no session {name_or_id}"))?;
+        info!(session = name_or_id, "Killing session");
+        self.remove_session(session_id).await
+    }
+
+    /// every client hears of the new name, including ones attached to other sessions
+    async fn handle_rename_session(&mut self, session_id: u32, name: String) -> Result<()> {
+        if self
+            .state
+            .session_name_to_id
+            .get(&name)
+            .is_some_and(|id| *id != session_id)
+        {
+            return Err(eyre!("duplicate session {name}"));
+        }
+        let info = self.state.sessions.get_mut(&session_id).ok_or_eyre("no such session")?;
+        let old_name = std::mem::replace(&mut info.name, name.clone());
+        let handle = info.handle.clone();
+        self.state.session_name_to_id.remove(&old_name);
+        self.state.session_name_to_id.insert(name.clone(), session_id);
+        self.state.queue_delta(
+            None,
+            StateDelta::SessionRenamed {
+                id: session_id,
+                name: name.clone(),
+            },
+        );
+        self.state.queue_hook(
+            HookEvent::SessionRenamed,
+            CommandContext {
+                client_id: None,
+                session_id: Some(session_id),
+            },
+        );
+        handle.rename_session(name).await
+    }
+
+    /// sessions are stopped off the event loop, they may be waiting on the manager
+    fn handle_shutdown(&mut self, reply: oneshot::Sender<()>) {
+        info!("Shutting down");
+        let clients: Vec<ClientConnectionHandle> = self.state.clients.drain().map(|(_, client)| client).collect();
+        let sessions: Vec<SessionHandle> = self
+            .state
+            .sessions
+            .drain()
+            .map(|(_, info)| info.handle)
+            .chain(self.state.stopping_sessions.drain(..))
+            .collect();
+        let _task: DaemonTask = tokio::spawn(
+            async move {
+                for client in &clients {
+                    let _ = client.disconnect(DetachReason::ServerExited).await;
+                }
+                for session in &sessions {
+                    let _ = session.kill().await;
+                }
+                for session in &sessions {
+                    session.closed().await;
+                }
+                info!("All sessions stopped");
+                let _ = reply.send(());
+                Ok(())
+            }
+            .in_current_span(),
+        );
+    }
+
+    /// sessions are asked for their counts off the event loop, they may be waiting on the manager
+    fn handle_list_sessions(&self, reply: oneshot::Sender<Vec<SessionListing>>) {
+        let sessions: Vec<(SessionListing, SessionHandle)> = self
+            .state
+            .sessions
+            .values()
+            .sorted_by_key(|info| info.id)
+            .map(|info| {
+                let listing = SessionListing {
+                    id: info.id,
+                    name: info.name.clone(),
+                    windows: 0,
+                    panes: 0,
+                    clients: self.state.session_to_client_mapping.get(&info.id).map_or(0, Vec::len),
+                    created: info.created,
+                };
+                (listing, info.handle.clone())
+            })
+            .collect();
+        let _task: DaemonTask = tokio::spawn(
+            async move {
+                let mut listings = Vec::with_capacity(sessions.len());
+                for (mut listing, handle) in sessions {
+                    // a session killed in the meantime is left out
+                    if let Ok((windows, panes)) = handle.count_panes().await {
+                        (listing.windows, listing.panes) = (windows, panes);
+                        listings.push(listing);
+                    }
+                }
+                let _ = reply.send(listings);
+                Ok(())
+            }
+            .in_current_span(),
+        );
+    }
+
+    /// like `handle_list_sessions`, sessions are asked for their windows off the event loop
+    fn handle_choose_tree(&self, client: ClientConnectionHandle, sessions_only: bool) {
+        let sessions: Vec<(TreeSession, SessionHandle)> = self
+            .state
+            .sessions
+            .values()
+            .sorted_by_key(|info| info.id)
+            .map(|info| {
+                let tree = TreeSession {
+                    id: info.id,
+                    name: info.name.clone(),
+                    windows: Vec::new(),
+                };
+                (tree, info.handle.clone())
+            })
+            .collect();
+        let _task: DaemonTask = tokio::spawn(
+            async move {
+                let mut trees = Vec::with_capacity(sessions.len());
+                for (mut tree, handle) in sessions {
+                    // a session killed in the meantime is left out
+                    if let Ok(windows) = handle.tree().await {
+                        tree.windows = windows;
+                        trees.push(tree);
+                    }
+                }
+                client.tree(trees, sessions_only).await?;
+                Ok(())
+            }
+            .in_current_span(),
+        );
+    }
+
+    /// like `handle_list_sessions`, the session is asked off the event loop
+    fn handle_preview_session_pane(
+        &self,
+        name_or_id: &str,
+        rows: u16,
+        cols: u16,
+        reply: oneshot::Sender<Result<PreviewPane>>,
+    ) {
+        let Some(info) = self
+            .state
+            .find_session(name_or_id)
+            .and_then(|id| self.state.sessions.get(&id))
+        else {
+            let _ = reply.send(Err(eyre!("no session {name_or_id}")));
+            return;
+        };
+        let handle = info.handle.clone();
+        let _task: DaemonTask = tokio::spawn(
+            async move {
+                let _ = reply.send(handle.preview(rows, cols).await);
+                Ok(())
+            }
+            .in_current_span(),
+        );
+    }
+
+    async fn handle_client_disconnect(&mut self, client_id: Uuid, reason: DetachReason) -> Result<()> {
+        let context = CommandContext {
+            client_id: None,
+            session_id: self.state.client_to_session_mapping.get(&client_id).copied(),
+        };
         if let Some(client) = self.state.detach_client(client_id) {
-            client.disconnect().await
+            self.state.client_environments.remove(&client_id);
+            self.state.client_sizes.remove(&client_id);
+            self.state.unfocused_clients.remove(&client_id);
+            self.state.last_sessions.remove(&client_id);
+            // it may have been the client holding its session's panes back
+            self.schedule_output_flush();
+            if let Some(session_id) = context.session_id {
+                // the exec cli hanging up takes its command down with it
+                if self.state.exec_sessions.get(&session_id) == Some(&client_id) {
+                    self.remove_session(session_id).await?;
+                } else if !self.destroy_if_unattached(session_id).await? {
+                    self.resize_session(session_id).await?;
+                    self.sync_session_focus(session_id).await?;
+                }
+            }
+            self.state.queue_hook(HookEvent::ClientDetached, context);
+            // a client can hang up without waiting for the confirmation
+            let _ = client.disconnect(reason).await;
+            Ok(())
         } else {
             Ok(())
         }
     }
 
+    /// `destroy-unattached` - removes the session once its last client has left, returns whether it did
+    async fn destroy_if_unattached(&mut self, session_id: u32) -> Result<bool> {
+        let Some(session) = self.state.sessions.get(&session_id) else {
+            return Ok(false);
+        };
+        let destroy = session
+            .destroy_unattached
+            .unwrap_or(self.state.options.borrow().destroy_unattached);
+        let attached = self
+            .state
+            .session_to_client_mapping
+            .get(&session_id)
+            .is_some_and(|clients| !clients.is_empty());
+        if !destroy || attached {
+            return Ok(false);
+        }
+        info!(session=%session.name, "Destroying unattached session");
+        self.remove_session(session_id).await?;
+        Ok(true)
+    }
+
     async fn handle_client_switch_session(&mut self, client_id: Uuid, session_name: &str) -> Result<()> {
+        let control = self.state.control_clients.contains(&client_id);
+        let previous_session = self.state.client_to_session_mapping.get(&client_id).copied();
         let client = self.state.detach_client(client_id).ok_or_eyre("no such client")?;
         self.state
-            .attach_client(client_id, client.clone(), session_name, false)?;
+            .attach_client(client_id, client.clone(), session_name, None)?;
+        if control {
+            self.state.control_clients.insert(client_id);
+        }
+        // the session left behind may be able to grow again
+        if let Some(previous_session) = previous_session
+            && !self.destroy_if_unattached(previous_session).await?
+        {
+            self.resize_session(previous_session).await?;
+            self.sync_session_focus(previous_session).await?;
+        }
+        let session_id = self.state.get_session_for_client(&client_id)?.id;
+        // a session that went with `destroy-unattached` is no use to come back to
+        if let Some(previous_session) = previous_session
+            && previous_session != session_id
+            && self.state.sessions.contains_key(&previous_session)
+        {
+            self.state.last_sessions.insert(client_id, previous_session);
+        }
+        self.resize_session(session_id).await?;
+        self.sync_session_focus(session_id).await?;
+        self.state.update_environment(client_id, session_id);
         let session = self.state.get_session_for_client(&client_id)?;
         session.handle.redraw().await?;
-        client.success_attach_to_session(session.id).await
+        client.success_attach_to_session(session.id).await?;
+        client
+            .last_session(self.state.last_sessions.get(&client_id).copied())
+            .await?;
+        let (windows, active) = session.windows.clone();
+        client.session_windows(windows, active).await?;
+        if self.state.options.borrow().screen_reader {
+            client.notify(format!("session {}", session.name)).await?;
+        }
+        Ok(())
+    }
+
+    /// the active pane is shared by every client of a session (as in tmux), so its program is told it has
+    /// focus while any of their terminals does - one client switching away doesn't take focus from the others
+    async fn sync_session_focus(&self, session_id: u32) -> Result<()> {
+        let Some(session) = self.state.sessions.get(&session_id) else {
+            return Ok(());
+        };
+        let clients = self
+            .state
+            .session_to_client_mapping
+            .get(&session_id)
+            .into_iter()
+            .flatten()
+            .filter(|client_id| !self.state.control_clients.contains(client_id))
+            .collect_vec();
+        // nobody's looking, the pane keeps the focus it was last given
+        if clients.is_empty() {
+            return Ok(());
+        }
+        let focused = clients
+            .iter()
+            .any(|client_id| !self.state.unfocused_clients.contains(client_id));
+        session.handle.client_focus(focused).await
+    }
+
+    /// sizes the session's window to the smallest client viewing it, clients with a larger terminal
+    /// are told the size so they can fill the space around it
+    async fn resize_session(&mut self, session_id: u32) -> Result<()> {
+        // everything calling this has just changed who's attached or how big they are
+        self.send_session_clients(session_id).await?;
+        let clients = self
+            .state
+            .session_to_client_mapping
+            .get(&session_id)
+            .into_iter()
+            .flatten()
+            .filter_map(|client_id| {
+                Some((
+                    self.state.clients.get(client_id)?,
+                    self.state.client_sizes.get(client_id)?,
+                ))
+            })
+            .collect_vec();
+        let size = smallest_size(clients.iter().map(|(_, size)| **size));
+        if self.state.session_sizes.get(&session_id) != size.as_ref() {
+            if let Some(session) = self.state.sessions.get(&session_id) {
+                session.handle.terminal_resize(size).await?;
+            }
+            match size {
+                Some(size) => self.state.session_sizes.insert(session_id, size),
+                None => self.state.session_sizes.remove(&session_id),
+            };
+        }
+        // the group sizes the window to the clients of every session viewing it and reports back with
+        // `SessionWindowSize`, until then the clients keep what they had
+        if let Some(&(rows, cols)) = self.state.view_sizes.get(&session_id) {
+            for (client, _) in clients {
+                client.window_size(rows, cols).await?;
+            }
+        }
+        Ok(())
+    }
+
+    /// attached clients rebuild their input parser from the new table
+    async fn send_key_bindings(&self) -> Result<()> {
+        for client in self.state.clients.values() {
+            client.key_bindings(self.state.key_bindings.clone()).await?;
+        }
+        Ok(())
+    }
+
+    /// `window-changed` and `window-renamed` come from comparing the window list with what the session
+    /// reported before, the first report of a new session fires neither and neither does a report following a
+    /// hook's command
+    fn queue_window_hooks(&mut self, session_id: u32, old_windows: &[WindowInfo], old_active: usize) {
+        let Some(session) = self.state.sessions.get(&session_id) else {
+            return;
+        };
+        // `set-hook window-changed new-window` would otherwise never stop
+        let from_hook = self.state.hook_window_changes.remove(&session_id);
+        if old_windows.is_empty() || from_hook {
+            return;
+        }
+        let (windows, active) = &session.windows;
+        let changed = *active != old_active;
+        let renamed = windows.iter().any(|window| {
+            old_windows
+                .iter()
+                .any(|old| old.index == window.index && old.name != window.name)
+        });
+        let context = CommandContext {
+            client_id: None,
+            session_id: Some(session_id),
+        };
+        if changed {
+            self.state.queue_hook(HookEvent::WindowChanged, context);
+        }
+        if renamed {
+            self.state.queue_hook(HookEvent::WindowRenamed, context);
+        }
+    }
+
+    /// keeps the window list of every client's status line current
+    async fn send_session_windows(&self, session_id: u32) -> Result<()> {
+        let Some(session) = self.state.sessions.get(&session_id) else {
+            return Ok(());
+        };
+        let (windows, active) = &session.windows;
+        for client_id in self
+            .state
+            .session_to_client_mapping
+            .get(&session_id)
+            .into_iter()
+            .flatten()
+        {
+            if let Some(client) = self.state.clients.get(client_id) {
+                client.session_windows(windows.clone(), *active).await?;
+            }
+        }
+        Ok(())
+    }
+
+    /// tells every client of the session who else is attached to it
+    async fn send_session_clients(&self, session_id: u32) -> Result<()> {
+        let client_ids = self
+            .state
+            .session_to_client_mapping
+            .get(&session_id)
+            .cloned()
+            .unwrap_or_default();
+        let client_cursors = self.state.options.borrow().client_cursors;
+        let infos = client_ids
+            .iter()
+            .map(|client_id| ClientInfo {
+                name: self.client_name(client_id),
+                size: self.state.client_sizes.get(client_id).copied(),
+                pointer: self
+                    .state
+                    .client_pointers
+                    .get(client_id)
+                    .copied()
+                    .filter(|_| client_cursors),
+                current: false,
+            })
+            .collect_vec();
+        for (i, client_id) in client_ids.iter().enumerate() {
+            let Some(client) = self.state.clients.get(client_id) else {
+                continue;
+            };
+            let mut infos = infos.clone();
+            infos[i].current = true;
+            client.session_clients(infos).await?;
+        }
+        Ok(())
+    }
+
+    /// every client shares the session's active pane and cursor, so the mouse is what tells them apart
+    async fn handle_client_pointer(&mut self, client_id: Uuid, row: u16, col: u16) -> Result<()> {
+        let Some(&session_id) = self.state.client_to_session_mapping.get(&client_id) else {
+            return Ok(());
+        };
+        self.state.client_pointers.insert(client_id, (row, col));
+        if self.state.options.borrow().client_cursors {
+            self.send_session_clients(session_id).await?;
+        }
+        Ok(())
+    }
+
+    /// the user the client was started by, with the start of its id to tell a user's clients apart
+    fn client_name(&self, client_id: &Uuid) -> String {
+        let short_id = &client_id.simple().to_string()[..4];
+        match self
+            .state
+            .client_environments
+            .get(client_id)
+            .and_then(|environment| environment.0.get("USER"))
+        {
+            Some(user) => format!("{user}-{short_id}"),
+            None => format!("client-{short_id}"),
+        }
     }
 
     async fn handle_client_send_user_input(&mut self, client_id: Uuid, bytes: Bytes) -> Result<()> {
@@ -348,6 +1549,15 @@ impl SessionManager {
             .await
     }
 
+    // the client strips its terminal's markers, the pane adds them back if its program turned them on
+    async fn handle_client_paste(&mut self, client_id: Uuid, text: Bytes) -> Result<()> {
+        self.state
+            .get_session_for_client(&client_id)?
+            .handle
+            .paste(text, true)
+            .await
+    }
+
     async fn handle_client_kill_pane(&mut self, client_id: Uuid) -> Result<()> {
         self.state
             .get_session_for_client(&client_id)?
@@ -360,7 +1570,7 @@ impl SessionManager {
         self.state
             .get_session_for_client(&client_id)?
             .handle
-            .user_split_pane(direction)
+            .user_split_pane(direction, Vec::new())
             .await
     }
 
@@ -372,10 +1582,877 @@ impl SessionManager {
             .await
     }
 
-    async fn handle_session_send_output(&mut self, session_id: u32, bytes: Bytes) -> Result<()> {
-        for client in self.state.get_clients_for_session(&session_id)? {
-            client.session_output(bytes.clone()).await?;
+    /// output is queued per client and sent on after a short window, so bursts go out as few frames
+    fn handle_session_send_output(&mut self, session_id: u32, bytes: &[u8]) {
+        // nobody may have attached yet, or the session was just removed with output still in flight
+        let Some(client_ids) = self.state.session_to_client_mapping.get(&session_id) else {
+            return;
+        };
+        for client_id in client_ids {
+            // control mode clients get the raw per pane output instead
+            if !self.state.control_clients.contains(client_id) {
+                self.state.client_output.entry(*client_id).or_default().push(bytes);
+            }
+        }
+        self.schedule_output_flush();
+    }
+
+    fn handle_session_pane_output(&mut self, session_id: u32, pane_id: usize, bytes: &[u8]) {
+        let Some(client_ids) = self.state.session_to_client_mapping.get(&session_id) else {
+            return;
+        };
+        for client_id in client_ids {
+            if self.state.control_clients.contains(client_id) {
+                self.state
+                    .client_output
+                    .entry(*client_id)
+                    .or_default()
+                    .push_pane(pane_id, bytes);
+            }
+        }
+        self.schedule_output_flush();
+    }
+
+    fn schedule_output_flush(&mut self) {
+        if std::mem::replace(&mut self.state.output_flush_scheduled, true) {
+            return;
+        }
+        let handle = self.handle.clone();
+        let _task: DaemonTask = tokio::spawn(
+            async move {
+                tokio::time::sleep(OUTPUT_BATCH_WINDOW).await;
+                handle.flush_output().await
+            }
+            .in_current_span(),
+        );
+    }
+
+    /// a client with batches still in flight is skipped and its output stays queued for the next flush
+    async fn handle_flush_output(&mut self) {
+        self.state.output_flush_scheduled = false;
+        let mut redraw_sessions = HashSet::new();
+        for (client_id, output) in &mut self.state.client_output {
+            if output.take_overflow()
+                && let Some(&session_id) = self.state.client_to_session_mapping.get(client_id)
+            {
+                redraw_sessions.insert(session_id);
+            }
+            // the client may have gone away before its disconnect reached us
+            let Some(client) = self.state.clients.get(client_id) else {
+                continue;
+            };
+            if let Some((bytes, credit)) = output.take_batch() {
+                let _ = client.session_output(bytes, credit).await;
+            }
+            if let Some((pane_id, bytes, credit)) = output.take_pane_batch() {
+                let _ = client.pane_output(pane_id, bytes, credit).await;
+            }
+        }
+        for session_id in redraw_sessions {
+            let Some(session) = self.state.sessions.get(&session_id) else {
+                continue;
+            };
+            // off the event loop, the session may be waiting on us to take more of its output
+            let handle = session.handle.clone();
+            let _task: DaemonTask = tokio::spawn(async move { handle.redraw().await }.in_current_span());
+        }
+        self.throttle_behind_sessions();
+        if self.state.client_output.values().any(ClientOutput::has_pending) {
+            self.schedule_output_flush();
+        }
+    }
+
+    /// pauses the panes of sessions with a client that can't keep up and resumes them once it has caught up
+    fn throttle_behind_sessions(&mut self) {
+        let now = Instant::now();
+        let behind: HashSet<u32> = self
+            .state
+            .client_output
+            .iter_mut()
+            .filter_map(|(client_id, output)| {
+                output
+                    .is_behind(now)
+                    .then(|| self.state.client_to_session_mapping.get(client_id).copied())?
+            })
+            .collect();
+        let changed = behind
+            .symmetric_difference(&self.state.behind_sessions)
+            .copied()
+            .collect_vec();
+        for session_id in changed {
+            let is_behind = behind.contains(&session_id);
+            // never waits on the group, its windows may be waiting on us to take their output
+            if let Some(info) = self.state.sessions.get(&session_id)
+                && info.group.try_throttle(session_id, is_behind).is_err()
+            {
+                self.schedule_output_flush(); // tried again then
+                continue;
+            }
+            if is_behind {
+                self.state.behind_sessions.insert(session_id);
+            } else {
+                self.state.behind_sessions.remove(&session_id);
+            }
+        }
+    }
+
+    /// panes only send their raw output up to us while a control mode client is watching them
+    fn forward_raw_output(&mut self) {
+        let wanted: HashSet<u32> = self
+            .state
+            .control_clients
+            .iter()
+            .filter_map(|client_id| self.state.client_to_session_mapping.get(client_id).copied())
+            .collect();
+        let changed = wanted
+            .symmetric_difference(&self.state.raw_output_sessions)
+            .copied()
+            .collect_vec();
+        for session_id in changed {
+            let forward = wanted.contains(&session_id);
+            // never waits on the group, its windows may be waiting on us to take their output
+            if let Some(info) = self.state.sessions.get(&session_id)
+                && info.group.try_forward_raw_output(session_id, forward).is_err()
+            {
+                self.schedule_output_flush(); // tried again after it
+                continue;
+            }
+            if forward {
+                self.state.raw_output_sessions.insert(session_id);
+            } else {
+                self.state.raw_output_sessions.remove(&session_id);
+            }
+        }
+    }
+
+    async fn handle_session_pane_exited(&mut self, session_id: u32, pane_id: usize, status: ExitStatus) -> Result<()> {
+        let message = format!("pane {pane_id} {status}");
+        for client in self.state.get_clients_for_session(&session_id).unwrap_or_default() {
+            client.notify(message.clone()).await?;
+        }
+        self.state.queue_hook(
+            HookEvent::PaneDied,
+            CommandContext {
+                client_id: None,
+                session_id: Some(session_id),
+            },
+        );
+        if pane_id == 0
+            && let Some(client) = self
+                .state
+                .exec_sessions
+                .get(&session_id)
+                .and_then(|client_id| self.state.clients.get(client_id))
+        {
+            client.exec_finished(status.code()).await?;
+            self.remove_session(session_id).await?;
+        }
+        Ok(())
+    }
+
+    /// the session's clients are told why before they're detached from it
+    async fn handle_session_failed(&mut self, session_id: u32, error: String) -> Result<()> {
+        let Some(info) = self.state.sessions.get(&session_id) else {
+            return Ok(());
+        };
+        let message = format!("session {} failed: {error}", info.name);
+        for client in self.state.get_clients_for_session(&session_id).unwrap_or_default() {
+            let _ = client.notify(message.clone()).await;
+        }
+        self.remove_session(session_id).await
+    }
+
+    /// alerts only matter for sessions nobody is looking at, each is raised once until a client attaches
+    async fn handle_session_alert(&mut self, session_id: u32, alert: Alert) -> Result<()> {
+        let viewed = self
+            .state
+            .session_to_client_mapping
+            .get(&session_id)
+            .is_some_and(|clients| !clients.is_empty());
+        let Some(info) = self.state.sessions.get_mut(&session_id) else {
+            return Ok(());
+        };
+        if viewed || info.alert == Some(alert) {
+            return Ok(());
+        }
+        info.alert = Some(alert);
+        let (message, hook) = match alert {
+            Alert::Activity => (format!("activity in session {}", info.name), HookEvent::AlertActivity),
+            Alert::Silence => (format!("silence in session {}", info.name), HookEvent::AlertSilence),
+        };
+        let bell = self.state.options.borrow().alert_bell;
+        for client in self.state.clients.values() {
+            client.session_alert(session_id, alert, bell).await?;
+            client.notify(message.clone()).await?;
+        }
+        self.state.queue_hook(
+            hook,
+            CommandContext {
+                client_id: None,
+                session_id: Some(session_id),
+            },
+        );
+        Ok(())
+    }
+
+    /// passed on to the session's clients when `bell-action` allows it for the window
+    async fn handle_session_bell(&self, session_id: u32, window: usize) -> Result<()> {
+        let Some(info) = self.state.sessions.get(&session_id) else {
+            return Ok(());
+        };
+        let current = info.windows.1 == window;
+        if !self.state.options.borrow().bell_action.passes(current) {
+            return Ok(());
+        }
+        for client in self.state.get_clients_for_session(&session_id).unwrap_or_default() {
+            client.bell(session_id, window).await?;
+        }
+        Ok(())
+    }
+
+    /// commands from connections that never attach or from control mode clients, the output is returned
+    /// rather than shown to a client
+    async fn handle_run_command_once(
+        &mut self,
+        command: RemuxCommand,
+        target_session: Option<String>,
+        client_id: Option<Uuid>,
+    ) -> Result<Option<String>> {
+        let session_id = target_session
+            .map(|target| {
+                self.state
+                    .find_session(&target)
+                    .ok_or_else(|| eyre!("no session {target}"))
+            })
+            .transpose()?;
+        let context = CommandContext { client_id, session_id };
+        match command {
+            RemuxCommand::ShowBuffer { buffer } => self.buffer_text(buffer).await.map(Some),
+            RemuxCommand::ShowEnvironment { global, session_name } => {
+                let session_id = self.state.get_session_id_for_context(&context);
+                self.environment_text(global, session_name, session_id).map(Some)
+            }
+            command => self.execute_command(context, command).await.map(|()| None),
+        }
+    }
+
+    async fn buffer_text(&self, buffer: Option<String>) -> Result<String> {
+        let text = self.state.buffers.get(buffer.clone()).await?;
+        text.ok_or_else(|| missing_buffer(buffer.as_deref()))
+    }
+
+    /// queues the commands of config files to run after the current one, nothing is queued if any has errors
+    fn source_files(&mut self, context: CommandContext, paths: Vec<PathBuf>) -> Result<()> {
+        let depth = self.state.running_origin.source_depth;
+        if depth >= MAX_SOURCE_DEPTH {
+            return Err(eyre!("source-file: too many nested files, stopped at {depth}"));
         }
+        let paths = if paths.is_empty() { config_files()? } else { paths };
+        let mut commands = vec![];
+        for path in paths {
+            info!(path=?path, "Sourcing config file");
+            commands.extend(read_config(&path)?);
+        }
+        let origin = CommandOrigin {
+            source_depth: depth + 1,
+            ..self.state.running_origin
+        };
+        self.state
+            .pending_commands
+            .extend(commands.into_iter().map(|command| (context, command, origin)));
         Ok(())
     }
+
+    /// commands may queue further commands through hooks and `source-file`, so keep going until the queue is
+    /// drained. commands run by hooks fire no hooks, so hooks can't keep queueing each other
+    async fn run_pending_commands(&mut self) {
+        while let Some((context, command, origin)) = self.state.pending_commands.pop_front() {
+            debug!(context=?context, command=?command, "Running command");
+            self.state.running_origin = origin;
+            let result = self.execute_command(context, command).await;
+            self.state.running_origin = CommandOrigin::default();
+            // the window report that follows comes back as an event of its own, after the hook is done
+            if origin.in_hook
+                && result.is_ok()
+                && let Some(session_id) = self.state.get_session_id_for_context(&context)
+            {
+                self.state.hook_window_changes.insert(session_id);
+            }
+            if let Err(e) = result {
+                error!("error running command: {e}");
+                // the client that ran it gets to see why nothing happened
+                if let Some(client) = context.client_id.and_then(|id| self.state.clients.get(&id)) {
+                    let _ = client.message(Message::error(e.to_string())).await;
+                }
+            }
+        }
+        self.send_fired_hooks().await;
+        self.send_state_deltas().await;
+    }
+
+    /// deltas go out in the order they were queued, a client that's going away doesn't need them
+    async fn send_state_deltas(&mut self) {
+        for (session_id, delta) in std::mem::take(&mut self.state.state_deltas) {
+            let clients = match session_id {
+                Some(session_id) => self.state.get_clients_for_session(&session_id).unwrap_or_default(),
+                None => self.state.clients.values().collect_vec(),
+            };
+            for client in clients {
+                let _ = client.state_delta(delta.clone()).await;
+            }
+        }
+    }
+
+    /// lua callbacks registered with `remux.on` run in the clients, control mode clients get nothing
+    async fn send_fired_hooks(&mut self) {
+        for (event, session_name) in std::mem::take(&mut self.state.fired_hooks) {
+            for (client_id, client) in &self.state.clients {
+                if self.state.control_clients.contains(client_id) {
+                    continue;
+                }
+                // a client that's going away doesn't need it
+                let _ = client.hook_fired(event, session_name.clone()).await;
+            }
+        }
+    }
+
+    /// the session whose environment table a command changes, `None` for the global table
+    fn environment_target(
+        &self,
+        global: bool,
+        session_name: Option<String>,
+        context_session: Option<u32>,
+    ) -> Result<Option<u32>> {
+        match (global, session_name) {
+            (true, _) => Ok(None),
+            (false, Some(name)) => self
+                .state
+                .get_session_by_name(&name)
+                .map(|info| Some(info.id))
+                .ok_or_else(|| eyre!("no session named {name}")),
+            (false, None) => context_session
+                .map(Some)
+                .ok_or_eyre("command requires a target session"),
+        }
+    }
+
+    fn environment_text(&self, global: bool, session_name: Option<String>, session_id: Option<u32>) -> Result<String> {
+        let target = self.environment_target(global, session_name, session_id)?;
+        let entries = self.state.environment.borrow().show(target);
+        if entries.is_empty() {
+            Ok("no environment set".to_owned())
+        } else {
+            Ok(entries.join(" "))
+        }
+    }
+
+    async fn execute_command(&mut self, context: CommandContext, command: RemuxCommand) -> Result<()> {
+        let session_id = self.state.get_session_id_for_context(&context);
+        let session = || {
+            session_id
+                .and_then(|id| self.state.sessions.get(&id))
+                .ok_or_eyre("command requires a target session")
+        };
+        let client_id = || context.client_id.ok_or_eyre("command requires a target client");
+
+        match command {
+            RemuxCommand::SplitWindow { vertical, environment } => {
+                let direction = if vertical {
+                    SplitDirection::Vertical
+                } else {
+                    SplitDirection::Horizontal
+                };
+                session()?.handle.user_split_pane(direction, environment).await
+            }
+            RemuxCommand::SelectPane {
+                target: Some(pane_id), ..
+            } => session()?.handle.focus_pane(pane_id).await,
+            RemuxCommand::SelectPane { is_next, .. } => session()?.handle.user_iterate_pane(is_next).await,
+            RemuxCommand::SetPaneTitle { target, title } => {
+                let title = (!title.is_empty()).then_some(title);
+                session()?.handle.set_pane_title(target, title).await
+            }
+            RemuxCommand::MarkPane { target, clear: false } => session()?.handle.mark_pane(target, false).await,
+            RemuxCommand::MarkPane { clear: true, .. } => match self.state.marked_pane {
+                Some((marked_session, _)) => {
+                    let session = self.state.sessions.get(&marked_session).ok_or_eyre("no such session")?;
+                    session.handle.mark_pane(None, true).await
+                }
+                None => Ok(()),
+            },
+            RemuxCommand::SelectMarkedPane => {
+                let (marked_session, pane_id) = self.state.marked_pane.ok_or_eyre("no marked pane")?;
+                if let Some(client_id) = context.client_id
+                    && session_id != Some(marked_session)
+                {
+                    let name = self
+                        .state
+                        .sessions
+                        .get(&marked_session)
+                        .ok_or_eyre("no such session")?
+                        .name
+                        .clone();
+                    self.handle_client_switch_session(client_id, &name).await?;
+                }
+                let session = self.state.sessions.get(&marked_session).ok_or_eyre("no such session")?;
+                session.handle.focus_pane(pane_id).await
+            }
+            RemuxCommand::KillPane => session()?.handle.user_kill_pane().await,
+            RemuxCommand::ResizePane { direction, amount } => session()?.handle.resize_pane(direction, amount).await,
+            RemuxCommand::ZoomPane => session()?.handle.zoom_pane().await,
+            RemuxCommand::SwapPane { with } => session()?.handle.swap_pane(with).await,
+            RemuxCommand::RotateWindow { downward } => session()?.handle.rotate_window(downward).await,
+            RemuxCommand::SelectLayout { layout } => session()?.handle.select_layout(layout).await,
+            RemuxCommand::BreakPane { source, name, detached } => {
+                session()?.handle.break_pane(source, name, detached).await
+            }
+            RemuxCommand::JoinPane {
+                source,
+                target,
+                vertical,
+            } => {
+                let direction = if vertical {
+                    SplitDirection::Vertical
+                } else {
+                    SplitDirection::Horizontal
+                };
+                session()?.handle.join_pane(source, target, direction).await
+            }
+            RemuxCommand::NewWindow { name } => session()?.handle.new_window(name).await,
+            RemuxCommand::NextWindow => session()?.handle.next_window().await,
+            RemuxCommand::PreviousWindow => session()?.handle.prev_window().await,
+            RemuxCommand::SelectWindow { index } => session()?.handle.select_window(index).await,
+            RemuxCommand::RenameWindow { name } => session()?.handle.rename_window(name).await,
+            RemuxCommand::ToggleFloating {
+                width_percent,
+                height_percent,
+            } => {
+                session()?
+                    .handle
+                    .toggle_floating_pane(width_percent, height_percent)
+                    .await
+            }
+            RemuxCommand::MirrorPane {
+                source,
+                target,
+                vertical,
+            } => {
+                let source = self
+                    .state
+                    .get_session_by_name(&source)
+                    .ok_or_else(|| eyre!("no session named {source}"))?;
+                let target = match target {
+                    Some(name) => self
+                        .state
+                        .get_session_by_name(&name)
+                        .ok_or_else(|| eyre!("no session named {name}"))?,
+                    None => session()?,
+                };
+                if source.id == target.id {
+                    return Err(eyre!("can't mirror a pane into its own session"));
+                }
+                let direction = if vertical {
+                    SplitDirection::Vertical
+                } else {
+                    SplitDirection::Horizontal
+                };
+                source.handle.mirror_active_pane(target.handle.clone(), direction).await
+            }
+            RemuxCommand::SendPrefix => {
+                session()?
+                    .handle
+                    .user_input(Bytes::from(vec![self.state.key_bindings.prefix]))
+                    .await
+            }
+            RemuxCommand::SendKeys { keys } => {
+                let bytes: Vec<u8> = keys.iter().flat_map(|key| key_bytes(key)).collect();
+                session()?.handle.user_input(Bytes::from(bytes)).await
+            }
+            RemuxCommand::NewSession { name, command, group } => {
+                let args = NewSessionArgs {
+                    command,
+                    argv: Vec::new(),
+                    group_with: group,
+                };
+                self.state.create_session(name.as_deref(), args)?;
+                Ok(())
+            }
+            RemuxCommand::KillSession { target } => {
+                let target = match target {
+                    Some(target) => target,
+                    None => session()?.id.to_string(),
+                };
+                self.handle_kill_session(&target).await
+            }
+            RemuxCommand::RenameSession { target, name } => {
+                let session_id = match target {
+                    Some(target) => self
+                        .state
+                        .find_session(&target)
+                        .ok_or_else(|| eyre!("no session {target}"))?,
+                    None => session()?.id,
+                };
+                self.handle_rename_session(session_id, name).await
+            }
+            RemuxCommand::SwitchClient { target } => {
+                let client_id = client_id()?;
+                let session_name = self.state.switch_target(client_id, target)?;
+                self.handle_client_switch_session(client_id, &session_name).await
+            }
+            RemuxCommand::DetachClient { others: false } => {
+                self.handle_client_disconnect(client_id()?, DetachReason::Detached)
+                    .await
+            }
+            RemuxCommand::DetachClient { others: true } => {
+                let client_id = client_id()?;
+                let session_id = session()?.id;
+                let others = self
+                    .state
+                    .session_to_client_mapping
+                    .get(&session_id)
+                    .into_iter()
+                    .flatten()
+                    .filter(|other| **other != client_id && !self.state.control_clients.contains(other))
+                    .copied()
+                    .collect_vec();
+                for other in others {
+                    self.handle_client_disconnect(other, DetachReason::Replaced).await?;
+                }
+                Ok(())
+            }
+            RemuxCommand::RunShell { command } => {
+                let session_name = session().map(|info| info.name.clone()).unwrap_or_default();
+                run_shell(command, session_name)
+            }
+            RemuxCommand::SetBuffer { name: Some(name), data } => self.state.buffers.set(name, data).await,
+            RemuxCommand::SetBuffer { name: None, data } => self.state.buffers.push(data).await,
+            RemuxCommand::PasteBuffer { buffer, bracketed } => {
+                let text = self.buffer_text(buffer).await?;
+                session()?.handle.paste(Bytes::from(text), bracketed).await
+            }
+            RemuxCommand::DeleteBuffer { buffer } => {
+                if self.state.buffers.delete(buffer.clone()).await? {
+                    Ok(())
+                } else {
+                    Err(missing_buffer(buffer.as_deref()))
+                }
+            }
+            RemuxCommand::DisplayMessage { text, duration } => {
+                let clients = match context.client_id.and_then(|id| self.state.clients.get(&id)) {
+                    Some(client) => vec![client],
+                    None => self.state.get_clients_for_session(&session()?.id)?,
+                };
+                let message = Message {
+                    text,
+                    level: MessageLevel::Info,
+                    duration: duration.map(Duration::from_millis),
+                };
+                for client in clients {
+                    client.message(message.clone()).await?;
+                }
+                Ok(())
+            }
+            RemuxCommand::ShowBuffer { buffer } => {
+                let text = self.buffer_text(buffer).await?;
+                let client = self
+                    .state
+                    .clients
+                    .get(&client_id()?)
+                    .ok_or_eyre("client does not exist")?;
+                client.notify(text).await
+            }
+            RemuxCommand::SaveBuffer { buffer, path, append } => {
+                let text = self.buffer_text(buffer).await?;
+                let path = self.client_path(context.client_id, &path);
+                let mut file = tokio::fs::OpenOptions::new()
+                    .create(true)
+                    .write(true)
+                    .append(append)
+                    .truncate(!append)
+                    .open(&path)
+                    .await
+                    .map_err(|e| eyre!("{}: {e}", path.display()))?;
+                file.write_all(text.as_bytes()).await?;
+                Ok(())
+            }
+            RemuxCommand::LoadBuffer { path } => {
+                let path = self.client_path(context.client_id, &path);
+                let bytes = tokio::fs::read(&path)
+                    .await
+                    .map_err(|e| eyre!("{}: {e}", path.display()))?;
+                self.state
+                    .buffers
+                    .push(String::from_utf8_lossy(&bytes).into_owned())
+                    .await
+            }
+            RemuxCommand::ChooseBuffer => {
+                let client = self
+                    .state
+                    .clients
+                    .get(&client_id()?)
+                    .ok_or_eyre("client does not exist")?;
+                let previews = self.state.buffers.previews().await?;
+                if previews.is_empty() {
+                    client.notify("no paste buffers".to_owned()).await
+                } else {
+                    client.buffers(previews).await
+                }
+            }
+            RemuxCommand::ChooseTree { sessions_only } => {
+                let client = self
+                    .state
+                    .clients
+                    .get(&client_id()?)
+                    .ok_or_eyre("client does not exist")?;
+                self.handle_choose_tree(client.clone(), sessions_only);
+                Ok(())
+            }
+            RemuxCommand::QuickOpen => {
+                let client = self
+                    .state
+                    .clients
+                    .get(&client_id()?)
+                    .ok_or_eyre("client does not exist")?;
+                session()?.handle.scan_links(client.clone()).await
+            }
+            RemuxCommand::SearchPanes { pattern } => {
+                let client = self
+                    .state
+                    .clients
+                    .get(&client_id()?)
+                    .ok_or_eyre("client does not exist")?;
+                session()?.handle.search_panes(pattern, client.clone()).await
+            }
+            RemuxCommand::CopyMode { search } => session()?.handle.enter_copy_mode(search).await,
+            RemuxCommand::LogPane { enabled } => {
+                // hooks and the config file have no client to tell where the log went
+                let client = client_id().ok().and_then(|id| self.state.clients.get(&id)).cloned();
+                session()?.handle.log_pane(enabled, client).await
+            }
+            RemuxCommand::DisplayPanes => {
+                let client = self
+                    .state
+                    .clients
+                    .get(&client_id()?)
+                    .ok_or_eyre("client does not exist")?;
+                session()?.handle.display_panes(client.clone()).await
+            }
+            RemuxCommand::SetOption {
+                name,
+                value,
+                scope: OptionScope::Session,
+            } => {
+                let session_id = session()?.id;
+                let SessionOption::DestroyUnattached(enabled) = SessionOption::parse(&name, &value)?;
+                if let Some(session) = self.state.sessions.get_mut(&session_id) {
+                    session.destroy_unattached = Some(enabled);
+                }
+                Ok(())
+            }
+            RemuxCommand::SetOption {
+                name,
+                value,
+                scope: OptionScope::Pane,
+            } => {
+                session()?
+                    .handle
+                    .set_pane_option(PaneOption::parse(&name, &value)?, false)
+                    .await
+            }
+            RemuxCommand::SetOption {
+                name,
+                value,
+                scope: OptionScope::Window,
+            } => {
+                session()?
+                    .handle
+                    .set_pane_option(PaneOption::parse(&name, &value)?, true)
+                    .await
+            }
+            RemuxCommand::SetOption { name, value, .. } if name == "prefix" => {
+                self.state.key_bindings.prefix = parse_prefix(&value)?;
+                self.send_key_bindings().await
+            }
+            RemuxCommand::SetOption { name, value, .. } => {
+                let mut result = Ok(());
+                let before = self.state.options.borrow().clone();
+                self.state
+                    .options
+                    .send_modify(|options| result = options.set(&name, &value));
+                result?;
+                let (screen_reader, status, client_cursors) = {
+                    let options = self.state.options.borrow();
+                    (options.screen_reader, options.status, options.client_cursors)
+                };
+                for client in self.state.clients.values() {
+                    if screen_reader != before.screen_reader {
+                        client.screen_reader(screen_reader).await?;
+                    }
+                    // the client resizes to take or give back the row, the panes follow from that
+                    if status != before.status {
+                        client.status_line(status).await?;
+                    }
+                }
+                if client_cursors != before.client_cursors {
+                    for &session_id in self.state.sessions.keys() {
+                        self.send_session_clients(session_id).await?;
+                    }
+                }
+                // panes read options when rendering
+                for session in self.state.sessions.values() {
+                    session.handle.redraw().await?;
+                }
+                Ok(())
+            }
+            RemuxCommand::BindKey { key, action } => {
+                self.state.key_bindings.bind(&key, action);
+                self.send_key_bindings().await
+            }
+            RemuxCommand::UnbindKey { key } => {
+                self.state
+                    .key_bindings
+                    .unbind(&key)
+                    .ok_or_else(|| eyre!("unbind-key: {key} isn't bound"))?;
+                self.send_key_bindings().await
+            }
+            RemuxCommand::SourceFile { paths } => {
+                let paths = paths
+                    .iter()
+                    .map(|path| self.client_path(context.client_id, path))
+                    .collect();
+                self.source_files(context, paths)
+            }
+            RemuxCommand::SetEnvironment {
+                global,
+                session_name,
+                name,
+                change,
+            } => {
+                let target = self.environment_target(global, session_name, session_id)?;
+                self.state
+                    .environment
+                    .send_modify(|environment| environment.apply(target, name, change));
+                Ok(())
+            }
+            RemuxCommand::ShowEnvironment { global, session_name } => {
+                let client = self
+                    .state
+                    .clients
+                    .get(&client_id()?)
+                    .ok_or_eyre("client does not exist")?;
+                client
+                    .notify(self.environment_text(global, session_name, session_id)?)
+                    .await
+            }
+            RemuxCommand::RefreshEnvironment => {
+                let session = session()?;
+                let vars = self.state.environment.borrow().for_session(session.id);
+                let names = self.state.options.borrow().update_environment.clone();
+                match shell_exports(&vars, &names) {
+                    Some(exports) => session.handle.user_input(Bytes::from(exports)).await,
+                    None => Ok(()),
+                }
+            }
+            RemuxCommand::SetHook {
+                event,
+                session_name,
+                commands,
+            } => {
+                self.state.hooks.set(event, session_name, commands);
+                Ok(())
+            }
+        }
+    }
+}
+
+fn missing_buffer(buffer: Option<&str>) -> Error {
+    match buffer {
+        Some(buffer) => eyre!("no buffer {buffer}"),
+        None => eyre!("no paste buffers"),
+    }
+}
+
+/// runs a shell command in the background, its output is only logged
+fn run_shell(command: String, session_name: String) -> Result<()> {
+    let child = Command::new("sh")
+        .arg("-c")
+        .arg(&command)
+        .env("REMUX_SESSION", session_name)
+        .stdin(Stdio::null())
+        .stdout(Stdio::null())
+        .stderr(Stdio::piped())
+        .spawn()?;
+    let _task: DaemonTask = tokio::spawn(
+        async move {
+            let output = child.wait_with_output().await?;
+            if output.status.success() {
+                debug!(status=%output.status, "shell command finished");
+            } else {
+                warn!(
+                    status=%output.status,
+                    stderr=%String::from_utf8_lossy(&output.stderr),
+                    "shell command failed"
+                );
+            }
+            Ok(())
+        }
+        .instrument(info_span!("run-shell", command = %command)),
+    );
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    #![allow(clippy::unwrap_used)]
+    use super::*;
+
+    #[tokio::test]
+    async fn test_hook_commands_fire_no_hooks() {
+        let (handle, _rx) = SessionManagerHandle::spawn(10);
+        let mut state = SessionManagerState::new(&handle, watch::Sender::new(false));
+        let new_session: RemuxCommand = "new-session".parse().unwrap();
+        state
+            .hooks
+            .set(HookEvent::SessionCreated, None, vec![new_session.clone()]);
+
+        state.queue_hook(HookEvent::SessionCreated, CommandContext::default());
+        let (_, command, origin) = state.pending_commands.pop_front().unwrap();
+        assert_eq!(command, new_session);
+        assert!(origin.in_hook);
+
+        // the session the hook's `new-session` creates doesn't run the hook again
+        state.running_origin = origin;
+        state.queue_hook(HookEvent::SessionCreated, CommandContext::default());
+        assert!(state.pending_commands.is_empty());
+        assert_eq!(state.fired_hooks.len(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_source_self_including_file() {
+        let path = std::env::temp_dir().join(format!("remux-test-{}-source.conf", std::process::id()));
+        std::fs::write(&path, format!("source-file {}\n", path.display())).unwrap();
+        let mut manager = SessionManager::new(watch::Sender::new(false));
+
+        manager
+            .source_files(CommandContext::default(), vec![path.clone()])
+            .unwrap();
+        tokio::time::timeout(Duration::from_secs(5), manager.run_pending_commands())
+            .await
+            .unwrap();
+        assert!(manager.state.pending_commands.is_empty());
+
+        // the file sourced at the deepest level is rejected
+        manager.state.running_origin.source_depth = MAX_SOURCE_DEPTH;
+        assert!(
+            manager
+                .source_files(CommandContext::default(), vec![path.clone()])
+                .is_err()
+        );
+        manager.state.running_origin.source_depth = MAX_SOURCE_DEPTH - 1;
+        manager
+            .source_files(CommandContext::default(), vec![path.clone()])
+            .unwrap();
+        assert_eq!(manager.state.pending_commands[0].2.source_depth, MAX_SOURCE_DEPTH);
+        std::fs::remove_file(path).unwrap();
+    }
 }
@@ -1,36 +1,206 @@
-use std::{collections::HashMap, mem};
+use std::{
+    collections::{HashMap, HashSet},
+    mem,
+    path::PathBuf,
+    sync::{
+        Arc,
+        atomic::{AtomicUsize, Ordering},
+    },
+};
 
 use bytes::Bytes;
+use color_eyre::eyre;
 use crossterm::terminal;
 use handle_macro::Handle;
-use tokio::sync::mpsc;
-use tracing::Instrument;
+use remux_core::{
+    commands::{LayoutPreset, PaneDirection, SwapWith},
+    events::{MessageLevel, MouseKind},
+    messages::response::PreviewPane,
+    states::{Alert, PaneLocation, SearchMatch},
+};
+use tokio::sync::{mpsc, oneshot};
+use tracing::{Instrument, Span};
 
 use crate::{
     actors::{
+        client_connection::ClientConnectionHandle,
+        message_span,
         pane::{Pane, PaneHandle},
+        pty::ExitStatus,
         session::SessionHandle,
+        window_group::{PaneDestination, WindowGroupHandle, WindowGroupHandleError},
     },
-    layout::{LayoutNode, Rect, SplitDirection},
+    environment::SessionEnvironment,
+    layout::{FloatingGeometry, LayoutNode, Rect, SplitDirection, ZoomedLayout, border_cells},
+    options::{OptionsReceiver, PaneOption},
     prelude::*,
+    supervision::{Supervisor, Verdict, wait_for_children},
 };
 
+// (rows, cols) until a client has told the session how big its terminal is
+const DEFAULT_SIZE: (u16, u16) = (24, 80);
+
 #[derive(Handle)]
+#[handle(spawn, error = Error)]
 pub enum WindowEvent {
     UserInput(Bytes), // input from user
+    Paste {
+        text: Bytes,
+        bracketed: bool,
+    }, // a paste buffer for the active pane
     PaneOutput {
         id: usize,
         bytes: Bytes,
         cursor: Option<(u16, u16)>,
+        cursor_hidden: bool,
     }, // output from pane
+    PaneRawOutput {
+        id: usize,
+        bytes: Bytes,
+    }, // unrendered pty output from pane
+    PaneImage {
+        id: usize,
+        bytes: Bytes,
+        cursor: (u16, u16),
+    }, // sixel/kitty/iTerm2 image the pane's program drew at `cursor` (pane relative row, col)
+    PaneCopied {
+        text: String,
+    }, // text copied by a pane's program (OSC 52)
+    PaneExited {
+        id: usize,
+        status: ExitStatus,
+    },
+    PaneFailed {
+        id: usize,
+        error: String,
+    }, // the pane gave up after its handlers kept failing and has stopped
     IteratePane {
         is_next: bool,
     },
     SplitPane {
         direction: SplitDirection,
+        environment: Vec<(String, String)>, // the new pane's own variables
+    }, // asks the active pane where it is, then splits with `SplitPaneIn`
+    SplitPaneIn {
+        direction: SplitDirection,
+        cwd: Option<PathBuf>,
+        environment: Vec<(String, String)>,
     },
     KillPane,
+    ClosePane {
+        id: usize,
+    }, // `x` pressed in a dead pane
+    ResizePane {
+        direction: PaneDirection,
+        amount: u16,
+    },
+    ZoomPane, // toggles the active pane filling the window
+    SwapPane {
+        with: SwapWith,
+    },
+    RotateWindow {
+        downward: bool,
+    },
+    SelectLayout {
+        layout: LayoutPreset,
+    },
+    ToggleFloatingPane {
+        width_percent: Option<u16>,
+        height_percent: Option<u16>,
+    },
+    MirrorActivePane {
+        target: SessionHandle,
+        direction: SplitDirection,
+    }, // mirror this window's active pane into the target session's window
+    AttachMirror {
+        pane: PaneHandle,
+        direction: SplitDirection,
+    }, // show a pane owned by another window next to the active pane
+    ReleasePane {
+        pane_id: Option<usize>,
+        destination: PaneDestination,
+    }, // hand the pane (the active one if not given) back to the group, ignored if it isn't this window's
+    AdoptPane {
+        pane_id: usize,
+        pane: PaneHandle,
+        direction: SplitDirection,
+    }, // take over a pane another window released, next to the active pane
+    MirrorClosed {
+        id: usize,
+    }, // the pane behind a mirror died
+    ScanLinks {
+        client: ClientConnectionHandle,
+    },
+    SearchPanes {
+        pattern: String,
+        client: ClientConnectionHandle,
+    },
+    CapturePane {
+        pane_id: usize,
+        client: ClientConnectionHandle,
+    },
+    JumpToLine {
+        pane_id: usize,
+        line: usize,
+    }, // focus the pane and show the line in copy mode
+    EnterCopyMode(Option<String>), // with the pattern of a `/` search
+    PaneAlert {
+        pane_id: usize,
+        alert: Alert,
+    },
+    PaneBell {
+        pane_id: usize,
+    },
+    PaneCommand {
+        pane_id: usize,
+        command: String,
+    }, // the pane's foreground program changed
+    ClientFocus(bool),      // the outer terminal gained/lost focus
+    PauseOutput(bool),      // the clients are behind, every pane stops reading its pty until they catch up
+    ForwardRawOutput(bool), // a control mode client is watching, every pane sends its raw output on
+    LogPane {
+        enabled: Option<bool>,
+        session_name: String,
+        client: Option<ClientConnectionHandle>,
+    },
+    SetPaneOption {
+        option: PaneOption,
+        whole_window: bool,
+    },
+    DisplayPanes {
+        client: ClientConnectionHandle,
+    },
+    FocusPane {
+        pane_id: usize,
+    },
+    SetPaneTitle {
+        pane_id: Option<usize>,
+        title: Option<String>,
+    },
+    MarkPane {
+        pane_id: Option<usize>,
+        clear: bool, // drop the mark instead of toggling it
+    },
+    Mouse {
+        kind: MouseKind,
+        row: u16,
+        col: u16,
+    },
     Redraw,
+    #[reply(usize)]
+    CountPanes {
+        reply: oneshot::Sender<usize>,
+    }, // panes owned by the window, mirrors of other windows' panes aren't counted
+    #[reply(PreviewPane)]
+    Preview {
+        rows: u16,
+        cols: u16,
+        reply: oneshot::Sender<PreviewPane>,
+    }, // the focused pane's screen for the session switcher
+    #[reply(Vec<usize>)]
+    ListPanes {
+        reply: oneshot::Sender<Vec<usize>>,
+    }, // ids of the owned panes in layout order, the floating pane last
     TerminalResize {
         rows: u16,
         cols: u16,
@@ -45,38 +215,114 @@ pub enum WindowState {
     Focused,
     Unfocused,
 }
+/// hands out pane ids, shared by a session's windows so ids stay unique across them -
+/// control clients and `select-pane -t` tell panes apart by id
+#[derive(Debug, Clone, Default)]
+pub struct PaneIds(Arc<AtomicUsize>);
+
+impl PaneIds {
+    fn next(&self) -> usize {
+        self.0.fetch_add(1, Ordering::Relaxed)
+    }
+}
+
+/// what a new window starts out with
+#[derive(Debug)]
+pub enum FirstPane {
+    Spawn(Option<Vec<String>>), // a new pane running the command, or the shell
+    Adopt(usize, PaneHandle),   // a pane `break-pane` moved out of another window
+}
+
+/// scratchpad pane drawn above the tiled layout - it is not part of the layout tree
+#[derive(Debug)]
+struct FloatingPane {
+    id: usize,
+    handle: PaneHandle,
+    visible: bool,
+}
+
+/// read-only view of a pane owned by another window
+#[derive(Debug)]
+struct MirroredPane {
+    handle: PaneHandle,
+    mirror_id: u32,
+}
+
 #[derive(Debug)]
 pub struct Window {
-    session_handle: SessionHandle,
+    id: usize, // tags this window's output so the session only passes on the active window's
+    group_handle: WindowGroupHandle,
     handle: WindowHandle,
-    rx: mpsc::Receiver<WindowEvent>,
+    rx: mpsc::Receiver<(WindowEvent, Span)>,
+    supervisor: Supervisor,
 
     layout: LayoutNode,
     layout_sizing_map: HashMap<usize, Rect>,
+    zoomed: Option<ZoomedLayout>, // while set `layout` holds just the zoomed pane
     panes: HashMap<usize, PaneHandle>,
+    mirrored_panes: HashMap<usize, MirroredPane>,
     pane_cursors: HashMap<usize, (u16, u16)>,
-    active_pane_id: usize,
-    next_pane_id: usize,
+    hidden_cursors: HashSet<usize>,      // panes whose program turned the cursor off
+    mouse_target: Option<(usize, Rect)>, // pane receiving the current mouse press/drag
+    divider_drag: Option<Vec<bool>>,     // layout path of the split divider being dragged
+    active_pane_id: usize,               // shared by every client viewing the session, like tmux
+    pane_ids: PaneIds,
     root_rect: Rect,
+    floating: Option<FloatingPane>,
+    floating_geometry: FloatingGeometry,
+    options: OptionsReceiver,      // handed to every pane spawned in this window
+    pane_options: Vec<PaneOption>, // `set-option -w`, applied to panes spawned later too
+    environment: SessionEnvironment,
+    client_focused: bool,
+    reported_focus: Option<(usize, PaneHandle)>, // the pane last sent a focus-in
+    marked_pane: Option<usize>,                  // `select-pane -m`
+    announced_focus: usize,                      // the focused pane screen reader users were last told about
+    pane_commands: HashMap<usize, String>,       // each pane's foreground program, for `automatic-rename`
+    reported_panes: Option<(usize, usize, Option<String>)>, // (pane count, focused pane, its program) as last reported
+    borders: bool,                               // `pane-borders` as the layout was last calculated with
+    output_paused: bool,                         // panes spawned or adopted while paused start out paused
+    raw_output: bool,                            // and ones spawned or adopted while forwarding forward too
 
     #[allow(unused)]
     window_state: WindowState,
 }
 impl Window {
-    #[instrument(skip(session_handle), name = "Window")]
-    pub fn spawn(session_handle: SessionHandle) -> Result<WindowHandle> {
-        let window = Window::new(session_handle)?;
-        window.run()
+    #[instrument(skip(group_handle, options, environment, pane_ids), name = "Window")]
+    pub fn spawn(
+        id: usize,
+        group_handle: WindowGroupHandle,
+        options: OptionsReceiver,
+        environment: SessionEnvironment,
+        pane_ids: PaneIds,
+        first_pane: FirstPane,
+        size: Option<(u16, u16)>, // the session's terminal size, if a client has reported one
+    ) -> Result<WindowHandle> {
+        let adopted = match &first_pane {
+            FirstPane::Adopt(pane_id, _) => Some(*pane_id),
+            FirstPane::Spawn(_) => None,
+        };
+        let window = Window::new(id, group_handle, options, environment, pane_ids, first_pane, size)?;
+        window.run(adopted)
     }
 
-    fn new(session_handle: SessionHandle) -> Result<Self> {
-        let (tx, rx) = mpsc::channel(10);
-        let handle = WindowHandle { tx };
+    fn new(
+        id: usize,
+        group_handle: WindowGroupHandle,
+        options: OptionsReceiver,
+        environment: SessionEnvironment,
+        pane_ids: PaneIds,
+        first_pane: FirstPane,
+        size: Option<(u16, u16)>,
+    ) -> Result<Self> {
+        let (handle, rx) = WindowHandle::spawn(10);
 
-        let init_pane_id = 0;
+        let init_pane_id = match &first_pane {
+            FirstPane::Spawn(_) => pane_ids.next(),
+            FirstPane::Adopt(pane_id, _) => *pane_id,
+        };
         let init_layout_node = LayoutNode::Pane { id: init_pane_id };
 
-        let (cols, rows) = (80, 24);
+        let (rows, cols) = size.unwrap_or(DEFAULT_SIZE);
         let root_rect = Rect {
             x: 0,
             y: 0,
@@ -86,118 +332,867 @@ impl Window {
         let mut layout_sizing_map = HashMap::new();
         layout_sizing_map.insert(init_pane_id, root_rect);
         init_layout_node.calculate_layout(root_rect, &mut layout_sizing_map)?;
+        let borders = options.borrow().pane_borders;
 
         let mut panes = HashMap::new();
-        if let Some(rect) = layout_sizing_map.get(&init_pane_id) {
-            let pane_handle = Pane::spawn(handle.clone(), init_pane_id, *rect)?;
-            panes.insert(init_pane_id, pane_handle);
+        match first_pane {
+            FirstPane::Spawn(command) => {
+                if let Some(rect) = layout_sizing_map.get(&init_pane_id) {
+                    let pane_handle = Pane::spawn(
+                        handle.clone(),
+                        init_pane_id,
+                        *rect,
+                        options.clone(),
+                        environment.clone(),
+                        command,
+                        None,
+                    )?;
+                    panes.insert(init_pane_id, pane_handle);
+                }
+            }
+            FirstPane::Adopt(pane_id, pane_handle) => {
+                panes.insert(pane_id, pane_handle);
+            }
         }
 
         Ok(Self {
-            session_handle,
+            id,
+            group_handle,
             handle,
             rx,
+            supervisor: Supervisor::default(),
             layout: init_layout_node,
             layout_sizing_map,
+            zoomed: None,
             panes,
+            mirrored_panes: HashMap::new(),
             active_pane_id: init_pane_id,
-            next_pane_id: init_pane_id + 1,
+            pane_ids,
             window_state: WindowState::Focused,
             pane_cursors: HashMap::new(),
+            hidden_cursors: HashSet::new(),
+            mouse_target: None,
+            divider_drag: None,
             root_rect,
+            floating: None,
+            floating_geometry: FloatingGeometry::default(),
+            options,
+            pane_options: Vec::new(),
+            environment,
+            client_focused: true,
+            reported_focus: None,
+            marked_pane: None,
+            announced_focus: init_pane_id,
+            pane_commands: HashMap::new(),
+            reported_panes: None,
+            borders,
+            output_paused: false,
+            raw_output: false,
         })
     }
     #[instrument(skip(self))]
-    fn run(mut self) -> Result<WindowHandle> {
+    fn run(mut self, adopted: Option<usize>) -> Result<WindowHandle> {
         let handle_clone = self.handle.clone();
         let _task = tokio::spawn({
             async move {
-                loop {
-                    if let Some(event) = self.rx.recv().await {
-                        match event {
-                            UserInput(bytes) => {
-                                trace!("Window: UserInput");
-                                self.handle_user_input(bytes).await.unwrap();
-                            }
-                            PaneOutput { id, bytes, cursor } => {
-                                trace!("Window: PaneOutput");
-                                self.handle_pane_output(id, bytes, cursor).await.unwrap();
-                            }
-                            IteratePane { is_next } => {
-                                debug!("Window: IteratePane");
-                                self.handle_iterate_pane(is_next).await.unwrap();
-                            }
-                            SplitPane { direction } => {
-                                debug!("Window: SplitPane");
-                                self.handle_split_pane(direction).await.unwrap();
-                            }
-                            KillPane => {
-                                debug!("Window: IteratePane");
-                                self.handle_kill_pane().await.unwrap();
-                            }
-                            Redraw => {
-                                debug!("Window: Redraw");
-                                self.handle_redraw().await.unwrap();
+                if let Err(e) = self.start(adopted).await {
+                    error!("error starting window: {e}");
+                }
+                while let Some((event, cause)) = self.rx.recv().await {
+                    match self.handle_event(event).instrument(message_span(&cause)).await {
+                        Ok(true) => {}
+                        Ok(false) => break,
+                        Err(e) => match self.supervisor.check(&e, WindowGroupHandleError::Closed) {
+                            Verdict::Continue => {
+                                let _ = self.group_handle.notify(e.to_string(), MessageLevel::Error).await;
                             }
-                            Kill => {
-                                debug!("Window: Kill");
-                                for pane in self.panes.values() {
-                                    pane.kill().await.unwrap();
-                                }
+                            Verdict::ParentGone => break,
+                            Verdict::GiveUp => {
+                                let _ = self.group_handle.window_failed(self.id, e.to_string()).await;
                                 break;
                             }
-                            TerminalResize { rows, cols } => {
-                                for pane in self.panes.values_mut() {
-                                    pane.resize(Rect {
-                                        x: 0,
-                                        y: 0,
-                                        width: cols,
-                                        height: rows,
-                                    })
-                                    .await
-                                    .unwrap();
-                                }
-                            }
-                        }
+                        },
                     }
                 }
+                self.stop().await;
             }
             .in_current_span()
         });
 
         Ok(handle_clone)
     }
+
+    /// an adopted pane is pointed at this window before anything else reaches it
+    async fn start(&mut self, adopted: Option<usize>) -> Result<()> {
+        if let Some(pane_id) = adopted {
+            self.claim_pane(pane_id).await?;
+            self.resize_panes().await?;
+        }
+        self.report_panes().await
+    }
+
+    /// returns false once the window should stop
+    async fn handle_event(&mut self, event: WindowEvent) -> Result<bool> {
+        match event {
+            UserInput(bytes) => {
+                trace!("Window: UserInput");
+                self.handle_user_input(bytes).await?;
+            }
+            Paste { text, bracketed } => {
+                debug!("Window: Paste");
+                if let Some(pane) = self.focused_pane() {
+                    pane.paste(text, bracketed).await?;
+                }
+            }
+            PaneOutput {
+                id,
+                bytes,
+                cursor,
+                cursor_hidden,
+            } => {
+                trace!("Window: PaneOutput");
+                self.handle_pane_output(id, bytes, cursor, cursor_hidden).await?;
+            }
+            PaneRawOutput { id, bytes } => {
+                trace!("Window: PaneRawOutput");
+                self.group_handle.pane_output(id, bytes).await?;
+            }
+            PaneImage { id, bytes, cursor } => {
+                trace!("Window: PaneImage");
+                self.handle_pane_image(id, bytes, cursor).await?;
+            }
+            PaneCopied { text } => {
+                debug!("Window: PaneCopied");
+                self.group_handle.pane_copied(text).await?;
+            }
+            PaneExited { id, status } => {
+                debug!("Window: PaneExited");
+                self.group_handle.pane_exited(id, status).await?;
+            }
+            PaneFailed { id, error } => {
+                debug!("Window: PaneFailed");
+                self.handle_pane_failed(id, error).await?;
+            }
+            IteratePane { is_next } => {
+                debug!("Window: IteratePane");
+                self.handle_iterate_pane(is_next).await?;
+            }
+            SplitPane { direction, environment } => {
+                debug!("Window: SplitPane");
+                self.handle_split_pane(direction, environment);
+            }
+            SplitPaneIn {
+                direction,
+                cwd,
+                environment,
+            } => {
+                debug!("Window: SplitPaneIn");
+                self.handle_split_pane_in(direction, cwd, environment).await?;
+            }
+            KillPane => {
+                debug!("Window: IteratePane");
+                self.handle_kill_pane().await?;
+            }
+            ClosePane { id } => {
+                debug!("Window: ClosePane");
+                self.close_pane(id).await?;
+            }
+            ResizePane { direction, amount } => {
+                debug!("Window: ResizePane");
+                self.handle_resize_pane(direction, amount).await?;
+            }
+            ZoomPane => {
+                debug!("Window: ZoomPane");
+                self.handle_zoom_pane().await?;
+            }
+            SwapPane { with } => {
+                debug!("Window: SwapPane");
+                self.handle_swap_pane(with).await?;
+            }
+            RotateWindow { downward } => {
+                debug!("Window: RotateWindow");
+                self.handle_rotate_window(downward).await?;
+            }
+            SelectLayout { layout } => {
+                debug!("Window: SelectLayout");
+                self.handle_select_layout(layout).await?;
+            }
+            ToggleFloatingPane {
+                width_percent,
+                height_percent,
+            } => {
+                debug!("Window: ToggleFloatingPane");
+                self.handle_toggle_floating_pane(width_percent, height_percent).await?;
+            }
+            MirrorActivePane { target, direction } => {
+                debug!("Window: MirrorActivePane");
+                if let Some(pane) = self.panes.get(&self.active_pane_id) {
+                    target.attach_mirror(pane.clone(), direction).await?;
+                }
+            }
+            AttachMirror { pane, direction } => {
+                debug!("Window: AttachMirror");
+                self.handle_attach_mirror(pane, direction).await?;
+            }
+            ReleasePane { pane_id, destination } => {
+                debug!("Window: ReleasePane");
+                self.handle_release_pane(pane_id, destination).await?;
+            }
+            AdoptPane {
+                pane_id,
+                pane,
+                direction,
+            } => {
+                debug!("Window: AdoptPane");
+                self.handle_adopt_pane(pane_id, pane, direction).await?;
+            }
+            MirrorClosed { id } => {
+                debug!("Window: MirrorClosed");
+                if self.mirrored_panes.remove(&id).is_some() {
+                    self.remove_from_layout(id).await?;
+                }
+            }
+            ScanLinks { client } => {
+                debug!("Window: ScanLinks");
+                self.handle_scan_links(client).await?;
+            }
+            SearchPanes { pattern, client } => {
+                debug!("Window: SearchPanes");
+                self.handle_search_panes(pattern, client);
+            }
+            CapturePane { pane_id, client } => {
+                debug!("Window: CapturePane");
+                match self.pane_by_id(pane_id) {
+                    Some(pane) => pane.capture(client).await?,
+                    None => client.notify(format!("pane %{pane_id} not found")).await?,
+                }
+            }
+            JumpToLine { pane_id, line } => {
+                debug!("Window: JumpToLine");
+                self.handle_jump_to_line(pane_id, line).await?;
+            }
+            EnterCopyMode(search) => {
+                debug!("Window: EnterCopyMode");
+                if let Some(pane) = self.focused_pane() {
+                    pane.enter_copy_mode(None, search).await?;
+                }
+            }
+            ClientFocus(focused) => {
+                trace!("Window: ClientFocus");
+                self.client_focused = focused;
+            }
+            PaneAlert { pane_id, alert } => {
+                trace!(pane_id, alert=?alert, "Window: PaneAlert");
+                self.group_handle.pane_alert(alert).await?;
+            }
+            PaneBell { pane_id } => {
+                trace!(pane_id, "Window: PaneBell");
+                self.group_handle.window_bell(self.id).await?;
+            }
+            PaneCommand { pane_id, command } => {
+                trace!(pane_id, command, "Window: PaneCommand");
+                self.pane_commands.insert(pane_id, command);
+            }
+            LogPane {
+                enabled,
+                session_name,
+                client,
+            } => {
+                debug!("Window: LogPane");
+                if let Some(pane) = self.focused_pane() {
+                    pane.set_logging(enabled, session_name, client).await?;
+                }
+            }
+            SetPaneOption { option, whole_window } => {
+                debug!("Window: SetPaneOption");
+                self.handle_set_pane_option(option, whole_window).await?;
+            }
+            PauseOutput(paused) => {
+                debug!(paused, "Window: PauseOutput");
+                self.output_paused = paused;
+                for pane in self.panes.values() {
+                    pane.pause_output(paused).await?;
+                }
+                if let Some(floating) = &self.floating {
+                    floating.handle.pause_output(paused).await?;
+                }
+            }
+            ForwardRawOutput(forward) => {
+                debug!(forward, "Window: ForwardRawOutput");
+                self.raw_output = forward;
+                for pane in self.panes.values() {
+                    pane.forward_raw_output(forward).await?;
+                }
+                if let Some(floating) = &self.floating {
+                    floating.handle.forward_raw_output(forward).await?;
+                }
+            }
+            DisplayPanes { client } => {
+                debug!("Window: DisplayPanes");
+                self.handle_display_panes(client).await?;
+            }
+            FocusPane { pane_id } => {
+                debug!("Window: FocusPane");
+                if self.focus_pane(pane_id).await?.is_none() {
+                    warn!("pane {} does not exist", pane_id);
+                }
+            }
+            SetPaneTitle { pane_id, title } => {
+                debug!("Window: SetPaneTitle");
+                self.handle_set_pane_title(pane_id, title).await?;
+            }
+            MarkPane { pane_id, clear } => {
+                debug!("Window: MarkPane");
+                self.handle_mark_pane(pane_id, clear).await?;
+            }
+            Mouse { kind, row, col } => {
+                trace!("Window: Mouse");
+                self.handle_mouse(kind, row, col).await?;
+            }
+            Redraw => {
+                debug!("Window: Redraw");
+                self.handle_redraw().await?;
+            }
+            CountPanes { reply } => {
+                let _ = reply.send(self.panes.len() + usize::from(self.floating.is_some()));
+            }
+            Preview { rows, cols, reply } => {
+                self.handle_preview(rows, cols, reply);
+            }
+            ListPanes { reply } => {
+                let ids = self
+                    .layout
+                    .pane_ids()
+                    .into_iter()
+                    .filter(|id| self.panes.contains_key(id))
+                    .chain(self.floating.as_ref().map(|floating| floating.id))
+                    .collect();
+                let _ = reply.send(ids);
+            }
+            Kill => {
+                debug!("Window: Kill");
+                return Ok(false);
+            }
+            TerminalResize { rows, cols } => {
+                debug!("Window: TerminalResize");
+                self.handle_terminal_resize(rows, cols).await?;
+            }
+        }
+        self.sync_focus().await;
+        self.announce_focus().await?;
+        self.report_panes().await?;
+        Ok(true)
+    }
+
+    /// the window's panes go with it however it stopped, they may have stopped on their own already
+    /// done once every pane has stopped
+    async fn stop(&mut self) {
+        for mirrored in self.mirrored_panes.values() {
+            // the pane is owned elsewhere, only stop rendering here
+            let _ = mirrored.handle.remove_mirror(mirrored.mirror_id).await;
+        }
+        let panes: Vec<PaneHandle> = self
+            .panes
+            .values()
+            .chain(self.floating.as_ref().map(|floating| &floating.handle))
+            .cloned()
+            .collect();
+        wait_for_children(&mut self.rx, async {
+            for pane in &panes {
+                let _ = pane.kill().await;
+            }
+            for pane in &panes {
+                pane.closed().await;
+            }
+        })
+        .await;
+    }
 }
 
 impl Window {
+    /// the visible floating pane takes focus over the tiled panes
+    fn focused_pane_id(&self) -> usize {
+        match &self.floating {
+            Some(floating) if floating.visible => floating.id,
+            _ => self.active_pane_id,
+        }
+    }
+    /// tells panes when they gain or lose focus, checked after every event instead of at each place focus moves
+    async fn sync_focus(&mut self) {
+        let focused = match self.focused_pane() {
+            Some(pane) if self.client_focused => Some((self.focused_pane_id(), pane.clone())),
+            _ => None,
+        };
+        if focused.as_ref().map(|(id, _)| *id) == self.reported_focus.as_ref().map(|(id, _)| *id) {
+            return;
+        }
+        // the pane may already be gone, e.g. after kill-pane
+        if let Some((_, pane)) = self.reported_focus.take() {
+            let _ = pane.focus(false).await;
+        }
+        if let Some((_, pane)) = &focused {
+            let _ = pane.focus(true).await;
+        }
+        self.reported_focus = focused;
+    }
+    /// with `screen-reader` on, focus moving to another pane is said as a status message
+    /// since the cursor jumping is all a screen reader would otherwise notice
+    async fn announce_focus(&mut self) -> Result<()> {
+        let focused = self.focused_pane_id();
+        if focused == self.announced_focus {
+            return Ok(());
+        }
+        self.announced_focus = focused;
+        if !self.options.borrow().screen_reader {
+            return Ok(());
+        }
+        let message = match &self.floating {
+            Some(floating) if floating.id == focused => "floating pane".to_owned(),
+            _ => format!("pane {focused}"),
+        };
+        self.group_handle.notify(message, MessageLevel::Info).await
+    }
+    /// clients show the pane count and focused pane in their status line, checked after every event like focus
+    async fn report_panes(&mut self) -> Result<()> {
+        let focused = self.focused_pane_id();
+        let panes = (
+            self.panes.len() + usize::from(self.floating.is_some()),
+            focused,
+            self.pane_commands.get(&focused).cloned(),
+        );
+        if self.reported_panes.as_ref() == Some(&panes) {
+            return Ok(());
+        }
+        self.reported_panes = Some(panes.clone());
+        self.group_handle.window_panes(self.id, panes.0, panes.1, panes.2).await
+    }
+    /// the owned pane receiving input, mirrored panes are laid out by their own window so they're excluded
+    fn focused_pane(&self) -> Option<&PaneHandle> {
+        match &self.floating {
+            Some(floating) if floating.visible => Some(&floating.handle),
+            _ => self.panes.get(&self.active_pane_id),
+        }
+    }
+    async fn handle_scan_links(&mut self, client: ClientConnectionHandle) -> Result<()> {
+        match self.focused_pane() {
+            Some(pane) => pane.scan_links(client).await,
+            None => client.notify("no links found".to_owned()).await,
+        }
+    }
+    /// the pane may be waiting on the window, so it's asked off the window's loop
+    fn handle_preview(&self, rows: u16, cols: u16, reply: oneshot::Sender<PreviewPane>) {
+        let pane = self.focused_pane().cloned();
+        let _task: DaemonTask = tokio::spawn(
+            async move {
+                // a pane that's gone drops the reply
+                if let Some(pane) = pane {
+                    let _ = reply.send(pane.preview(rows, cols).await?);
+                }
+                Ok(())
+            }
+            .in_current_span(),
+        );
+    }
+    /// panes answer on their own time, so results are collected off the window's loop
+    fn handle_search_panes(&self, pattern: String, client: ClientConnectionHandle) {
+        let panes: Vec<(usize, PaneHandle)> = self
+            .panes
+            .iter()
+            .map(|(id, pane)| (*id, pane.clone()))
+            .chain(
+                self.floating
+                    .iter()
+                    .map(|floating| (floating.id, floating.handle.clone())),
+            )
+            .collect();
+
+        tokio::spawn(
+            async move {
+                let mut matches = Vec::new();
+                for (pane_id, pane) in panes {
+                    if let Ok(lines) = pane.search(pattern.clone()).await {
+                        matches.extend(
+                            lines
+                                .into_iter()
+                                .map(|(line, text)| SearchMatch { pane_id, line, text }),
+                        );
+                    }
+                }
+                if matches.is_empty() {
+                    client.notify(format!("no matches for {pattern}")).await
+                } else {
+                    client.search_results(matches).await
+                }
+            }
+            .in_current_span(),
+        );
+    }
+    /// makes a pane the focused one, showing or hiding the floating pane as needed.
+    /// returns the pane's handle, or `None` if the window has no such pane
+    async fn focus_pane(&mut self, pane_id: usize) -> Result<Option<PaneHandle>> {
+        let floating_id = self.floating.as_ref().map(|floating| floating.id);
+        let floating_visible = self.floating.as_ref().is_some_and(|floating| floating.visible);
+
+        if floating_id == Some(pane_id) {
+            if !floating_visible {
+                self.handle_toggle_floating_pane(None, None).await?;
+            }
+            return Ok(self.floating.as_ref().map(|floating| floating.handle.clone()));
+        }
+
+        let pane = match (self.panes.get(&pane_id), self.mirrored_panes.get(&pane_id)) {
+            (Some(pane), _) => pane.clone(),
+            (None, Some(mirrored)) => mirrored.handle.clone(),
+            (None, None) => return Ok(None),
+        };
+        if floating_visible {
+            self.handle_toggle_floating_pane(None, None).await?;
+        }
+        if pane_id != self.active_pane_id && self.unzoom().await? {
+            self.clear_and_redraw().await?;
+        }
+        self.active_pane_id = pane_id;
+        self.move_cursor_to_active_pane().await?;
+        Ok(Some(pane))
+    }
+    async fn handle_set_pane_title(&mut self, pane_id: Option<usize>, title: Option<String>) -> Result<()> {
+        let pane_id = pane_id.unwrap_or_else(|| self.focused_pane_id());
+        match self.pane_by_id(pane_id) {
+            Some(pane) => pane.set_title(title).await,
+            None => Err(eyre::eyre!("pane {pane_id} does not exist")),
+        }
+    }
+    /// the session manager hears about every change, it keeps the one mark across sessions
+    async fn handle_mark_pane(&mut self, pane_id: Option<usize>, clear: bool) -> Result<()> {
+        let pane_id = pane_id.unwrap_or_else(|| self.focused_pane_id());
+        let unmark = clear || self.marked_pane == Some(pane_id);
+        if let Some(marked) = self.marked_pane.take()
+            && let Some(pane) = self.pane_by_id(marked)
+        {
+            pane.set_marked(false).await?;
+        }
+        if !unmark {
+            let pane = self
+                .pane_by_id(pane_id)
+                .ok_or_else(|| eyre::eyre!("pane {pane_id} does not exist"))?;
+            pane.set_marked(true).await?;
+            self.marked_pane = Some(pane_id);
+        }
+        self.group_handle.pane_marked(self.marked_pane).await
+    }
+    /// a tiled or floating pane owned by this window
+    fn pane_by_id(&self, pane_id: usize) -> Option<&PaneHandle> {
+        match &self.floating {
+            Some(floating) if floating.id == pane_id => Some(&floating.handle),
+            _ => self.panes.get(&pane_id),
+        }
+    }
+    async fn handle_jump_to_line(&mut self, pane_id: usize, line: usize) -> Result<()> {
+        // mirrored panes scroll in their own window, only owned panes are searched
+        if self.mirrored_panes.contains_key(&pane_id) {
+            return Ok(());
+        }
+        match self.focus_pane(pane_id).await? {
+            Some(pane) => pane.enter_copy_mode(Some(line), None).await,
+            None => {
+                warn!("pane {} no longer exists", pane_id);
+                Ok(())
+            }
+        }
+    }
+    /// the owned pane drawn at a terminal cell, the visible floating pane is on top
+    fn pane_at(&self, x: u16, y: u16) -> Option<(usize, Rect)> {
+        if let Some(floating) = self.floating.as_ref().filter(|floating| floating.visible) {
+            let rect = self.floating_geometry.rect(self.root_rect).inner();
+            if rect.contains(x, y) {
+                return Some((floating.id, rect));
+            }
+        }
+        self.panes.keys().find_map(|id| {
+            self.layout_sizing_map
+                .get(id)
+                .filter(|rect| rect.contains(x, y))
+                .map(|rect| (*id, *rect))
+        })
+    }
+    async fn handle_mouse(&mut self, kind: MouseKind, row: u16, col: u16) -> Result<()> {
+        let floating_visible = self.floating.as_ref().is_some_and(|floating| floating.visible);
+        if let Some(path) = &self.divider_drag {
+            match kind {
+                MouseKind::Drag => {
+                    if self.layout.drag_divider(self.root_rect, path, col, row) {
+                        self.relayout().await?;
+                        self.clear_and_redraw().await?;
+                    }
+                    return Ok(());
+                }
+                MouseKind::Up => {
+                    self.divider_drag = None;
+                    return Ok(());
+                }
+                _ => self.divider_drag = None,
+            }
+        }
+        // a press on a divider grabs it, the floating pane covers whatever is underneath it
+        if kind == MouseKind::Down
+            && self
+                .pane_at(col, row)
+                .is_none_or(|(id, _)| self.panes.contains_key(&id))
+            && let Some(path) = self.layout.divider_at(self.root_rect, col, row)
+        {
+            self.divider_drag = Some(path);
+            return Ok(());
+        }
+
+        // drags and releases go to the pane the press started in, even once the pointer leaves it
+        let target = match (kind, self.mouse_target) {
+            (MouseKind::Drag | MouseKind::Up, Some(target)) => Some(target),
+            _ => self.pane_at(col, row),
+        };
+        self.mouse_target = match kind {
+            MouseKind::Up => None,
+            // the wheel scrolls whatever is under the pointer without ending a drag
+            MouseKind::ScrollUp | MouseKind::ScrollDown => self.mouse_target,
+            _ => target,
+        };
+        let Some((id, rect)) = target else {
+            return Ok(());
+        };
+        // clicking a tiled pane focuses it, the click still reaches the pane
+        if kind == MouseKind::Down && id != self.active_pane_id && !floating_visible && self.panes.contains_key(&id) {
+            self.focus_pane(id).await?;
+        }
+
+        let pane = match &self.floating {
+            Some(floating) if floating.id == id => Some(&floating.handle),
+            _ => self.panes.get(&id),
+        };
+        if let Some(pane) = pane {
+            let local_row = row.saturating_sub(rect.y).min(rect.height.saturating_sub(1));
+            let local_col = col.saturating_sub(rect.x).min(rect.width.saturating_sub(1));
+            pane.mouse(kind, local_row, local_col).await?;
+        }
+        Ok(())
+    }
+    async fn handle_display_panes(&mut self, client: ClientConnectionHandle) -> Result<()> {
+        let mut locations: Vec<PaneLocation> = self
+            .panes
+            .keys()
+            .chain(self.mirrored_panes.keys())
+            .filter_map(|id| self.layout_sizing_map.get(id).map(|rect| pane_location(*id, *rect)))
+            .collect();
+        locations.sort_by_key(|location| location.pane_id);
+        if let Some(floating) = self.floating.as_ref().filter(|floating| floating.visible) {
+            let rect = self.floating_geometry.rect(self.root_rect).inner();
+            // the floating pane covers whatever is underneath it
+            locations.retain(|location| {
+                !rect.contains(location.col + location.width / 2, location.row + location.height / 2)
+            });
+            locations.push(pane_location(floating.id, rect));
+        }
+        client.pane_locations(locations).await
+    }
     async fn handle_user_input(&mut self, bytes: Bytes) -> Result<()> {
-        if let Some(pane) = self.panes.get(&self.active_pane_id) {
-            pane.user_input(bytes).await?;
+        match &self.floating {
+            Some(floating) if floating.visible => floating.handle.user_input(bytes).await?,
+            _ => {
+                if let Some(pane) = self.panes.get(&self.active_pane_id) {
+                    pane.user_input(bytes).await?;
+                }
+            }
         }
         Ok(())
     }
-    async fn handle_pane_output(&mut self, id: usize, bytes: Bytes, cursor: Option<(u16, u16)>) -> Result<()> {
+    async fn handle_pane_output(
+        &mut self,
+        id: usize,
+        bytes: Bytes,
+        cursor: Option<(u16, u16)>,
+        cursor_hidden: bool,
+    ) -> Result<()> {
+        // queued by a pane before `break-pane`/`join-pane` moved it to another window
+        if !self.layout_sizing_map.contains_key(&id) && self.floating.as_ref().is_none_or(|floating| floating.id != id)
+        {
+            return Ok(());
+        }
         if let Some(pos) = cursor {
             self.pane_cursors.insert(id, pos);
         }
+        if cursor_hidden {
+            self.hidden_cursors.insert(id);
+        } else {
+            self.hidden_cursors.remove(&id);
+        }
+        // output queued before the zoom, or from a mirror - those aren't hidden as the pane is drawn elsewhere too
+        if let Some(zoomed) = &self.zoomed
+            && zoomed.pane_id != id
+            && self.floating.as_ref().is_none_or(|floating| floating.id != id)
+        {
+            return Ok(());
+        }
 
-        self.session_handle.window_output(bytes).await?;
+        match &self.floating {
+            // hidden floating pane keeps its state but draws nothing
+            Some(floating) if floating.id == id && !floating.visible => return Ok(()),
+            Some(floating) if floating.id == id => {
+                self.group_handle.window_output(self.id, bytes).await?;
+                let ascii = self.options.borrow().screen_reader;
+                let border = floating_border(self.floating_geometry.rect(self.root_rect), ascii);
+                self.group_handle.window_output(self.id, border).await?;
+            }
+            // tiled output may have drawn over the floating pane
+            Some(floating) if floating.visible => {
+                self.group_handle.window_output(self.id, bytes).await?;
+                floating.handle.rerender().await?;
+            }
+            _ => self.group_handle.window_output(self.id, bytes).await?,
+        }
 
-        if let Some(&(active_x, active_y)) = self.pane_cursors.get(&self.active_pane_id) {
-            let restore_cursor = format!("\x1b[{};{}H", active_y, active_x);
-            self.session_handle.window_output(Bytes::from(restore_cursor)).await?;
+        if let Some(restore_cursor) = self.restore_cursor(self.focused_pane_id()) {
+            self.group_handle
+                .window_output(self.id, Bytes::from(restore_cursor))
+                .await?;
         }
 
         Ok(())
     }
+    async fn handle_set_pane_option(&mut self, option: PaneOption, whole_window: bool) -> Result<()> {
+        if !whole_window {
+            return match self.focused_pane() {
+                Some(pane) => pane.set_option(option).await,
+                None => Ok(()),
+            };
+        }
+        // a later value for the same option replaces the earlier one
+        self.pane_options
+            .retain(|existing| mem::discriminant(existing) != mem::discriminant(&option));
+        self.pane_options.push(option);
+        for pane in self.panes.values() {
+            pane.set_option(option).await?;
+        }
+        if let Some(floating) = &self.floating {
+            floating.handle.set_option(option).await?;
+        }
+        Ok(())
+    }
+
+    async fn apply_pane_options(&self, pane: &PaneHandle) -> Result<()> {
+        for option in &self.pane_options {
+            pane.set_option(*option).await?;
+        }
+        // an adopted pane may have been paused by its old window's clients
+        pane.pause_output(self.output_paused).await?;
+        pane.forward_raw_output(self.raw_output).await?;
+        Ok(())
+    }
+
+    /// images can't be clipped to a pane, so they're only passed through while the pane fills the window
+    async fn handle_pane_image(&mut self, id: usize, bytes: Bytes, (row, col): (u16, u16)) -> Result<()> {
+        let floating_visible = self.floating.as_ref().is_some_and(|floating| floating.visible);
+        let fills_window = self.panes.len() == 1 && self.mirrored_panes.is_empty() && !floating_visible;
+        let Some(rect) = self.layout_sizing_map.get(&id).filter(|_| fills_window) else {
+            trace!(id, "dropping image from a pane that doesn't fill the window");
+            return Ok(());
+        };
+        let mut output = format!("\x1b[{};{}H", rect.y + 1 + row, rect.x + 1 + col).into_bytes();
+        output.extend_from_slice(&bytes);
+        self.group_handle.window_output(self.id, Bytes::from(output)).await?;
+
+        if let Some(restore_cursor) = self.restore_cursor(self.focused_pane_id()) {
+            self.group_handle
+                .window_output(self.id, Bytes::from(restore_cursor))
+                .await?;
+        }
+        Ok(())
+    }
     async fn handle_redraw(&mut self) -> Result<()> {
+        // `pane-borders` changed, the panes make room for the lines or take the space back
+        let borders = self.options.borrow().pane_borders;
+        if borders != self.borders {
+            self.borders = borders;
+            self.relayout().await?;
+            self.group_handle
+                .window_output(
+                    self.id,
+                    Bytes::from(crossterm::terminal::Clear(crossterm::terminal::ClearType::All).to_string()),
+                )
+                .await?;
+        }
+        if self.borders {
+            let panes: Vec<Rect> = self.layout_sizing_map.values().copied().collect();
+            let ascii = self.options.borrow().screen_reader;
+            if let Some(lines) = pane_borders(self.root_rect, &panes, ascii) {
+                self.group_handle.window_output(self.id, lines).await?;
+            }
+        }
         for pane in self.panes.values() {
             pane.rerender().await?;
         }
+        for mirrored in self.mirrored_panes.values() {
+            mirrored.handle.rerender().await?;
+        }
+        if let Some(floating) = self.floating.as_ref().filter(|floating| floating.visible) {
+            floating.handle.rerender().await?;
+        }
+        Ok(())
+    }
+    async fn handle_toggle_floating_pane(
+        &mut self,
+        width_percent: Option<u16>,
+        height_percent: Option<u16>,
+    ) -> Result<()> {
+        if let Some(width_percent) = width_percent {
+            self.floating_geometry.width_percent = width_percent.clamp(10, 100);
+        }
+        if let Some(height_percent) = height_percent {
+            self.floating_geometry.height_percent = height_percent.clamp(10, 100);
+        }
+        let rect = self.floating_geometry.rect(self.root_rect).inner();
+
+        match self.floating.as_mut() {
+            None => {
+                let id = self.pane_ids.next();
+                debug!("Spawning floating pane {}", id);
+                let handle = Pane::spawn(
+                    self.handle.clone(),
+                    id,
+                    rect,
+                    self.options.clone(),
+                    self.environment.clone(),
+                    None,
+                    None,
+                )?;
+                self.apply_pane_options(&handle).await?;
+                self.floating = Some(FloatingPane {
+                    id,
+                    handle,
+                    visible: true,
+                });
+            }
+            Some(floating) if floating.visible => {
+                floating.visible = false;
+                // uncover the tiled panes underneath
+                self.group_handle
+                    .window_output(
+                        self.id,
+                        Bytes::from(crossterm::terminal::Clear(crossterm::terminal::ClearType::All).to_string()),
+                    )
+                    .await?;
+                self.handle_redraw().await?;
+            }
+            Some(floating) => {
+                floating.visible = true;
+                // resizing also rerenders and picks up geometry changes
+                floating.handle.resize(rect).await?;
+            }
+        }
         Ok(())
     }
     async fn handle_iterate_pane(&mut self, is_next: bool) -> Result<()> {
-        let mut ids: Vec<usize> = self.panes.keys().copied().collect();
+        if self.unzoom().await? {
+            self.clear_and_redraw().await?;
+        }
+        let mut ids: Vec<usize> = self.panes.keys().chain(self.mirrored_panes.keys()).copied().collect();
         if ids.is_empty() {
             return Ok(());
         }
@@ -217,44 +1212,82 @@ impl Window {
 
         self.active_pane_id = ids[new_idx];
         debug!("Switched to Pane ID: {}", self.active_pane_id);
-        let (tx, ty) = if let Some(&pos) = self.pane_cursors.get(&self.active_pane_id) {
-            pos
+        self.move_cursor_to_active_pane().await
+    }
+    async fn move_cursor_to_active_pane(&self) -> Result<()> {
+        let move_cursor = if let Some(restore_cursor) = self.restore_cursor(self.active_pane_id) {
+            restore_cursor
         } else {
             if let Some(rect) = self.layout_sizing_map.get(&self.active_pane_id) {
-                (rect.x + 1, rect.y + 1)
+                format!("\x1b[{};{}H\x1b[?25h", rect.y + 1, rect.x + 1)
             } else {
                 warn!("Active pane has no rect in layout map!");
                 return Ok(());
             }
         };
 
-        let move_cursor = format!("\x1b[{};{}H", ty, tx);
-        self.session_handle.window_output(Bytes::from(move_cursor)).await?;
+        self.group_handle
+            .window_output(self.id, Bytes::from(move_cursor))
+            .await?;
 
         Ok(())
     }
-    async fn handle_split_pane(&mut self, direction: SplitDirection) -> Result<()> {
-        self.layout.add_split(self.active_pane_id, self.next_pane_id, direction);
-        self.layout
-            .calculate_layout(self.root_rect, &mut self.layout_sizing_map)?;
+
+    /// puts the cursor back where the pane left it, shown or hidden the way its program wants
+    fn restore_cursor(&self, id: usize) -> Option<String> {
+        let (x, y) = self.pane_cursors.get(&id)?;
+        let visibility = if self.hidden_cursors.contains(&id) { 'l' } else { 'h' };
+        Some(format!("\x1b[{y};{x}H\x1b[?25{visibility}"))
+    }
+    /// the new pane starts in the active pane's directory, asked for off the window's loop since the pane
+    /// may be waiting on the window to take its output
+    fn handle_split_pane(&self, direction: SplitDirection, environment: Vec<(String, String)>) {
+        let pane = self.panes.get(&self.active_pane_id).cloned();
+        let handle = self.handle.clone();
+        let _task: DaemonTask = tokio::spawn(
+            async move {
+                let cwd = match pane {
+                    // a pane that's gone drops the reply
+                    Some(pane) => pane.current_dir().await.ok().flatten(),
+                    None => None,
+                };
+                handle.split_pane_in(direction, cwd, environment).await
+            }
+            .in_current_span(),
+        );
+    }
+    async fn handle_split_pane_in(
+        &mut self,
+        direction: SplitDirection,
+        cwd: Option<PathBuf>,
+        environment: Vec<(String, String)>,
+    ) -> Result<()> {
+        self.unzoom().await?;
+        let id = self.pane_ids.next();
+        self.layout.add_split(self.active_pane_id, id, direction);
+        self.calculate_layout()?;
 
         // new pane rect
-        if let Some(rect) = self.layout_sizing_map.get(&self.next_pane_id) {
-            let pane_handle = Pane::spawn(self.handle.clone(), self.next_pane_id, *rect)?;
-            self.panes.insert(self.next_pane_id, pane_handle);
+        if let Some(rect) = self.layout_sizing_map.get(&id) {
+            let pane_handle = Pane::spawn(
+                self.handle.clone(),
+                id,
+                *rect,
+                self.options.clone(),
+                self.environment.for_pane(environment),
+                None,
+                cwd,
+            )?;
+            self.apply_pane_options(&pane_handle).await?;
+            self.panes.insert(id, pane_handle);
         }
 
-        self.active_pane_id = self.next_pane_id;
-        self.next_pane_id += 1;
+        self.active_pane_id = id;
 
-        for (id, pane) in self.panes.iter() {
-            if let Some(new_rect) = self.layout_sizing_map.get(id) {
-                pane.resize(*new_rect).await?;
-            }
-        }
+        self.resize_panes().await?;
 
-        // self.session_handle
-        //     .window_output(Bytes::from(
+        // self.group_handle
+        //     .window_output(self.id, Bytes::from(
         //         crossterm::terminal::Clear(crossterm::terminal::ClearType::All).to_string(),
         //     ))
         //     .await?;
@@ -262,23 +1295,239 @@ impl Window {
         Ok(())
     }
     async fn handle_kill_pane(&mut self) -> Result<()> {
-        let dead_pane_id = self.active_pane_id;
-        if self.panes.len() <= 1 {
+        let pane_id = match self.floating.as_ref().filter(|floating| floating.visible) {
+            Some(floating) => floating.id,
+            None => self.active_pane_id,
+        };
+        self.close_pane(pane_id).await
+    }
+    /// kills the pane (or drops the mirror) and gives its space to the rest of the layout
+    async fn close_pane(&mut self, dead_pane_id: usize) -> Result<()> {
+        if self
+            .floating
+            .as_ref()
+            .is_some_and(|floating| floating.id == dead_pane_id)
+        {
+            if let Some(floating) = self.floating.take() {
+                debug!("Killing floating pane {}", floating.id);
+                // a failed pane has already stopped
+                let _ = floating.handle.kill().await;
+                self.pane_cursors.remove(&floating.id);
+                self.hidden_cursors.remove(&floating.id);
+                self.pane_commands.remove(&floating.id);
+                self.forget_mark(floating.id).await?;
+            }
+            self.group_handle
+                .window_output(
+                    self.id,
+                    Bytes::from(crossterm::terminal::Clear(crossterm::terminal::ClearType::All).to_string()),
+                )
+                .await?;
+            return self.handle_redraw().await;
+        }
+
+        if self.panes.len() + self.mirrored_panes.len() <= 1 {
             // TODO: kill window if last pane is killed
             warn!("Can't kill last pane {}", dead_pane_id);
-            return Ok(());
+            return self
+                .group_handle
+                .notify("can't close the only pane of a window".to_owned(), MessageLevel::Error)
+                .await;
         }
 
         debug!("Killing pane {}", dead_pane_id);
-        if let Some(pane_handle) = self.panes.get(&dead_pane_id) {
+        if let Some(pane_handle) = self.panes.remove(&dead_pane_id) {
             if let Err(e) = pane_handle.kill().await {
                 error!("Error while killing pane! {}", e);
             }
+        } else if let Some(mirrored) = self.mirrored_panes.remove(&dead_pane_id) {
+            // killing a mirror only removes the view, the pane lives on in its own window
+            mirrored.handle.remove_mirror(mirrored.mirror_id).await?;
         }
 
-        self.panes.remove(&dead_pane_id);
+        self.remove_from_layout(dead_pane_id).await
+    }
+    /// the rest of the layout takes the failed pane's space, a window's only pane is replaced with a fresh
+    /// shell instead so the window stays usable
+    async fn handle_pane_failed(&mut self, id: usize, error: String) -> Result<()> {
+        self.group_handle
+            .notify(format!("pane {id} failed: {error}"), MessageLevel::Error)
+            .await?;
+        if !self.panes.contains_key(&id) || self.panes.len() + self.mirrored_panes.len() > 1 {
+            return self.close_pane(id).await;
+        }
+        let rect = *self
+            .layout_sizing_map
+            .get(&id)
+            .ok_or_else(|| eyre::eyre!("pane {id} has no rect in layout map"))?;
+        let pane = Pane::spawn(
+            self.handle.clone(),
+            id,
+            rect,
+            self.options.clone(),
+            self.environment.clone(),
+            None,
+            None,
+        )?;
+        self.apply_pane_options(&pane).await?;
+        self.panes.insert(id, pane);
+        self.reported_focus = None;
+        self.clear_and_redraw().await
+    }
+    /// the floating pane isn't part of the layout, nothing is resized while it's shown
+    async fn handle_resize_pane(&mut self, direction: PaneDirection, amount: u16) -> Result<()> {
+        if self.floating.as_ref().is_some_and(|floating| floating.visible) {
+            return Ok(());
+        }
+        let unzoomed = self.unzoom().await?;
+        let amount = i32::from(amount);
+        let (split, amount) = match direction {
+            PaneDirection::Left => (SplitDirection::Vertical, -amount),
+            PaneDirection::Right => (SplitDirection::Vertical, amount),
+            PaneDirection::Up => (SplitDirection::Horizontal, -amount),
+            PaneDirection::Down => (SplitDirection::Horizontal, amount),
+        };
+        if !self.layout.resize(self.root_rect, self.active_pane_id, split, amount) {
+            return if unzoomed {
+                self.clear_and_redraw().await
+            } else {
+                Ok(())
+            };
+        }
+        self.relayout().await?;
+        self.clear_and_redraw().await
+    }
+    /// tmux's `resize-pane -Z` - the active pane fills the window until toggled again,
+    /// the others keep running hidden
+    async fn handle_zoom_pane(&mut self) -> Result<()> {
+        if self.unzoom().await? {
+            return self.clear_and_redraw().await;
+        }
+        let floating_visible = self.floating.as_ref().is_some_and(|floating| floating.visible);
+        if floating_visible || self.panes.len() + self.mirrored_panes.len() <= 1 {
+            return Ok(());
+        }
+        let pane_id = self.active_pane_id;
+        for (id, pane) in &self.panes {
+            if *id != pane_id {
+                pane.hide().await?;
+            }
+        }
+        self.zoomed = Some(ZoomedLayout::zoom(&mut self.layout, pane_id));
+        self.relayout().await?;
+        self.clear_and_redraw().await
+    }
+    /// the panes keep running and follow their spot's rect, focus stays with the active pane as it moves
+    async fn handle_swap_pane(&mut self, with: SwapWith) -> Result<()> {
+        if self.floating.as_ref().is_some_and(|floating| floating.visible) {
+            return Ok(());
+        }
+        let unzoomed = self.unzoom().await?;
+        let ids = self.layout.pane_ids();
+        let Some(current_idx) = ids.iter().position(|&id| id == self.active_pane_id) else {
+            return Ok(());
+        };
+        let other_idx = match with {
+            SwapWith::Next => (current_idx + 1) % ids.len(),
+            SwapWith::Prev => (current_idx + ids.len() - 1) % ids.len(),
+        };
+        if other_idx == current_idx {
+            return if unzoomed {
+                self.clear_and_redraw().await
+            } else {
+                Ok(())
+            };
+        }
+        self.layout.swap_panes(self.active_pane_id, ids[other_idx]);
+        self.relayout().await?;
+        self.clear_and_redraw().await
+    }
+    async fn handle_rotate_window(&mut self, downward: bool) -> Result<()> {
+        if self.floating.as_ref().is_some_and(|floating| floating.visible) {
+            return Ok(());
+        }
+        self.unzoom().await?;
+        self.layout.rotate(downward);
+        self.relayout().await?;
+        self.clear_and_redraw().await
+    }
+    async fn handle_select_layout(&mut self, preset: LayoutPreset) -> Result<()> {
+        if self.floating.as_ref().is_some_and(|floating| floating.visible) {
+            return Ok(());
+        }
+        self.unzoom().await?;
+        if let Some(layout) = LayoutNode::preset(&self.layout.pane_ids(), preset) {
+            self.layout = layout;
+        }
+        self.relayout().await?;
+        self.clear_and_redraw().await
+    }
+    /// puts back the layout from before the zoom and shows the hidden panes again, returns whether the
+    /// window was zoomed - redrawing is up to the caller
+    async fn unzoom(&mut self) -> Result<bool> {
+        let Some(zoomed) = self.zoomed.take() else {
+            return Ok(false);
+        };
+        zoomed.restore(&mut self.layout);
+        for pane in self.panes.values() {
+            pane.reveal().await?;
+        }
+        self.relayout().await?;
+        Ok(true)
+    }
+    /// recalculates every rect from the layout tree and resizes the panes to match
+    /// lays the panes out into `layout_sizing_map`, leaving the cells between them for borders
+    fn calculate_layout(&mut self) -> Result<()> {
+        self.layout_sizing_map.clear();
+        self.layout
+            .calculate_layout(self.root_rect, &mut self.layout_sizing_map)?;
+        if self.borders {
+            for rect in self.layout_sizing_map.values_mut() {
+                *rect = rect.inside_borders(self.root_rect);
+            }
+        }
+        Ok(())
+    }
+    /// the window fills the session's terminal, the panes keep their share of it
+    async fn handle_terminal_resize(&mut self, rows: u16, cols: u16) -> Result<()> {
+        let root_rect = Rect {
+            x: 0,
+            y: 0,
+            width: cols,
+            height: rows,
+        };
+        if root_rect == self.root_rect {
+            return Ok(());
+        }
+        self.root_rect = root_rect;
+        self.relayout().await?;
+        if let Some(floating) = &self.floating {
+            let rect = self.floating_geometry.rect(root_rect).inner();
+            floating.handle.resize(rect).await?;
+        }
+        self.clear_and_redraw().await
+    }
+    async fn relayout(&mut self) -> Result<()> {
+        self.calculate_layout()?;
+        self.resize_panes().await
+    }
+    async fn clear_and_redraw(&mut self) -> Result<()> {
+        self.group_handle
+            .window_output(
+                self.id,
+                Bytes::from(crossterm::terminal::Clear(crossterm::terminal::ClearType::All).to_string()),
+            )
+            .await?;
+        self.handle_redraw().await
+    }
+    /// drops a pane (already removed from `panes`/`mirrored_panes`) from the layout and redraws the rest
+    async fn remove_from_layout(&mut self, dead_pane_id: usize) -> Result<()> {
+        self.unzoom().await?;
         self.pane_cursors.remove(&dead_pane_id);
+        self.hidden_cursors.remove(&dead_pane_id);
+        self.pane_commands.remove(&dead_pane_id);
         self.layout_sizing_map.remove(&dead_pane_id);
+        self.forget_mark(dead_pane_id).await?;
 
         let dummy_node = LayoutNode::Pane { id: 0 };
         let old_layout = mem::replace(&mut self.layout, dummy_node);
@@ -290,27 +1539,167 @@ impl Window {
             return Ok(());
         }
 
-        if let Some(&new_id) = self.panes.keys().next() {
+        if self.active_pane_id == dead_pane_id
+            && let Some(&new_id) = self.panes.keys().chain(self.mirrored_panes.keys()).next()
+        {
             self.active_pane_id = new_id;
         }
 
-        self.layout_sizing_map.clear();
-        self.layout
-            .calculate_layout(self.root_rect, &mut self.layout_sizing_map)?;
+        self.calculate_layout()?;
+        self.resize_panes().await?;
+
+        self.group_handle
+            .window_output(
+                self.id,
+                Bytes::from(crossterm::terminal::Clear(crossterm::terminal::ClearType::All).to_string()),
+            )
+            .await?;
+        self.handle_redraw().await?;
 
+        Ok(())
+    }
+    async fn forget_mark(&mut self, dead_pane_id: usize) -> Result<()> {
+        if self.marked_pane == Some(dead_pane_id) {
+            self.marked_pane = None;
+            self.group_handle.pane_marked(None).await?;
+        }
+        Ok(())
+    }
+    /// pushes the rects from the layout sizing map to every pane and mirror
+    async fn resize_panes(&self) -> Result<()> {
         for (id, pane) in self.panes.iter() {
             if let Some(new_rect) = self.layout_sizing_map.get(id) {
                 pane.resize(*new_rect).await?;
             }
         }
+        for (id, mirrored) in self.mirrored_panes.iter() {
+            if let Some(new_rect) = self.layout_sizing_map.get(id) {
+                mirrored.handle.resize_mirror(mirrored.mirror_id, *new_rect).await?;
+            }
+        }
+        Ok(())
+    }
+    /// the pane keeps running while it's handed over, the group passes it on to its destination
+    async fn handle_release_pane(&mut self, pane_id: Option<usize>, destination: PaneDestination) -> Result<()> {
+        let pane_id = pane_id.unwrap_or(self.active_pane_id);
+        if !self.panes.contains_key(&pane_id) {
+            return Ok(());
+        }
+        // TODO: close the window instead once windows can be closed
+        if self.panes.len() + self.mirrored_panes.len() <= 1 {
+            return self
+                .group_handle
+                .notify("can't move the only pane of a window".to_owned(), MessageLevel::Error)
+                .await;
+        }
+        let Some(pane) = self.panes.remove(&pane_id) else {
+            return Ok(());
+        };
+        if self.reported_focus.as_ref().is_some_and(|(id, _)| *id == pane_id) {
+            self.reported_focus = None;
+        }
+        self.remove_from_layout(pane_id).await?;
+        self.group_handle.pane_released(pane_id, pane, destination).await
+    }
+    async fn handle_adopt_pane(&mut self, pane_id: usize, pane: PaneHandle, direction: SplitDirection) -> Result<()> {
+        self.unzoom().await?;
+        self.layout.add_split(self.active_pane_id, pane_id, direction);
+        self.calculate_layout()?;
+        self.apply_pane_options(&pane).await?;
+        self.panes.insert(pane_id, pane);
+        self.active_pane_id = pane_id;
+        self.claim_pane(pane_id).await?;
+        self.resize_panes().await?;
+        self.clear_and_redraw().await
+    }
+    /// points a pane moved over from another window at this one, its rect follows with `resize_panes`
+    async fn claim_pane(&self, pane_id: usize) -> Result<()> {
+        match self.panes.get(&pane_id) {
+            Some(pane) => pane.move_to_window(self.handle.clone()).await,
+            None => Ok(()),
+        }
+    }
+    async fn handle_attach_mirror(&mut self, pane: PaneHandle, direction: SplitDirection) -> Result<()> {
+        self.unzoom().await?;
+        let id = self.pane_ids.next();
+        self.layout.add_split(self.active_pane_id, id, direction);
+        self.calculate_layout()?;
 
-        self.session_handle
-            .window_output(Bytes::from(
-                crossterm::terminal::Clear(crossterm::terminal::ClearType::All).to_string(),
-            ))
-            .await?;
-        self.handle_redraw().await?;
+        let rect = *self
+            .layout_sizing_map
+            .get(&id)
+            .ok_or_else(|| eyre::eyre!("mirror has no rect in layout map"))?;
+        let mirror_id = remux_core::rand::generate_id();
+        pane.add_mirror(mirror_id, self.handle.clone(), id, rect).await?;
+        self.mirrored_panes.insert(
+            id,
+            MirroredPane {
+                handle: pane,
+                mirror_id,
+            },
+        );
 
-        Ok(())
+        self.resize_panes().await?;
+        self.handle_redraw().await
+    }
+}
+
+fn pane_location(pane_id: usize, rect: Rect) -> PaneLocation {
+    PaneLocation {
+        pane_id,
+        row: rect.y,
+        col: rect.x,
+        width: rect.width,
+        height: rect.height,
+    }
+}
+
+/// lines through the cells between tiled panes, `None` when a single pane fills the window
+fn pane_borders(area: Rect, panes: &[Rect], ascii: bool) -> Option<Bytes> {
+    let cells = border_cells(area, panes);
+    if cells.is_empty() {
+        return None;
+    }
+    let mut lines = String::from("\x1b[0m");
+    for cell in cells {
+        let glyph = match (cell.joins, ascii) {
+            ([true, true, false, false] | [true, false, false, false] | [false, true, false, false], true) => '|',
+            ([false, false, _, _], true) => '-',
+            (_, true) => '+',
+            ([true, true, false, false] | [true, false, false, false] | [false, true, false, false], false) => '│',
+            ([false, false, _, _], false) => '─',
+            ([true, true, true, true], false) => '┼',
+            ([true, true, false, true], false) => '├',
+            ([true, true, true, false], false) => '┤',
+            ([false, true, true, true], false) => '┬',
+            ([true, false, true, true], false) => '┴',
+            ([false, true, false, true], false) => '┌',
+            ([false, true, true, false], false) => '┐',
+            ([true, false, false, true], false) => '└',
+            ([true, false, true, false], false) => '┘',
+        };
+        lines.push_str(&format!("\x1b[{};{}H{glyph}", cell.y + 1, cell.x + 1));
+    }
+    Some(Bytes::from(lines))
+}
+
+/// box drawn around the floating pane's rect, `ascii` avoids box drawing characters for screen readers
+fn floating_border(rect: Rect, ascii: bool) -> Bytes {
+    let (corner, horizontal, vertical) = if ascii {
+        (['+'; 4], "-", "|")
+    } else {
+        (['┌', '┐', '└', '┘'], "─", "│")
+    };
+    let horizontal = horizontal.repeat(rect.width.saturating_sub(2) as usize);
+    let (left, top) = (rect.x + 1, rect.y + 1);
+    let (right, bottom) = (rect.x + rect.width, rect.y + rect.height);
+
+    let [top_left, top_right, bottom_left, bottom_right] = corner;
+    let mut border = format!(
+        "\x1b[0m\x1b[{top};{left}H{top_left}{horizontal}{top_right}\x1b[{bottom};{left}H{bottom_left}{horizontal}{bottom_right}"
+    );
+    for row in (top + 1)..bottom {
+        border.push_str(&format!("\x1b[{row};{left}H{vertical}\x1b[{row};{right}H{vertical}"));
     }
+    Bytes::from(border)
 }
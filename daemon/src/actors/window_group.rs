@@ -0,0 +1,639 @@
+// the windows behind one or more sessions. a session created with `new-session -t` joins the group of the
+// session it names instead of starting its own, each member picks its own active window while the windows
+// themselves, their names and their panes are shared. the group passes what the windows report on to every
+// member and tells them whenever the window list changes, it stops with its windows once the last member
+// has left. each window is sized to the smallest client of the members viewing it, and their panes stop
+// reading while any member's clients are behind on output and send their raw output on while any member has
+// a control mode client
+
+use std::{
+    collections::{HashMap, HashSet},
+    future::Future,
+};
+
+use bytes::Bytes;
+use handle_macro::Handle;
+use itertools::Itertools;
+use remux_core::{events::MessageLevel, states::Alert};
+use tokio::sync::{mpsc, oneshot};
+use tracing::{Instrument, Span};
+
+use crate::{
+    actors::{
+        message_span,
+        pane::PaneHandle,
+        pty::ExitStatus,
+        session::{SessionHandle, SessionHandleError},
+        window::{FirstPane, PaneIds, Window, WindowHandle},
+    },
+    environment::SessionEnvironment,
+    layout::SplitDirection,
+    options::OptionsReceiver,
+    prelude::*,
+    supervision::{Supervisor, Verdict, wait_for_children},
+};
+
+#[allow(unused)]
+#[derive(Handle, Debug)]
+#[handle(spawn, try_send, error = Error)]
+pub enum WindowGroupEvent {
+    // member sessions
+    Join {
+        session_id: u32,
+        session: SessionHandle,
+    }, // the session is sent the window list straight away
+    #[reply(bool)]
+    Leave {
+        session_id: u32,
+        reply: oneshot::Sender<bool>,
+    }, // true when it was the last member and the group is stopping
+    NewWindow {
+        name: Option<String>, // named after the shell when not given
+        session_id: u32,      // the session that switches to it
+    },
+    RenameWindow {
+        window_id: usize,
+        name: String,
+    },
+    TerminalResize {
+        session_id: u32,
+        size: Option<(u16, u16)>, // (rows, cols) of the member's smallest client, `None` once it has none
+    },
+    ViewWindow {
+        session_id: u32,
+        window_id: usize,
+    }, // the member switched to the window
+    Throttle {
+        session_id: u32,
+        behind: bool,
+    }, // a member's clients fell behind or caught up, the panes don't read their ptys while any member is behind
+    ForwardRawOutput {
+        session_id: u32,
+        forward: bool,
+    }, // a member gained its first or lost its last control mode client
+
+    // windows
+    WindowPanes {
+        window_id: usize,
+        panes: usize,
+        active_pane: usize,
+        command: Option<String>, // the focused pane's foreground program, once it's known
+    }, // a window's pane count, focused pane or what runs in it changed
+    PaneReleased {
+        pane_id: usize,
+        pane: PaneHandle,
+        destination: PaneDestination,
+    }, // a window let go of a pane for `break-pane`/`join-pane`, it's handed on from here
+    WindowOutput {
+        window_id: usize,
+        bytes: Bytes,
+    },
+    PaneOutput {
+        pane_id: usize,
+        bytes: Bytes,
+    },
+    PaneExited {
+        pane_id: usize,
+        status: ExitStatus,
+    },
+    PaneCopied {
+        text: String,
+    },
+    PaneAlert {
+        alert: Alert,
+    },
+    PaneMarked(Option<usize>),
+    WindowBell {
+        window_id: usize,
+    },
+    WindowFailed {
+        window_id: usize,
+        error: String,
+    }, // the window gave up after its handlers kept failing and has stopped
+    Notify(String, MessageLevel),
+}
+use WindowGroupEvent::*;
+
+/// where a pane moved by `break-pane`/`join-pane` goes once its window has let go of it, `session_id` is the
+/// session that moved it
+#[derive(Debug, Clone)]
+pub enum PaneDestination {
+    NewWindow {
+        name: Option<String>,
+        detached: bool,
+        session_id: u32,
+    },
+    Window {
+        window_id: usize,
+        direction: SplitDirection,
+        session_id: u32,
+    },
+}
+
+/// a window as its sessions see it, which of them is active is up to each session
+#[derive(Debug, Clone)]
+pub struct GroupWindow {
+    pub id: usize,
+    pub name: String,
+    pub handle: WindowHandle,
+    pub panes: usize,
+    pub active_pane: usize,
+    automatic_rename: bool, // follows its focused pane's program until it's given a name
+}
+
+impl GroupWindow {
+    fn new(id: usize, name: String, handle: WindowHandle, automatic_rename: bool) -> Self {
+        Self {
+            id,
+            name,
+            handle,
+            panes: 0,
+            active_pane: 0,
+            automatic_rename,
+        }
+    }
+}
+
+pub struct WindowGroup {
+    handle: WindowGroupHandle,
+    rx: mpsc::Receiver<(WindowGroupEvent, Span)>,
+    supervisor: Supervisor,
+    members: Vec<(u32, SessionHandle)>, // in the order they joined, the first one speaks for the group
+    windows: Vec<GroupWindow>,          // never empty
+    next_window_id: usize,
+    pane_ids: PaneIds,
+    // handed to every window spawned in this group
+    options: OptionsReceiver,
+    environment: SessionEnvironment,          // of the session that started the group
+    size: Option<(u16, u16)>,                 // the last terminal size, new windows start at it
+    member_sizes: HashMap<u32, (u16, u16)>,   // of the members with clients
+    member_windows: HashMap<u32, usize>,      // the window each member views
+    window_sizes: HashMap<usize, (u16, u16)>, // what each window was last sized to
+    member_views: HashMap<u32, (u16, u16)>,   // the window size each member was last told
+    behind_members: HashSet<u32>,             // sessions whose clients can't keep up with the output
+    raw_output_members: HashSet<u32>,         // sessions with a control mode client
+    window_task: Option<DaemonTask>,          // telling the windows the last pause or raw output change
+}
+impl WindowGroup {
+    #[instrument(parent=None, skip(options, environment), name="WindowGroup")]
+    pub fn spawn(
+        options: OptionsReceiver,
+        environment: SessionEnvironment,
+        command: Option<Vec<String>>, // `remux exec` sessions run a command instead of a shell
+        raw_output: bool,             // and their client wants the raw output of the command from the start
+    ) -> Result<WindowGroupHandle> {
+        let mut group = WindowGroup::new(options, environment, command)?;
+        if raw_output {
+            group.raw_output_members.insert(group.environment.session_id);
+        }
+        // a new pane sends its raw output on until its window says whether anyone wants it
+        group.tell_windows(move |window| async move { window.forward_raw_output(raw_output).await });
+        group.run()
+    }
+    fn new(options: OptionsReceiver, environment: SessionEnvironment, command: Option<Vec<String>>) -> Result<Self> {
+        let (handle, rx) = WindowGroupHandle::spawn(10);
+        let pane_ids = PaneIds::default();
+        let window_name = window_name(command.as_deref(), &options);
+        let window_handle = Window::spawn(
+            0,
+            handle.clone(),
+            options.clone(),
+            environment.clone(),
+            pane_ids.clone(),
+            FirstPane::Spawn(command),
+            None,
+        )?;
+        Ok(Self {
+            handle,
+            rx,
+            supervisor: Supervisor::default(),
+            members: Vec::new(),
+            windows: vec![GroupWindow::new(0, window_name, window_handle, true)],
+            next_window_id: 1,
+            pane_ids,
+            options,
+            environment,
+            size: None,
+            member_sizes: HashMap::new(),
+            member_windows: HashMap::new(),
+            window_sizes: HashMap::new(),
+            member_views: HashMap::new(),
+            behind_members: HashSet::new(),
+            raw_output_members: HashSet::new(),
+            window_task: None,
+        })
+    }
+    fn run(mut self) -> Result<WindowGroupHandle> {
+        let handle_clone = self.handle.clone();
+        let _task = tokio::spawn(
+            async move {
+                while let Some((event, cause)) = self.rx.recv().await {
+                    match &event {
+                        WindowOutput { .. } | PaneOutput { .. } | PaneAlert { .. } => {
+                            trace!(event=?event);
+                        }
+                        _ => {
+                            info!(event=?event);
+                        }
+                    }
+                    match self.handle_event(event).instrument(message_span(&cause)).await {
+                        Ok(true) => {}
+                        Ok(false) => break,
+                        Err(e) => match self.supervisor.check(&e, SessionHandleError::Closed) {
+                            Verdict::Continue => self.notify(e.to_string(), MessageLevel::Error).await,
+                            Verdict::ParentGone => break,
+                            Verdict::GiveUp => {
+                                for (_, session) in &self.members {
+                                    let _ = session.group_failed(e.to_string()).await;
+                                }
+                                break;
+                            }
+                        },
+                    }
+                }
+                self.stop().await;
+            }
+            .in_current_span(),
+        );
+
+        Ok(handle_clone)
+    }
+
+    /// returns false once the group should stop. a member that can't be reached is stopping and about to
+    /// leave, so what's passed on to the members is only best effort
+    async fn handle_event(&mut self, event: WindowGroupEvent) -> Result<bool> {
+        match event {
+            Join { session_id, session } => {
+                let _ = session.group_windows(self.windows.clone(), None).await;
+                self.members.push((session_id, session));
+            }
+            Leave { session_id, reply } => {
+                self.members.retain(|(id, _)| *id != session_id);
+                let _ = reply.send(self.members.is_empty());
+                if self.members.is_empty() {
+                    return Ok(false);
+                }
+                self.throttle(session_id, false);
+                self.forward_raw_output(session_id, false);
+                self.member_sizes.remove(&session_id);
+                self.member_windows.remove(&session_id);
+                self.member_views.remove(&session_id);
+                self.resize_windows().await?;
+            }
+            NewWindow { name, session_id } => {
+                self.add_window(name, FirstPane::Spawn(None), Some(session_id)).await?;
+            }
+            RenameWindow { window_id, name } => {
+                if let Some(index) = self.windows.iter().position(|window| window.id == window_id) {
+                    let window = &mut self.windows[index];
+                    window.name = name;
+                    window.automatic_rename = false;
+                    self.notify(format!("window {index} renamed"), MessageLevel::Info).await;
+                }
+                self.send_windows(None).await;
+            }
+            TerminalResize { session_id, size } => {
+                match size {
+                    Some(size) => {
+                        self.size = Some(size);
+                        self.member_sizes.insert(session_id, size);
+                    }
+                    None => {
+                        self.member_sizes.remove(&session_id);
+                    }
+                }
+                self.resize_windows().await?;
+            }
+            ViewWindow { session_id, window_id } => {
+                self.member_windows.insert(session_id, window_id);
+                self.resize_windows().await?;
+            }
+            Throttle { session_id, behind } => {
+                self.throttle(session_id, behind);
+            }
+            ForwardRawOutput { session_id, forward } => {
+                self.forward_raw_output(session_id, forward);
+            }
+            WindowPanes {
+                window_id,
+                panes,
+                active_pane,
+                command,
+            } => {
+                let automatic_rename = self.options.borrow().automatic_rename;
+                if let Some(window) = self.windows.iter_mut().find(|window| window.id == window_id) {
+                    window.panes = panes;
+                    window.active_pane = active_pane;
+                    if let Some(command) = command
+                        && automatic_rename
+                        && window.automatic_rename
+                    {
+                        window.name = command;
+                    }
+                }
+                self.send_windows(None).await;
+            }
+            PaneReleased {
+                pane_id,
+                pane,
+                destination,
+            } => {
+                self.handle_pane_released(pane_id, pane, destination).await?;
+            }
+            WindowOutput { window_id, bytes } => {
+                for (_, session) in &self.members {
+                    let _ = session.window_output(window_id, bytes.clone()).await;
+                }
+            }
+            PaneOutput { pane_id, bytes } => {
+                for (_, session) in &self.members {
+                    let _ = session.pane_output(pane_id, bytes.clone()).await;
+                }
+            }
+            PaneExited { pane_id, status } => {
+                for (_, session) in &self.members {
+                    let _ = session.pane_exited(pane_id, status).await;
+                }
+            }
+            PaneCopied { text } => {
+                // one paste buffer however many sessions share the pane
+                if let Some((_, session)) = self.members.first() {
+                    let _ = session.pane_copied(text).await;
+                }
+            }
+            PaneAlert { alert } => {
+                for (_, session) in &self.members {
+                    let _ = session.pane_alert(alert).await;
+                }
+            }
+            PaneMarked(pane_id) => {
+                // there's only one marked pane, it's kept against the first member
+                if let Some((_, session)) = self.members.first() {
+                    let _ = session.pane_marked(pane_id).await;
+                }
+            }
+            WindowBell { window_id } => {
+                for (_, session) in &self.members {
+                    let _ = session.window_bell(window_id).await;
+                }
+            }
+            WindowFailed { window_id, error } => {
+                return Ok(self.handle_window_failed(window_id, error).await);
+            }
+            Notify(message, level) => {
+                self.notify(message, level).await;
+            }
+        }
+        Ok(true)
+    }
+
+    /// the windows go with the group however it stopped
+    /// done once every window has stopped
+    async fn stop(&mut self) {
+        let windows = &self.windows;
+        wait_for_children(&mut self.rx, async {
+            for window in windows {
+                let _ = window.handle.kill().await;
+            }
+            for window in windows {
+                window.handle.closed().await;
+            }
+        })
+        .await;
+    }
+
+    async fn notify(&self, message: String, level: MessageLevel) {
+        for (_, session) in &self.members {
+            let _ = session.notify(message.clone(), level).await;
+        }
+    }
+
+    /// every member hears of a change to the window list, `select` is a (session id, window id) for the one
+    /// member that switches to a new window
+    async fn send_windows(&self, select: Option<(u32, usize)>) {
+        for (session_id, session) in &self.members {
+            let select = select.and_then(|(id, window_id)| (id == *session_id).then_some(window_id));
+            let _ = session.group_windows(self.windows.clone(), select).await;
+        }
+    }
+
+    /// the failed window is dropped and each member picks another if it was theirs, losing the last window
+    /// takes every member down with it. returns false once the group should stop
+    async fn handle_window_failed(&mut self, window_id: usize, error: String) -> bool {
+        let Some(index) = self.windows.iter().position(|window| window.id == window_id) else {
+            return true;
+        };
+        self.windows.remove(index);
+        self.window_sizes.remove(&window_id);
+        if self.windows.is_empty() {
+            for (_, session) in &self.members {
+                let _ = session.group_failed(error.clone()).await;
+            }
+            return false;
+        }
+        self.notify(format!("window {index} failed: {error}"), MessageLevel::Error)
+            .await;
+        self.send_windows(None).await;
+        true
+    }
+
+    /// each window viewed by a member with clients is sized to the smallest of them, the others keep their
+    /// size. every member hears what its window was sized to so its clients can pad out the rest
+    async fn resize_windows(&mut self) -> Result<()> {
+        let sizes = window_sizes(&self.member_windows, &self.member_sizes);
+        for window in &self.windows {
+            if let Some(&(rows, cols)) = sizes.get(&window.id)
+                && self.window_sizes.insert(window.id, (rows, cols)) != Some((rows, cols))
+            {
+                window.handle.terminal_resize(rows, cols).await?;
+            }
+        }
+        for (session_id, session) in &self.members {
+            let Some(&(rows, cols)) = self
+                .member_windows
+                .get(session_id)
+                .and_then(|window_id| self.window_sizes.get(window_id))
+            else {
+                continue;
+            };
+            if self.member_views.insert(*session_id, (rows, cols)) != Some((rows, cols)) {
+                let _ = session.window_size(rows, cols).await;
+            }
+        }
+        Ok(())
+    }
+
+    /// the windows are only told when the group as a whole starts or stops being behind
+    fn throttle(&mut self, session_id: u32, behind: bool) {
+        let was_paused = !self.behind_members.is_empty();
+        if behind {
+            self.behind_members.insert(session_id);
+        } else {
+            self.behind_members.remove(&session_id);
+        }
+        let paused = !self.behind_members.is_empty();
+        if paused == was_paused {
+            return;
+        }
+        debug!(paused, "pausing pane output");
+        self.tell_windows(move |window| async move { window.pause_output(paused).await });
+    }
+
+    fn forward_raw_output(&mut self, session_id: u32, forward: bool) {
+        let was_forwarding = !self.raw_output_members.is_empty();
+        if forward {
+            self.raw_output_members.insert(session_id);
+        } else {
+            self.raw_output_members.remove(&session_id);
+        }
+        let forwarding = !self.raw_output_members.is_empty();
+        if forwarding == was_forwarding {
+            return;
+        }
+        debug!(forwarding, "forwarding raw pane output");
+        self.tell_windows(move |window| async move { window.forward_raw_output(forwarding).await });
+    }
+
+    fn tell_windows<F, Fut>(&mut self, send: F)
+    where
+        F: Fn(WindowHandle) -> Fut + Send + 'static,
+        Fut: Future + Send,
+    {
+        let windows: Vec<WindowHandle> = self.windows.iter().map(|window| window.handle.clone()).collect();
+        let previous = self.window_task.take();
+        // off the event loop, a flooded window may be waiting on the group to take its output
+        self.window_task = Some(tokio::spawn(
+            async move {
+                // a change can't overtake the one before it
+                if let Some(previous) = previous {
+                    let _ = previous.await;
+                }
+                for window in windows {
+                    let _ = send(window).await;
+                }
+                Ok(())
+            }
+            .in_current_span(),
+        ));
+    }
+
+    /// opens a window at the end of the list, the `select` session switches to it
+    async fn add_window(&mut self, name: Option<String>, first_pane: FirstPane, select: Option<u32>) -> Result<()> {
+        let id = self.next_window_id;
+        self.next_window_id += 1;
+        let handle = Window::spawn(
+            id,
+            self.handle.clone(),
+            self.options.clone(),
+            self.environment.clone(),
+            self.pane_ids.clone(),
+            first_pane,
+            self.size,
+        )?;
+        let automatic_rename = name.is_none();
+        let name = name.unwrap_or_else(|| window_name(None, &self.options));
+        if !self.behind_members.is_empty() {
+            handle.pause_output(true).await?;
+        }
+        handle.forward_raw_output(!self.raw_output_members.is_empty()).await?;
+        self.windows.push(GroupWindow::new(id, name, handle, automatic_rename));
+        self.send_windows(select.map(|session_id| (session_id, id))).await;
+        Ok(())
+    }
+
+    async fn handle_pane_released(
+        &mut self,
+        pane_id: usize,
+        pane: PaneHandle,
+        destination: PaneDestination,
+    ) -> Result<()> {
+        let (window_id, direction, session_id) = match destination {
+            PaneDestination::NewWindow {
+                name,
+                detached,
+                session_id,
+            } => {
+                let select = (!detached).then_some(session_id);
+                return self.add_window(name, FirstPane::Adopt(pane_id, pane), select).await;
+            }
+            PaneDestination::Window {
+                window_id,
+                direction,
+                session_id,
+            } => (window_id, direction, session_id),
+        };
+        match self.windows.iter().find(|window| window.id == window_id) {
+            Some(window) => window.handle.adopt_pane(pane_id, pane, direction).await,
+            // the target window closed while the pane was on its way, it gets a window of its own
+            None => {
+                self.add_window(None, FirstPane::Adopt(pane_id, pane), Some(session_id))
+                    .await
+            }
+        }
+    }
+}
+
+/// the smallest of the sizes in each direction, `None` if there are none
+pub fn smallest_size(sizes: impl IntoIterator<Item = (u16, u16)>) -> Option<(u16, u16)> {
+    sizes
+        .into_iter()
+        .reduce(|(rows, cols), (other_rows, other_cols)| (rows.min(other_rows), cols.min(other_cols)))
+}
+
+/// the size of every window viewed by a member with clients, fitting each of those members
+fn window_sizes(
+    member_windows: &HashMap<u32, usize>,
+    member_sizes: &HashMap<u32, (u16, u16)>,
+) -> HashMap<usize, (u16, u16)> {
+    member_windows
+        .iter()
+        .filter_map(|(session_id, window_id)| Some((*window_id, *member_sizes.get(session_id)?)))
+        .into_group_map()
+        .into_iter()
+        .filter_map(|(window_id, sizes)| Some((window_id, smallest_size(sizes)?)))
+        .collect()
+}
+
+/// windows are named after what runs in their first pane
+fn window_name(command: Option<&[String]>, options: &OptionsReceiver) -> String {
+    let program = match command.and_then(<[String]>::first) {
+        Some(program) => program.clone(),
+        None => options.borrow().shell(),
+    };
+    program.rsplit('/').next().unwrap_or_default().to_owned()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_window_sizes() {
+        // the clients of two member sessions, sized as the session manager sees them
+        let member_sizes = HashMap::from([
+            (1, smallest_size([(24, 80), (40, 120)]).unwrap()),
+            (2, smallest_size([(50, 200), (30, 100)]).unwrap()),
+        ]);
+        assert_eq!(member_sizes[&1], (24, 80));
+        assert_eq!(member_sizes[&2], (30, 100));
+
+        // both view window 0, it fits the smallest client of either
+        let mut member_windows = HashMap::from([(1, 0), (2, 0)]);
+        assert_eq!(
+            window_sizes(&member_windows, &member_sizes),
+            HashMap::from([(0, (24, 80))])
+        );
+
+        // once the second switches away each window fits its own viewers
+        member_windows.insert(2, 1);
+        assert_eq!(
+            window_sizes(&member_windows, &member_sizes),
+            HashMap::from([(0, (24, 80)), (1, (30, 100))])
+        );
+
+        // a member without clients doesn't hold the window it views back
+        member_windows.insert(3, 1);
+        assert_eq!(window_sizes(&member_windows, &member_sizes)[&1], (30, 100));
+        assert_eq!(smallest_size([]), None);
+    }
+}
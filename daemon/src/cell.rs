@@ -0,0 +1,408 @@
+// the cells of a pane's screen as last sent to clients, so the next frame only has to send what changed
+
+use std::fmt::Write;
+
+use crate::layout::Rect;
+
+/// a cell's text and attributes, wide characters take up their cell and a continuation cell after it. the text
+/// is a whole character with any combining marks, a grapheme cluster vt100 splits over several cells (ZWJ
+/// sequences, skin tones, flags) has its later cells marked as joining the one before
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RemuxCell {
+    contents: String,
+    fg: vt100::Color,
+    bg: vt100::Color,
+    bold: bool,
+    italic: bool,
+    underline: bool,
+    inverse: bool,
+    wide: bool,
+    wide_continuation: bool,
+    joins_previous: bool,
+}
+
+impl RemuxCell {
+    fn blank() -> Self {
+        Self {
+            contents: String::new(),
+            fg: vt100::Color::Default,
+            bg: vt100::Color::Default,
+            bold: false,
+            italic: false,
+            underline: false,
+            inverse: false,
+            wide: false,
+            wide_continuation: false,
+            joins_previous: false,
+        }
+    }
+
+    fn from_vt(cell: &vt100::Cell) -> Self {
+        Self {
+            contents: cell.contents().to_owned(),
+            fg: cell.fgcolor(),
+            bg: cell.bgcolor(),
+            bold: cell.bold(),
+            italic: cell.italic(),
+            underline: cell.underline(),
+            inverse: cell.inverse(),
+            wide: cell.is_wide(),
+            wide_continuation: cell.is_wide_continuation(),
+            joins_previous: false,
+        }
+    }
+
+    fn same_attrs(&self, other: &Self) -> bool {
+        (self.fg, self.bg, self.bold, self.italic, self.underline, self.inverse)
+            == (
+                other.fg,
+                other.bg,
+                other.bold,
+                other.italic,
+                other.underline,
+                other.inverse,
+            )
+    }
+
+    /// the SGR sequence for the cell's attributes, starting from a reset
+    fn sgr(&self) -> String {
+        let mut sgr = String::from("\x1b[0");
+        for (on, code) in [(self.bold, 1), (self.italic, 3), (self.underline, 4), (self.inverse, 7)] {
+            if on {
+                let _ = write!(sgr, ";{code}");
+            }
+        }
+        push_color(&mut sgr, self.fg, 30, 90, 38);
+        push_color(&mut sgr, self.bg, 40, 100, 48);
+        sgr.push('m');
+        sgr
+    }
+}
+
+const ZERO_WIDTH_JOINER: char = '\u{200d}';
+const SKIN_TONES: std::ops::RangeInclusive<char> = '\u{1f3fb}'..='\u{1f3ff}';
+const REGIONAL_INDICATORS: std::ops::RangeInclusive<char> = '\u{1f1e6}'..='\u{1f1ff}';
+
+/// marks the cells that carry on the grapheme cluster of the cell before them
+fn join_clusters(row: &mut [RemuxCell]) {
+    let mut prev: Option<usize> = None;
+    let mut flag_started = false; // regional indicators pair up into flags
+    for x in 0..row.len() {
+        if row[x].wide_continuation {
+            continue;
+        }
+        let first = row[x].contents.chars().next();
+        let joins = match (prev, first) {
+            (Some(prev), Some(c)) => {
+                row[prev].contents.ends_with(ZERO_WIDTH_JOINER)
+                    || SKIN_TONES.contains(&c)
+                    || (flag_started && REGIONAL_INDICATORS.contains(&c))
+            }
+            _ => false,
+        };
+        flag_started = !flag_started && !joins && first.is_some_and(|c| REGIONAL_INDICATORS.contains(&c));
+        row[x].joins_previous = joins;
+        prev = Some(x);
+    }
+}
+
+fn push_color(sgr: &mut String, color: vt100::Color, base: u8, bright: u8, extended: u8) {
+    let _ = match color {
+        vt100::Color::Default => Ok(()),
+        vt100::Color::Idx(i) if i < 8 => write!(sgr, ";{}", base + i),
+        vt100::Color::Idx(i) if i < 16 => write!(sgr, ";{}", bright + i - 8),
+        vt100::Color::Idx(i) => write!(sgr, ";{extended};5;{i}"),
+        vt100::Color::Rgb(r, g, b) => write!(sgr, ";{extended};2;{r};{g};{b}"),
+    };
+}
+
+/// a pane's visible screen, row by row
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CellGrid {
+    width: u16,
+    rows: Vec<Vec<RemuxCell>>,
+}
+
+impl CellGrid {
+    pub fn from_screen(screen: &vt100::Screen, width: u16, height: u16) -> Self {
+        let rows = (0..height)
+            .map(|row| {
+                (0..width)
+                    .map(|col| screen.cell(row, col).map_or_else(RemuxCell::blank, RemuxCell::from_vt))
+                    .collect::<Vec<_>>()
+            })
+            .map(|mut row| {
+                join_clusters(&mut row);
+                row
+            })
+            .collect();
+        Self { width, rows }
+    }
+
+    /// draws the cells that differ from `prev` into `rect`, `None` when the grids aren't the same size
+    /// and the whole screen has to be drawn instead
+    pub fn render_diff(&self, prev: &CellGrid, rect: Rect) -> Option<Vec<u8>> {
+        if self.width != prev.width || self.rows.len() != prev.rows.len() {
+            return None;
+        }
+        Some(self.render_cells(Some(prev), rect))
+    }
+
+    /// draws every cell into `rect`, whatever attributes the terminal was left with
+    pub fn render(&self, rect: Rect) -> Vec<u8> {
+        self.render_cells(None, rect)
+    }
+
+    /// every run of cells starts from a reset, attributes never carry over from other panes' output
+    fn render_cells(&self, prev: Option<&CellGrid>, rect: Rect) -> Vec<u8> {
+        let mut output = String::new();
+        let mut cursor = None; // where the next written cell lands without moving the cursor
+        let mut attrs: Option<&RemuxCell> = None;
+        for (y, row) in self.rows.iter().enumerate() {
+            let prev_row = prev.map(|prev| &prev.rows[y]);
+            let mut changed: Vec<bool> = (0..row.len())
+                .map(|x| {
+                    prev_row.is_none_or(|prev_row| {
+                        row[x] != prev_row[x] || (row[x].wide && row.get(x + 1) != prev_row.get(x + 1))
+                    })
+                })
+                .collect();
+            // a grapheme cluster is drawn whole or the terminal would only see part of it
+            let mut start = 0;
+            for x in 1..=row.len() {
+                if x < row.len() && (row[x].joins_previous || row[x].wide_continuation) {
+                    continue;
+                }
+                if changed[start..x].contains(&true) {
+                    changed[start..x].fill(true);
+                }
+                start = x;
+            }
+            for (x, cell) in row.iter().enumerate() {
+                // the wide character before it draws both cells
+                if cell.wide_continuation || !changed[x] {
+                    continue;
+                }
+                if cursor != Some((x, y)) {
+                    let _ = write!(output, "\x1b[{};{}H", rect.y as usize + y + 1, rect.x as usize + x + 1);
+                }
+                if attrs.is_none_or(|attrs| !attrs.same_attrs(cell)) {
+                    output.push_str(&cell.sgr());
+                    attrs = Some(cell);
+                }
+                // a wide character cut off by the right edge would spill into the neighbouring pane
+                let wide = cell.wide && x + 1 < row.len();
+                if cell.contents.is_empty() || (cell.wide && !wide) {
+                    output.push(' ');
+                } else {
+                    output.push_str(&cell.contents);
+                }
+                cursor = Some((x + if wide { 2 } else { 1 }, y));
+                // the terminal may draw a cluster narrower than the cells vt100 gave it, so what follows is
+                // placed explicitly
+                if cell.joins_previous
+                    && row
+                        .get(x + 1 + usize::from(wide))
+                        .is_none_or(|next| !next.joins_previous)
+                {
+                    cursor = None;
+                }
+            }
+        }
+        if attrs.is_some() {
+            output.push_str("\x1b[0m");
+        }
+        output.into_bytes()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    #![allow(clippy::unwrap_used)]
+    use super::*;
+
+    #[test]
+    fn test_render_diff() {
+        let rect = Rect {
+            x: 10,
+            y: 5,
+            width: 10,
+            height: 2,
+        };
+        let mut vte = vt100::Parser::new(2, 10, 0);
+        vte.process(b"hello");
+        let prev = CellGrid::from_screen(vte.screen(), 10, 2);
+        assert_eq!(prev.render_diff(&prev, rect).unwrap(), b"");
+
+        vte.process(b"\r\nw\x1b[1;31mo\x1b[0mrld\x1b[1;2Ha");
+        let next = CellGrid::from_screen(vte.screen(), 10, 2);
+        assert_eq!(
+            String::from_utf8(next.render_diff(&prev, rect).unwrap()).unwrap(),
+            "\x1b[6;12H\x1b[0ma\x1b[7;11Hw\x1b[0;1;31mo\x1b[0mrld\x1b[0m"
+        );
+
+        let smaller = CellGrid::from_screen(vte.screen(), 5, 2);
+        assert!(smaller.render_diff(&prev, rect).is_none());
+    }
+
+    /// blank cells are drawn as spaces, which look the same
+    fn normalized(mut grid: CellGrid) -> CellGrid {
+        for cell in grid.rows.iter_mut().flatten() {
+            if cell.contents.is_empty() && !cell.wide_continuation {
+                cell.contents.push(' ');
+            }
+        }
+        grid
+    }
+
+    /// what a client's terminal shows after the output, starting from attributes another pane left behind
+    fn replay(outputs: &[&[u8]], rows: u16, cols: u16) -> CellGrid {
+        let mut terminal = vt100::Parser::new(rows, cols, 0);
+        for output in outputs {
+            terminal.process(b"\x1b[1;3;4;7;38;2;1;2;3;48;5;200m");
+            terminal.process(output);
+        }
+        normalized(CellGrid::from_screen(terminal.screen(), cols, rows))
+    }
+
+    #[test]
+    fn test_sgr_fidelity() {
+        let rect = Rect {
+            x: 0,
+            y: 0,
+            width: 12,
+            height: 3,
+        };
+        let mut vte = vt100::Parser::new(3, 12, 0);
+        vte.process(b"\x1b[38;2;255;128;0mtrue\x1b[0m \x1b[1;3mbi\x1b[22mi\x1b[0m\x1b[4;7mur\x1b[0m");
+        vte.process(b"\r\n\x1b[38;5;196;48;5;21m256\x1b[0m\x1b[94;101mbright\x1b[0m\x1b[42m  ");
+        vte.process("\r\n\x1b[0m\x1b[31m\u{4e2d}\x1b[0m \x1b[1;3;4;7;38;2;9;9;9;48;2;1;1;1mall".as_bytes());
+        let cells = CellGrid::from_screen(vte.screen(), 12, 3);
+        assert_eq!(replay(&[&cells.render(rect)], 3, 12), normalized(cells.clone()));
+
+        // a diff drawn over the previous frame ends up at the same screen
+        let prev = cells.clone();
+        vte.process(b"\x1b[1;1H\x1b[0;3;48;2;10;20;30mT\x1b[2;4H\x1b[0m\x1b[7mB");
+        let next = CellGrid::from_screen(vte.screen(), 12, 3);
+        let diff = next.render_diff(&prev, rect).unwrap();
+        assert_eq!(replay(&[&prev.render(rect), &diff], 3, 12), normalized(next));
+    }
+
+    #[test]
+    fn test_wide_char_at_edge() {
+        let rect = Rect {
+            x: 0,
+            y: 0,
+            width: 3,
+            height: 1,
+        };
+        let mut vte = vt100::Parser::new(1, 4, 0);
+        vte.process("ab\u{4e2d}".as_bytes());
+        // the pane is narrower than the screen the character was written to
+        let cells = CellGrid::from_screen(vte.screen(), 3, 1);
+        assert_eq!(
+            String::from_utf8(cells.render(rect)).unwrap(),
+            "\x1b[1;1H\x1b[0mab \x1b[0m"
+        );
+    }
+
+    #[test]
+    fn test_wide_chars_at_pane_boundary() {
+        let left = Rect {
+            x: 0,
+            y: 0,
+            width: 4,
+            height: 3,
+        };
+        let right = Rect { x: 5, ..left };
+        let frames = [
+            "ab\u{4e2d}\r\nwxyz\r\n\u{1f600}\u{1f600}",
+            "\u{6587}\u{5b57}\r\nw\u{4e2d}z\r\nabcd",
+            "a\u{1f600}b\r\nwxyz\r\n\u{4e2d}\u{1f600}",
+        ];
+        let grid = |frame: &str| {
+            let mut vte = vt100::Parser::new(3, 4, 0);
+            vte.process(frame.as_bytes());
+            CellGrid::from_screen(vte.screen(), 4, 3)
+        };
+
+        let border = b"\x1b[1;5H|\x1b[2;5H|\x1b[3;5H|";
+        let mut outputs: Vec<Vec<u8>> = vec![border.to_vec()];
+        let (mut prev_left, mut prev_right) = (grid(""), grid(""));
+        for (i, frame) in frames.iter().enumerate() {
+            // the panes go narrow to wide and back out of step with each other
+            let (next_left, next_right) = (grid(frame), grid(frames[(i + 1) % frames.len()]));
+            outputs.push(next_left.render_diff(&prev_left, left).unwrap());
+            outputs.push(next_right.render_diff(&prev_right, right).unwrap());
+            let outputs: Vec<&[u8]> = outputs.iter().map(Vec::as_slice).collect();
+            let terminal = replay(&outputs, 3, 9);
+            for (y, row) in terminal.rows.iter().enumerate() {
+                assert_eq!(row[..4], normalized(next_left.clone()).rows[y][..], "frame {i} row {y}");
+                assert_eq!(row[4].contents, "|", "frame {i} row {y}");
+                assert_eq!(
+                    row[5..],
+                    normalized(next_right.clone()).rows[y][..],
+                    "frame {i} row {y}"
+                );
+            }
+            (prev_left, prev_right) = (next_left, next_right);
+        }
+    }
+
+    #[test]
+    fn test_grapheme_clusters() {
+        let rect = Rect {
+            x: 0,
+            y: 0,
+            width: 12,
+            height: 3,
+        };
+        let mut vte = vt100::Parser::new(3, 12, 0);
+        // a combining accent, a ZWJ family over three wide cells, a flag and a lone regional indicator
+        vte.process("e\u{301} \u{1f468}\u{200d}\u{1f469}\u{200d}\u{1f467}\u{1f1fa}\u{1f1f8}\u{1f1e9}!".as_bytes());
+        let prev = CellGrid::from_screen(vte.screen(), 12, 3);
+        let joins: Vec<bool> = prev.rows[0].iter().map(|cell| cell.joins_previous).collect();
+        assert_eq!(
+            joins,
+            [
+                false, false, false, false, true, false, true, false, false, true, false, false
+            ]
+        );
+        assert_eq!(replay(&[&prev.render(rect)], 3, 12), normalized(prev.clone()));
+
+        vte.process("\x1b[1;1He\u{300}\x1b[1;5H\u{1f466}\u{200d}\x1b[1;10H\u{1f1e6}".as_bytes());
+        let next = CellGrid::from_screen(vte.screen(), 12, 3);
+        let diff = next.render_diff(&prev, rect).unwrap();
+        // only one part of the family and of the flag changed, both are drawn whole
+        assert_eq!(
+            String::from_utf8(diff.clone()).unwrap(),
+            "\x1b[1;1H\x1b[0me\u{300}\x1b[1;3H\u{1f468}\u{200d}\u{1f466}\u{200d}\u{1f467}\x1b[1;9H\u{1f1fa}\u{1f1e6}\x1b[0m"
+        );
+        assert_eq!(replay(&[&prev.render(rect), &diff], 3, 12), normalized(next));
+    }
+
+    #[test]
+    fn test_alternate_screen() {
+        let rect = Rect {
+            x: 4,
+            y: 2,
+            width: 6,
+            height: 2,
+        };
+        let mut vte = vt100::Parser::new(2, 6, 0);
+        vte.process(b"$ vim");
+        let shell = CellGrid::from_screen(vte.screen(), 6, 2);
+
+        vte.process(b"\x1b[?1049h\x1b[H~");
+        let vim = CellGrid::from_screen(vte.screen(), 6, 2);
+        let output = String::from_utf8(vim.render_diff(&shell, rect).unwrap()).unwrap();
+        // only the pane's cells are drawn, the switch itself isn't passed on
+        assert_eq!(output, "\x1b[3;5H\x1b[0m~    \x1b[0m");
+
+        vte.process(b"\x1b[?1049l");
+        let restored = CellGrid::from_screen(vte.screen(), 6, 2);
+        assert_eq!(restored, shell);
+        let output = String::from_utf8(restored.render_diff(&vim, rect).unwrap()).unwrap();
+        assert_eq!(output, "\x1b[3;5H\x1b[0m$ vim\x1b[0m");
+    }
+}
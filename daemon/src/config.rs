@@ -0,0 +1,274 @@
+use std::{
+    fs,
+    path::{Path, PathBuf},
+};
+
+use color_eyre::eyre::eyre;
+use remux_core::{
+    commands::{OptionScope, RemuxCommand, parse_sequence},
+    daemon_utils::get_config_dir,
+};
+use toml::{Table, Value};
+
+use crate::prelude::*;
+
+// both are read when present, the toml file first so the command file can override it
+const CONFIG_FILES: [&str; 2] = ["remux.toml", "remux.conf"];
+
+/// the config files that exist, in the order they're read
+pub fn config_files() -> Result<Vec<PathBuf>> {
+    let dir = get_config_dir()?;
+    Ok(CONFIG_FILES
+        .iter()
+        .map(|name| dir.join(name))
+        .filter(|path| path.exists())
+        .collect())
+}
+
+/// reads the config files at startup, broken lines are logged and skipped so the daemon still comes up
+pub fn load_config() -> Result<Vec<RemuxCommand>> {
+    let mut commands = vec![];
+    for path in config_files()? {
+        info!(path=?path, "Loading config file");
+        let (parsed, errors) = parse_config(&path, &fs::read_to_string(&path)?);
+        for e in errors {
+            warn!("{e}");
+        }
+        commands.extend(parsed);
+    }
+    Ok(commands)
+}
+
+/// reads a config file for `source-file`, any error rejects the whole file so it isn't half applied
+pub fn read_config(path: &Path) -> Result<Vec<RemuxCommand>> {
+    let text = fs::read_to_string(path).map_err(|e| eyre!("{}: {e}", path.display()))?;
+    match parse_config(path, &text) {
+        (commands, errors) if errors.is_empty() => Ok(commands),
+        (_, errors) => Err(eyre!(errors.join("; "))),
+    }
+}
+
+/// `.toml` files are tables of settings, anything else has one command (or `;` separated sequence) per line
+/// with `#` starting a comment
+fn parse_config(path: &Path, text: &str) -> (Vec<RemuxCommand>, Vec<String>) {
+    if path.extension().is_some_and(|extension| extension == "toml") {
+        return match parse_toml(text) {
+            Ok(commands) => (commands, vec![]),
+            Err(e) => (vec![], vec![format!("{}: {e}", path.display())]),
+        };
+    }
+
+    let mut commands = vec![];
+    let mut errors = vec![];
+    for (i, line) in text.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        match parse_sequence(line) {
+            Ok(parsed) => commands.extend(parsed),
+            Err(e) => errors.push(format!("{}:{}: {e}", path.display(), i + 1)),
+        }
+    }
+    (commands, errors)
+}
+
+/// turns the toml settings into the commands the line format would use:
+///
+/// ```toml
+/// prefix = "C-a"
+/// layout = ["split-window -h"]  # run in every new session, before the session-created hook
+/// commands = ["set-environment -g EDITOR nvim"]
+///
+/// [options]
+/// default-shell = "/bin/zsh"
+/// history-limit = 10000
+/// status = true
+///
+/// [keys]
+/// v = "split-window -h"
+///
+/// [hooks]
+/// client-attached = ["display-panes"]
+/// ```
+fn parse_toml(text: &str) -> Result<Vec<RemuxCommand>> {
+    let mut table: Table = text.parse()?;
+    let mut commands = vec![];
+    let command = |args: &[&str]| -> Result<RemuxCommand> {
+        Ok(RemuxCommand::from_args(
+            &args.iter().map(|arg| arg.to_string()).collect::<Vec<_>>(),
+        )?)
+    };
+
+    if let Some(prefix) = table.remove("prefix") {
+        commands.push(RemuxCommand::SetOption {
+            name: "prefix".to_owned(),
+            value: string(&prefix, "prefix")?,
+            scope: OptionScope::Global,
+        });
+    }
+    for (name, value) in section(&mut table, "options")? {
+        let value = match value {
+            Value::Boolean(true) => "on".to_owned(),
+            Value::Boolean(false) => "off".to_owned(),
+            Value::Integer(n) => n.to_string(),
+            value => string(&value, &name)?,
+        };
+        commands.push(RemuxCommand::SetOption {
+            name,
+            value,
+            scope: OptionScope::Global,
+        });
+    }
+    for (key, value) in section(&mut table, "keys")? {
+        commands.push(command(&["bind-key", &key, &lines(&value, &key)?])?);
+    }
+    let mut hooks = section(&mut table, "hooks")?;
+    if let Some(layout) = table.remove("layout") {
+        let layout = lines(&layout, "layout")?;
+        let hook = match hooks.remove("session-created") {
+            Some(hook) => format!("{layout} ; {}", lines(&hook, "session-created")?),
+            None => layout,
+        };
+        hooks.insert("session-created".to_owned(), Value::String(hook));
+    }
+    for (event, value) in hooks {
+        commands.push(command(&["set-hook", &event, &lines(&value, &event)?])?);
+    }
+    if let Some(extra) = table.remove("commands") {
+        commands.extend(parse_sequence(&lines(&extra, "commands")?)?);
+    }
+
+    if let Some(key) = table.keys().next() {
+        return Err(eyre!("unknown setting: {key}"));
+    }
+    Ok(commands)
+}
+
+fn section(table: &mut Table, name: &str) -> Result<Table> {
+    match table.remove(name) {
+        Some(Value::Table(section)) => Ok(section),
+        Some(_) => Err(eyre!("{name}: expected a table")),
+        None => Ok(Table::new()),
+    }
+}
+
+fn string(value: &Value, name: &str) -> Result<String> {
+    value
+        .as_str()
+        .map(str::to_owned)
+        .ok_or_else(|| eyre!("{name}: expected a string"))
+}
+
+/// a command line, or a list of them run one after another
+fn lines(value: &Value, name: &str) -> Result<String> {
+    match value {
+        Value::Array(values) => Ok(values
+            .iter()
+            .map(|value| string(value, name))
+            .collect::<Result<Vec<_>>>()?
+            .join(" ; ")),
+        value => string(value, name),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    #![allow(clippy::unwrap_used)]
+    use remux_core::{
+        commands::{HookEvent, PaneDirection},
+        key_bindings::KeyAction,
+    };
+
+    use super::*;
+
+    fn set(name: &str, value: &str) -> RemuxCommand {
+        RemuxCommand::SetOption {
+            name: name.to_owned(),
+            value: value.to_owned(),
+            scope: OptionScope::Global,
+        }
+    }
+
+    #[test]
+    fn test_toml_config() {
+        let commands = parse_toml(
+            r#"
+            prefix = "C-a"
+            layout = ["split-window -h", "resize-pane -L 10"]
+            commands = ["kill-session -t scratch"]
+
+            [options]
+            default-shell = "/bin/zsh"
+            history-limit = 10000
+            status = false
+
+            [keys]
+            v = "split-window -h"
+
+            [hooks]
+            session-created = "new-window"
+            "#,
+        )
+        .unwrap();
+        assert_eq!(
+            commands,
+            vec![
+                set("prefix", "C-a"),
+                set("default-shell", "/bin/zsh"),
+                set("history-limit", "10000"),
+                set("status", "off"),
+                RemuxCommand::BindKey {
+                    key: "v".to_owned(),
+                    action: KeyAction::Command("split-window -h".to_owned()),
+                },
+                RemuxCommand::SetHook {
+                    event: HookEvent::SessionCreated,
+                    session_name: None,
+                    commands: vec![
+                        RemuxCommand::SplitWindow {
+                            vertical: true,
+                            environment: vec![],
+                        },
+                        RemuxCommand::ResizePane {
+                            direction: PaneDirection::Left,
+                            amount: 10
+                        },
+                        RemuxCommand::NewWindow { name: None },
+                    ],
+                },
+                RemuxCommand::KillSession {
+                    target: Some("scratch".to_owned())
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn test_toml_errors() {
+        assert!(parse_toml("statusbar = true").is_err());
+        assert!(parse_toml("options = 1").is_err());
+        assert!(parse_toml("[keys]\nv = \"no-such-command\"").is_err());
+    }
+
+    #[test]
+    fn test_line_config() {
+        let (commands, errors) = parse_config(
+            Path::new("remux.conf"),
+            "# comment\nset status off\n\nnot-a-command\nsplit-window -h ; new-window\n",
+        );
+        assert_eq!(
+            commands,
+            vec![
+                set("status", "off"),
+                RemuxCommand::SplitWindow {
+                    vertical: true,
+                    environment: vec![],
+                },
+                RemuxCommand::NewWindow { name: None },
+            ]
+        );
+        assert_eq!(errors.len(), 1);
+        assert!(errors[0].starts_with("remux.conf:4:"));
+    }
+}
@@ -0,0 +1,104 @@
+// line based notifications for control mode clients, modeled after tmux's `-CC` protocol
+
+use std::{
+    fmt::Display,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+pub enum ControlNotification<'a> {
+    Output { pane_id: usize, bytes: &'a [u8] },
+    SessionChanged { session_id: u32, name: &'a str },
+    SessionsChanged,
+    WindowAdd { window_id: u32 },
+    WindowClose { window_id: u32 },
+    Message(&'a str),
+    Done { number: u32, output: &'a str },
+    Error { number: u32, message: &'a str },
+    Exit(Option<&'a str>), // with the reason, as `%exit detached`
+}
+
+impl Display for ControlNotification<'_> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        use ControlNotification::*;
+        match self {
+            Output { pane_id, bytes } => writeln!(f, "%output %{pane_id} {}", escape_output(bytes)),
+            SessionChanged { session_id, name } => writeln!(f, "%session-changed ${session_id} {name}"),
+            SessionsChanged => writeln!(f, "%sessions-changed"),
+            WindowAdd { window_id } => writeln!(f, "%window-add @{window_id}"),
+            WindowClose { window_id } => writeln!(f, "%window-close @{window_id}"),
+            Message(message) => writeln!(f, "%message {message}"),
+            // what the command printed goes inside its block
+            Done { number, output } => {
+                writeln!(f, "%begin {} {number} 1", timestamp())?;
+                for line in output.lines() {
+                    writeln!(f, "{line}")?;
+                }
+                writeln!(f, "%end {} {number} 1", timestamp())
+            }
+            // a failed command still opens a block, the error text goes inside it
+            Error { number, message } => {
+                writeln!(f, "%begin {} {number} 1", timestamp())?;
+                writeln!(f, "{message}")?;
+                writeln!(f, "%error {} {number} 1", timestamp())
+            }
+            Exit(None) => writeln!(f, "%exit"),
+            Exit(Some(reason)) => writeln!(f, "%exit {reason}"),
+        }
+    }
+}
+
+fn timestamp() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or_default()
+}
+
+/// control mode output must stay on one line - non printable bytes and backslashes are sent as octal escapes
+pub fn escape_output(bytes: &[u8]) -> String {
+    let mut escaped = String::with_capacity(bytes.len());
+    for &b in bytes {
+        if b < b' ' || b == b'\\' || b >= 0x7f {
+            escaped.push_str(&format!("\\{b:03o}"));
+        } else {
+            escaped.push(b as char);
+        }
+    }
+    escaped
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_escape_output() {
+        assert_eq!(escape_output(b"ls\r\n"), "ls\\015\\012");
+        assert_eq!(escape_output(b"a\\b"), "a\\134b");
+        assert_eq!(escape_output(b"\x1b[0m"), "\\033[0m");
+    }
+
+    #[test]
+    fn test_command_blocks() {
+        let done = ControlNotification::Done {
+            number: 2,
+            output: "a=1\nb=2",
+        }
+        .to_string();
+        let lines: Vec<&str> = done.lines().collect();
+        assert!(lines[0].starts_with("%begin ") && lines[0].ends_with(" 2 1"));
+        assert_eq!(&lines[1..3], ["a=1", "b=2"]);
+        assert!(lines[3].starts_with("%end ") && lines[3].ends_with(" 2 1"));
+
+        let error = ControlNotification::Error {
+            number: 3,
+            message: "no session main",
+        }
+        .to_string();
+        let lines: Vec<&str> = error.lines().collect();
+        assert_eq!(lines[1], "no session main");
+        assert!(lines[2].starts_with("%error ") && lines[2].ends_with(" 3 1"));
+        let empty = ControlNotification::Done { number: 0, output: "" };
+        assert_eq!(empty.to_string().lines().count(), 2);
+    }
+}
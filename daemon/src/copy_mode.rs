@@ -0,0 +1,391 @@
+// key handling for a pane's copy mode - scrolling back through the pane's history
+
+use color_eyre::eyre::eyre;
+use regex_automata::meta::Regex;
+
+use crate::prelude::*;
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum CopyModeAction {
+    Exit,
+    ScrollUp(usize),
+    ScrollDown(usize),
+    Top,
+    Bottom,
+    Search,    // `/` opens the search prompt on the pane's last row
+    NextMatch, // the next older match of the last search
+    PrevMatch, // back to a newer one
+    None,
+}
+
+/// maps raw input bytes to a copy mode action, `page` is the pane height
+pub fn copy_mode_action(bytes: &[u8], page: usize) -> CopyModeAction {
+    use CopyModeAction::*;
+    let half_page = (page / 2).max(1);
+    match bytes {
+        b"q" | b"\x1b" | b"\x03" => Exit,
+        b"k" | b"\x1b[A" | b"\x1bOA" => ScrollUp(1),
+        b"j" | b"\x1b[B" | b"\x1bOB" => ScrollDown(1),
+        b"u" | b"\x15" => ScrollUp(half_page),
+        b"d" | b"\x04" => ScrollDown(half_page),
+        b"\x1b[5~" => ScrollUp(page), // not C-b, the cli keeps it as the default prefix
+        b"\x1b[6~" | b"\x06" => ScrollDown(page),
+        b"g" => Top,
+        b"G" => Bottom,
+        b"/" => Search,
+        b"n" => NextMatch,
+        b"N" => PrevMatch,
+        _ => None,
+    }
+}
+
+/// scrollback offset that puts `line` (counted from the oldest scrollback line) on the top row,
+/// lines on the visible screen need no scrolling
+pub fn offset_for_line(line: usize, scrollback_len: usize) -> usize {
+    scrollback_len.saturating_sub(line)
+}
+
+/// `[offset/total]` shown while scrolled back, like tmux's copy mode position
+pub fn position_indicator(offset: usize, scrollback_len: usize) -> String {
+    format!("[{offset}/{scrollback_len}]")
+}
+
+/// first row and length of the scrollbar thumb for a pane `height` rows tall
+pub fn scrollbar_thumb(offset: usize, scrollback_len: usize, height: u16) -> (u16, u16) {
+    let height = height as usize;
+    let total = scrollback_len + height;
+    if height == 0 || scrollback_len == 0 {
+        return (0, height as u16);
+    }
+    let len = (height * height / total).max(1);
+    // offset 0 is the live view at the bottom, the whole scrollback puts the thumb at the top
+    let start = (scrollback_len - offset.min(scrollback_len)) * (height - len) / scrollback_len;
+    (start as u16, len as u16)
+}
+
+// characters that end a word for double click selection
+const WORD_SEPARATORS: &str = " \t\"'`()[]{}<>,;|";
+
+/// an inclusive range of cells between two (row, col) positions, `start` never comes after `end`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Selection {
+    pub start: (u16, u16),
+    pub end: (u16, u16),
+}
+
+impl Selection {
+    pub fn new(a: (u16, u16), b: (u16, u16)) -> Self {
+        Self {
+            start: a.min(b),
+            end: a.max(b),
+        }
+    }
+
+    /// smallest selection covering both
+    pub fn union(&self, other: &Selection) -> Self {
+        Self {
+            start: self.start.min(other.start),
+            end: self.end.max(other.end),
+        }
+    }
+
+    /// the selected columns of `row`, `width` bounds rows that are fully selected
+    pub fn columns(&self, row: u16, width: u16) -> Option<(u16, u16)> {
+        if row < self.start.0 || row > self.end.0 || width == 0 {
+            return None;
+        }
+        let first = if row == self.start.0 { self.start.1 } else { 0 };
+        let last = if row == self.end.0 { self.end.1 } else { width - 1 };
+        Some((first, last.min(width - 1)))
+    }
+}
+
+/// what a click selects, a double click selects words and a triple click whole lines
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SelectionUnit {
+    Char,
+    Word,
+    Line,
+}
+
+impl SelectionUnit {
+    pub fn from_click_count(count: u8) -> Self {
+        match count {
+            0 | 1 => SelectionUnit::Char,
+            2 => SelectionUnit::Word,
+            _ => SelectionUnit::Line,
+        }
+    }
+}
+
+/// a cell of a row: its contents and whether it is the right half of a wide character
+pub struct RowCell {
+    pub contents: String,
+    pub is_wide_continuation: bool,
+}
+
+/// column range of the word under `col`, wide characters are never split
+pub fn word_bounds(cells: &[RowCell], col: usize) -> (usize, usize) {
+    let is_word = |i: usize| {
+        // the right half of a wide character belongs to whatever its left half is
+        let i = if cells[i].is_wide_continuation {
+            i.saturating_sub(1)
+        } else {
+            i
+        };
+        let contents = &cells[i].contents;
+        !contents.is_empty() && !contents.chars().all(|c| WORD_SEPARATORS.contains(c))
+    };
+    if cells.is_empty() {
+        return (0, 0);
+    }
+    let col = col.min(cells.len() - 1);
+    let mut start = if cells[col].is_wide_continuation {
+        col.saturating_sub(1)
+    } else {
+        col
+    };
+    let mut end = col;
+    if is_word(col) {
+        while start > 0 && is_word(start - 1) {
+            start -= 1;
+        }
+        while end + 1 < cells.len() && is_word(end + 1) {
+            end += 1;
+        }
+    }
+    if end + 1 < cells.len() && cells[end + 1].is_wide_continuation {
+        end += 1;
+    }
+    (start, end)
+}
+
+/// a row's text as `Screen::rows` has it - blank cells between contents read as spaces, trailing ones
+/// are dropped - and the column each byte of it came from
+pub fn row_text(cells: &[RowCell]) -> (String, Vec<u16>) {
+    let mut text = String::new();
+    let mut columns = Vec::new();
+    let mut len = 0; // up to the last cell with contents
+    for (col, cell) in cells.iter().enumerate() {
+        if cell.is_wide_continuation {
+            continue;
+        }
+        let contents = if cell.contents.is_empty() { " " } else { &cell.contents };
+        text.push_str(contents);
+        columns.extend(std::iter::repeat_n(col as u16, contents.len()));
+        if !cell.contents.is_empty() {
+            len = text.len();
+        }
+    }
+    text.truncate(len);
+    columns.truncate(len);
+    (text, columns)
+}
+
+/// what the last row of a pane in copy mode shows for `/`
+#[derive(Debug)]
+pub enum CopySearchState {
+    Typing(String),
+    Results(CopySearch),
+    Failed(String),
+}
+
+impl CopySearchState {
+    pub fn status(&self) -> String {
+        match self {
+            Self::Typing(pattern) => format!("/{pattern}"),
+            Self::Results(search) => search.status(),
+            Self::Failed(error) => error.clone(),
+        }
+    }
+}
+
+/// `/` in copy mode - the matches of a regex over the scrollback and the visible screen. it searches up
+/// through the history like tmux's `?`, `n` goes to the next older match and `N` back to a newer one
+#[derive(Debug)]
+pub struct CopySearch {
+    pattern: String,
+    regex: Regex,
+    hits: Vec<usize>, // the line of every match, oldest first, once per match on the line
+    current: usize,
+}
+
+impl CopySearch {
+    /// `lines` are counted from the oldest scrollback line, the first match is the newest one
+    /// at or above `bottom` and wraps around to the newest overall
+    pub fn new(pattern: &str, lines: &[String], bottom: usize) -> Result<Self> {
+        let regex = Regex::new(pattern).map_err(|e| eyre!("invalid regex {pattern}: {e}"))?;
+        let hits: Vec<usize> = lines
+            .iter()
+            .enumerate()
+            .flat_map(|(i, line)| {
+                let count = regex.find_iter(line).filter(|m| !m.is_empty()).count();
+                std::iter::repeat_n(i, count)
+            })
+            .collect();
+        let current = hits
+            .iter()
+            .rposition(|&line| line <= bottom)
+            .unwrap_or(hits.len().saturating_sub(1));
+        Ok(Self {
+            pattern: pattern.to_owned(),
+            regex,
+            hits,
+            current,
+        })
+    }
+
+    /// the line of the current match
+    pub fn line(&self) -> Option<usize> {
+        self.hits.get(self.current).copied()
+    }
+
+    pub fn step(&mut self, older: bool) {
+        let len = self.hits.len();
+        if len == 0 {
+            return;
+        }
+        self.current = if older {
+            (self.current + len - 1) % len
+        } else {
+            (self.current + 1) % len
+        };
+    }
+
+    /// inclusive column ranges of the matches in the row of `line` and whether each is the current match
+    pub fn row_matches(&self, line: usize, cells: &[RowCell]) -> Vec<(u16, u16, bool)> {
+        let (text, columns) = row_text(cells);
+        // which of the line's matches is the current one
+        let current =
+            (self.line() == Some(line)).then(|| self.hits[..self.current].iter().filter(|&&hit| hit == line).count());
+        self.regex
+            .find_iter(&text)
+            .filter(|m| !m.is_empty())
+            .enumerate()
+            .map(|(i, m)| {
+                let last = columns[m.end() - 1] as usize;
+                // the match covers both halves of a wide character it ends in
+                let last = if cells.get(last + 1).is_some_and(|cell| cell.is_wide_continuation) {
+                    last + 1
+                } else {
+                    last
+                };
+                (columns[m.start()], last as u16, current == Some(i))
+            })
+            .collect()
+    }
+
+    /// shown on the pane's last row, e.g. `/err (2/5)`
+    pub fn status(&self) -> String {
+        if self.hits.is_empty() {
+            format!("/{}: no matches", self.pattern)
+        } else {
+            format!(
+                "/{} ({}/{})",
+                self.pattern,
+                self.hits.len() - self.current,
+                self.hits.len()
+            )
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    #![allow(clippy::unwrap_used)]
+    use super::*;
+
+    fn cells(row: &str) -> Vec<RowCell> {
+        // `_` marks the right half of a wide character
+        row.chars()
+            .map(|c| RowCell {
+                contents: if c == '_' { String::new() } else { c.to_string() },
+                is_wide_continuation: c == '_',
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_word_bounds() {
+        let row = cells("ls (src/main.rs) 日_本_ x");
+        assert_eq!(word_bounds(&row, 0), (0, 1));
+        assert_eq!(word_bounds(&row, 6), (4, 14));
+        assert_eq!(word_bounds(&row, 2), (2, 2));
+        // clicking either half of a wide character selects the whole word
+        assert_eq!(word_bounds(&row, 18), (17, 20));
+    }
+
+    #[test]
+    fn test_selection_columns() {
+        let selection = Selection::new((3, 4), (1, 2));
+        assert_eq!(selection.start, (1, 2));
+        assert_eq!(selection.columns(0, 10), None);
+        assert_eq!(selection.columns(1, 10), Some((2, 9)));
+        assert_eq!(selection.columns(2, 10), Some((0, 9)));
+        assert_eq!(selection.columns(3, 10), Some((0, 4)));
+    }
+
+    #[test]
+    fn test_scrollbar_thumb() {
+        assert_eq!(scrollbar_thumb(0, 80, 20), (16, 4));
+        assert_eq!(scrollbar_thumb(80, 80, 20), (0, 4));
+        assert_eq!(scrollbar_thumb(40, 80, 20), (8, 4));
+        assert_eq!(scrollbar_thumb(0, 0, 20), (0, 20));
+    }
+
+    #[test]
+    fn test_copy_mode_action() {
+        assert_eq!(copy_mode_action(b"q", 20), CopyModeAction::Exit);
+        assert_eq!(copy_mode_action(b"\x1b[A", 20), CopyModeAction::ScrollUp(1));
+        assert_eq!(copy_mode_action(b"d", 20), CopyModeAction::ScrollDown(10));
+        assert_eq!(copy_mode_action(b"N", 20), CopyModeAction::PrevMatch);
+        assert_eq!(copy_mode_action(b"x", 20), CopyModeAction::None);
+        assert_eq!(copy_mode_action(b"\x02", 20), CopyModeAction::None);
+    }
+
+    #[test]
+    fn test_row_text() {
+        let mut row = cells("a 日_b  ");
+        // blank cells have no contents at all
+        for cell in row.iter_mut().filter(|cell| cell.contents == " ") {
+            cell.contents.clear();
+        }
+        let (text, columns) = row_text(&row);
+        assert_eq!(text, "a 日b");
+        assert_eq!(columns, vec![0, 1, 2, 2, 2, 4]);
+    }
+
+    #[test]
+    fn test_copy_search() {
+        let lines: Vec<String> = ["error: a", "ok", "error: b error", "ok"].map(str::to_owned).to_vec();
+        let mut search = CopySearch::new("err(or)?", &lines, 1).unwrap();
+        assert_eq!(search.line(), Some(0));
+        assert_eq!(search.status(), "/err(or)? (3/3)");
+        search.step(true);
+        assert_eq!(search.line(), Some(2));
+        assert_eq!(search.status(), "/err(or)? (1/3)");
+        assert_eq!(
+            search.row_matches(2, &cells("error: b error")),
+            vec![(0, 4, false), (9, 13, true)]
+        );
+        search.step(false);
+        assert_eq!(search.line(), Some(0));
+
+        // matches ending in a wide character cover both of its cells
+        let search = CopySearch::new("日", &["日本".to_owned()], 0).unwrap();
+        assert_eq!(search.row_matches(0, &cells("日_本_")), vec![(0, 1, true)]);
+
+        assert!(CopySearch::new("(", &lines, 0).is_err());
+        assert_eq!(
+            CopySearch::new("missing", &lines, 0).unwrap().status(),
+            "/missing: no matches"
+        );
+    }
+
+    #[test]
+    fn test_offset_for_line() {
+        assert_eq!(offset_for_line(0, 100), 100);
+        assert_eq!(offset_for_line(40, 100), 60);
+        assert_eq!(offset_for_line(105, 100), 0);
+    }
+}
@@ -1,75 +1,336 @@
-use std::fs::{File, remove_file};
+use std::{
+    fs::{self, File, Permissions, remove_file},
+    os::unix::fs::PermissionsExt,
+    time::Duration,
+};
 
+use nix::unistd::getuid;
 use remux_core::{
     comm,
+    commands::RemuxCommand,
     daemon_utils::{get_sock_path, lock_daemon_file},
 };
-use tokio::net::{UnixListener, UnixStream};
+use tokio::{
+    net::{UnixListener, UnixStream},
+    signal::unix::{SignalKind, signal},
+    sync::watch,
+    task::JoinSet,
+    time::timeout,
+};
 
 use crate::{
     actors::{
-        client_connection::ClientConnection,
-        session_manager::{SessionManager, SessionManagerHandle},
+        client_connection::{ClientConnection, ClientConnectionHandle},
+        control_connection::ControlConnection,
+        exec_connection::ExecConnection,
+        session_manager::{CommandContext, NewSessionArgs, SessionManager, SessionManagerHandle},
     },
+    config::load_config,
     prelude::*,
 };
 
+// how long the sessions get to stop and reap their processes, and the clients to be told, before the daemon
+// exits anyway
+const SHUTDOWN_TIMEOUT: Duration = Duration::from_secs(5);
+
 pub struct RemuxDaemon {
     _daemon_file: File, // daemon must hold the exclusive file lock while it is alive and running
     session_manager_handle: SessionManagerHandle,
+    shutdown: watch::Receiver<bool>,
+    socket_name: String, // `-L`, picks the socket path
 }
 
 impl RemuxDaemon {
     /// Makes sure there can only ever be once instance at the
     /// process level through use of OS level file locks
-    pub fn new() -> Result<Self> {
-        let session_manager_handle = SessionManager::spawn()?;
+    pub fn new(socket_name: String) -> Result<Self> {
+        let (shutdown_tx, shutdown) = watch::channel(false);
+        let session_manager_handle = SessionManager::spawn(shutdown_tx)?;
         Ok(Self {
-            _daemon_file: lock_daemon_file()?,
+            _daemon_file: lock_daemon_file(&socket_name)?,
             session_manager_handle,
+            shutdown,
+            socket_name,
         })
     }
 
+    /// runs every command from the config file before any client can connect
+    pub async fn source_config(&self) -> Result<()> {
+        for command in load_config()? {
+            self.session_manager_handle
+                .run_command(CommandContext::default(), command)
+                .await?;
+        }
+        Ok(())
+    }
+
     #[instrument(skip(self), name = "Daemon")]
     pub async fn listen(&self) -> Result<()> {
-        let socket_path = get_sock_path()?;
+        let socket_path = get_sock_path(&self.socket_name)?;
 
         if socket_path.exists() {
             remove_file(&socket_path)?;
         }
 
         info!(path = ?socket_path, "Connecting to unix socket");
-        let listener = UnixListener::bind(socket_path)?;
+        let listener = UnixListener::bind(&socket_path)?;
+        fs::set_permissions(&socket_path, Permissions::from_mode(0o600))?;
+        let mut shutdown = self.shutdown.clone();
+        let mut terminate = signal(SignalKind::terminate())?;
+        let mut interrupt = signal(SignalKind::interrupt())?;
+        let mut hangup = signal(SignalKind::hangup())?;
+        // each lasts as long as its connection, so shutting down can wait for the last writes to the clients
+        let mut connections = JoinSet::new();
         loop {
-            let (stream, _) = listener.accept().await?;
-            info!("Accepting connection");
-            if let Err(e) = handle_message(self.session_manager_handle.clone(), stream).await {
-                error!("{e}");
+            tokio::select! {
+                res = listener.accept() => {
+                    let (stream, _) = res?;
+                    if !is_same_user(&stream) {
+                        continue;
+                    }
+                    info!("Accepting connection");
+                    // a client that is slow to send its request doesn't hold up the ones behind it
+                    let session_manager_handle = self.session_manager_handle.clone();
+                    connections.spawn(
+                        async move {
+                            match handle_message(session_manager_handle, stream).await {
+                                Ok(Some(client)) => client.closed().await,
+                                Ok(None) => {}
+                                Err(e) => error!("{e}"),
+                            }
+                        }
+                        .in_current_span(),
+                    );
+                }
+                Some(_) = connections.join_next() => {}
+                // `exit-empty`
+                _ = shutdown.wait_for(|shutdown| *shutdown) => break,
+                _ = terminate.recv() => { info!("Received SIGTERM"); break }
+                _ = interrupt.recv() => { info!("Received SIGINT"); break }
+                _ = hangup.recv() => { info!("Received SIGHUP"); break }
             }
         }
+        remove_file(&socket_path)?;
+        self.shutdown().await;
+        // the clients were told the server exited, an exec'd command's exit code may still be on its way
+        let closed = async { while connections.join_next().await.is_some() {} };
+        if timeout(SHUTDOWN_TIMEOUT, closed).await.is_err() {
+            warn!("clients did not disconnect within {SHUTDOWN_TIMEOUT:?}, exiting anyway");
+        }
+        info!("daemon stopped");
+        Ok(())
+    }
+
+    /// waits for every session to stop so no process outlives the daemon
+    async fn shutdown(&self) {
+        match timeout(SHUTDOWN_TIMEOUT, self.session_manager_handle.shutdown()).await {
+            Ok(Ok(())) => {}
+            Ok(Err(e)) => error!("Could not shut down the sessions: {e}"),
+            Err(_) => warn!("sessions did not stop within {SHUTDOWN_TIMEOUT:?}, exiting anyway"),
+        }
     }
 }
 
+/// the socket's permissions already keep other users out, this also turns away anyone the directory
+/// was opened up to
+fn is_same_user(stream: &UnixStream) -> bool {
+    match stream.peer_cred() {
+        Ok(cred) if cred.uid() == getuid().as_raw() => true,
+        Ok(cred) => {
+            warn!(uid = cred.uid(), "Rejecting connection from another user");
+            false
+        }
+        Err(e) => {
+            error!("Could not read the peer's credentials: {e}");
+            false
+        }
+    }
+}
+
+/// the client actor when the connection attached, it runs until the client goes away
 #[instrument(skip(session_manager_handle, stream))]
-async fn handle_message(session_manager_handle: SessionManagerHandle, mut stream: UnixStream) -> Result<()> {
-    use remux_core::messages::request::{self, DaemonRequestMessage, DaemonRequestMessageBody};
+async fn handle_message(
+    session_manager_handle: SessionManagerHandle,
+    mut stream: UnixStream,
+) -> Result<Option<ClientConnectionHandle>> {
+    use remux_core::messages::{
+        Features, ResponseBuilder, ResponseResult,
+        request::{self, DaemonRequestMessageBody},
+        response,
+    };
 
-    let req: DaemonRequestMessage = comm::read_message(&mut stream).await?;
+    let req = match comm::read_request(&mut stream).await {
+        Ok(req) => req,
+        // the client can still read a failure and tell the user which end to restart
+        Err(e @ remux_core::error::Error::IncompatibleVersion { .. }) => {
+            warn!("Rejecting client: {e}");
+            refuse(&mut stream, e).await?;
+            return Ok(None);
+        }
+        Err(e) => return Err(e.into()),
+    };
     info!(request=?req, "Handling request");
-    match req.body {
-        DaemonRequestMessageBody::Attach(request::Attach {
+    let features = Features::SUPPORTED.negotiate(req.features);
+    let attaching = matches!(
+        req.body,
+        DaemonRequestMessageBody::Attach(..)
+            | DaemonRequestMessageBody::ControlAttach(..)
+            | DaemonRequestMessageBody::Exec(..)
+    );
+    if attaching && !features.contains(Features::REQUIRED) {
+        refuse(&mut stream, "the client can't read binary frames, upgrade it").await?;
+        return Ok(None);
+    }
+    let control = match &req.body {
+        DaemonRequestMessageBody::Attach(attach) => attach.control,
+        DaemonRequestMessageBody::ControlAttach(..) => true,
+        _ => false,
+    };
+    if control && !features.contains(Features::CONTROL_MODE) {
+        refuse(
+            &mut stream,
+            "control mode isn't supported by both the client and the daemon",
+        )
+        .await?;
+        return Ok(None);
+    }
+    let client = match req.body {
+        DaemonRequestMessageBody::Attach(attach) => {
+            info!(
+                connecting_session = attach.session_name,
+                create = attach.create,
+                color_depth = ?attach.color_depth,
+                command = ?attach.command,
+                argv = ?attach.argv,
+                control = attach.control,
+                "Creating new client actor"
+            );
+            Some(ClientConnection::spawn(
+                stream,
+                session_manager_handle,
+                attach,
+                features,
+            )?)
+        }
+        DaemonRequestMessageBody::ControlAttach(request::ControlAttach {
             id,
-            session_name,
-            create,
+            control_session_name,
         }) => {
             info!(
-                connecting_session = session_name,
-                create = create,
-                "Creating new client actor"
+                connecting_session = control_session_name,
+                "Creating new control mode client actor"
             );
-            let _client = ClientConnection::spawn(id, stream, session_manager_handle, &session_name)?;
+            Some(ControlConnection::spawn(
+                id,
+                stream,
+                session_manager_handle,
+                &control_session_name,
+                features,
+            )?)
+        }
+        DaemonRequestMessageBody::Exec(request::Exec {
+            id,
+            exec_session_name,
+            command,
+        }) => {
+            info!(session = ?exec_session_name, command = ?command, "Creating new exec client actor");
+            Some(ExecConnection::spawn(
+                id,
+                stream,
+                session_manager_handle,
+                exec_session_name,
+                command,
+                features,
+            )?)
+        }
+        DaemonRequestMessageBody::NewSession(request::NewSession {
+            new_session_name,
+            command,
+            argv,
+            group_with,
+        }) => {
+            let args = NewSessionArgs {
+                command,
+                argv,
+                group_with,
+            };
+            let result = match session_manager_handle.new_session(new_session_name, args).await? {
+                Ok((id, name)) => ResponseResult::Success(response::NewSession { id, name }),
+                Err(e) => ResponseResult::Failure(e.to_string()),
+            };
+            comm::send_message(&mut stream, &ResponseBuilder::default().result(result).build()).await?;
+            None
+        }
+        DaemonRequestMessageBody::KillSession(request::KillSession { name_or_id }) => {
+            let result = match session_manager_handle.kill_session(name_or_id).await? {
+                Ok(()) => ResponseResult::Success(()),
+                Err(e) => ResponseResult::Failure(e.to_string()),
+            };
+            comm::send_message(&mut stream, &ResponseBuilder::default().result(result).build()).await?;
+            None
+        }
+        DaemonRequestMessageBody::SourceFile(request::SourceFile { config_paths }) => {
+            let result = match session_manager_handle.source_file(config_paths).await? {
+                Ok(()) => ResponseResult::Success(()),
+                Err(e) => ResponseResult::Failure(e.to_string()),
+            };
+            comm::send_message(&mut stream, &ResponseBuilder::default().result(result).build()).await?;
+            None
+        }
+        DaemonRequestMessageBody::RunCommand(request::RunCommand {
+            command_args,
+            target_session,
+        }) => {
+            let result = match RemuxCommand::from_args(&command_args) {
+                Ok(command) => session_manager_handle
+                    .run_command_once(command, target_session, None)
+                    .await?
+                    .map(|output| response::CommandOutput { output }),
+                Err(e) => Err(e.into()),
+            };
+            let result = match result {
+                Ok(output) => ResponseResult::Success(output),
+                Err(e) => ResponseResult::Failure(e.to_string()),
+            };
+            comm::send_message(&mut stream, &ResponseBuilder::default().result(result).build()).await?;
+            None
+        }
+        DaemonRequestMessageBody::PreviewPane(request::PreviewPane {
+            preview_session,
+            rows,
+            cols,
+        }) => {
+            let result = match session_manager_handle
+                .preview_session_pane(preview_session, rows, cols)
+                .await?
+            {
+                Ok(preview) => ResponseResult::Success(preview),
+                Err(e) => ResponseResult::Failure(e.to_string()),
+            };
+            comm::send_message(&mut stream, &ResponseBuilder::default().result(result).build()).await?;
+            None
+        }
+        DaemonRequestMessageBody::ListSessions(request::ListSessions {}) => {
+            // answered right here, the connection never attaches
+            let sessions = session_manager_handle.list_sessions().await?;
+            let res = ResponseBuilder::default()
+                .result(ResponseResult::Success(response::ListSessions { sessions }))
+                .build();
+            comm::send_message(&mut stream, &res).await?;
+            None
         }
     };
+    Ok(client)
+}
+
+/// answers a request with a failure, the cli shows the reason and exits
+async fn refuse(stream: &mut UnixStream, reason: impl std::fmt::Display) -> Result<()> {
+    use remux_core::messages::{ResponseBuilder, ResponseResult};
+
+    let res = ResponseBuilder::default()
+        .result(ResponseResult::Failure::<()>(reason.to_string()))
+        .build();
+    comm::send_message(stream, &res).await?;
     Ok(())
 }
 
@@ -0,0 +1,193 @@
+// `set-environment` tables, panes read them through a watch channel when their shell starts. a pane's own
+// variables (`split-window -e`) are kept by the pane and win over both tables
+
+use std::collections::{BTreeMap, HashMap};
+
+use remux_core::commands::EnvironmentChange;
+use tokio::sync::watch;
+
+pub type EnvironmentReceiver = watch::Receiver<Environment>;
+type EnvTable = BTreeMap<String, Option<String>>; // `None` removes the variable from new panes
+
+#[derive(Debug, Clone, Default)]
+pub struct Environment {
+    global: EnvTable,
+    sessions: HashMap<u32, EnvTable>,
+}
+
+impl Environment {
+    /// `session_id` of `None` changes the global table
+    pub fn apply(&mut self, session_id: Option<u32>, name: String, change: EnvironmentChange) {
+        let table = match session_id {
+            Some(id) => self.sessions.entry(id).or_default(),
+            None => &mut self.global,
+        };
+        match change {
+            EnvironmentChange::Set(value) => {
+                table.insert(name, Some(value));
+            }
+            EnvironmentChange::Remove => {
+                table.insert(name, None);
+            }
+            EnvironmentChange::Unset => {
+                table.remove(&name);
+            }
+        }
+    }
+
+    /// `NAME=value` entries, removed variables are shown as `-NAME` like tmux does
+    pub fn show(&self, session_id: Option<u32>) -> Vec<String> {
+        let table = match session_id {
+            Some(id) => self.sessions.get(&id),
+            None => Some(&self.global),
+        };
+        table
+            .into_iter()
+            .flatten()
+            .map(|(name, value)| match value {
+                Some(value) => format!("{name}={value}"),
+                None => format!("-{name}"),
+            })
+            .collect()
+    }
+
+    pub fn remove_session(&mut self, session_id: u32) {
+        self.sessions.remove(&session_id);
+    }
+
+    /// what a new pane in the session starts with on top of the daemon's environment
+    pub fn for_session(&self, session_id: u32) -> Vec<(String, Option<String>)> {
+        let mut vars = self.global.clone();
+        if let Some(table) = self.sessions.get(&session_id) {
+            vars.extend(table.clone());
+        }
+        vars.into_iter().collect()
+    }
+}
+
+/// a shell line setting `names` to what a new pane would get, names the tables don't mention are skipped
+pub fn shell_exports(vars: &[(String, Option<String>)], names: &[String]) -> Option<String> {
+    let statements = vars
+        .iter()
+        .filter(|(name, _)| names.contains(name))
+        .map(|(name, value)| match value {
+            Some(value) => format!("export {name}='{}'", value.replace('\'', r"'\''")),
+            None => format!("unset {name}"),
+        })
+        .collect::<Vec<_>>();
+    // the leading space keeps it out of most shells' history
+    (!statements.is_empty()).then(|| format!(" {}\r", statements.join("; ")))
+}
+
+/// a session's view of the environment, handed down to the panes it creates
+#[derive(Debug, Clone)]
+pub struct SessionEnvironment {
+    pub session_id: u32,
+    pub environment: EnvironmentReceiver,
+    pub pane: Vec<(String, String)>, // only ever set on a single pane's copy
+}
+
+impl SessionEnvironment {
+    pub fn new(session_id: u32, environment: EnvironmentReceiver) -> Self {
+        Self {
+            session_id,
+            environment,
+            pane: Vec::new(),
+        }
+    }
+
+    /// the copy a pane spawned with its own variables keeps, respawning it sets them again
+    pub fn for_pane(&self, pane: Vec<(String, String)>) -> Self {
+        Self { pane, ..self.clone() }
+    }
+
+    pub fn vars(&self) -> Vec<(String, Option<String>)> {
+        let mut vars = self.environment.borrow().for_session(self.session_id);
+        vars.extend(
+            self.pane
+                .iter()
+                .map(|(name, value)| (name.clone(), Some(value.clone()))),
+        );
+        vars
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_session_overrides_global() {
+        let mut environment = Environment::default();
+        let set = |value: &str| EnvironmentChange::Set(value.to_owned());
+        environment.apply(None, "DOCKER_HOST".to_owned(), set("unix:///run/docker.sock"));
+        environment.apply(None, "EDITOR".to_owned(), set("vim"));
+        environment.apply(Some(1), "DOCKER_HOST".to_owned(), set("tcp://dev:2375"));
+        environment.apply(Some(1), "EDITOR".to_owned(), EnvironmentChange::Remove);
+        assert_eq!(
+            environment.for_session(1),
+            vec![
+                ("DOCKER_HOST".to_owned(), Some("tcp://dev:2375".to_owned())),
+                ("EDITOR".to_owned(), None),
+            ]
+        );
+        assert_eq!(environment.show(Some(1)), vec!["DOCKER_HOST=tcp://dev:2375", "-EDITOR"]);
+
+        environment.apply(Some(1), "EDITOR".to_owned(), EnvironmentChange::Unset);
+        assert_eq!(
+            environment.for_session(1)[1],
+            ("EDITOR".to_owned(), Some("vim".to_owned()))
+        );
+        assert_eq!(
+            environment.for_session(2)[0].1.as_deref(),
+            Some("unix:///run/docker.sock")
+        );
+    }
+
+    #[test]
+    fn test_pane_overrides_session() {
+        let environment = watch::Sender::new(Environment::default());
+        environment.send_modify(|environment| {
+            environment.apply(None, "EDITOR".to_owned(), EnvironmentChange::Set("vim".to_owned()));
+            environment.apply(Some(1), "DOCKER_HOST".to_owned(), EnvironmentChange::Remove);
+        });
+        let session = SessionEnvironment::new(1, environment.subscribe());
+        let pane = session.for_pane(vec![("DOCKER_HOST".to_owned(), "tcp://dev:2375".to_owned())]);
+        // `child_env` applies them in order, the pane's entry comes last
+        assert_eq!(
+            pane.vars(),
+            vec![
+                ("DOCKER_HOST".to_owned(), None),
+                ("EDITOR".to_owned(), Some("vim".to_owned())),
+                ("DOCKER_HOST".to_owned(), Some("tcp://dev:2375".to_owned())),
+            ]
+        );
+        assert_eq!(session.vars().len(), 2);
+
+        // a killed session's table goes with it
+        environment.send_modify(|environment| environment.remove_session(1));
+        assert_eq!(
+            pane.vars(),
+            vec![
+                ("EDITOR".to_owned(), Some("vim".to_owned())),
+                ("DOCKER_HOST".to_owned(), Some("tcp://dev:2375".to_owned())),
+            ]
+        );
+        assert!(environment.borrow().show(Some(1)).is_empty());
+    }
+
+    #[test]
+    fn test_shell_exports() {
+        let vars = vec![
+            ("DISPLAY".to_owned(), None),
+            ("EDITOR".to_owned(), Some("vim".to_owned())),
+            ("SSH_AUTH_SOCK".to_owned(), Some("/tmp/it's/agent".to_owned())),
+        ];
+        let names = ["SSH_AUTH_SOCK".to_owned(), "DISPLAY".to_owned()];
+        assert_eq!(
+            shell_exports(&vars, &names).as_deref(),
+            Some(" unset DISPLAY; export SSH_AUTH_SOCK='/tmp/it'\\''s/agent'\r")
+        );
+        assert_eq!(shell_exports(&vars, &[]), None);
+    }
+}
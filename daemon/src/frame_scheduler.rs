@@ -0,0 +1,117 @@
+// holds back the frames of a busy pane so it's drawn at most `frame-rate` times a second, output arriving in
+// the meantime goes out with the next frame and the ones in between are never drawn. each pane is scheduled on
+// its own, a window with several busy panes sends up to `frame-rate` frames for each of them
+
+use tokio::time::{Duration, Instant};
+
+use crate::{actors::pane::PaneHandle, prelude::*};
+
+#[derive(Debug)]
+pub struct FrameScheduler {
+    last_frame: Instant,
+    scheduled: Option<DaemonTask>, // sends the pane `Render` once the frame interval is up
+}
+
+impl Default for FrameScheduler {
+    fn default() -> Self {
+        Self {
+            last_frame: Instant::now(),
+            scheduled: None,
+        }
+    }
+}
+
+impl FrameScheduler {
+    /// true if the output should be drawn straight away, otherwise `pane` is sent `Render` once the frame
+    /// interval is up. a `frame_rate` of 0 draws every read
+    pub fn output(&mut self, frame_rate: u32, pane: &PaneHandle) -> bool {
+        if frame_rate == 0 {
+            return true;
+        }
+        if self.scheduled.is_some() {
+            return false;
+        }
+        let interval = Duration::from_secs(1) / frame_rate;
+        let elapsed = self.last_frame.elapsed();
+        if elapsed >= interval {
+            return true;
+        }
+        let pane = pane.clone();
+        self.scheduled = Some(tokio::spawn(
+            async move {
+                tokio::time::sleep(interval - elapsed).await;
+                // fails if the pane was killed in the meantime
+                let _ = pane.render().await;
+                Ok(())
+            }
+            .in_current_span(),
+        ));
+        false
+    }
+
+    /// a `Render` that arrives after the frame was drawn some other way has nothing left to draw
+    pub fn is_scheduled(&self) -> bool {
+        self.scheduled.is_some()
+    }
+
+    /// called for every frame drawn, whatever way it was, a frame still scheduled isn't needed anymore
+    pub fn drawn(&mut self) {
+        self.last_frame = Instant::now();
+        if let Some(task) = self.scheduled.take() {
+            task.abort();
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::actors::pane::PaneEvent;
+
+    /// the pane's loop fed `reads` pty reads a millisecond apart, when it drew frames in ms since it started
+    async fn frames(frame_rate: u32, reads: u32) -> Vec<u128> {
+        let (pane, mut rx) = PaneHandle::spawn(10);
+        let mut scheduler = FrameScheduler::default();
+        let start = Instant::now();
+        // the pane drew its first frame when it started
+        let mut drawn = vec![0];
+        // half way between frames so a read and a scheduled frame never fall on the same instant
+        let mut reads = (0..reads).map(|i| start + Duration::from_micros(500) + Duration::from_millis(i.into()));
+        let mut next_read = reads.next();
+        while next_read.is_some() || scheduler.is_scheduled() {
+            let draw = tokio::select! {
+                Some((event, _)) = rx.recv() => matches!(event, PaneEvent::Render) && scheduler.is_scheduled(),
+                _ = tokio::time::sleep_until(next_read.unwrap_or(start)), if next_read.is_some() => {
+                    next_read = reads.next();
+                    scheduler.output(frame_rate, &pane)
+                }
+            };
+            if draw {
+                scheduler.drawn();
+                drawn.push(start.elapsed().as_millis());
+            }
+        }
+        drawn
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_frame_rate() {
+        // a burst of 100 reads over 100ms, drawn once every 20ms
+        assert_eq!(frames(50, 100).await, vec![0, 20, 40, 60, 80, 100]);
+        // every read is drawn
+        assert_eq!(frames(0, 100).await.len(), 101);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_drawn_cancels_scheduled_frame() {
+        let (pane, mut rx) = PaneHandle::spawn(10);
+        let mut scheduler = FrameScheduler::default();
+        assert!(!scheduler.output(50, &pane));
+        assert!(scheduler.is_scheduled());
+        // e.g. images force a frame out before the interval is up
+        scheduler.drawn();
+        assert!(!scheduler.is_scheduled());
+        tokio::time::sleep(Duration::from_millis(100)).await;
+        assert!(rx.try_recv().is_err());
+    }
+}
@@ -0,0 +1,102 @@
+use std::collections::HashMap;
+
+use remux_core::commands::{HookEvent, RemuxCommand};
+
+#[derive(Debug, Clone)]
+struct Hook {
+    session_name: Option<String>, // only run for this session when set
+    commands: Vec<RemuxCommand>,
+}
+
+#[derive(Debug, Default)]
+pub struct Hooks {
+    hooks: HashMap<HookEvent, Vec<Hook>>,
+}
+
+impl Hooks {
+    /// replaces any hook previously set for the same event and session
+    pub fn set(&mut self, event: HookEvent, session_name: Option<String>, commands: Vec<RemuxCommand>) {
+        let hooks = self.hooks.entry(event).or_default();
+        hooks.retain(|hook| hook.session_name != session_name);
+        if !commands.is_empty() {
+            hooks.push(Hook { session_name, commands });
+        }
+    }
+
+    pub fn commands_for(&self, event: HookEvent, session_name: Option<&str>) -> Vec<RemuxCommand> {
+        self.hooks
+            .get(&event)
+            .into_iter()
+            .flatten()
+            .filter(|hook| hook.session_name.is_none() || hook.session_name.as_deref() == session_name)
+            .flat_map(|hook| hook.commands.iter().cloned())
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    #![allow(clippy::unwrap_used)]
+    use super::*;
+
+    fn commands(line: &str) -> Vec<RemuxCommand> {
+        remux_core::commands::parse_sequence(line).unwrap()
+    }
+
+    #[test]
+    fn test_set() {
+        let mut hooks = Hooks::default();
+        hooks.set(HookEvent::SessionCreated, None, commands("new-window"));
+        hooks.set(
+            HookEvent::SessionCreated,
+            Some("dev".to_owned()),
+            commands("split-window"),
+        );
+        // replaces only the hook of the same session
+        hooks.set(
+            HookEvent::SessionCreated,
+            Some("dev".to_owned()),
+            commands("split-window -h"),
+        );
+        assert_eq!(
+            hooks.commands_for(HookEvent::SessionCreated, Some("dev")),
+            commands("new-window ; split-window -h")
+        );
+
+        // an empty list clears it
+        hooks.set(HookEvent::SessionCreated, None, vec![]);
+        assert_eq!(
+            hooks.commands_for(HookEvent::SessionCreated, Some("dev")),
+            commands("split-window -h")
+        );
+        hooks.set(HookEvent::SessionCreated, Some("dev".to_owned()), vec![]);
+        assert!(hooks.commands_for(HookEvent::SessionCreated, Some("dev")).is_empty());
+    }
+
+    #[test]
+    fn test_commands_for() {
+        let mut hooks = Hooks::default();
+        hooks.set(HookEvent::WindowChanged, None, commands("display-message global"));
+        hooks.set(
+            HookEvent::WindowChanged,
+            Some("dev".to_owned()),
+            commands("display-message dev"),
+        );
+        hooks.set(HookEvent::SessionClosed, None, commands("display-message closed"));
+
+        // global hooks run for every session and for none
+        assert_eq!(
+            hooks.commands_for(HookEvent::WindowChanged, Some("dev")),
+            commands("display-message global ; display-message dev")
+        );
+        assert_eq!(
+            hooks.commands_for(HookEvent::WindowChanged, Some("work")),
+            commands("display-message global")
+        );
+        assert_eq!(
+            hooks.commands_for(HookEvent::WindowChanged, None),
+            commands("display-message global")
+        );
+        assert!(hooks.commands_for(HookEvent::PaneDied, Some("dev")).is_empty());
+    }
+}
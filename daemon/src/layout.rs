@@ -1,14 +1,16 @@
-use std::collections::HashMap;
+use std::{collections::HashMap, mem};
+
+use remux_core::commands::LayoutPreset;
 
 use crate::prelude::*;
 
-#[derive(Debug, Clone, Copy)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum SplitDirection {
     Horizontal,
     Vertical,
 }
 
-#[derive(Debug, Clone, Copy)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub struct Rect {
     pub x: u16,
     pub y: u16,
@@ -96,6 +98,195 @@ impl LayoutNode {
         }
     }
 
+    pub fn contains(&self, target_id: usize) -> bool {
+        match self {
+            LayoutNode::Pane { id } => *id == target_id,
+            LayoutNode::Split { left, right, .. } => left.contains(target_id) || right.contains(target_id),
+        }
+    }
+
+    /// moves the divider of the innermost `direction` split holding the target by `amount` cells,
+    /// positive amounts move it right/down. the split's weights become the cell sizes of its two sides.
+    /// returns false when there is no such split
+    pub fn resize(&mut self, area: Rect, target_id: usize, direction: SplitDirection, amount: i32) -> bool {
+        let LayoutNode::Split {
+            direction: split_direction,
+            left,
+            right,
+            left_weight,
+            right_weight,
+        } = self
+        else {
+            return false;
+        };
+        let (left_area, right_area) = split_area(*split_direction, area, *left_weight, *right_weight);
+        if left.resize(left_area, target_id, direction, amount)
+            || right.resize(right_area, target_id, direction, amount)
+        {
+            return true;
+        }
+        if *split_direction != direction || !(left.contains(target_id) || right.contains(target_id)) {
+            return false;
+        }
+        let (left_size, total) = match direction {
+            SplitDirection::Vertical => (left_area.width, area.width),
+            SplitDirection::Horizontal => (left_area.height, area.height),
+        };
+        if total < 2 {
+            return false;
+        }
+        // both sides keep at least one cell
+        let left_size = (i32::from(left_size) + amount).clamp(1, i32::from(total) - 1) as u32;
+        *left_weight = left_size;
+        *right_weight = u32::from(total) - left_size;
+        true
+    }
+
+    /// the split whose divider - the first column/row of its right/bottom side - runs through the cell,
+    /// as the sides (false left, true right) to take from this node to reach it. outer splits win
+    pub fn divider_at(&self, area: Rect, x: u16, y: u16) -> Option<Vec<bool>> {
+        let LayoutNode::Split {
+            direction,
+            left,
+            right,
+            left_weight,
+            right_weight,
+        } = self
+        else {
+            return None;
+        };
+        let (left_area, right_area) = split_area(*direction, area, *left_weight, *right_weight);
+        let on_divider = match direction {
+            SplitDirection::Vertical => x == right_area.x && area.contains(x, y),
+            SplitDirection::Horizontal => y == right_area.y && area.contains(x, y),
+        };
+        if on_divider {
+            return Some(Vec::new());
+        }
+        let (side, child, child_area) = if left_area.contains(x, y) {
+            (false, left, left_area)
+        } else {
+            (true, right, right_area)
+        };
+        let mut path = child.divider_at(child_area, x, y)?;
+        path.insert(0, side);
+        Some(path)
+    }
+
+    /// moves the divider found by `divider_at` to the cell, both sides keep at least one cell.
+    /// returns whether it moved
+    pub fn drag_divider(&mut self, area: Rect, path: &[bool], x: u16, y: u16) -> bool {
+        let LayoutNode::Split {
+            direction,
+            left,
+            right,
+            left_weight,
+            right_weight,
+        } = self
+        else {
+            return false;
+        };
+        let (left_area, right_area) = split_area(*direction, area, *left_weight, *right_weight);
+        match path.split_first() {
+            Some((false, rest)) => left.drag_divider(left_area, rest, x, y),
+            Some((true, rest)) => right.drag_divider(right_area, rest, x, y),
+            None => {
+                let (position, start, total) = match direction {
+                    SplitDirection::Vertical => (x, area.x, area.width),
+                    SplitDirection::Horizontal => (y, area.y, area.height),
+                };
+                if total < 2 {
+                    return false;
+                }
+                let left_size = u32::from(position.saturating_sub(start).clamp(1, total - 1));
+                if (*left_weight, *right_weight) == (left_size, u32::from(total) - left_size) {
+                    return false;
+                }
+                *left_weight = left_size;
+                *right_weight = u32::from(total) - left_size;
+                true
+            }
+        }
+    }
+
+    /// the pane ids in layout order, left to right and top to bottom
+    pub fn pane_ids(&self) -> Vec<usize> {
+        match self {
+            LayoutNode::Pane { id } => vec![*id],
+            LayoutNode::Split { left, right, .. } => {
+                let mut ids = left.pane_ids();
+                ids.extend(right.pane_ids());
+                ids
+            }
+        }
+    }
+
+    /// exchanges where two panes sit, the rest of the tree is untouched
+    pub fn swap_panes(&mut self, a: usize, b: usize) {
+        self.set_pane_ids(&mut |id| match id {
+            id if id == a => b,
+            id if id == b => a,
+            id => id,
+        });
+    }
+
+    /// moves every pane to the next spot in layout order (the previous one when `downward` is false),
+    /// the last pane wrapping around to the first
+    pub fn rotate(&mut self, downward: bool) {
+        let mut ids = self.pane_ids();
+        if downward {
+            ids.rotate_right(1);
+        } else {
+            ids.rotate_left(1);
+        }
+        let mut ids = ids.into_iter();
+        self.set_pane_ids(&mut |id| ids.next().unwrap_or(id));
+    }
+
+    /// a new tree of the panes in layout order arranged as `preset`, `None` without any panes
+    pub fn preset(ids: &[usize], preset: LayoutPreset) -> Option<LayoutNode> {
+        let panes = |ids: &[usize]| ids.iter().map(|id| LayoutNode::Pane { id: *id }).collect();
+        match preset {
+            LayoutPreset::EvenHorizontal => Self::even(panes(ids), SplitDirection::Vertical),
+            LayoutPreset::EvenVertical => Self::even(panes(ids), SplitDirection::Horizontal),
+            LayoutPreset::Tiled => {
+                let cols = (1..).find(|cols| cols * cols >= ids.len())?;
+                let rows = ids
+                    .chunks(cols)
+                    .map(|row| Self::even(panes(row), SplitDirection::Vertical))
+                    .collect::<Option<Vec<_>>>()?;
+                Self::even(rows, SplitDirection::Horizontal)
+            }
+        }
+    }
+
+    /// chains `nodes` in one direction, weighted so each gets the same share of the area
+    fn even(mut nodes: Vec<LayoutNode>, direction: SplitDirection) -> Option<LayoutNode> {
+        let last = nodes.pop()?;
+        // built from the end, every split gives its left side one share and the right side the rest
+        let (node, _) = nodes.into_iter().rev().fold((last, 1), |(right, shares), left| {
+            let node = LayoutNode::Split {
+                direction,
+                left: Box::new(left),
+                right: Box::new(right),
+                left_weight: 1,
+                right_weight: shares,
+            };
+            (node, shares + 1)
+        });
+        Some(node)
+    }
+
+    fn set_pane_ids(&mut self, f: &mut impl FnMut(usize) -> usize) {
+        match self {
+            LayoutNode::Pane { id } => *id = f(*id),
+            LayoutNode::Split { left, right, .. } => {
+                left.set_pane_ids(f);
+                right.set_pane_ids(f);
+            }
+        }
+    }
+
     pub fn calculate_layout(&self, area: Rect, results: &mut HashMap<usize, Rect>) -> Result<()> {
         match self {
             LayoutNode::Pane { id } => {
@@ -109,55 +300,322 @@ impl LayoutNode {
                 left_weight,
                 right_weight,
             } => {
-                let total_weight = left_weight + right_weight;
-
-                match direction {
-                    SplitDirection::Vertical => {
-                        let left_width = (area.width as u32 * left_weight / total_weight) as u16;
-                        let right_width = area.width - left_width;
-
-                        let left_rect = Rect {
-                            width: left_width,
-                            ..area
-                        };
-
-                        let right_rect = Rect {
-                            width: right_width,
-                            x: area.x + left_width,
-                            ..area
-                        };
-
-                        trace!("left: {:?} left rect: {:?}", left, left_rect);
-                        trace!("right: {:?} right rect: {:?}", right, right_rect);
-
-                        left.calculate_layout(left_rect, results)?;
-                        right.calculate_layout(right_rect, results)?;
-                        Ok(())
-                    }
-                    SplitDirection::Horizontal => {
-                        let top_height = (area.height as u32 * left_weight / total_weight) as u16;
-                        let bottom_height = area.height - top_height;
-
-                        let top_rect = Rect {
-                            height: top_height,
-                            ..area
-                        };
-
-                        let bottom_rect = Rect {
-                            height: bottom_height,
-                            y: area.y + top_height,
-                            ..area
-                        };
-
-                        trace!("left: {:?} top rect: {:?}", left, top_rect);
-                        trace!("right: {:?} bottom rect: {:?}", right, bottom_rect);
-
-                        left.calculate_layout(top_rect, results)?;
-                        right.calculate_layout(bottom_rect, results)?;
-                        Ok(())
-                    }
-                }
+                let (left_rect, right_rect) = split_area(*direction, area, *left_weight, *right_weight);
+                trace!("left: {:?} rect: {:?}", left, left_rect);
+                trace!("right: {:?} rect: {:?}", right, right_rect);
+
+                left.calculate_layout(left_rect, results)?;
+                right.calculate_layout(right_rect, results)?;
+                Ok(())
+            }
+        }
+    }
+}
+
+/// the layout of a window with a zoomed pane - the window lays out only that pane while the full tree
+/// waits here to be put back
+#[derive(Debug)]
+pub struct ZoomedLayout {
+    pub pane_id: usize,
+    saved: LayoutNode,
+}
+impl ZoomedLayout {
+    /// swaps the layout for the pane alone
+    pub fn zoom(layout: &mut LayoutNode, pane_id: usize) -> Self {
+        let saved = mem::replace(layout, LayoutNode::Pane { id: pane_id });
+        Self { pane_id, saved }
+    }
+
+    pub fn restore(self, layout: &mut LayoutNode) {
+        *layout = self.saved;
+    }
+}
+
+/// divides the area between the two sides of a split - side by side when vertical, stacked when horizontal
+fn split_area(direction: SplitDirection, area: Rect, left_weight: u32, right_weight: u32) -> (Rect, Rect) {
+    let total_weight = left_weight + right_weight;
+    match direction {
+        SplitDirection::Vertical => {
+            let left_width = (area.width as u32 * left_weight / total_weight) as u16;
+            let left_rect = Rect {
+                width: left_width,
+                ..area
+            };
+            let right_rect = Rect {
+                width: area.width - left_width,
+                x: area.x + left_width,
+                ..area
+            };
+            (left_rect, right_rect)
+        }
+        SplitDirection::Horizontal => {
+            let top_height = (area.height as u32 * left_weight / total_weight) as u16;
+            let top_rect = Rect {
+                height: top_height,
+                ..area
+            };
+            let bottom_rect = Rect {
+                height: area.height - top_height,
+                y: area.y + top_height,
+                ..area
+            };
+            (top_rect, bottom_rect)
+        }
+    }
+}
+
+/// size of a floating pane relative to the window it floats above
+#[derive(Debug, Clone, Copy)]
+pub struct FloatingGeometry {
+    pub width_percent: u16,
+    pub height_percent: u16,
+}
+impl Default for FloatingGeometry {
+    fn default() -> Self {
+        Self {
+            width_percent: 80,
+            height_percent: 80,
+        }
+    }
+}
+impl FloatingGeometry {
+    /// outer rect (including the border) centered in the given area
+    pub fn rect(&self, area: Rect) -> Rect {
+        let width = (area.width as u32 * self.width_percent as u32 / 100).max(3) as u16;
+        let height = (area.height as u32 * self.height_percent as u32 / 100).max(3) as u16;
+        let width = width.min(area.width);
+        let height = height.min(area.height);
+        Rect {
+            x: area.x + (area.width - width) / 2,
+            y: area.y + (area.height - height) / 2,
+            width,
+            height,
+        }
+    }
+}
+
+impl Rect {
+    /// the rect shrunk by one cell on every side
+    pub fn inner(&self) -> Rect {
+        Rect {
+            x: self.x + 1,
+            y: self.y + 1,
+            width: self.width.saturating_sub(2),
+            height: self.height.saturating_sub(2),
+        }
+    }
+
+    pub fn contains(&self, x: u16, y: u16) -> bool {
+        x >= self.x && x < self.x + self.width && y >= self.y && y < self.y + self.height
+    }
+
+    /// the rect without the divider column on its left and row above it, which `pane-borders` draws
+    /// lines in - there's no divider along the area's edge. a single cell is kept for the pane
+    pub fn inside_borders(&self, area: Rect) -> Rect {
+        let mut rect = *self;
+        if rect.x > area.x && rect.width > 1 {
+            rect.x += 1;
+            rect.width -= 1;
+        }
+        if rect.y > area.y && rect.height > 1 {
+            rect.y += 1;
+            rect.height -= 1;
+        }
+        rect
+    }
+}
+
+/// a cell of the area no pane covers, with which of its neighbours (up, down, left, right)
+/// are border cells too so lines can be joined where they meet
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct BorderCell {
+    pub x: u16,
+    pub y: u16,
+    pub joins: [bool; 4],
+}
+
+pub fn border_cells(area: Rect, panes: &[Rect]) -> Vec<BorderCell> {
+    let (width, height) = (usize::from(area.width), usize::from(area.height));
+    let mut covered = vec![false; width * height];
+    for pane in panes {
+        for y in pane.y.max(area.y)..(pane.y + pane.height).min(area.y + area.height) {
+            for x in pane.x.max(area.x)..(pane.x + pane.width).min(area.x + area.width) {
+                covered[usize::from(y - area.y) * width + usize::from(x - area.x)] = true;
+            }
+        }
+    }
+    let is_border = |x: Option<usize>, y: Option<usize>| match (x, y) {
+        (Some(x), Some(y)) if x < width && y < height => !covered[y * width + x],
+        _ => false,
+    };
+    let mut cells = Vec::new();
+    for y in 0..height {
+        for x in 0..width {
+            if covered[y * width + x] {
+                continue;
             }
+            cells.push(BorderCell {
+                x: area.x + x as u16,
+                y: area.y + y as u16,
+                joins: [
+                    is_border(Some(x), y.checked_sub(1)),
+                    is_border(Some(x), Some(y + 1)),
+                    is_border(x.checked_sub(1), Some(y)),
+                    is_border(Some(x + 1), Some(y)),
+                ],
+            });
         }
     }
+    cells
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_resize() {
+        let area = Rect {
+            x: 0,
+            y: 0,
+            width: 80,
+            height: 24,
+        };
+        // 0 | (1 over 2)
+        let mut layout = LayoutNode::Pane { id: 0 };
+        layout.add_split(0, 1, SplitDirection::Vertical);
+        layout.add_split(1, 2, SplitDirection::Horizontal);
+
+        let rects = |layout: &LayoutNode| {
+            let mut results = HashMap::new();
+            layout.calculate_layout(area, &mut results).unwrap();
+            results
+        };
+        assert!(layout.resize(area, 2, SplitDirection::Vertical, -10));
+        assert_eq!(rects(&layout)[&0].width, 30);
+        assert_eq!(rects(&layout)[&2].width, 50);
+        // the innermost split of the direction moves
+        assert!(layout.resize(area, 2, SplitDirection::Horizontal, -2));
+        assert_eq!(rects(&layout)[&1].height, 10);
+        assert_eq!(rects(&layout)[&2].height, 14);
+        // clamped so both sides keep a cell
+        assert!(layout.resize(area, 0, SplitDirection::Vertical, -100));
+        assert_eq!(rects(&layout)[&0].width, 1);
+        // no horizontal split holds pane 0
+        assert!(!layout.resize(area, 0, SplitDirection::Horizontal, 1));
+
+        let before = rects(&layout);
+        let zoomed = ZoomedLayout::zoom(&mut layout, 1);
+        assert_eq!(rects(&layout), HashMap::from([(1, area)]));
+        zoomed.restore(&mut layout);
+        assert_eq!(rects(&layout), before);
+    }
+
+    #[test]
+    fn test_swap_and_rotate() {
+        // 0 | (1 over 2)
+        let mut layout = LayoutNode::Pane { id: 0 };
+        layout.add_split(0, 1, SplitDirection::Vertical);
+        layout.add_split(1, 2, SplitDirection::Horizontal);
+        assert_eq!(layout.pane_ids(), vec![0, 1, 2]);
+
+        layout.swap_panes(0, 2);
+        assert_eq!(layout.pane_ids(), vec![2, 1, 0]);
+        layout.rotate(true);
+        assert_eq!(layout.pane_ids(), vec![0, 2, 1]);
+        layout.rotate(false);
+        assert_eq!(layout.pane_ids(), vec![2, 1, 0]);
+    }
+
+    #[test]
+    fn test_preset() {
+        let area = Rect {
+            x: 0,
+            y: 0,
+            width: 90,
+            height: 24,
+        };
+        let rects = |layout: &LayoutNode| {
+            let mut results = HashMap::new();
+            layout.calculate_layout(area, &mut results).unwrap();
+            results
+        };
+        let layout = LayoutNode::preset(&[3, 4, 5], LayoutPreset::EvenHorizontal).unwrap();
+        assert_eq!(layout.pane_ids(), vec![3, 4, 5]);
+        assert!(
+            rects(&layout)
+                .values()
+                .all(|rect| rect.width == 30 && rect.height == 24)
+        );
+
+        // 3 | 4 | 5 over 6 | 7
+        let layout = LayoutNode::preset(&[3, 4, 5, 6, 7], LayoutPreset::Tiled).unwrap();
+        assert_eq!(layout.pane_ids(), vec![3, 4, 5, 6, 7]);
+        let rects = rects(&layout);
+        assert_eq!((rects[&5].x, rects[&5].width, rects[&5].height), (60, 30, 12));
+        assert_eq!((rects[&7].x, rects[&7].y, rects[&7].width), (45, 12, 45));
+        assert!(LayoutNode::preset(&[], LayoutPreset::EvenVertical).is_none());
+    }
+
+    #[test]
+    fn test_drag_divider() {
+        let area = Rect {
+            x: 0,
+            y: 0,
+            width: 80,
+            height: 24,
+        };
+        // 0 | (1 over 2)
+        let mut layout = LayoutNode::Pane { id: 0 };
+        layout.add_split(0, 1, SplitDirection::Vertical);
+        layout.add_split(1, 2, SplitDirection::Horizontal);
+
+        assert_eq!(layout.divider_at(area, 40, 5), Some(vec![]));
+        assert_eq!(layout.divider_at(area, 60, 12), Some(vec![true]));
+        assert_eq!(layout.divider_at(area, 60, 5), None);
+        assert_eq!(layout.divider_at(area, 10, 12), None);
+
+        assert!(layout.drag_divider(area, &[true], 60, 6));
+        assert!(!layout.drag_divider(area, &[true], 70, 6));
+        assert!(layout.drag_divider(area, &[], 90, 6));
+        let mut results = HashMap::new();
+        layout.calculate_layout(area, &mut results).unwrap();
+        assert_eq!(results[&0].width, 79);
+        assert_eq!(results[&1].height, 6);
+    }
+
+    #[test]
+    fn test_borders() {
+        let area = Rect {
+            x: 0,
+            y: 0,
+            width: 9,
+            height: 5,
+        };
+        // 0 | (1 over 2)
+        let mut layout = LayoutNode::Pane { id: 0 };
+        layout.add_split(0, 1, SplitDirection::Vertical);
+        layout.add_split(1, 2, SplitDirection::Horizontal);
+        let mut results = HashMap::new();
+        layout.calculate_layout(area, &mut results).unwrap();
+        let panes: Vec<Rect> = (0..3).map(|id| results[&id].inside_borders(area)).collect();
+        assert_eq!(panes[0], Rect { width: 4, ..area });
+        assert_eq!(
+            panes[2],
+            Rect {
+                x: 5,
+                y: 3,
+                width: 4,
+                height: 2
+            }
+        );
+
+        let cells = border_cells(area, &panes);
+        // the divider down column 4 and the one across row 2 on the right
+        assert_eq!(cells.len(), 5 + 4);
+        let tee = cells.iter().find(|cell| (cell.x, cell.y) == (4, 2)).unwrap();
+        assert_eq!(tee.joins, [true, true, false, true]);
+        let end = cells.iter().find(|cell| (cell.x, cell.y) == (8, 2)).unwrap();
+        assert_eq!(end.joins, [false, false, true, false]);
+    }
 }
@@ -1,10 +1,29 @@
 mod actors;
+mod cell;
+mod config;
+mod control_mode;
 mod control_signals;
+mod copy_mode;
 mod daemon;
+mod environment;
+mod frame_scheduler;
+mod hooks;
 mod layout;
+mod options;
+#[cfg(feature = "otel")]
+mod otel;
+mod output_batch;
+mod pane_log;
+mod pane_title;
+mod paste_buffers;
+mod platform;
 mod prelude;
+mod supervision;
+mod terminal_modes;
 
+use color_eyre::eyre::eyre;
 use daemon::RemuxDaemon;
+use remux_core::constants::DEFAULT_SOCKET_NAME;
 
 use crate::prelude::*;
 
@@ -36,6 +55,8 @@ fn setup_logging() -> Result<()> {
         .with_thread_ids(false)
         .finish()
         .with(ErrorLayer::default());
+    #[cfg(feature = "otel")]
+    let subscriber = subscriber.with(otel::layer_from_env()?);
 
     tracing::subscriber::set_global_default(subscriber)?;
     Ok(())
@@ -43,7 +64,32 @@ fn setup_logging() -> Result<()> {
 
 #[instrument(err)]
 async fn run() -> Result<()> {
-    let daemon = RemuxDaemon::new()?;
+    let daemon = RemuxDaemon::new(socket_name(std::env::args().skip(1))?)?;
     info!("daemon started");
+    daemon.source_config().await?;
     daemon.listen().await
 }
+
+/// `-L <name>` as the cli passes it on, so each socket name gets its own daemon
+fn socket_name(mut args: impl Iterator<Item = String>) -> Result<String> {
+    match (args.next().as_deref(), args.next()) {
+        (None, _) => Ok(DEFAULT_SOCKET_NAME.to_owned()),
+        (Some("-L"), Some(name)) => Ok(name),
+        (Some(arg), _) => Err(eyre!("usage: remux-daemon [-L socket-name], got {arg}")),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    #![allow(clippy::unwrap_used)]
+    use super::*;
+
+    #[test]
+    fn test_socket_name() {
+        let args = |args: &[&str]| args.iter().map(|arg| arg.to_string()).collect::<Vec<_>>().into_iter();
+        assert_eq!(socket_name(args(&[])).unwrap(), "default");
+        assert_eq!(socket_name(args(&["-L", "work"])).unwrap(), "work");
+        assert!(socket_name(args(&["-L"])).is_err());
+        assert!(socket_name(args(&["--help"])).is_err());
+    }
+}
@@ -0,0 +1,249 @@
+// server wide options changed with `set-option`, panes read them through a watch channel
+
+use color_eyre::eyre::eyre;
+use tokio::sync::watch;
+
+use crate::{pane_title::DEFAULT_TITLE_FORMAT, platform, prelude::*};
+
+pub type OptionsReceiver = watch::Receiver<Options>;
+
+pub const DEFAULT_TERMINAL: &str = "remux-256color";
+// tmux's defaults plus wayland
+const UPDATE_ENVIRONMENT: [&str; 8] = [
+    "DISPLAY",
+    "KRB5CCNAME",
+    "SSH_ASKPASS",
+    "SSH_AUTH_SOCK",
+    "SSH_AGENT_PID",
+    "SSH_CONNECTION",
+    "WAYLAND_DISPLAY",
+    "XAUTHORITY",
+];
+
+#[derive(Debug, Clone)]
+pub struct Options {
+    pub scrollbar: bool,                 // scrollbar down the right edge of scrolled back panes
+    pub monitor_activity: bool,          // alert when a session nobody is viewing prints something
+    pub monitor_silence: u64,            // alert after this many seconds without output, 0 disables
+    pub alert_bell: bool,                // clients ring the terminal bell on alerts
+    pub bell_action: BellAction,         // which panes' bells clients pass on to the outer terminal
+    pub default_terminal: String,        // `TERM` new panes start with
+    pub default_shell: Option<String>,   // program new panes run, the user's shell when unset
+    pub update_environment: Vec<String>, // copied from a client's environment into the session when it attaches
+    pub auto_respawn: bool,              // respawn shells that exit cleanly, panes can override it
+    pub screen_reader: bool,             // plain ascii decorations and spoken focus changes
+    pub destroy_unattached: bool,        // sessions go away when their last client detaches
+    pub exit_empty: bool,                // the daemon exits once its last session is destroyed
+    pub status: bool,                    // clients show the status line, off gives its row to the panes
+    pub pane_borders: bool,              // lines between tiled panes, off lets them sit side by side
+    pub pane_titles: bool,               // a header row above every pane
+    pub pane_title_format: String,       // header text, see `pane_title::format_title`
+    pub client_cursors: bool,            // clients show where the others sharing their session last clicked
+    pub history_limit: usize,            // scrollback lines kept per pane, panes spawned later pick up changes
+    pub automatic_rename: bool,          // windows without a given name follow their focused pane's program
+    pub frame_rate: u32,                 // most frames a second a busy pane is drawn at, 0 draws every read
+}
+
+impl Default for Options {
+    fn default() -> Self {
+        Self {
+            scrollbar: false,
+            monitor_activity: false,
+            monitor_silence: 0,
+            alert_bell: false,
+            bell_action: BellAction::Any,
+            default_terminal: DEFAULT_TERMINAL.to_owned(),
+            default_shell: None,
+            update_environment: UPDATE_ENVIRONMENT.map(str::to_owned).to_vec(),
+            auto_respawn: false,
+            screen_reader: false,
+            destroy_unattached: false,
+            // unlike tmux, nothing starts the daemon again on the next attach
+            exit_empty: false,
+            status: true,
+            pane_borders: true,
+            pane_titles: false,
+            pane_title_format: DEFAULT_TITLE_FORMAT.to_owned(),
+            client_cursors: false,
+            history_limit: 2000,
+            automatic_rename: true,
+            frame_rate: 60,
+        }
+    }
+}
+
+impl Options {
+    pub fn set(&mut self, name: &str, value: &str) -> Result<()> {
+        match name {
+            "scrollbar" => self.scrollbar = parse_flag(name, value)?,
+            "monitor-activity" => self.monitor_activity = parse_flag(name, value)?,
+            "monitor-silence" => {
+                self.monitor_silence = value
+                    .parse()
+                    .map_err(|_| eyre!("{name}: expected seconds, got {value}"))?
+            }
+            "alert-bell" => self.alert_bell = parse_flag(name, value)?,
+            "bell-action" => self.bell_action = BellAction::parse(name, value)?,
+            "auto-respawn" => self.auto_respawn = parse_flag(name, value)?,
+            "screen-reader" => self.screen_reader = parse_flag(name, value)?,
+            "destroy-unattached" => self.destroy_unattached = parse_flag(name, value)?,
+            "exit-empty" => self.exit_empty = parse_flag(name, value)?,
+            "status" if value == "toggle" => self.status = !self.status,
+            "status" => self.status = parse_flag(name, value)?,
+            "pane-borders" => self.pane_borders = parse_flag(name, value)?,
+            "pane-titles" => self.pane_titles = parse_flag(name, value)?,
+            "pane-title-format" => self.pane_title_format = value.to_owned(),
+            "client-cursors" => self.client_cursors = parse_flag(name, value)?,
+            "automatic-rename" => self.automatic_rename = parse_flag(name, value)?,
+            "history-limit" => {
+                self.history_limit = value
+                    .parse()
+                    .map_err(|_| eyre!("{name}: expected a number of lines, got {value}"))?
+            }
+            "frame-rate" => {
+                self.frame_rate = value
+                    .parse()
+                    .map_err(|_| eyre!("{name}: expected frames per second, got {value}"))?
+            }
+            "default-terminal" if value.is_empty() => return Err(eyre!("{name}: expected a terminal name")),
+            "default-terminal" => self.default_terminal = value.to_owned(),
+            "default-shell" if value.is_empty() => self.default_shell = None,
+            "default-shell" => self.default_shell = Some(value.to_owned()),
+            "update-environment" => self.update_environment = value.split_whitespace().map(str::to_owned).collect(),
+            _ => return Err(eyre!("unknown option: {name}")),
+        }
+        Ok(())
+    }
+
+    /// `default-shell`, then `$SHELL` and the login shell
+    pub fn shell(&self) -> String {
+        self.default_shell.clone().unwrap_or_else(platform::default_shell)
+    }
+
+    /// argv running `command` through the shell, like tmux does for `new-session <command>`
+    pub fn shell_command(&self, command: String) -> Vec<String> {
+        vec![self.shell(), "-c".to_owned(), command]
+    }
+}
+
+/// `bell-action` like tmux's, whether a bell is passed on depends on the window it rang in
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BellAction {
+    Any,
+    None,
+    Current, // only the session's active window
+    Other,   // only windows in the background
+}
+
+impl BellAction {
+    fn parse(name: &str, value: &str) -> Result<Self> {
+        match value {
+            "any" => Ok(Self::Any),
+            "none" => Ok(Self::None),
+            "current" => Ok(Self::Current),
+            "other" => Ok(Self::Other),
+            _ => Err(eyre!("{name}: expected any/none/current/other, got {value}")),
+        }
+    }
+
+    pub fn passes(self, current: bool) -> bool {
+        match self {
+            Self::Any => true,
+            Self::None => false,
+            Self::Current => current,
+            Self::Other => !current,
+        }
+    }
+}
+
+/// options `set-option -s` can override for a single session
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SessionOption {
+    DestroyUnattached(bool),
+}
+
+impl SessionOption {
+    pub fn parse(name: &str, value: &str) -> Result<Self> {
+        match name {
+            "destroy-unattached" => Ok(Self::DestroyUnattached(parse_flag(name, value)?)),
+            _ => Err(eyre!("{name} can't be set per session")),
+        }
+    }
+}
+
+/// options `set-option -p`/`-w` can override for a single pane or window
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PaneOption {
+    AutoRespawn(bool),
+}
+
+impl PaneOption {
+    pub fn parse(name: &str, value: &str) -> Result<Self> {
+        match name {
+            "auto-respawn" => Ok(Self::AutoRespawn(parse_flag(name, value)?)),
+            _ => Err(eyre!("{name} can't be set per pane or window")),
+        }
+    }
+}
+
+fn parse_flag(name: &str, value: &str) -> Result<bool> {
+    match value {
+        "on" | "true" | "1" => Ok(true),
+        "off" | "false" | "0" => Ok(false),
+        _ => Err(eyre!("{name}: expected on/off, got {value}")),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    #![allow(clippy::unwrap_used)]
+    use super::*;
+
+    #[test]
+    fn test_set_option() {
+        let mut options = Options::default();
+        options.set("scrollbar", "on").unwrap();
+        assert!(options.scrollbar);
+        assert!(options.set("scrollbar", "maybe").is_err());
+        options.set("history-limit", "50000").unwrap();
+        assert_eq!(options.history_limit, 50000);
+        assert!(options.set("history-limit", "lots").is_err());
+        options.set("monitor-silence", "30").unwrap();
+        assert_eq!(options.monitor_silence, 30);
+        assert!(options.set("monitor-silence", "-1").is_err());
+        options.set("frame-rate", "30").unwrap();
+        assert_eq!(options.frame_rate, 30);
+        assert!(options.set("frame-rate", "fast").is_err());
+        options.set("bell-action", "other").unwrap();
+        assert!(!options.bell_action.passes(true));
+        assert!(options.bell_action.passes(false));
+        assert!(options.set("bell-action", "loud").is_err());
+        options.set("default-terminal", "xterm-256color").unwrap();
+        assert_eq!(options.default_terminal, "xterm-256color");
+        options.set("default-shell", "/bin/fish").unwrap();
+        assert_eq!(
+            options.shell_command("make".to_owned()),
+            vec!["/bin/fish", "-c", "make"]
+        );
+        options.set("default-shell", "").unwrap();
+        assert_eq!(options.default_shell, None);
+        options.set("update-environment", "SSH_AUTH_SOCK DISPLAY").unwrap();
+        assert_eq!(options.update_environment, vec!["SSH_AUTH_SOCK", "DISPLAY"]);
+        options.set("status", "toggle").unwrap();
+        assert!(!options.status);
+        options.set("status", "toggle").unwrap();
+        assert!(options.status);
+        options.set("automatic-rename", "off").unwrap();
+        assert!(!options.automatic_rename);
+        assert!(options.set("not-an-option", "on").is_err());
+        assert_eq!(
+            PaneOption::parse("auto-respawn", "on").unwrap(),
+            PaneOption::AutoRespawn(true)
+        );
+        assert!(PaneOption::parse("scrollbar", "on").is_err());
+        assert_eq!(
+            SessionOption::parse("destroy-unattached", "on").unwrap(),
+            SessionOption::DestroyUnattached(true)
+        );
+    }
+}
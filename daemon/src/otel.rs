@@ -0,0 +1,116 @@
+// span export to an OTLP collector over HTTP, built with `--features otel`
+//
+// setting `OTEL_EXPORTER_OTLP_ENDPOINT` (e.g. `http://localhost:4318`, `https://` works too) turns it on, the
+// exporter reads the rest of the `OTEL_EXPORTER_OTLP_*` and `OTEL_BSP_*` variables itself. actors hand the span
+// a message was sent from along with it (see `actors::message_span`), so a keypress is one trace from the
+// client through the pty and back out to the frame it drew
+
+use std::time::Duration;
+
+use opentelemetry::{
+    Context, KeyValue,
+    trace::{Link, SamplingDecision, SamplingResult, SpanKind, TraceContextExt, TraceId, TracerProvider},
+};
+use opentelemetry_otlp::{SpanExporter, WithExportConfig};
+use opentelemetry_sdk::{
+    Resource,
+    trace::{BatchSpanProcessor, Sampler, SdkTracer, SdkTracerProvider, ShouldSample},
+};
+use tracing::Subscriber;
+use tracing_opentelemetry::OpenTelemetryLayer;
+use tracing_subscriber::registry::LookupSpan;
+
+use crate::prelude::*;
+
+const ENDPOINT_VARS: [&str; 2] = ["OTEL_EXPORTER_OTLP_ENDPOINT", "OTEL_EXPORTER_OTLP_TRACES_ENDPOINT"];
+const SERVICE_NAME_VAR: &str = "OTEL_SERVICE_NAME";
+const DEFAULT_SERVICE_NAME: &str = "remux-daemon";
+// connecting included, a collector that's gone only holds up the exporter's thread for this long
+const EXPORT_TIMEOUT: Duration = Duration::from_secs(5);
+// the spans traces start at, `client_connection.rs` starts one for every cli event
+const TRACE_ROOTS: [&str; 1] = ["Recieved Cli Event"];
+
+/// the exporting layer when an OTLP endpoint is set, `None` otherwise
+pub fn layer_from_env<S>() -> Result<Option<OpenTelemetryLayer<S, SdkTracer>>>
+where
+    S: Subscriber + for<'a> LookupSpan<'a>,
+{
+    if !ENDPOINT_VARS.iter().any(|var| std::env::var_os(var).is_some()) {
+        return Ok(None);
+    }
+    let service_name = std::env::var(SERVICE_NAME_VAR).unwrap_or_else(|_| DEFAULT_SERVICE_NAME.to_owned());
+    let exporter = SpanExporter::builder()
+        .with_http()
+        .with_timeout(EXPORT_TIMEOUT)
+        .build()?;
+    // exports from a thread of its own, spans past its queue (`OTEL_BSP_MAX_QUEUE_SIZE`, 2048 by default) are
+    // dropped rather than held while the collector is slow
+    let provider = SdkTracerProvider::builder()
+        .with_span_processor(BatchSpanProcessor::builder(exporter).build())
+        .with_sampler(Sampler::ParentBased(Box::new(TraceRoots)))
+        .with_resource(Resource::builder().with_service_name(service_name).build())
+        .build();
+    Ok(Some(
+        tracing_opentelemetry::layer().with_tracer(provider.tracer(DEFAULT_SERVICE_NAME)),
+    ))
+}
+
+/// keeps the traces started by a cli event, `Sampler::ParentBased` keeps everything in them. the actors' own
+/// spans and what they do unprompted would otherwise be a trace per actor, as long as the actor lives
+#[derive(Debug, Clone)]
+struct TraceRoots;
+
+impl ShouldSample for TraceRoots {
+    fn should_sample(
+        &self,
+        parent_context: Option<&Context>,
+        _trace_id: TraceId,
+        name: &str,
+        _span_kind: &SpanKind,
+        _attributes: &[KeyValue],
+        _links: &[Link],
+    ) -> SamplingResult {
+        let decision = if TRACE_ROOTS.contains(&name) {
+            SamplingDecision::RecordAndSample
+        } else {
+            SamplingDecision::Drop
+        };
+        SamplingResult {
+            decision,
+            attributes: Vec::new(),
+            trace_state: parent_context
+                .map(|cx| cx.span().span_context().trace_state().clone())
+                .unwrap_or_default(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use opentelemetry::trace::{SpanContext, SpanId, TraceFlags, TraceState};
+
+    use super::*;
+
+    #[test]
+    fn test_trace_roots() {
+        let sampler = Sampler::ParentBased(Box::new(TraceRoots));
+        let decision = |parent: Option<&Context>, name| {
+            sampler
+                .should_sample(parent, TraceId::from(1), name, &SpanKind::Internal, &[], &[])
+                .decision
+        };
+        assert_eq!(decision(None, "Recieved Cli Event"), SamplingDecision::RecordAndSample);
+        assert_eq!(decision(None, "Client Actor"), SamplingDecision::Drop);
+        assert_eq!(decision(None, "message"), SamplingDecision::Drop);
+
+        // messages sent while handling a cli event are in its trace
+        let keypress = Context::new().with_remote_span_context(SpanContext::new(
+            TraceId::from(1),
+            SpanId::from(2),
+            TraceFlags::SAMPLED,
+            false,
+            TraceState::default(),
+        ));
+        assert_eq!(decision(Some(&keypress), "message"), SamplingDecision::RecordAndSample);
+    }
+}
@@ -0,0 +1,227 @@
+// per client batching of session output, so a burst of small pty reads goes out as a few frames
+// and a client that can't keep up never blocks the session manager on its channel. a client that
+// falls behind has the panes it's watching stop reading their ptys until it catches up. control mode
+// clients get each pane's raw output instead, queued the same way
+
+use std::{
+    collections::VecDeque,
+    sync::{
+        Arc,
+        atomic::{AtomicUsize, Ordering},
+    },
+};
+
+use bytes::{Bytes, BytesMut};
+use tokio::time::{Duration, Instant};
+
+/// how long output is collected before it's sent on
+pub const OUTPUT_BATCH_WINDOW: Duration = Duration::from_millis(5);
+const MAX_BATCH_BYTES: usize = 64 * 1024;
+// kept below the client actor's channel capacity so sending a batch never has to wait
+const MAX_BATCHES_IN_FLIGHT: usize = 4;
+// past this the client is far enough behind that a redraw is cheaper than catching up
+const MAX_PENDING_BYTES: usize = 4 * 1024 * 1024;
+// past this the client is behind and its panes are paused
+const BEHIND_PENDING_BYTES: usize = 1024 * 1024;
+// a client stuck for longer stops holding its panes back, its output is dropped and redrawn instead
+const MAX_STALL: Duration = Duration::from_secs(2);
+
+/// held by the client actor until the batch is written to its socket
+#[derive(Debug)]
+pub struct OutputCredit(Arc<AtomicUsize>);
+
+impl Drop for OutputCredit {
+    fn drop(&mut self) {
+        self.0.fetch_sub(1, Ordering::Relaxed);
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct ClientOutput {
+    pending: BytesMut,
+    pane_pending: VecDeque<(usize, BytesMut)>, // raw pane output in the order it was read, by pane id
+    pane_pending_bytes: usize,
+    in_flight: Arc<AtomicUsize>, // batches handed to the client actor and not yet written
+    overflowed: bool,
+    behind_since: Option<Instant>,
+}
+
+impl ClientOutput {
+    /// queues output for the next batch, everything is dropped once too much has piled up
+    pub fn push(&mut self, bytes: &[u8]) {
+        if self.overflows(bytes.len()) {
+            self.clear();
+        } else {
+            self.pending.extend_from_slice(bytes);
+        }
+    }
+
+    /// queues a pane's raw output, runs of output from the same pane go out together
+    pub fn push_pane(&mut self, pane_id: usize, bytes: &[u8]) {
+        if self.overflows(bytes.len()) {
+            self.clear();
+            return;
+        }
+        self.pane_pending_bytes += bytes.len();
+        match self.pane_pending.back_mut() {
+            Some((id, pending)) if *id == pane_id => pending.extend_from_slice(bytes),
+            _ => self.pane_pending.push_back((pane_id, BytesMut::from(bytes))),
+        }
+    }
+
+    fn pending_bytes(&self) -> usize {
+        self.pending.len() + self.pane_pending_bytes
+    }
+
+    fn overflows(&self, len: usize) -> bool {
+        self.pending_bytes() + len > MAX_PENDING_BYTES
+    }
+
+    fn clear(&mut self) {
+        self.pending.clear();
+        self.pane_pending.clear();
+        self.pane_pending_bytes = 0;
+        self.overflowed = true;
+    }
+
+    fn take_credit(&self) -> Option<OutputCredit> {
+        if self.in_flight.load(Ordering::Relaxed) >= MAX_BATCHES_IN_FLIGHT {
+            return None;
+        }
+        self.in_flight.fetch_add(1, Ordering::Relaxed);
+        Some(OutputCredit(self.in_flight.clone()))
+    }
+
+    /// the next batch if the client has room for it, anything over the batch size stays queued
+    pub fn take_batch(&mut self) -> Option<(Bytes, OutputCredit)> {
+        if self.pending.is_empty() {
+            return None;
+        }
+        let credit = self.take_credit()?;
+        let len = self.pending.len().min(MAX_BATCH_BYTES);
+        Some((self.pending.split_to(len).freeze(), credit))
+    }
+
+    /// the next pane's batch of raw output, with its pane id
+    pub fn take_pane_batch(&mut self) -> Option<(usize, Bytes, OutputCredit)> {
+        if self.pane_pending.is_empty() {
+            return None;
+        }
+        let credit = self.take_credit()?;
+        let (pane_id, pending) = self.pane_pending.front_mut()?;
+        let pane_id = *pane_id;
+        let batch = pending.split_to(pending.len().min(MAX_BATCH_BYTES)).freeze();
+        if pending.is_empty() {
+            self.pane_pending.pop_front();
+        }
+        self.pane_pending_bytes -= batch.len();
+        Some((pane_id, batch, credit))
+    }
+
+    pub fn has_pending(&self) -> bool {
+        self.pending_bytes() > 0
+    }
+
+    /// whether output was dropped since the last call, the client's screen needs redrawing
+    pub fn take_overflow(&mut self) -> bool {
+        std::mem::take(&mut self.overflowed)
+    }
+
+    /// whether the client has enough queued up that the panes feeding it should stop reading for now
+    pub fn is_behind(&mut self, now: Instant) -> bool {
+        if self.pending_bytes() < BEHIND_PENDING_BYTES {
+            self.behind_since = None;
+            return false;
+        }
+        now.duration_since(*self.behind_since.get_or_insert(now)) < MAX_STALL
+    }
+}
+
+#[cfg(test)]
+mod test {
+    #![allow(clippy::unwrap_used)]
+    use super::*;
+
+    #[test]
+    fn test_client_output() {
+        let mut output = ClientOutput::default();
+        assert!(output.take_batch().is_none());
+
+        output.push(b"hello ");
+        output.push(b"world");
+        let (batch, credit) = output.take_batch().unwrap();
+        assert_eq!(&batch[..], b"hello world");
+        assert!(!output.has_pending());
+
+        // a slow client stops getting batches until it writes some of them
+        let mut credits = vec![credit];
+        for _ in 1..MAX_BATCHES_IN_FLIGHT {
+            output.push(b"x");
+            credits.push(output.take_batch().unwrap().1);
+        }
+        output.push(b"y");
+        assert!(output.take_batch().is_none());
+        credits.pop();
+        assert_eq!(&output.take_batch().unwrap().0[..], b"y");
+    }
+
+    #[test]
+    fn test_client_output_limits() {
+        let mut output = ClientOutput::default();
+        output.push(&vec![b'a'; MAX_BATCH_BYTES + 1]);
+        assert_eq!(output.take_batch().unwrap().0.len(), MAX_BATCH_BYTES);
+        assert_eq!(output.take_batch().unwrap().0.len(), 1);
+
+        output.push(&vec![b'a'; MAX_PENDING_BYTES]);
+        assert!(!output.take_overflow());
+        output.push(b"b");
+        assert!(!output.has_pending());
+        assert!(output.take_overflow());
+        assert!(!output.take_overflow());
+    }
+
+    #[test]
+    fn test_pane_output() {
+        let mut output = ClientOutput::default();
+        output.push_pane(1, b"ls");
+        output.push_pane(1, b"\r\n");
+        output.push_pane(2, b"top");
+        output.push_pane(1, &vec![b'a'; MAX_BATCH_BYTES + 1]);
+        assert!(output.take_batch().is_none());
+
+        let (pane_id, batch, _credit) = output.take_pane_batch().unwrap();
+        assert_eq!((pane_id, &batch[..]), (1, &b"ls\r\n"[..]));
+        let (pane_id, batch, _credit) = output.take_pane_batch().unwrap();
+        assert_eq!((pane_id, &batch[..]), (2, &b"top"[..]));
+        let (pane_id, batch, _credit) = output.take_pane_batch().unwrap();
+        assert_eq!((pane_id, batch.len()), (1, MAX_BATCH_BYTES));
+        // the credits are shared with session output, a slow client stops getting either
+        output.push(b"x");
+        let _batch = output.take_batch().unwrap();
+        assert!(output.take_pane_batch().is_none());
+        assert!(output.has_pending());
+
+        output.push_pane(3, &vec![b'a'; MAX_PENDING_BYTES]);
+        assert!(!output.has_pending());
+        assert!(output.take_overflow());
+    }
+
+    #[test]
+    fn test_client_output_behind() {
+        let start = Instant::now();
+        let mut output = ClientOutput::default();
+        output.push(&vec![b'a'; BEHIND_PENDING_BYTES - 1]);
+        assert!(!output.is_behind(start));
+        output.push(b"b");
+        assert!(output.is_behind(start));
+        assert!(output.is_behind(start + MAX_STALL / 2));
+        // stuck for too long, the panes go on without it
+        assert!(!output.is_behind(start + MAX_STALL));
+
+        // catching up starts the clock again
+        while output.take_batch().is_some() {}
+        assert!(!output.is_behind(start + MAX_STALL));
+        output.push(&vec![b'a'; BEHIND_PENDING_BYTES]);
+        assert!(output.is_behind(start + MAX_STALL * 2));
+    }
+}
@@ -0,0 +1,211 @@
+// `log-pane` - timestamped plain text log of everything a pane prints
+
+use std::{
+    fs::{self, File, OpenOptions},
+    io::Write,
+    path::PathBuf,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use remux_core::daemon_utils::get_state_dir;
+
+use crate::prelude::*;
+
+const MAX_LOG_SIZE: u64 = 5 * 1024 * 1024;
+const ROTATIONS: usize = 3; // `name.log.1` is the newest rotated file
+
+pub struct PaneLog {
+    path: PathBuf,
+    file: File,
+    size: u64,
+    decoder: LineDecoder,
+}
+
+impl PaneLog {
+    /// opens (appending to) `<state dir>/logs/<name>.log`
+    pub fn open(name: &str) -> Result<Self> {
+        let dir = get_state_dir()?.join("logs");
+        fs::create_dir_all(&dir)?;
+        let path = dir.join(format!("{name}.log"));
+        let file = OpenOptions::new().create(true).append(true).open(&path)?;
+        let size = file.metadata()?.len();
+        info!(path=?path, "Logging pane output");
+        Ok(Self {
+            path,
+            file,
+            size,
+            decoder: LineDecoder::default(),
+        })
+    }
+
+    pub fn path(&self) -> &PathBuf {
+        &self.path
+    }
+
+    pub fn write(&mut self, bytes: &[u8]) -> Result<()> {
+        for line in self.decoder.feed(bytes) {
+            let entry = format!("[{}] {line}\n", timestamp(SystemTime::now()));
+            self.file.write_all(entry.as_bytes())?;
+            self.size += entry.len() as u64;
+            if self.size >= MAX_LOG_SIZE {
+                self.rotate()?;
+            }
+        }
+        Ok(())
+    }
+
+    /// writes out a partially printed line (e.g. a shell prompt)
+    fn flush(&mut self) -> Result<()> {
+        if let Some(line) = self.decoder.take_partial() {
+            let entry = format!("[{}] {line}\n", timestamp(SystemTime::now()));
+            self.file.write_all(entry.as_bytes())?;
+        }
+        Ok(())
+    }
+
+    fn rotate(&mut self) -> Result<()> {
+        let rotated = |i: usize| PathBuf::from(format!("{}.{i}", self.path.display()));
+        for i in (1..ROTATIONS).rev() {
+            if rotated(i).exists() {
+                fs::rename(rotated(i), rotated(i + 1))?;
+            }
+        }
+        fs::rename(&self.path, rotated(1))?;
+        self.file = OpenOptions::new().create(true).append(true).open(&self.path)?;
+        self.size = 0;
+        Ok(())
+    }
+}
+
+impl Drop for PaneLog {
+    fn drop(&mut self) {
+        if let Err(e) = self.flush() {
+            warn!("Error while flushing pane log: {}", e);
+        }
+    }
+}
+
+#[derive(Debug, Default, PartialEq, Eq)]
+enum DecoderState {
+    #[default]
+    Ground,
+    Escape,
+    Csi,
+    Osc,
+    OscEscape, // `ESC` inside an osc, a following `\` terminates it
+}
+
+/// splits pty output into lines of text with escape sequences and carriage returns removed,
+/// keeps its state between calls so sequences split across reads are still stripped
+#[derive(Debug, Default)]
+struct LineDecoder {
+    state: DecoderState,
+    line: Vec<u8>,
+}
+
+impl LineDecoder {
+    fn feed(&mut self, bytes: &[u8]) -> Vec<String> {
+        use DecoderState::*;
+        let mut lines = Vec::new();
+        for &b in bytes {
+            self.state = match (&self.state, b) {
+                (Ground, b'\x1b') => Escape,
+                (Ground, b'\n') => {
+                    lines.push(String::from_utf8_lossy(&self.line).into_owned());
+                    self.line.clear();
+                    Ground
+                }
+                (Ground, b'\x08') => {
+                    self.line.pop();
+                    Ground
+                }
+                (Ground, b'\t') => {
+                    self.line.push(b);
+                    Ground
+                }
+                (Ground, b) if b < 0x20 || b == 0x7f => Ground,
+                (Ground, b) => {
+                    self.line.push(b);
+                    Ground
+                }
+                (Escape, b'[') => Csi,
+                (Escape, b']') => Osc,
+                // two byte sequences like `ESC 7` or `ESC =`, charset designations leave a stray byte
+                (Escape, _) => Ground,
+                (Csi, 0x40..=0x7e) => Ground,
+                (Csi, _) => Csi,
+                (Osc, b'\x07') => Ground,
+                (Osc, b'\x1b') => OscEscape,
+                (Osc, _) => Osc,
+                (OscEscape, b'\\') => Ground,
+                (OscEscape, _) => Osc,
+            };
+        }
+        lines
+    }
+
+    fn take_partial(&mut self) -> Option<String> {
+        if self.line.is_empty() {
+            return None;
+        }
+        let line = String::from_utf8_lossy(&self.line).into_owned();
+        self.line.clear();
+        Some(line)
+    }
+}
+
+/// `YYYY-MM-DD HH:MM:SS` in UTC
+fn timestamp(time: SystemTime) -> String {
+    let secs = time.duration_since(UNIX_EPOCH).map_or(0, |d| d.as_secs());
+    let (days, rem) = (secs / 86400, secs % 86400);
+    let (year, month, day) = civil_from_days(days as i64);
+    format!(
+        "{year:04}-{month:02}-{day:02} {:02}:{:02}:{:02}",
+        rem / 3600,
+        rem % 3600 / 60,
+        rem % 60
+    )
+}
+
+// days since 1970-01-01 to a (year, month, day) date, Howard Hinnant's algorithm
+fn civil_from_days(days: i64) -> (i64, u32, u32) {
+    let z = days + 719468;
+    let era = z.div_euclid(146097);
+    let doe = z.rem_euclid(146097);
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = (doy - (153 * mp + 2) / 5 + 1) as u32;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 } as u32;
+    let year = yoe + era * 400 + i64::from(month <= 2);
+    (year, month, day)
+}
+
+#[cfg(test)]
+mod test {
+    use std::time::Duration;
+
+    use super::*;
+
+    #[test]
+    fn test_line_decoder() {
+        let mut decoder = LineDecoder::default();
+        assert_eq!(
+            decoder.feed(b"\x1b[1;32mok\x1b[0m done\r\n\x1b]0;title\x07ab"),
+            vec!["ok done".to_owned()]
+        );
+        // sequence split across reads
+        assert_eq!(decoder.feed(b"c\x1b["), Vec::<String>::new());
+        assert_eq!(decoder.feed(b"2Kd\x08e\n"), vec!["abce".to_owned()]);
+        decoder.feed(b"$ ");
+        assert_eq!(decoder.take_partial(), Some("$ ".to_owned()));
+        assert_eq!(decoder.take_partial(), None);
+    }
+
+    #[test]
+    fn test_timestamp() {
+        assert_eq!(timestamp(UNIX_EPOCH), "1970-01-01 00:00:00");
+        let time = UNIX_EPOCH + Duration::from_secs(1_709_210_096);
+        assert_eq!(timestamp(time), "2024-02-29 12:34:56");
+    }
+}
@@ -0,0 +1,91 @@
+// pane header text - `pane-title-format` expanded with what the pane is running
+
+use std::{fs, path::PathBuf};
+
+pub const DEFAULT_TITLE_FORMAT: &str = "#{pane_index}: #{pane_title}";
+
+/// what's running in the foreground of a pane's terminal
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct ForegroundProcess {
+    pub command: String,
+    pub path: String,
+}
+
+impl ForegroundProcess {
+    /// looks up the terminal's foreground process group through the shell's `/proc` entry,
+    /// `None` where there's no procfs
+    pub fn of_shell(shell_pid: i32) -> Option<Self> {
+        let stat = fs::read_to_string(format!("/proc/{shell_pid}/stat")).ok()?;
+        // `comm` is in parens and may contain spaces, `tpgid` is the 6th field after it
+        let fields: Vec<&str> = stat.get(stat.rfind(')')? + 2..)?.split_whitespace().collect();
+        let foreground = fields
+            .get(5)?
+            .parse::<i32>()
+            .ok()
+            .filter(|pid| *pid > 0)
+            .unwrap_or(shell_pid);
+        let proc_dir = PathBuf::from(format!("/proc/{foreground}"));
+        let command = fs::read_to_string(proc_dir.join("comm")).ok()?.trim_end().to_owned();
+        let path = fs::read_link(proc_dir.join("cwd"))
+            .map(|path| path.display().to_string())
+            .unwrap_or_default();
+        Some(Self { command, path })
+    }
+}
+
+/// the values a title format can refer to
+pub struct TitleVars<'a> {
+    pub index: usize,
+    pub title: &'a str, // `select-pane -T`, else the program's own (OSC 0/2) title
+    pub process: &'a ForegroundProcess,
+}
+
+/// expands `#{name}` variables, unknown ones expand to nothing
+pub fn format_title(format: &str, vars: &TitleVars) -> String {
+    let mut out = String::with_capacity(format.len());
+    let mut rest = format;
+    while let Some(start) = rest.find("#{") {
+        out.push_str(&rest[..start]);
+        let Some(len) = rest[start..].find('}') else {
+            rest = &rest[start..];
+            break;
+        };
+        match &rest[start + 2..start + len] {
+            "pane_index" => out.push_str(&vars.index.to_string()),
+            "pane_title" if vars.title.is_empty() => out.push_str(&vars.process.command),
+            "pane_title" => out.push_str(vars.title),
+            "pane_current_command" => out.push_str(&vars.process.command),
+            "pane_current_path" => out.push_str(&vars.process.path),
+            _ => {}
+        }
+        rest = &rest[start + len + 1..];
+    }
+    out.push_str(rest);
+    out
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_format_title() {
+        let process = ForegroundProcess {
+            command: "vim".to_owned(),
+            path: "/home/user".to_owned(),
+        };
+        let vars = TitleVars {
+            index: 2,
+            title: "",
+            process: &process,
+        };
+        assert_eq!(format_title(DEFAULT_TITLE_FORMAT, &vars), "2: vim");
+        assert_eq!(
+            format_title("#{pane_current_command} in #{pane_current_path}", &vars),
+            "vim in /home/user"
+        );
+        assert_eq!(format_title("#{nope}x #{unterminated", &vars), "x #{unterminated");
+        let vars = TitleVars { title: "logs", ..vars };
+        assert_eq!(format_title("[#{pane_title}]", &vars), "[logs]");
+    }
+}
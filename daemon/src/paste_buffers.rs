@@ -0,0 +1,239 @@
+// history of copied text (OSC 52 copies from panes, copy mode and `set-buffer`), newest first
+
+use std::collections::VecDeque;
+
+use base64::{
+    Engine, alphabet,
+    engine::{DecodePaddingMode, GeneralPurpose, GeneralPurposeConfig},
+};
+
+pub const PASTE_BUFFER_LIMIT: usize = 50;
+const PREVIEW_LENGTH: usize = 60;
+// an unterminated OSC 52 sequence larger than this is dropped instead of buffered
+const MAX_PENDING_OSC: usize = 1024 * 1024;
+const PASTE_START: &[u8] = b"\x1b[200~";
+const PASTE_END: &[u8] = b"\x1b[201~";
+// programs don't always pad OSC 52 payloads, and ones piped through `base64` come wrapped at 76 columns
+const OSC52_BASE64: GeneralPurpose = GeneralPurpose::new(
+    &alphabet::STANDARD,
+    GeneralPurposeConfig::new().with_decode_padding_mode(DecodePaddingMode::Indifferent),
+);
+
+#[derive(Debug)]
+struct PasteBuffer {
+    name: String,
+    text: String,
+}
+
+#[derive(Debug)]
+pub struct PasteBuffers {
+    buffers: VecDeque<PasteBuffer>,
+    limit: usize,
+    next_id: usize, // automatic names count up like tmux's `buffer0`, `buffer1`, ...
+}
+
+impl Default for PasteBuffers {
+    fn default() -> Self {
+        Self::new(PASTE_BUFFER_LIMIT)
+    }
+}
+
+impl PasteBuffers {
+    pub fn new(limit: usize) -> Self {
+        Self {
+            buffers: VecDeque::new(),
+            limit,
+            next_id: 0,
+        }
+    }
+
+    /// adds a buffer as the newest one, copying the newest text again is a no-op
+    pub fn push(&mut self, text: String) {
+        if text.is_empty() || self.buffers.front().is_some_and(|buffer| buffer.text == text) {
+            return;
+        }
+        let name = format!("buffer{}", self.next_id);
+        self.next_id += 1;
+        self.buffers.push_front(PasteBuffer { name, text });
+        self.buffers.truncate(self.limit);
+    }
+
+    /// replaces the buffer called `name`, it becomes the newest one either way
+    pub fn set(&mut self, name: String, text: String) {
+        self.buffers.retain(|buffer| buffer.name != name);
+        self.buffers.push_front(PasteBuffer { name, text });
+        self.buffers.truncate(self.limit);
+    }
+
+    /// the index of a buffer given by name or index, `None` is the newest buffer
+    pub fn find(&self, buffer: Option<&str>) -> Option<usize> {
+        let Some(buffer) = buffer else {
+            return (!self.buffers.is_empty()).then_some(0);
+        };
+        self.buffers
+            .iter()
+            .position(|candidate| candidate.name == buffer)
+            .or_else(|| buffer.parse().ok().filter(|&index| index < self.buffers.len()))
+    }
+
+    pub fn get(&self, index: usize) -> Option<&str> {
+        self.buffers.get(index).map(|buffer| buffer.text.as_str())
+    }
+
+    pub fn remove(&mut self, index: usize) -> Option<String> {
+        self.buffers.remove(index).map(|buffer| buffer.text)
+    }
+
+    /// single line previews used by `choose-buffer`, in the same order as the indices
+    pub fn previews(&self) -> Vec<String> {
+        self.buffers
+            .iter()
+            .enumerate()
+            .map(|(i, PasteBuffer { name, text })| {
+                let line = text.lines().next().unwrap_or_default();
+                let mut preview: String = line.chars().take(PREVIEW_LENGTH).collect();
+                if line.chars().count() > PREVIEW_LENGTH || text.lines().nth(1).is_some() {
+                    preview.push('…');
+                }
+                format!("{i}: {name} ({} bytes) {preview}", text.len())
+            })
+            .collect()
+    }
+}
+
+/// wraps text in bracketed paste markers, an end marker inside the text would let it escape the paste
+pub fn bracketed_paste(text: &[u8]) -> Vec<u8> {
+    let mut pasted = PASTE_START.to_vec();
+    let mut rest = text;
+    while let Some(i) = rest.windows(PASTE_END.len()).position(|window| window == PASTE_END) {
+        pasted.extend_from_slice(&rest[..i]);
+        rest = &rest[i + PASTE_END.len()..];
+    }
+    pasted.extend_from_slice(rest);
+    pasted.extend_from_slice(PASTE_END);
+    pasted
+}
+
+/// picks OSC 52 clipboard writes (`ESC ] 52 ; <target> ; <base64> BEL|ST`) out of pty output,
+/// sequences split across reads are held back until their terminator arrives
+#[derive(Debug, Default)]
+pub struct Osc52Scanner {
+    pending: Vec<u8>,
+}
+
+impl Osc52Scanner {
+    pub fn feed(&mut self, bytes: &[u8]) -> Vec<String> {
+        const START: &[u8] = b"\x1b]52;";
+
+        let mut copies = Vec::new();
+        let mut data = std::mem::take(&mut self.pending);
+        data.extend_from_slice(bytes);
+
+        let mut rest = data.as_slice();
+        while let Some(start) = find(rest, START) {
+            let body = &rest[start + START.len()..];
+            let Some((end, terminator_len)) = find_terminator(body) else {
+                if body.len() < MAX_PENDING_OSC {
+                    self.pending = rest[start..].to_vec();
+                }
+                return copies;
+            };
+            // the body is `<target>;<payload>`, a `?` payload is a clipboard query not a copy
+            if let Some(payload) = body[..end].splitn(2, |&b| b == b';').nth(1)
+                && let Some(text) = OSC52_BASE64
+                    .decode(
+                        payload
+                            .iter()
+                            .filter(|b| !b.is_ascii_whitespace())
+                            .copied()
+                            .collect::<Vec<_>>(),
+                    )
+                    .ok()
+                    .and_then(|bytes| String::from_utf8(bytes).ok())
+            {
+                copies.push(text);
+            }
+            rest = &body[end + terminator_len..];
+        }
+
+        // keep a trailing partial `ESC ] 5` so a start sequence split across reads isn't missed
+        if let Some(partial) = (1..START.len()).rev().find(|&n| rest.ends_with(&START[..n])) {
+            self.pending = rest[rest.len() - partial..].to_vec();
+        }
+        copies
+    }
+}
+
+fn find(haystack: &[u8], needle: &[u8]) -> Option<usize> {
+    haystack.windows(needle.len()).position(|window| window == needle)
+}
+
+/// position and length of the BEL or ST (`ESC \`) ending an OSC sequence
+fn find_terminator(body: &[u8]) -> Option<(usize, usize)> {
+    body.iter().enumerate().find_map(|(i, &b)| match b {
+        0x07 => Some((i, 1)),
+        0x1b if body.get(i + 1) == Some(&b'\\') => Some((i, 2)),
+        _ => None,
+    })
+}
+
+#[cfg(test)]
+mod test {
+    #![allow(clippy::unwrap_used)]
+    use super::*;
+
+    #[test]
+    fn test_push_limit_and_dedup() {
+        let mut buffers = PasteBuffers::new(2);
+        buffers.push("a".to_owned());
+        buffers.push("a".to_owned());
+        buffers.push("b".to_owned());
+        buffers.push("c".to_owned());
+        assert_eq!(buffers.get(0), Some("c"));
+        assert_eq!(buffers.get(1), Some("b"));
+        assert_eq!(buffers.get(2), None);
+    }
+
+    #[test]
+    fn test_named_buffers() {
+        let mut buffers = PasteBuffers::default();
+        buffers.push("a".to_owned());
+        buffers.set("notes".to_owned(), "b".to_owned());
+        buffers.push("c".to_owned());
+        assert_eq!(buffers.find(None), Some(0));
+        assert_eq!(buffers.find(Some("buffer0")), Some(2));
+        assert_eq!(buffers.find(Some("notes")), Some(1));
+        assert_eq!(buffers.find(Some("1")), Some(1));
+        assert_eq!(buffers.find(Some("3")), None);
+        buffers.set("notes".to_owned(), "d".to_owned());
+        assert_eq!(buffers.get(0), Some("d"));
+        assert_eq!(buffers.find(Some("buffer1")), Some(1));
+        assert_eq!(buffers.previews()[0], "0: notes (1 bytes) d");
+        assert_eq!(PasteBuffers::default().find(None), None);
+    }
+
+    #[test]
+    fn test_bracketed_paste() {
+        assert_eq!(bracketed_paste(b"ls\n"), b"\x1b[200~ls\n\x1b[201~");
+        assert_eq!(
+            bracketed_paste(b"a\x1b[201~rm -rf ~\n"),
+            b"\x1b[200~arm -rf ~\n\x1b[201~"
+        );
+    }
+
+    #[test]
+    fn test_osc52_scanner() {
+        let mut scanner = Osc52Scanner::default();
+        assert_eq!(scanner.feed(b"ls\x1b]52;c;aGVsbG8=\x07\r\n"), vec!["hello"]);
+        // split across reads, terminated with ST
+        assert!(scanner.feed(b"\x1b]5").is_empty());
+        assert!(scanner.feed(b"2;c;aGVs").is_empty());
+        assert_eq!(scanner.feed(b"bG8=\x1b\\"), vec!["hello"]);
+        // clipboard queries are not copies
+        assert!(scanner.feed(b"\x1b]52;c;?\x07").is_empty());
+        // unpadded, and wrapped the way `base64` wraps long output
+        assert_eq!(scanner.feed(b"\x1b]52;c;aGk\x07"), vec!["hi"]);
+        assert_eq!(scanner.feed(b"\x1b]52;c;aGVs\nbG8=\n\x07"), vec!["hello"]);
+        assert_eq!(scanner.feed(b"\x1b]52;c;aGVs\r\nbG8\x1b\\"), vec!["hello"]);
+    }
+}
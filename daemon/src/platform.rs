@@ -0,0 +1,115 @@
+// the few places the daemon talks to the OS's terminal layer directly, kept to calls that linux,
+// macOS and the BSDs all have
+
+use std::{
+    os::fd::{AsFd, AsRawFd},
+    path::{Path, PathBuf},
+};
+
+use nix::{
+    fcntl::{FcntlArg, OFlag, fcntl},
+    ioctl_write_ptr_bad,
+    pty::Winsize,
+    unistd::{Pid, Uid, User},
+};
+
+use crate::prelude::*;
+
+const FALLBACK_SHELL: &str = "/bin/sh";
+
+// `TIOCSWINSZ` is an `int` on some libcs and an `unsigned long` on others, the macro papers over it
+ioctl_write_ptr_bad!(tiocswinsz, nix::libc::TIOCSWINSZ, Winsize);
+
+pub fn winsize(rows: u16, cols: u16) -> Winsize {
+    Winsize {
+        ws_row: rows,
+        ws_col: cols,
+        ws_xpixel: 0, // unused
+        ws_ypixel: 0, // unused
+    }
+}
+
+pub fn set_winsize(fd: impl AsRawFd, rows: u16, cols: u16) -> Result<()> {
+    unsafe { tiocswinsz(fd.as_raw_fd(), &winsize(rows, cols))? };
+    Ok(())
+}
+
+pub fn set_nonblocking(fd: impl AsFd) -> Result<()> {
+    let flags = OFlag::from_bits_truncate(fcntl(&fd, FcntlArg::F_GETFL)?);
+    fcntl(&fd, FcntlArg::F_SETFL(flags | OFlag::O_NONBLOCK))?;
+    Ok(())
+}
+
+/// the working directory of process `pid`, `None` if it's gone or the platform has no way to tell
+#[cfg(target_os = "linux")]
+pub fn process_cwd(pid: Pid) -> Option<PathBuf> {
+    std::fs::read_link(format!("/proc/{pid}/cwd")).ok()
+}
+
+#[cfg(target_os = "macos")]
+pub fn process_cwd(pid: Pid) -> Option<PathBuf> {
+    use std::{
+        ffi::{CStr, OsStr},
+        mem,
+        os::unix::ffi::OsStrExt,
+    };
+
+    use nix::libc::{PROC_PIDVNODEPATHINFO, proc_pidinfo, proc_vnodepathinfo};
+
+    let mut info: proc_vnodepathinfo = unsafe { mem::zeroed() };
+    let size = mem::size_of::<proc_vnodepathinfo>() as i32;
+    let written = unsafe { proc_pidinfo(pid.as_raw(), PROC_PIDVNODEPATHINFO, 0, (&raw mut info).cast(), size) };
+    if written != size {
+        return None;
+    }
+    let path = unsafe { CStr::from_ptr(info.pvi_cdir.vip_path.as_ptr().cast()) };
+    Some(PathBuf::from(OsStr::from_bytes(path.to_bytes())))
+}
+
+#[cfg(not(any(target_os = "linux", target_os = "macos")))]
+pub fn process_cwd(_pid: Pid) -> Option<PathBuf> {
+    None
+}
+
+/// `$SHELL`, then the login shell from the passwd database, then `/bin/sh`
+pub fn default_shell() -> String {
+    let from_env = std::env::var("SHELL").ok();
+    let from_passwd = || {
+        User::from_uid(Uid::current())
+            .ok()
+            .flatten()
+            .map(|user| user.shell.display().to_string())
+    };
+    pick_shell(from_env, from_passwd, |path| Path::new(path).is_file())
+}
+
+fn pick_shell(
+    from_env: Option<String>,
+    from_passwd: impl FnOnce() -> Option<String>,
+    exists: impl Fn(&str) -> bool,
+) -> String {
+    // execve doesn't search `PATH`, only absolute paths are any use
+    let usable = |shell: &String| shell.starts_with('/') && exists(shell);
+    from_env
+        .filter(usable)
+        .or_else(|| from_passwd().filter(usable))
+        .unwrap_or_else(|| {
+            debug!("no usable shell found, falling back to {FALLBACK_SHELL}");
+            FALLBACK_SHELL.to_owned()
+        })
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_pick_shell() {
+        let exists = |path: &str| path != "/bin/missing";
+        let passwd = || Some("/bin/bash".to_owned());
+        assert_eq!(pick_shell(Some("/bin/zsh".to_owned()), passwd, exists), "/bin/zsh");
+        assert_eq!(pick_shell(Some("/bin/missing".to_owned()), passwd, exists), "/bin/bash");
+        assert_eq!(pick_shell(Some("fish".to_owned()), passwd, exists), "/bin/bash");
+        assert_eq!(pick_shell(None, || None, exists), FALLBACK_SHELL);
+    }
+}
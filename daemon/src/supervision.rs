@@ -0,0 +1,171 @@
+// how an actor reacts to one of its event handlers failing. a failed event is logged and the actor carries
+// on, one that keeps failing is wedged and gives up so its parent can restart or drop it, and one whose
+// parent has stopped just stops too since there's nobody left to report to. stopping goes the other way,
+// a parent stops its children and waits for them before it's done itself, down to the ptys reaping their
+// processes
+
+use std::{
+    collections::VecDeque,
+    time::{Duration, Instant},
+};
+
+use tokio::{sync::mpsc, time::timeout};
+
+use crate::prelude::*;
+
+// more failures than this within the period and the actor gives up
+const MAX_FAILURES: usize = 5;
+const FAILURE_PERIOD: Duration = Duration::from_secs(10);
+// how long an actor waits for its children to stop, a wedged one is given up on and left to its parent
+const STOP_TIMEOUT: Duration = Duration::from_secs(2);
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum Verdict {
+    Continue,
+    ParentGone, // stop quietly
+    GiveUp,     // stop and report the error to the parent
+}
+
+/// counts an actor's recent failures, kept by the actor itself since the parent only hears of it giving up
+#[derive(Debug, Default)]
+pub struct Supervisor {
+    failures: VecDeque<Instant>,
+}
+
+impl Supervisor {
+    /// `parent_gone` is the parent handle's error for when the parent has stopped, e.g. `SessionHandleError::Closed`
+    pub fn check<P>(&mut self, error: &Error, parent_gone: P) -> Verdict
+    where
+        P: std::error::Error + PartialEq + Send + Sync + 'static,
+    {
+        let verdict = self.check_at(error, parent_gone, Instant::now());
+        match verdict {
+            Verdict::Continue => error!("error handling event: {error}"),
+            Verdict::ParentGone => debug!("parent has stopped: {error}"),
+            Verdict::GiveUp => error!("giving up after repeated failures: {error}"),
+        }
+        verdict
+    }
+
+    fn check_at<P>(&mut self, error: &Error, parent_gone: P, now: Instant) -> Verdict
+    where
+        P: std::error::Error + PartialEq + Send + Sync + 'static,
+    {
+        if error.downcast_ref::<P>() == Some(&parent_gone) {
+            return Verdict::ParentGone;
+        }
+        while self
+            .failures
+            .front()
+            .is_some_and(|failed| now.duration_since(*failed) > FAILURE_PERIOD)
+        {
+            self.failures.pop_front();
+        }
+        self.failures.push_back(now);
+        if self.failures.len() > MAX_FAILURES {
+            Verdict::GiveUp
+        } else {
+            Verdict::Continue
+        }
+    }
+}
+
+/// runs `stop_children` while dropping whatever the children still send, so none of them is stuck on a full
+/// channel waiting for a parent that's waiting for it
+pub async fn wait_for_children<E>(rx: &mut mpsc::Receiver<E>, stop_children: impl Future<Output = ()>) {
+    let stopped = timeout(STOP_TIMEOUT, stop_children);
+    tokio::pin!(stopped);
+    loop {
+        tokio::select! {
+            res = &mut stopped => {
+                if res.is_err() {
+                    warn!("children did not stop within {STOP_TIMEOUT:?}, giving up on them");
+                }
+                return;
+            }
+            Some(_) = rx.recv() => {}
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    #![allow(clippy::unwrap_used)]
+    use color_eyre::eyre::eyre;
+
+    use super::*;
+
+    #[derive(Debug)]
+    struct ParentEvent;
+
+    #[derive(Debug, PartialEq)]
+    enum ParentError {
+        Closed,
+        Full,
+    }
+
+    impl std::fmt::Display for ParentError {
+        fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+            write!(f, "{self:?}")
+        }
+    }
+
+    impl std::error::Error for ParentError {}
+
+    #[test]
+    fn test_supervisor() {
+        let mut supervisor = Supervisor::default();
+        let start = Instant::now();
+        for i in 0..MAX_FAILURES {
+            let at = start + Duration::from_millis(i as u64);
+            assert_eq!(
+                supervisor.check_at(&eyre!("oops"), ParentError::Closed, at),
+                Verdict::Continue
+            );
+        }
+        assert_eq!(
+            supervisor.check_at(&eyre!("oops"), ParentError::Closed, start + Duration::from_secs(1)),
+            Verdict::GiveUp
+        );
+
+        // failures spread out over time never add up
+        let mut supervisor = Supervisor::default();
+        for i in 0..MAX_FAILURES * 2 {
+            let at = start + FAILURE_PERIOD * i as u32;
+            assert_eq!(
+                supervisor.check_at(&eyre!("oops"), ParentError::Closed, at),
+                Verdict::Continue
+            );
+        }
+    }
+
+    #[test]
+    fn test_supervisor_parent_gone() {
+        let mut supervisor = Supervisor::default();
+        let error = Error::from(ParentError::Closed);
+        assert_eq!(
+            supervisor.check_at(&error, ParentError::Closed, Instant::now()),
+            Verdict::ParentGone
+        );
+        // a parent that's only busy hasn't gone anywhere
+        let error = Error::from(ParentError::Full);
+        assert_eq!(
+            supervisor.check_at(&error, ParentError::Closed, Instant::now()),
+            Verdict::Continue
+        );
+    }
+
+    #[tokio::test]
+    async fn test_wait_for_children() {
+        // the child only stops once its send to the parent goes through
+        let (tx, mut rx) = mpsc::channel(1);
+        tx.send(ParentEvent).await.unwrap();
+        let child = tokio::spawn(async move {
+            tx.send(ParentEvent).await.unwrap();
+        });
+        wait_for_children(&mut rx, async {
+            child.await.unwrap();
+        })
+        .await;
+    }
+}
@@ -0,0 +1,140 @@
+// terminal modes a pane's program turns on that vt100 doesn't keep track of
+
+const CSI: &[u8] = b"\x1b[";
+const MAX_PENDING_PARAMS: usize = 32;
+const FOCUS_REPORTING: &[u8] = b"1004";
+const MAX_KEYBOARD_FLAGS: usize = 16; // kitty keeps a bounded stack per screen too
+
+/// watches pty output for the mode setting sequences below, sequences split across reads are still seen
+/// - `CSI ? <modes> h/l` private modes
+/// - `CSI > <flags> u`, `CSI < <n> u` and `CSI = <flags> ; <mode> u` kitty keyboard flag stack
+#[derive(Debug, Default)]
+pub struct TerminalModes {
+    pub focus_reporting: bool, // DECSET 1004 - the program wants `CSI I`/`CSI O` on focus changes
+    keyboard_flags: Vec<u32>,
+    pending: Vec<u8>,
+}
+
+impl TerminalModes {
+    /// returns replies the program is waiting for, e.g. to a kitty keyboard `CSI ? u` query
+    pub fn feed(&mut self, bytes: &[u8]) -> Vec<u8> {
+        let mut replies = Vec::new();
+        let mut data = std::mem::take(&mut self.pending);
+        data.extend_from_slice(bytes);
+
+        let mut rest = data.as_slice();
+        while let Some(start) = find(rest, CSI) {
+            let body = &rest[start + CSI.len()..];
+            let (marker, params) = match body.first() {
+                Some(&b @ (b'?' | b'>' | b'<' | b'=')) => (Some(b), &body[1..]),
+                _ => (None, body),
+            };
+            let Some(end) = params.iter().position(|&b| !(b.is_ascii_digit() || b == b';')) else {
+                if params.len() < MAX_PENDING_PARAMS {
+                    self.pending = rest[start..].to_vec();
+                }
+                return replies;
+            };
+            let args = &params[..end];
+            match (marker, params[end]) {
+                (Some(b'?'), b'h') => self.set_private_modes(args, true),
+                (Some(b'?'), b'l') => self.set_private_modes(args, false),
+                (Some(b'?'), b'u') => {
+                    let reply = format!("\x1b[?{}u", self.keyboard_flags());
+                    replies.extend_from_slice(reply.as_bytes());
+                }
+                (Some(b'>'), b'u') => {
+                    if self.keyboard_flags.len() == MAX_KEYBOARD_FLAGS {
+                        self.keyboard_flags.remove(0);
+                    }
+                    self.keyboard_flags.push(number(args, 0, 0));
+                }
+                (Some(b'<'), b'u') => {
+                    let n = number(args, 0, 1) as usize;
+                    self.keyboard_flags
+                        .truncate(self.keyboard_flags.len().saturating_sub(n));
+                }
+                (Some(b'='), b'u') => {
+                    let flags = number(args, 0, 0);
+                    let current = self.keyboard_flags();
+                    let flags = match number(args, 1, 1) {
+                        2 => current | flags,
+                        3 => current & !flags,
+                        _ => flags,
+                    };
+                    match self.keyboard_flags.last_mut() {
+                        Some(top) => *top = flags,
+                        None => self.keyboard_flags.push(flags),
+                    }
+                }
+                _ => {}
+            }
+            rest = &params[end + 1..];
+        }
+
+        // keep a trailing `ESC` so a sequence split across reads isn't missed
+        if rest.ends_with(b"\x1b") {
+            self.pending = b"\x1b".to_vec();
+        }
+        replies
+    }
+
+    /// kitty keyboard protocol flags the program asked for, 0 means legacy key encoding
+    pub fn keyboard_flags(&self) -> u32 {
+        self.keyboard_flags.last().copied().unwrap_or(0)
+    }
+
+    fn set_private_modes(&mut self, params: &[u8], enabled: bool) {
+        for mode in params.split(|&b| b == b';') {
+            if mode == FOCUS_REPORTING {
+                self.focus_reporting = enabled;
+            }
+        }
+    }
+}
+
+fn find(haystack: &[u8], needle: &[u8]) -> Option<usize> {
+    haystack.windows(needle.len()).position(|window| window == needle)
+}
+
+/// the `i`th `;` separated parameter, `default` when it's missing or empty
+fn number(params: &[u8], i: usize, default: u32) -> u32 {
+    params
+        .split(|&b| b == b';')
+        .nth(i)
+        .and_then(|param| std::str::from_utf8(param).ok()?.parse().ok())
+        .unwrap_or(default)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_focus_reporting() {
+        let mut modes = TerminalModes::default();
+        modes.feed(b"\x1b[?1049h\x1b[?1004h");
+        assert!(modes.focus_reporting);
+        modes.feed(b"\x1b[?2004;1004l");
+        assert!(!modes.focus_reporting);
+        // split across reads
+        modes.feed(b"vim\x1b");
+        modes.feed(b"[?10");
+        modes.feed(b"04h");
+        assert!(modes.focus_reporting);
+    }
+
+    #[test]
+    fn test_keyboard_flags() {
+        let mut modes = TerminalModes::default();
+        assert_eq!(modes.feed(b"\x1b[?u"), b"\x1b[?0u");
+        modes.feed(b"\x1b[>1u\x1b[>3u");
+        assert_eq!(modes.keyboard_flags(), 3);
+        modes.feed(b"\x1b[=4;2u");
+        assert_eq!(modes.keyboard_flags(), 7);
+        modes.feed(b"\x1b[<u");
+        assert_eq!(modes.feed(b"\x1b[?u"), b"\x1b[?1u");
+        modes.feed(b"\x1b[<5u");
+        assert_eq!(modes.keyboard_flags(), 0);
+    }
+}
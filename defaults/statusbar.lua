@@ -1,7 +1,9 @@
 ui.status_line = {
 	sections = {
 		a = {
-			"active-session"
+			"active-session",
+			"alerts",
+			"clients",
 		},
 		b = {
 			function()
@@ -22,3 +24,19 @@ ui.status_line = {
 		},
 	},
 }
+
+-- #I index, #W name, #F flags (* current, ! activity), #P pane count
+ui.window_bar = {
+	enabled = true,
+	current = "#I:#W#F",
+	other = "#I:#W#F",
+	separator = " ",
+}
+
+-- kill-pane and kill-session bound to keys ask for a yes first
+ui.confirm_before_kill = true
+
+-- called whenever the daemon fires the hook, next to any `set-hook` commands
+remux.on("session-closed", function(hook)
+	os.execute("notify-send remux 'session " .. (hook.session or "?") .. " closed' >/dev/null 2>&1 &")
+end)
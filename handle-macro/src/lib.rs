@@ -1,8 +1,25 @@
 use proc_macro::TokenStream;
-use quote::quote;
-use syn::{Data, DeriveInput, Fields, parse_macro_input, spanned::Spanned};
+use proc_macro2::TokenStream as TokenStream2;
+use quote::{format_ident, quote};
+use syn::{Data, DeriveInput, Expr, Fields, Type, Variant, parse_macro_input};
 
-#[proc_macro_derive(Handle)]
+/// generates a `FooHandle` for a `FooEvent` enum with a method per variant that sends it to the actor.
+/// a variant marked `#[reply(T)]` has a `reply: oneshot::Sender<T>` field which the method fills in
+/// itself, it waits for the actor's answer and returns it.
+///
+/// each event goes out with the `tracing::Span` it was sent from, so the actor receives `(FooEvent, Span)`
+/// and can handle the event in the sender's trace (the crate deriving a handle needs `tracing`).
+///
+/// the methods fail with a generated `FooHandleError` saying whether the actor had stopped, its channel was
+/// full or it never replied.
+///
+/// `#[handle(..)]` on the enum takes
+/// - `capacity = N` or `unbounded`, also generates `FooHandle::channel()` making a channel of that kind
+/// - `spawn`, also generates `FooHandle::spawn(buffer)` making a channel with room for `buffer` events
+/// - `try_send`, also generates `try_foo` methods that fail instead of waiting on a full channel
+/// - `blocking`, also generates `foo_blocking` methods for threads outside the runtime
+/// - `error = E`, the methods fail with `E` instead, which needs a `From<FooHandleError>`
+#[proc_macro_derive(Handle, attributes(handle, reply))]
 pub fn handle(input: TokenStream) -> TokenStream {
     let input = parse_macro_input!(input as DeriveInput);
 
@@ -16,6 +33,7 @@ pub fn handle(input: TokenStream) -> TokenStream {
         enum_name_str.clone() + "Handle"
     };
     let handle_ident = syn::Ident::new(&handle_name_str, enum_name.span());
+    let error_ident = format_ident!("{}Error", handle_ident);
 
     // ensure it's an enum
     let variants = if let Data::Enum(ref e) = input.data {
@@ -26,68 +44,322 @@ pub fn handle(input: TokenStream) -> TokenStream {
             .into();
     };
 
-    // generate a method for each variant
-    let methods = variants.iter().map(|v| {
-        let variant_name = &v.ident;
+    let options = match HandleOptions::parse(&input) {
+        Ok(options) => options,
+        Err(e) => return e.to_compile_error().into(),
+    };
 
-        // convert CamelCase -> snake_case method name
-        let method_name = syn::Ident::new(&to_snake_case(&variant_name.to_string()), variant_name.span());
+    let error_ty = match &options.error {
+        Some(error) => quote! { #error },
+        None => quote! { #error_ident },
+    };
 
-        match &v.fields {
-            Fields::Named(fields_named) => {
-                let args = fields_named.named.iter().map(|f| {
-                    let name = &f.ident;
-                    let ty = &f.ty;
-                    quote! { #name: #ty }
-                });
-                let arg_names = fields_named.named.iter().map(|f| &f.ident);
+    // generate the methods for each variant
+    let methods = variants.iter().map(|v| match Call::new(&enum_name, v) {
+        Ok(call) => call.methods(&options, &error_ident, &error_ty),
+        Err(e) => e.to_compile_error(),
+    });
 
-                quote! {
-                    pub async fn #method_name(&self, #( #args ),* ) -> Result<()> {
-                        self.tx.send(#enum_name::#variant_name { #( #arg_names ),* }).await?;
-                        Ok(())
-                    }
-                }
+    let spawn = options.spawn.then(|| {
+        quote! {
+            /// a handle and the receiver for the actor to own, with room for `buffer` events
+            pub fn spawn(buffer: usize) -> (Self, tokio::sync::mpsc::Receiver<(#enum_name, tracing::Span)>) {
+                let (tx, rx) = tokio::sync::mpsc::channel(buffer);
+                (Self { tx }, rx)
             }
-            Fields::Unnamed(fields_unnamed) => {
-                let args = fields_unnamed.unnamed.iter().enumerate().map(|(i, f)| {
-                    let name = syn::Ident::new(&format!("arg{}", i), f.span());
-                    let ty = &f.ty;
-                    quote! { #name: #ty }
-                });
-                let arg_names =
-                    (0..fields_unnamed.unnamed.len()).map(|i| syn::Ident::new(&format!("arg{}", i), v.ident.span()));
+        }
+    });
 
-                quote! {
-                    pub async fn #method_name(&self, #( #args ),* ) -> Result<()> {
-                        self.tx.send(#enum_name::#variant_name( #( #arg_names ),* )).await?;
-                        Ok(())
-                    }
+    let (sender, channel) = if options.unbounded {
+        (
+            quote! { tokio::sync::mpsc::UnboundedSender<(#enum_name, tracing::Span)> },
+            Some(quote! {
+                /// a handle and the receiver for the actor to own
+                pub fn channel() -> (Self, tokio::sync::mpsc::UnboundedReceiver<(#enum_name, tracing::Span)>) {
+                    let (tx, rx) = tokio::sync::mpsc::unbounded_channel();
+                    (Self { tx }, rx)
                 }
-            }
-            Fields::Unit => {
+            }),
+        )
+    } else {
+        (
+            quote! { tokio::sync::mpsc::Sender<(#enum_name, tracing::Span)> },
+            options.capacity.as_ref().map(|capacity| {
                 quote! {
-                    pub async fn #method_name(&self) -> Result<()> {
-                        self.tx.send(#enum_name::#variant_name).await?;
-                        Ok(())
+                    /// a handle and the receiver for the actor to own
+                    pub fn channel() -> (Self, tokio::sync::mpsc::Receiver<(#enum_name, tracing::Span)>) {
+                        let (tx, rx) = tokio::sync::mpsc::channel(#capacity);
+                        (Self { tx }, rx)
                     }
                 }
-            }
-        }
-    });
+            }),
+        )
+    };
 
     let expanded = quote! {
         #[derive(Debug, Clone)]
         pub struct #handle_ident {
-            tx: tokio::sync::mpsc::Sender<#enum_name>,
+            tx: #sender,
         }
 
+        // `?` converts to the `error = E` type, without one the error is already the right type
+        #[allow(clippy::needless_question_mark)]
         impl #handle_ident {
+            #channel
+            #spawn
+
             #( #methods )*
+
+            /// resolves once the actor has stopped and dropped its receiver
+            pub async fn closed(&self) {
+                self.tx.closed().await
+            }
+
+            pub fn is_closed(&self) -> bool {
+                self.tx.is_closed()
+            }
         }
     };
 
-    TokenStream::from(expanded)
+    let error_messages = [
+        format!("{handle_name_str}: the actor has stopped"),
+        format!("{handle_name_str}: the actor's channel is full"),
+        format!("{handle_name_str}: the actor dropped the reply"),
+    ];
+    let error = quote! {
+        #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+        pub enum #error_ident {
+            Closed,  // the actor has stopped
+            Full,    // only from `try_*` methods
+            NoReply, // the actor dropped a `#[reply(..)]` without answering
+        }
+
+        impl std::fmt::Display for #error_ident {
+            fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+                let [closed, full, no_reply] = [#( #error_messages ),*];
+                match self {
+                    Self::Closed => f.write_str(closed),
+                    Self::Full => f.write_str(full),
+                    Self::NoReply => f.write_str(no_reply),
+                }
+            }
+        }
+
+        impl std::error::Error for #error_ident {}
+    };
+
+    TokenStream::from(quote! {
+        #expanded
+        #error
+    })
+}
+
+/// what `#[handle(..)]` asked for
+#[derive(Default)]
+struct HandleOptions {
+    capacity: Option<Expr>,
+    unbounded: bool,
+    spawn: bool,
+    try_send: bool,
+    blocking: bool,
+    error: Option<Type>,
+}
+
+impl HandleOptions {
+    fn parse(input: &DeriveInput) -> syn::Result<Self> {
+        let mut options = Self::default();
+        for attr in input.attrs.iter().filter(|attr| attr.path().is_ident("handle")) {
+            attr.parse_nested_meta(|meta| {
+                if meta.path.is_ident("capacity") {
+                    options.capacity = Some(meta.value()?.parse()?);
+                } else if meta.path.is_ident("unbounded") {
+                    options.unbounded = true;
+                } else if meta.path.is_ident("spawn") {
+                    options.spawn = true;
+                } else if meta.path.is_ident("error") {
+                    options.error = Some(meta.value()?.parse()?);
+                } else if meta.path.is_ident("try_send") {
+                    options.try_send = true;
+                } else if meta.path.is_ident("blocking") {
+                    options.blocking = true;
+                } else {
+                    return Err(meta.error(
+                        "expected `capacity = ..`, `unbounded`, `spawn`, `try_send`, `blocking` or `error = ..`",
+                    ));
+                }
+                Ok(())
+            })?;
+            if options.unbounded && (options.capacity.is_some() || options.spawn) {
+                return Err(syn::Error::new_spanned(
+                    attr,
+                    "a channel can't have a capacity and be unbounded",
+                ));
+            }
+        }
+        Ok(options)
+    }
+}
+
+/// how a variant is sent: the method's arguments, the event built from them and the reply waited for
+struct Call<'a> {
+    variant: &'a Variant,
+    args: Vec<TokenStream2>,
+    event: TokenStream2,
+    reply: Option<Type>,
+}
+
+impl<'a> Call<'a> {
+    fn new(enum_name: &syn::Ident, variant: &'a Variant) -> syn::Result<Self> {
+        let variant_name = &variant.ident;
+        let reply = reply_type(variant)?;
+        let (args, event) = match &variant.fields {
+            Fields::Named(fields_named) => {
+                // the `reply` of a `#[reply(..)]` variant is filled in by the method
+                let fields: Vec<_> = fields_named
+                    .named
+                    .iter()
+                    .filter(|f| reply.is_none() || f.ident.as_ref().is_some_and(|name| name != "reply"))
+                    .collect();
+                let args = fields
+                    .iter()
+                    .map(|f| {
+                        let name = &f.ident;
+                        let ty = &f.ty;
+                        quote! { #name: #ty }
+                    })
+                    .collect();
+                let arg_names = fields.iter().map(|f| &f.ident);
+                let reply_field = reply.as_ref().map(|_| quote! { reply });
+                (
+                    args,
+                    quote! { #enum_name::#variant_name { #( #arg_names, )* #reply_field } },
+                )
+            }
+            Fields::Unnamed(fields_unnamed) => {
+                let arg_names: Vec<_> = (0..fields_unnamed.unnamed.len())
+                    .map(|i| syn::Ident::new(&format!("arg{}", i), variant.ident.span()))
+                    .collect();
+                let args = fields_unnamed
+                    .unnamed
+                    .iter()
+                    .zip(&arg_names)
+                    .map(|(f, name)| {
+                        let ty = &f.ty;
+                        quote! { #name: #ty }
+                    })
+                    .collect();
+                (args, quote! { #enum_name::#variant_name( #( #arg_names ),* ) })
+            }
+            Fields::Unit => (Vec::new(), quote! { #enum_name::#variant_name }),
+        };
+        Ok(Self {
+            variant,
+            args,
+            event,
+            reply,
+        })
+    }
+
+    fn methods(&self, options: &HandleOptions, error_ident: &syn::Ident, error_ty: &TokenStream2) -> TokenStream2 {
+        // convert CamelCase -> snake_case method name
+        let variant_name = &self.variant.ident;
+        let method_name = syn::Ident::new(&to_snake_case(&variant_name.to_string()), variant_name.span());
+        let args = &self.args;
+        let event = &self.event;
+        let event = quote! { (#event, tracing::Span::current()) };
+        let closed = quote! { .map_err(|_| #error_ident::Closed)? };
+
+        let send = if options.unbounded {
+            quote! { self.tx.send(#event) #closed; }
+        } else {
+            quote! { self.tx.send(#event).await #closed; }
+        };
+        let async_method = match &self.reply {
+            Some(reply_ty) => quote! {
+                pub async fn #method_name(&self, #( #args ),* ) -> std::result::Result<#reply_ty, #error_ty> {
+                    let (reply, rx) = tokio::sync::oneshot::channel();
+                    #send
+                    Ok(rx.await.map_err(|_| #error_ident::NoReply)?)
+                }
+            },
+            None => quote! {
+                pub async fn #method_name(&self, #( #args ),* ) -> std::result::Result<(), #error_ty> {
+                    #send
+                    Ok(())
+                }
+            },
+        };
+
+        // waiting on the reply would defeat the point of not waiting
+        let try_method = (options.try_send && self.reply.is_none()).then(|| {
+            let try_name = format_ident!("try_{}", method_name);
+            let send = if options.unbounded {
+                quote! { self.tx.send(#event) #closed; }
+            } else {
+                quote! {
+                    self.tx.try_send(#event).map_err(|e| match e {
+                        tokio::sync::mpsc::error::TrySendError::Full(_) => #error_ident::Full,
+                        tokio::sync::mpsc::error::TrySendError::Closed(_) => #error_ident::Closed,
+                    })?;
+                }
+            };
+            quote! {
+                pub fn #try_name(&self, #( #args ),* ) -> std::result::Result<(), #error_ty> {
+                    #send
+                    Ok(())
+                }
+            }
+        });
+
+        let blocking_method = options.blocking.then(|| {
+            let blocking_name = format_ident!("{}_blocking", method_name);
+            let send = if options.unbounded {
+                quote! { self.tx.send(#event) #closed; }
+            } else {
+                quote! { self.tx.blocking_send(#event) #closed; }
+            };
+            match &self.reply {
+                Some(reply_ty) => quote! {
+                    pub fn #blocking_name(&self, #( #args ),* ) -> std::result::Result<#reply_ty, #error_ty> {
+                        let (reply, rx) = tokio::sync::oneshot::channel();
+                        #send
+                        Ok(rx.blocking_recv().map_err(|_| #error_ident::NoReply)?)
+                    }
+                },
+                None => quote! {
+                    pub fn #blocking_name(&self, #( #args ),* ) -> std::result::Result<(), #error_ty> {
+                        #send
+                        Ok(())
+                    }
+                },
+            }
+        });
+
+        quote! {
+            #async_method
+            #try_method
+            #blocking_method
+        }
+    }
+}
+
+/// the `T` of a `#[reply(T)]` variant
+fn reply_type(variant: &Variant) -> syn::Result<Option<Type>> {
+    let Some(attr) = variant.attrs.iter().find(|attr| attr.path().is_ident("reply")) else {
+        return Ok(None);
+    };
+    let ty = attr.parse_args::<Type>()?;
+    let has_reply_field = matches!(&variant.fields, Fields::Named(fields) if fields
+        .named
+        .iter()
+        .any(|f| f.ident.as_ref().is_some_and(|name| name == "reply")));
+    if !has_reply_field {
+        return Err(syn::Error::new_spanned(
+            attr,
+            "#[reply(..)] variants need a `reply: oneshot::Sender<..>` field",
+        ));
+    }
+    Ok(Some(ty))
 }
 
 // simple CamelCase -> snake_case
@@ -0,0 +1,140 @@
+use std::time::Duration;
+
+use handle_macro::Handle;
+use tokio::sync::oneshot;
+
+#[derive(Handle, Debug)]
+#[handle(capacity = 1, spawn, try_send, blocking)]
+pub enum CounterEvent {
+    Add(u32),
+    #[reply(u32)]
+    Total {
+        reply: oneshot::Sender<u32>,
+    },
+}
+
+// `unbounded` can't be combined with `capacity` or `spawn`
+#[derive(Handle, Debug)]
+#[handle(unbounded, try_send, blocking, error = LogError)]
+pub enum LogEvent {
+    Line {
+        text: String,
+    },
+    #[reply(usize)]
+    Count {
+        reply: oneshot::Sender<usize>,
+    },
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub struct LogError(LogHandleError);
+
+impl From<LogHandleError> for LogError {
+    fn from(e: LogHandleError) -> Self {
+        Self(e)
+    }
+}
+
+#[tokio::test]
+async fn test_reply() {
+    let (counter, mut rx) = CounterHandle::spawn(4);
+    let actor = tokio::spawn(async move {
+        let mut total = 0;
+        while let Some((event, _span)) = rx.recv().await {
+            match event {
+                CounterEvent::Add(n) => total += n,
+                CounterEvent::Total { reply } => {
+                    let _ = reply.send(total);
+                }
+            }
+        }
+    });
+    counter.add(2).await.unwrap();
+    counter.try_add(3).unwrap();
+    assert_eq!(counter.total().await, Ok(5));
+
+    drop(counter);
+    actor.await.unwrap();
+}
+
+#[tokio::test]
+async fn test_full() {
+    let (counter, mut rx) = CounterHandle::channel();
+    counter.try_add(1).unwrap();
+    assert_eq!(counter.try_add(2), Err(CounterHandleError::Full));
+    // a waiting send goes through once there's room
+    let (event, _span) = rx.recv().await.unwrap();
+    assert!(matches!(event, CounterEvent::Add(1)));
+    counter.add(2).await.unwrap();
+}
+
+#[tokio::test]
+async fn test_closed() {
+    let (counter, rx) = CounterHandle::spawn(1);
+    assert!(!counter.is_closed());
+    drop(rx);
+    counter.closed().await;
+    assert!(counter.is_closed());
+    assert_eq!(counter.add(1).await, Err(CounterHandleError::Closed));
+    assert_eq!(counter.try_add(1), Err(CounterHandleError::Closed));
+    assert_eq!(counter.total().await, Err(CounterHandleError::Closed));
+}
+
+#[tokio::test]
+async fn test_no_reply() {
+    let (counter, mut rx) = CounterHandle::spawn(1);
+    // drops the reply sender without answering
+    tokio::spawn(async move { while rx.recv().await.is_some() {} });
+    assert_eq!(counter.total().await, Err(CounterHandleError::NoReply));
+}
+
+#[test]
+fn test_blocking() {
+    let (counter, mut rx) = CounterHandle::spawn(1);
+    let actor = std::thread::spawn(move || {
+        let (event, _span) = rx.blocking_recv().unwrap();
+        assert!(matches!(event, CounterEvent::Add(7)));
+        let Some((CounterEvent::Total { reply }, _span)) = rx.blocking_recv() else {
+            panic!("expected a total request");
+        };
+        reply.send(7).unwrap();
+    });
+    counter.add_blocking(7).unwrap();
+    assert_eq!(counter.total_blocking(), Ok(7));
+    actor.join().unwrap();
+}
+
+#[tokio::test]
+async fn test_unbounded() {
+    let (log, mut rx) = LogHandle::channel();
+    // never full, nothing has to be received for the sends to go through
+    for i in 0..100 {
+        log.try_line(i.to_string()).unwrap();
+    }
+    log.line("async".to_owned()).await.unwrap();
+    log.line_blocking("blocking".to_owned()).unwrap();
+    let mut lines = 0;
+    while let Ok((event, _span)) = rx.try_recv() {
+        assert!(matches!(event, LogEvent::Line { .. }));
+        lines += 1;
+    }
+    assert_eq!(lines, 102);
+
+    let counted = tokio::spawn(async move {
+        let Some((LogEvent::Count { reply }, _span)) = rx.recv().await else {
+            panic!("expected a count request");
+        };
+        reply.send(lines).unwrap();
+        // the next count is never answered
+        let _ = tokio::time::timeout(Duration::from_secs(1), rx.recv()).await;
+    });
+    assert_eq!(log.count().await, Ok(102));
+    assert_eq!(log.count().await, Err(LogError(LogHandleError::NoReply)));
+    counted.await.unwrap();
+
+    assert_eq!(log.try_line("closed".to_owned()), Err(LogError(LogHandleError::Closed)));
+    assert_eq!(
+        log.line("closed".to_owned()).await,
+        Err(LogError(LogHandleError::Closed))
+    );
+}